@@ -0,0 +1,83 @@
+//! Benchmarks for the hot paths of the daemon.
+//!
+//! Busy compositors emit thousands of events per minute that never become
+//! heartbeats, so backend parsing and the heartbeat pipeline should stay
+//! cheap. Run with `cargo bench`.
+
+use std::hint::black_box;
+
+use criterion::Criterion;
+use criterion::criterion_group;
+use criterion::criterion_main;
+use wakatime_focusd::backend::FocusEvent;
+use wakatime_focusd::config::Config;
+use wakatime_focusd::heartbeat::HeartbeatBuilder;
+use wakatime_focusd::throttle::HeartbeatThrottle;
+use wakatime_focusd::throttle::ThrottleDecision;
+
+/// A socket2 stream dominated by events the backend ignores.
+fn hyprland_lines() -> Vec<String> {
+    (0..1_000)
+        .map(|i| match i % 10 {
+            0 => format!("activewindow>>kitty,~/src/project-{i}: nvim main.rs"),
+            1 => format!("activewindowv2>>0x55a1b2c3{i:04x}"),
+            2 => format!("workspace>>{}", i % 9),
+            3 => format!("openwindow>>0x{i:x},1,firefox,Mozilla Firefox"),
+            4 => format!("movewindowv2>>0x{i:x},2,2"),
+            _ => format!("windowtitlev2>>0x{i:x},Loading… {i}%"),
+        })
+        .collect()
+}
+
+fn focus_events() -> Vec<FocusEvent> {
+    let apps = [
+        ("firefox", "GitHub — Mozilla Firefox"),
+        ("kitty", "~/src/wakatime-focusd: cargo test"),
+        ("dev.zed.Zed", "main.rs — wakatime-focusd"),
+        ("Slack", "general | Slack"),
+    ];
+    (0..100)
+        .map(|i| {
+            let (class, title) = apps[i % apps.len()];
+            FocusEvent::new(class.to_string(), Some(title.to_string()), None)
+        })
+        .collect()
+}
+
+fn bench_hyprland_parsing(c: &mut Criterion) {
+    let lines = hyprland_lines();
+    c.bench_function("hyprland_socket2_1000_lines", |b| {
+        b.iter(|| {
+            wakatime_focusd::backend::replay_hyprland_lines(lines.iter().map(String::as_str))
+        });
+    });
+}
+
+fn bench_pipeline(c: &mut Criterion) {
+    let config = Config {
+        use_presets: vec!["default".to_string()],
+        parse_terminal_titles: true,
+        parse_editor_titles: true,
+        ..Config::default()
+    };
+    let builder = HeartbeatBuilder::from_config(&config);
+    let events = focus_events();
+
+    c.bench_function("pipeline_build_and_throttle_100_events", |b| {
+        b.iter(|| {
+            let mut throttle = HeartbeatThrottle::new(config.min_entity_resend_seconds);
+            for event in &events {
+                if !builder.is_app_allowed(&event.app_class) {
+                    continue;
+                }
+                let heartbeat = builder.build(black_box(event.clone()));
+                if throttle.should_send(&heartbeat.entity) == ThrottleDecision::Send {
+                    throttle.record_sent(heartbeat);
+                }
+            }
+        });
+    });
+}
+
+criterion_group!(benches, bench_hyprland_parsing, bench_pipeline);
+criterion_main!(benches);
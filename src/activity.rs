@@ -0,0 +1,169 @@
+//! Recent focus events and heartbeat decisions, for `wakatime-focusd tail`.
+//!
+//! The event loop records what it sees and decides: focus changes, sent and
+//! failed heartbeats, and why a heartbeat was skipped. [`ActivityLog`] keeps
+//! the most recent entries and broadcasts new ones, so a client attaching
+//! over the control socket first gets the context that led to the current
+//! state, then the live stream. Messages are formatted with
+//! [`sensitive`](crate::redact::sensitive), so `redact_logs` hides titles
+//! and entities here as well.
+
+use std::collections::VecDeque;
+use std::sync::LazyLock;
+use std::sync::Mutex;
+use std::sync::PoisonError;
+use std::time::SystemTime;
+
+use serde::Deserialize;
+use serde::Serialize;
+use tokio::sync::broadcast;
+
+/// Entries kept for replay, and the most a slow client can fall behind.
+pub const REPLAY_CAPACITY: usize = 200;
+
+static LOG: LazyLock<ActivityLog> = LazyLock::new(ActivityLog::new);
+
+/// What an activity entry is about.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ActivityKind {
+    /// Focus moved to another window or its title changed.
+    Focus,
+    /// A heartbeat was handed to the sender.
+    Sent,
+    /// No heartbeat was sent for a focus event, and why.
+    Skipped,
+    /// Sending a heartbeat failed.
+    Failed,
+}
+
+impl ActivityKind {
+    /// Short name for display.
+    #[must_use]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Focus => "focus",
+            Self::Sent => "sent",
+            Self::Skipped => "skipped",
+            Self::Failed => "failed",
+        }
+    }
+}
+
+/// One recorded event or decision.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ActivityEntry {
+    /// When it happened (Unix seconds).
+    pub unix_time: u64,
+    /// What it is about.
+    pub kind: ActivityKind,
+    /// Human-readable description.
+    pub message: String,
+}
+
+/// Recent activity, and a channel for new entries.
+#[derive(Debug)]
+pub struct ActivityLog {
+    recent: Mutex<VecDeque<ActivityEntry>>,
+    live: broadcast::Sender<ActivityEntry>,
+}
+
+impl Default for ActivityLog {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ActivityLog {
+    /// Create an empty log.
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            recent: Mutex::new(VecDeque::with_capacity(REPLAY_CAPACITY)),
+            live: broadcast::channel(REPLAY_CAPACITY).0,
+        }
+    }
+
+    /// Record an entry and pass it to subscribers.
+    pub fn record(&self, kind: ActivityKind, message: String) {
+        let entry = ActivityEntry {
+            unix_time: SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs()),
+            kind,
+            message,
+        };
+        let mut recent = self.lock();
+        if recent.len() == REPLAY_CAPACITY {
+            recent.pop_front();
+        }
+        recent.push_back(entry.clone());
+        // Sending under the lock keeps subscribe() from missing or repeating
+        // an entry; no subscribers is fine
+        let _ = self.live.send(entry);
+    }
+
+    /// The last `replay` entries, oldest first, and a receiver for the ones
+    /// after them.
+    #[must_use]
+    pub fn subscribe(
+        &self,
+        replay: usize,
+    ) -> (Vec<ActivityEntry>, broadcast::Receiver<ActivityEntry>) {
+        let recent = self.lock();
+        let receiver = self.live.subscribe();
+        let skip = recent.len().saturating_sub(replay);
+        (recent.iter().skip(skip).cloned().collect(), receiver)
+    }
+
+    /// Kept entries of `kind`, oldest first.
+    #[must_use]
+    pub fn recent(&self, kind: ActivityKind) -> Vec<ActivityEntry> {
+        self.lock()
+            .iter()
+            .filter(|entry| entry.kind == kind)
+            .cloned()
+            .collect()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, VecDeque<ActivityEntry>> {
+        self.recent.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Process-wide activity log.
+#[must_use]
+pub fn global() -> &'static ActivityLog {
+    &LOG
+}
+
+/// Record an entry in the process-wide log.
+pub fn record(kind: ActivityKind, message: String) {
+    global().record(kind, message);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_replay_then_live() {
+        let log = ActivityLog::new();
+        for i in 0..REPLAY_CAPACITY + 5 {
+            log.record(ActivityKind::Focus, format!("event {i}"));
+        }
+
+        let (replayed, mut live) = log.subscribe(3);
+        let messages: Vec<_> = replayed.iter().map(|e| e.message.as_str()).collect();
+        assert_eq!(messages, ["event 202", "event 203", "event 204"]);
+
+        log.record(ActivityKind::Sent, "kitty".to_string());
+        let entry = live.try_recv().unwrap();
+        assert_eq!(entry.kind, ActivityKind::Sent);
+        assert_eq!(entry.message, "kitty");
+
+        // Asking for more than is kept replays everything kept
+        assert_eq!(log.subscribe(usize::MAX).0.len(), REPLAY_CAPACITY);
+        assert_eq!(log.recent(ActivityKind::Sent).len(), 1);
+    }
+}
@@ -0,0 +1,182 @@
+//! Annotations attached by external tools.
+//!
+//! `wakatime-focusd annotate ticket PROJ-123 --for 2h` sets an annotation
+//! over the control socket, so a ticket tracker, a pairing script or a
+//! shell hook can note what the time is spent on. Until it expires, every
+//! heartbeat sent is recorded with its annotations in the activity log that
+//! `tail` and `snapshot` read. Annotations stay local: `WakaTime` has no
+//! field for them. Like temporary rules, they are kept in memory only.
+
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::sync::PoisonError;
+use std::time::Duration;
+
+use serde::Deserialize;
+use serde::Serialize;
+use tokio::time::Instant;
+use tracing::info;
+
+/// Longest time an annotation can be set for.
+pub const MAX_DURATION: Duration = Duration::from_hours(24);
+
+static ANNOTATIONS: Annotations = Annotations::new();
+
+/// An annotation, as reported in status.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Annotation {
+    /// What the annotation is, e.g. `ticket` or `pair`.
+    pub key: String,
+    /// Its value, e.g. `PROJ-123`.
+    pub value: String,
+    /// Seconds until the annotation expires.
+    pub remaining_seconds: u64,
+}
+
+#[derive(Debug)]
+struct Entry {
+    key: String,
+    value: String,
+    expires_at: Instant,
+}
+
+/// Annotations in effect.
+#[derive(Debug, Default)]
+pub struct Annotations {
+    entries: Mutex<Vec<Entry>>,
+}
+
+impl Annotations {
+    /// Create an empty set.
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            entries: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Set `key` to `value` for `duration`, replacing an earlier value.
+    ///
+    /// Returns `false`, setting nothing, if `duration` is too long to
+    /// represent as a deadline.
+    #[must_use]
+    pub fn set(&self, key: String, value: String, duration: Duration) -> bool {
+        let Some(expires_at) = Instant::now().checked_add(duration) else {
+            return false;
+        };
+        info!("Annotation {key}={value} for {duration:?}");
+        let mut entries = self.lock();
+        entries.retain(|entry| entry.key != key);
+        entries.push(Entry {
+            key,
+            value,
+            expires_at,
+        });
+        true
+    }
+
+    /// Remove the annotation `key`, or all if `None`. Returns the number
+    /// removed.
+    pub fn clear(&self, key: Option<&str>) -> usize {
+        let mut entries = self.lock();
+        let before = entries.len();
+        entries.retain(|entry| key.is_some_and(|key| entry.key != key));
+        let removed = before - entries.len();
+        if removed > 0 {
+            info!("Cleared {removed} annotation(s)");
+        }
+        removed
+    }
+
+    /// Annotations in effect, oldest first.
+    #[must_use]
+    pub fn list(&self) -> Vec<Annotation> {
+        let now = Instant::now();
+        self.lock()
+            .iter()
+            .map(|entry| Annotation {
+                key: entry.key.clone(),
+                value: entry.value.clone(),
+                remaining_seconds: (entry.expires_at - now).as_secs(),
+            })
+            .collect()
+    }
+
+    /// `description` with the annotations in effect appended, e.g.
+    /// `kitty [ticket=PROJ-123, pair=alex]`.
+    #[must_use]
+    pub fn annotate(&self, mut description: String) -> String {
+        let entries = self.lock();
+        for (i, entry) in entries.iter().enumerate() {
+            let separator = if i == 0 { " [" } else { ", " };
+            let _ = write!(description, "{separator}{}={}", entry.key, entry.value);
+        }
+        if !entries.is_empty() {
+            description.push(']');
+        }
+        description
+    }
+
+    /// Lock the annotations, dropping expired ones.
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<Entry>> {
+        let mut entries = self.entries.lock().unwrap_or_else(PoisonError::into_inner);
+        let now = Instant::now();
+        entries.retain(|entry| {
+            let expired = entry.expires_at <= now;
+            if expired {
+                info!("Annotation {} expired", entry.key);
+            }
+            !expired
+        });
+        entries
+    }
+}
+
+/// Process-wide annotations.
+#[must_use]
+pub fn global() -> &'static Annotations {
+    &ANNOTATIONS
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn test_annotations_apply_and_expire() {
+        let annotations = Annotations::new();
+        assert_eq!(annotations.annotate("kitty".to_string()), "kitty");
+
+        assert!(annotations.set(
+            "ticket".to_string(),
+            "PROJ-1".to_string(),
+            Duration::from_hours(2),
+        ));
+        assert!(annotations.set(
+            "pair".to_string(),
+            "alex".to_string(),
+            Duration::from_mins(30),
+        ));
+        // Setting a key again replaces its value
+        assert!(annotations.set(
+            "ticket".to_string(),
+            "PROJ-123".to_string(),
+            Duration::from_hours(2),
+        ));
+        assert_eq!(
+            annotations.annotate("kitty".to_string()),
+            "kitty [pair=alex, ticket=PROJ-123]"
+        );
+
+        tokio::time::advance(Duration::from_mins(30)).await;
+        assert_eq!(
+            annotations.annotate("kitty".to_string()),
+            "kitty [ticket=PROJ-123]"
+        );
+        assert_eq!(annotations.clear(None), 1);
+        assert!(annotations.list().is_empty());
+
+        assert!(!annotations.set("pair".to_string(), "alex".to_string(), Duration::MAX));
+        assert!(annotations.list().is_empty());
+    }
+}
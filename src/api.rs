@@ -3,12 +3,21 @@
 //! Sends heartbeats directly to the `WakaTime` API via HTTP POST.
 //! Heartbeats are buffered and sent in batches via the bulk endpoint.
 //! Failed sends are persisted to an offline queue and replayed later.
+//! Focus-change heartbeats are sent ahead of periodic refreshes, so when the
+//! API is rate limiting or a backlog builds up the most informative
+//! heartbeats get through first.
 
+use std::borrow::Borrow;
+use std::io;
 use std::io::Write;
+use std::path::Path;
 use std::path::PathBuf;
+use std::sync::Arc;
 use std::sync::Mutex;
-use std::sync::atomic::AtomicU32;
+use std::sync::PoisonError;
+use std::sync::atomic::AtomicU64;
 use std::sync::atomic::Ordering;
+use std::time::Duration;
 
 use anyhow::Context;
 use anyhow::Result;
@@ -20,6 +29,8 @@ use reqwest::StatusCode;
 use reqwest::header::AUTHORIZATION;
 use serde::Deserialize;
 use serde::Serialize;
+use thiserror::Error;
+use tokio::time::Instant;
 use tracing::debug;
 use tracing::error;
 use tracing::info;
@@ -28,7 +39,19 @@ use tracing::warn;
 
 use crate::api_key;
 use crate::config::Config;
+use crate::config::ConfigError;
+use crate::config::Destination;
 use crate::domain::Heartbeat;
+use crate::domain::HeartbeatKind;
+use crate::encryption;
+use crate::encryption::QueueCipher;
+use crate::environment;
+use crate::error_log;
+use crate::error_log::ErrorClass;
+use crate::metrics;
+use crate::onboarding;
+use crate::onboarding::CredentialProblem;
+use crate::redact::sensitive;
 
 /// Trait for sending heartbeats to `WakaTime`.
 pub trait HeartbeatSender: Send {
@@ -36,27 +59,104 @@ pub trait HeartbeatSender: Send {
     ///
     /// Implementations may buffer the heartbeat for later delivery (see
     /// [`flush`](Self::flush)).
-    fn send_heartbeat<'a>(&'a self, heartbeat: &'a Heartbeat) -> BoxFuture<'a, Result<()>>;
+    fn send_heartbeat<'a>(
+        &'a self,
+        heartbeat: &'a Heartbeat,
+    ) -> BoxFuture<'a, Result<(), SendError>>;
 
     /// Flush any buffered heartbeats.
     ///
     /// Called by the event loop on periodic ticks and before shutdown/reload.
     /// The default implementation is a no-op (for senders that deliver
     /// immediately, e.g., test mocks).
-    fn flush(&self) -> BoxFuture<'_, Result<()>> {
+    fn flush(&self) -> BoxFuture<'_, Result<(), SendError>> {
         Box::pin(async { Ok(()) })
     }
 }
 
-/// Default `WakaTime` API base URL.
-const DEFAULT_API_URL: &str = "https://api.wakatime.com/api";
+/// Why heartbeats could not be sent.
+#[derive(Debug, Error)]
+pub enum SendError {
+    /// The API rejected the API key (401).
+    #[error("WakaTime API authentication failed")]
+    Auth,
+
+    /// The API asked for fewer requests (429).
+    #[error("Rate limited by WakaTime API")]
+    RateLimited,
+
+    /// The API answered with another error status.
+    #[error("WakaTime API returned {0}")]
+    Status(StatusCode),
+
+    /// The request got no answer (DNS, connection, or timeout).
+    #[error("Failed to send heartbeat request")]
+    Request(#[source] reqwest::Error),
+
+    /// The destination failed recently and is waiting before the next try.
+    #[error("{name} is unreachable, retrying in {}s", retry_in.as_secs().max(1))]
+    Backoff { name: String, retry_in: Duration },
+
+    /// A dry run capture file could not be written.
+    #[error("Failed to write capture file {}", path.display())]
+    Capture {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+
+    /// The fleet server could not be reached.
+    #[error("Failed to connect to fleet server {server}")]
+    FleetConnect {
+        server: String,
+        #[source]
+        source: io::Error,
+    },
+
+    /// The connection to the fleet server broke.
+    #[error("Lost connection to fleet server")]
+    FleetIo(#[source] io::Error),
+
+    /// The fleet server didn't answer in time.
+    #[error("Timed out waiting for fleet server")]
+    FleetTimeout,
+
+    /// The fleet server refused the heartbeat.
+    #[error("Fleet server: {0}")]
+    FleetRefused(String),
+
+    /// The fleet server sent something other than an answer.
+    #[error("Invalid response from fleet server")]
+    FleetProtocol,
+
+    /// The sender panicked; the watchdog restarts it.
+    #[error("Heartbeat sender panicked")]
+    Panicked,
+
+    /// No API key was found, or reading one failed.
+    #[error("Failed to resolve WakaTime API key")]
+    ApiKey(#[source] Box<dyn std::error::Error + Send + Sync>),
+
+    /// The HTTP client could not be set up.
+    #[error("Failed to build HTTP client")]
+    Client(#[source] reqwest::Error),
+
+    /// `encrypt_queue` is set but the queue key hasn't been loaded.
+    #[error("The offline queue key is not loaded")]
+    QueueKeyMissing,
+
+    /// The sender settings in the config are invalid.
+    #[error(transparent)]
+    Config(#[from] ConfigError),
+}
 
-/// Log every Nth error after an initial burst of 5.
-const ERROR_LOG_RATE_LIMIT: u32 = 10;
+/// Default `WakaTime` API base URL.
+pub(crate) const DEFAULT_API_URL: &str = "https://api.wakatime.com/api";
 
 /// Plugin identifier sent with each heartbeat.
-/// `WakaTime` User-Agent format: `(OS) plugin/version`.
-fn user_agent() -> String {
+/// `WakaTime` User-Agent format: `(OS) plugin/version`, with the compositor,
+/// distribution and kernel added if `report_environment` is set.
+fn user_agent(report_environment: bool) -> String {
     let os = std::env::consts::OS;
     let os = match os {
         "macos" => "Darwin",
@@ -64,11 +164,11 @@ fn user_agent() -> String {
         "windows" => "Windows",
         other => other,
     };
-    format!(
-        "({os}) {}/{}",
-        env!("CARGO_PKG_NAME"),
-        env!("CARGO_PKG_VERSION")
-    )
+    let plugin = format!("{}/{}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+    match environment::current().filter(|_| report_environment) {
+        Some(environment) => environment.user_agent(os, &plugin),
+        None => format!("({os}) {plugin}"),
+    }
 }
 
 /// Flush the buffer when it reaches this many heartbeats.
@@ -86,32 +186,208 @@ const QUEUE_DRAIN_LIMIT: usize = 10;
 /// Offline queue file name.
 const QUEUE_FILE: &str = "queue.jsonl";
 
+/// Give up on a request after this long, so a hanging server can't hold up
+/// the daemon.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Wait this long before retrying after the first failed flush, doubling on
+/// every further failure up to [`RETRY_MAX_DELAY`].
+const RETRY_INITIAL_DELAY: Duration = Duration::from_mins(1);
+
+/// Longest wait between retries.
+const RETRY_MAX_DELAY: Duration = Duration::from_mins(30);
+
+/// Consecutive heartbeats for the same entity closer together than this
+/// (in seconds) are coalesced into one before sending.
+const COALESCE_WINDOW_SECONDS: f64 = 60.0;
+
 /// JSON payload for a heartbeat (fully owned for buffering and offline queue).
+///
+/// Also the wire format for heartbeats forwarded in fleet mode.
 #[derive(Debug, Clone, Serialize, Deserialize)]
-struct HeartbeatPayload {
+pub(crate) struct HeartbeatPayload {
     entity: String,
     #[serde(rename = "type")]
     entity_type: String,
     category: String,
     time: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    project: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    language: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    branch: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     machine_name_id: Option<String>,
+    /// Send priority only; not part of the API payload. Queued batches are
+    /// persisted in priority order, so it isn't needed after a round trip.
+    #[serde(skip)]
+    kind: HeartbeatKind,
 }
 
 impl HeartbeatPayload {
-    fn from_heartbeat(heartbeat: &Heartbeat) -> Self {
+    pub(crate) fn from_heartbeat(heartbeat: &Heartbeat) -> Self {
         Self {
             entity: heartbeat.entity.as_str().to_string(),
-            entity_type: "app".to_string(),
+            entity_type: heartbeat.entity_type.as_str().to_string(),
             category: heartbeat.category.as_str().to_string(),
             time: heartbeat.time,
+            project: heartbeat.project.clone(),
+            language: heartbeat.language.clone(),
+            branch: heartbeat.branch.clone(),
             machine_name_id: hostname().ok(),
+            kind: heartbeat.kind,
+        }
+    }
+
+    /// Attribute the heartbeat to another machine.
+    pub(crate) fn with_machine(mut self, machine: String) -> Self {
+        self.machine_name_id = Some(machine);
+        self
+    }
+
+    /// The heartbeat's entity.
+    pub(crate) fn entity(&self) -> &str {
+        &self.entity
+    }
+}
+
+/// Coalesce runs of consecutive duplicate heartbeats.
+///
+/// A heartbeat is dropped when it matches the previously kept heartbeat's
+/// entity, type, category, project, language, and branch and falls within
+/// [`COALESCE_WINDOW_SECONDS`] of it, so each run keeps its earliest
+/// timestamp. A heartbeat for a different entity breaks the run — dropping
+/// anything across a switch would shift time between entities on the
+/// dashboard.
+fn coalesce_payloads<P: Borrow<HeartbeatPayload>>(payloads: Vec<P>) -> Vec<P> {
+    let mut coalesced: Vec<P> = Vec::with_capacity(payloads.len());
+
+    for shared in payloads {
+        let payload = shared.borrow();
+        if let Some(kept) = coalesced.last().map(Borrow::borrow)
+            && kept.entity == payload.entity
+            && kept.entity_type == payload.entity_type
+            && kept.category == payload.category
+            && kept.project == payload.project
+            && kept.language == payload.language
+            && kept.branch == payload.branch
+            && (payload.time - kept.time).abs() < COALESCE_WINDOW_SECONDS
+        {
+            trace!(
+                "Coalescing duplicate heartbeat for {}",
+                sensitive(&payload.entity)
+            );
+            continue;
         }
+        coalesced.push(shared);
+    }
+
+    coalesced
+}
+
+/// Order payloads for delivery: focus changes before periodic refreshes.
+///
+/// The sort is stable, so heartbeats keep their time order within a priority.
+fn prioritize_payloads<P: Borrow<HeartbeatPayload>>(payloads: &mut [P]) {
+    payloads.sort_by_key(|p| p.borrow().kind.priority());
+}
+
+/// A send that was not fully accepted by the API.
+struct SendFailure {
+    error: SendError,
+    /// Payloads that were not accepted, in the order they were to be sent.
+    unsent: Vec<Arc<HeartbeatPayload>>,
+}
+
+/// Delivery counters and retry schedule of one destination.
+#[derive(Debug, Default)]
+pub struct DestinationStats {
+    name: String,
+    sent: AtomicU64,
+    failed: AtomicU64,
+    retry: Mutex<Retry>,
+}
+
+#[derive(Debug, Default)]
+struct Retry {
+    /// Flushes that failed in a row.
+    failures: u32,
+    /// No network requests before this.
+    at: Option<Instant>,
+}
+
+/// A destination as reported over the control socket.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DestinationStatus {
+    /// Destination name (the API host for the main destination).
+    pub name: String,
+    /// Heartbeats accepted since the daemon started.
+    pub heartbeats_sent: u64,
+    /// Flushes that failed since the daemon started.
+    pub failed_flushes: u64,
+    /// Seconds until the next attempt, while backing off after failures.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retry_in_seconds: Option<u64>,
+}
+
+impl DestinationStats {
+    fn new(name: String) -> Self {
+        Self {
+            name,
+            ..Self::default()
+        }
+    }
+
+    /// Destination name.
+    #[must_use]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Current counters.
+    #[must_use]
+    pub fn snapshot(&self) -> DestinationStatus {
+        DestinationStatus {
+            name: self.name.clone(),
+            heartbeats_sent: self.sent.load(Ordering::Relaxed),
+            failed_flushes: self.failed.load(Ordering::Relaxed),
+            retry_in_seconds: self.retry_in().map(|wait| wait.as_secs().max(1)),
+        }
+    }
+
+    /// Time left before the next attempt, if backing off.
+    fn retry_in(&self) -> Option<Duration> {
+        self.lock_retry()
+            .at
+            .map(|at| at.saturating_duration_since(Instant::now()))
+            .filter(|wait| !wait.is_zero())
+    }
+
+    /// Clear the backoff after a successful flush.
+    fn record_success(&self) {
+        *self.lock_retry() = Retry::default();
+    }
+
+    /// Back off after a failed flush. Returns the delay before the next try.
+    fn record_failure(&self) -> Duration {
+        self.failed.fetch_add(1, Ordering::Relaxed);
+        let mut retry = self.lock_retry();
+        let delay = RETRY_INITIAL_DELAY
+            .saturating_mul(1 << retry.failures.min(16))
+            .min(RETRY_MAX_DELAY);
+        retry.failures = retry.failures.saturating_add(1);
+        retry.at = Some(Instant::now() + delay);
+        delay
+    }
+
+    fn lock_retry(&self) -> std::sync::MutexGuard<'_, Retry> {
+        self.retry.lock().unwrap_or_else(PoisonError::into_inner)
     }
 }
 
 /// Get the machine hostname.
-fn hostname() -> Result<String> {
+pub(crate) fn hostname() -> Result<String> {
     let name = gethostname::gethostname();
     name.into_string()
         .map_err(|_| anyhow::anyhow!("hostname is not valid UTF-8"))
@@ -124,7 +400,9 @@ fn hostname() -> Result<String> {
 /// is called explicitly (on periodic ticks and shutdown).
 ///
 /// If a flush fails (network down, server error), the batch is persisted to
-/// an offline queue file and replayed on the next successful flush.
+/// an offline queue file and replayed on the next successful flush. Further
+/// flushes go straight to the queue until a retry delay has passed, which
+/// doubles with every failure.
 #[derive(Debug)]
 pub struct ApiSender {
     /// HTTP client (reused for connection pooling).
@@ -142,51 +420,96 @@ pub struct ApiSender {
     /// Dry run mode.
     dry_run: bool,
 
-    /// Buffered heartbeats waiting to be flushed.
-    buffer: Mutex<Vec<HeartbeatPayload>>,
+    /// In dry run mode, where to record heartbeats for a later import.
+    capture_path: Option<PathBuf>,
+
+    /// Buffered heartbeats waiting to be flushed, shared with other
+    /// destinations.
+    buffer: Mutex<Vec<Arc<HeartbeatPayload>>>,
 
     /// Path to the offline queue file.
     queue_path: Option<PathBuf>,
 
-    /// Per-instance error log counter for rate limiting.
-    error_log_count: AtomicU32,
+    /// Seals queue lines, with `encrypt_queue`.
+    cipher: Option<Arc<QueueCipher>>,
+
+    /// Delivery counters and retry schedule.
+    stats: Arc<DestinationStats>,
 }
 
 impl ApiSender {
     /// Create a new API sender from config.
     ///
-    /// Resolves the API key (from env var or `~/.wakatime.cfg`) and the API
+    /// Resolves the API key (see [`api_key`]) and the API
     /// base URL (from daemon config, `~/.wakatime.cfg`, or the default).
-    pub fn from_config(config: &Config) -> Result<Self> {
-        let api_key = api_key::resolve_api_key(config.wakatime_config_path.as_deref())
-            .context("Failed to resolve WakaTime API key")?;
+    pub fn from_config(config: &Config) -> Result<Self, SendError> {
+        let api_key = api_key::resolve_api_key(config).map_err(|e| SendError::ApiKey(e.into()))?;
 
         let base_url = Self::resolve_api_url(config);
+        let name = reqwest::Url::parse(&base_url)
+            .ok()
+            .and_then(|url| url.host_str().map(String::from))
+            .unwrap_or_else(|| base_url.clone());
+        let sender = Self::new(name, &base_url, api_key, config)?;
+        info!("Using WakaTime API: {}", sender.heartbeat_url);
+        Ok(sender)
+    }
+
+    /// Create a sender for an extra destination, with its own offline queue.
+    pub fn for_destination(destination: &Destination, config: &Config) -> Result<Self, SendError> {
+        let sender = Self::new(
+            destination.name.clone(),
+            &destination.api_url,
+            destination.api_key.clone(),
+            config,
+        )?
+        .with_queue_file(&format!("queue-{}.jsonl", destination.name));
+        info!(
+            "Sending copies to {}: {}",
+            destination.name, sender.heartbeat_url
+        );
+        Ok(sender)
+    }
+
+    fn new(
+        name: String,
+        base_url: &str,
+        api_key: String,
+        config: &Config,
+    ) -> Result<Self, SendError> {
         let base_url = base_url.trim_end_matches('/');
         let heartbeat_url = format!("{base_url}/v1/users/current/heartbeats");
         let bulk_url = format!("{base_url}/v1/users/current/heartbeats.bulk");
 
         let client = Client::builder()
-            .user_agent(user_agent())
+            .user_agent(user_agent(config.report_environment))
+            .timeout(REQUEST_TIMEOUT)
             .build()
-            .context("Failed to build HTTP client")?;
+            .map_err(SendError::Client)?;
 
         let queue_path = dirs::data_dir().map(|d| d.join("wakatime-focusd").join(QUEUE_FILE));
 
-        info!("Using WakaTime API: {heartbeat_url}");
         if let Some(ref qp) = queue_path {
             debug!("Offline queue path: {}", qp.display());
         }
 
+        let cipher = if config.encrypt_queue {
+            Some(encryption::cipher().ok_or(SendError::QueueKeyMissing)?)
+        } else {
+            None
+        };
+
         Ok(Self {
             client,
             heartbeat_url,
             bulk_url,
             api_key,
             dry_run: config.dry_run,
+            capture_path: config.dry_run_capture.clone(),
             buffer: Mutex::new(Vec::new()),
             queue_path,
-            error_log_count: AtomicU32::new(0),
+            cipher,
+            stats: Arc::new(DestinationStats::new(name)),
         })
     }
 
@@ -207,21 +530,72 @@ impl ApiSender {
         DEFAULT_API_URL.to_string()
     }
 
+    /// Use a different offline queue file, so several senders don't share one.
+    #[must_use]
+    pub fn with_queue_file(mut self, name: &str) -> Self {
+        self.queue_path = self.queue_path.map(|path| path.with_file_name(name));
+        if let Some(ref qp) = self.queue_path {
+            debug!("Offline queue path: {}", qp.display());
+        }
+        self
+    }
+
+    /// Delivery counters and retry schedule.
+    #[must_use]
+    pub fn stats(&self) -> Arc<DestinationStats> {
+        Arc::clone(&self.stats)
+    }
+
+    /// A sender that never reaches the network, for tests.
+    #[cfg(test)]
+    pub(crate) fn offline() -> Self {
+        Self {
+            client: Client::new(),
+            heartbeat_url: String::new(),
+            bulk_url: String::new(),
+            api_key: String::new(),
+            dry_run: false,
+            capture_path: None,
+            buffer: Mutex::new(Vec::new()),
+            queue_path: None,
+            cipher: None,
+            stats: Arc::default(),
+        }
+    }
+
+    /// Heartbeats waiting to be flushed, for tests.
+    #[cfg(test)]
+    pub(crate) fn buffered(&self) -> Vec<Arc<HeartbeatPayload>> {
+        self.buffer.lock().expect("buffer lock poisoned").clone()
+    }
+
     /// Buffer a heartbeat. Triggers a flush if the buffer reaches the threshold.
-    async fn buffer_heartbeat(&self, heartbeat: &Heartbeat) -> Result<()> {
-        let payload = HeartbeatPayload::from_heartbeat(heartbeat);
+    async fn buffer_heartbeat(&self, heartbeat: &Heartbeat) -> Result<(), SendError> {
+        self.buffer_payload(Arc::new(HeartbeatPayload::from_heartbeat(heartbeat)))
+            .await
+    }
 
+    /// Buffer a heartbeat payload. Triggers a flush if the buffer reaches the
+    /// threshold.
+    pub(crate) async fn buffer_payload(
+        &self,
+        payload: Arc<HeartbeatPayload>,
+    ) -> Result<(), SendError> {
         if self.dry_run {
             info!(
                 "[DRY RUN] Would send heartbeat: {}",
-                serde_json::to_string(&payload).unwrap_or_default()
+                sensitive(serde_json::to_string(&*payload).unwrap_or_default())
             );
+            if let Some(ref path) = self.capture_path {
+                capture_payload(path, &payload)?;
+            }
             return Ok(());
         }
 
         debug!(
             "Buffering heartbeat: entity={} category={}",
-            heartbeat.entity, heartbeat.category
+            sensitive(&payload.entity),
+            payload.category
         );
 
         let should_flush = {
@@ -243,41 +617,109 @@ impl ApiSender {
     /// Flush all buffered heartbeats to the API.
     ///
     /// On success, also drains any queued offline heartbeats.
-    /// On failure, persists the batch to the offline queue.
-    async fn flush_buffer(&self) -> Result<()> {
+    /// On failure, persists the batch to the offline queue and backs off.
+    async fn flush_buffer(&self) -> Result<(), SendError> {
         let payloads = {
             let mut buffer = self.buffer.lock().expect("buffer lock poisoned");
             std::mem::take(&mut *buffer)
         };
 
+        if let Some(retry_in) = self.stats.retry_in() {
+            if !payloads.is_empty() {
+                self.persist_to_queue(&coalesce_payloads(payloads));
+            }
+            return Err(SendError::Backoff {
+                name: self.stats.name.clone(),
+                retry_in,
+            });
+        }
+
         if payloads.is_empty() {
             // No new heartbeats, but still try to drain offline queue
             self.drain_queue().await;
             return Ok(());
         }
 
+        let buffered = payloads.len();
+        let payloads = coalesce_payloads(payloads);
+        if payloads.len() < buffered {
+            debug!(
+                "Coalesced {} duplicate heartbeat(s)",
+                buffered - payloads.len()
+            );
+        }
+
         debug!("Flushing {} buffered heartbeat(s)", payloads.len());
 
-        match self.send_payloads(&payloads).await {
+        match self.send_payloads(payloads).await {
             Ok(()) => {
+                self.stats.record_success();
                 self.drain_queue().await;
                 Ok(())
             }
-            Err(e) => {
-                self.persist_to_queue(&payloads);
-                Err(e)
+            Err(failure) => {
+                self.persist_to_queue(&failure.unsent);
+                let delay = self.stats.record_failure();
+                debug!("Retrying {} in {}s", self.stats.name, delay.as_secs());
+                Err(failure.error)
             }
         }
     }
 
-    /// Send a slice of payloads, using single or bulk endpoint as appropriate.
-    async fn send_payloads(&self, payloads: &[HeartbeatPayload]) -> Result<()> {
+    /// Send the heartbeats recorded in a dry run capture file.
+    ///
+    /// Returns how many were sent. If the API fails part way, the rest are
+    /// moved to the offline queue for the daemon to retry.
+    pub async fn import(&self, path: &Path) -> Result<usize> {
+        let payloads: Vec<_> = read_capture(path)?.into_iter().map(Arc::new).collect();
+        let total = payloads.len();
+        if total == 0 {
+            return Ok(0);
+        }
+
+        info!("Importing {total} heartbeat(s) from {}", path.display());
+        match self.send_payloads(payloads).await {
+            Ok(()) => Ok(total),
+            Err(failure) => {
+                let sent = total - failure.unsent.len();
+                self.persist_to_queue(&failure.unsent);
+                Err(anyhow::Error::new(failure.error).context(format!(
+                    "Imported {sent} of {total} heartbeat(s); the rest were moved to the offline queue"
+                )))
+            }
+        }
+    }
+
+    /// Send payloads, using single or bulk endpoint as appropriate.
+    ///
+    /// Payloads are sent in priority order, one bulk chunk at a time. If a
+    /// chunk fails, the remaining payloads (that chunk included) are returned
+    /// so only those are queued for retry.
+    async fn send_payloads(
+        &self,
+        mut payloads: Vec<Arc<HeartbeatPayload>>,
+    ) -> std::result::Result<(), SendFailure> {
+        prioritize_payloads(&mut payloads);
+
         if payloads.len() == 1 {
-            return self.post_single(&payloads[0]).await;
+            metrics::timed_send(self.post_single(&payloads[0]))
+                .await
+                .map_err(|error| SendFailure {
+                    error,
+                    unsent: payloads,
+                })?;
+            self.stats.sent.fetch_add(1, Ordering::Relaxed);
+            return Ok(());
         }
 
-        for chunk in payloads.chunks(MAX_BULK_SIZE) {
-            self.post_bulk(chunk).await?;
+        for (i, chunk) in payloads.chunks(MAX_BULK_SIZE).enumerate() {
+            if let Err(error) = metrics::timed_send(self.post_bulk(chunk)).await {
+                let unsent = payloads.split_off(i * MAX_BULK_SIZE);
+                return Err(SendFailure { error, unsent });
+            }
+            self.stats
+                .sent
+                .fetch_add(chunk.len() as u64, Ordering::Relaxed);
         }
 
         Ok(())
@@ -292,7 +734,7 @@ impl ApiSender {
     }
 
     /// POST a single heartbeat.
-    async fn post_single(&self, payload: &HeartbeatPayload) -> Result<()> {
+    async fn post_single(&self, payload: &HeartbeatPayload) -> Result<(), SendError> {
         let response = self
             .client
             .post(&self.heartbeat_url)
@@ -300,13 +742,13 @@ impl ApiSender {
             .json(payload)
             .send()
             .await
-            .context("Failed to send heartbeat request")?;
+            .map_err(SendError::Request)?;
 
         self.handle_response(response).await
     }
 
     /// POST a batch of heartbeats to the bulk endpoint.
-    async fn post_bulk(&self, payloads: &[HeartbeatPayload]) -> Result<()> {
+    async fn post_bulk(&self, payloads: &[Arc<HeartbeatPayload>]) -> Result<(), SendError> {
         debug!("Sending bulk request with {} heartbeat(s)", payloads.len());
 
         let response = self
@@ -316,18 +758,19 @@ impl ApiSender {
             .json(payloads)
             .send()
             .await
-            .context("Failed to send bulk heartbeat request")?;
+            .map_err(SendError::Request)?;
 
         self.handle_response(response).await
     }
 
     /// Handle an API response, mapping status codes to results.
-    async fn handle_response(&self, response: reqwest::Response) -> Result<()> {
+    async fn handle_response(&self, response: reqwest::Response) -> Result<(), SendError> {
         let status = response.status();
 
         match status {
             s if s.is_success() => {
                 trace!("Heartbeat(s) accepted ({})", s);
+                error_log::global().recovered(ErrorClass::Api);
                 Ok(())
             }
             StatusCode::UNAUTHORIZED => {
@@ -335,7 +778,8 @@ impl ApiSender {
                     "WakaTime API authentication failed (401). \
                      Check your API key in ~/.wakatime.cfg or $WAKATIME_API_KEY"
                 );
-                anyhow::bail!("WakaTime API authentication failed")
+                tokio::spawn(onboarding::notify_once(CredentialProblem::Rejected));
+                Err(SendError::Auth)
             }
             StatusCode::TOO_MANY_REQUESTS => {
                 let retry_after = response
@@ -344,30 +788,24 @@ impl ApiSender {
                     .and_then(|v| v.to_str().ok())
                     .unwrap_or("unknown");
                 warn!("WakaTime API rate limited (429). Retry-After: {retry_after}");
-                anyhow::bail!("Rate limited by WakaTime API")
+                Err(SendError::RateLimited)
             }
             _ => {
                 let body = response.text().await.unwrap_or_default();
-                let count = self.error_log_count.fetch_add(1, Ordering::Relaxed);
-                if count < 5 || count.is_multiple_of(ERROR_LOG_RATE_LIMIT) {
+                if error_log::global().should_log(ErrorClass::Api) {
                     error!("WakaTime API error ({status}): {body}");
-                    if count == 5 {
-                        warn!(
-                            "Rate-limiting error logs (showing every {}th error)",
-                            ERROR_LOG_RATE_LIMIT
-                        );
-                    }
                 }
-                anyhow::bail!("WakaTime API returned {status}")
+                Err(SendError::Status(status))
             }
         }
     }
 
     /// Persist a failed batch to the offline queue file.
     ///
-    /// Appends the batch as a single JSON line. Skips if the queue file
-    /// exceeds [`QUEUE_MAX_SIZE`] to prevent unbounded disk growth.
-    fn persist_to_queue(&self, payloads: &[HeartbeatPayload]) {
+    /// Appends the batch as a single JSON line, sealed with `encrypt_queue`.
+    /// Skips if the queue file exceeds [`QUEUE_MAX_SIZE`] to prevent
+    /// unbounded disk growth.
+    fn persist_to_queue<P: Serialize>(&self, payloads: &[P]) {
         let Some(ref queue_path) = self.queue_path else {
             warn!(
                 "No offline queue path available, dropping {} heartbeat(s)",
@@ -397,7 +835,7 @@ impl ApiSender {
         }
 
         let line = match serde_json::to_string(payloads) {
-            Ok(l) => l,
+            Ok(l) => self.seal(l),
             Err(e) => {
                 error!("Failed to serialize heartbeats for offline queue: {e}");
                 return;
@@ -420,6 +858,23 @@ impl ApiSender {
         }
     }
 
+    /// Seal a queue line if the queue is encrypted.
+    fn seal(&self, line: String) -> String {
+        match self.cipher {
+            Some(ref cipher) => cipher.seal(&line),
+            None => line,
+        }
+    }
+
+    /// Read a queue line, sealed or not.
+    fn open(&self, line: &str) -> Result<Vec<Arc<HeartbeatPayload>>, String> {
+        if !encryption::is_sealed(line) {
+            return serde_json::from_str(line).map_err(|e| e.to_string());
+        }
+        let cipher = self.cipher.as_ref().ok_or("the line is encrypted")?;
+        serde_json::from_str(&cipher.open(line)?).map_err(|e| e.to_string())
+    }
+
     /// Drain queued batches from the offline queue file.
     ///
     /// Sends up to [`QUEUE_DRAIN_LIMIT`] batches, oldest first. On failure,
@@ -443,6 +898,7 @@ impl ApiSender {
         info!("Draining offline queue ({} batch(es))", lines.len());
 
         let mut drained = 0;
+        let mut partial: Option<String> = None;
 
         for line in &lines {
             if drained >= QUEUE_DRAIN_LIMIT {
@@ -453,8 +909,21 @@ impl ApiSender {
                 break;
             }
 
-            let batch: Vec<HeartbeatPayload> = match serde_json::from_str(line) {
-                Ok(b) => b,
+            if self.cipher.is_none() && encryption::is_sealed(line) {
+                warn!(
+                    "The offline queue has encrypted batches; keeping them until encrypt_queue is turned back on"
+                );
+                break;
+            }
+
+            let batch: Vec<Arc<HeartbeatPayload>> = match self.open(line) {
+                Ok(b) => coalesce_payloads(b),
+                Err(e) if encryption::is_sealed(line) => {
+                    warn!(
+                        "An encrypted queue batch doesn't open with the current key ({e}); keeping it and the batches after it"
+                    );
+                    break;
+                }
                 Err(e) => {
                     warn!("Skipping corrupt queue entry: {e}");
                     drained += 1;
@@ -462,16 +931,28 @@ impl ApiSender {
                 }
             };
 
-            if let Err(e) = self.send_payloads(&batch).await {
-                warn!("Failed to drain queued batch: {e}. Will retry later.");
+            let batch_len = batch.len();
+            if let Err(failure) = self.send_payloads(batch).await {
+                let delay = self.stats.record_failure();
+                warn!(
+                    "Failed to drain queued batch: {}. Will retry in {}s.",
+                    failure.error,
+                    delay.as_secs()
+                );
+                if failure.unsent.len() < batch_len {
+                    // Part of the batch went out; keep only the rest queued
+                    partial = serde_json::to_string(&failure.unsent)
+                        .ok()
+                        .map(|line| self.seal(line));
+                }
                 break;
             }
 
-            debug!("Drained queued batch ({} heartbeat(s))", batch.len());
+            debug!("Drained queued batch ({} heartbeat(s))", batch_len);
             drained += 1;
         }
 
-        if drained == 0 {
+        if drained == 0 && partial.is_none() {
             return;
         }
 
@@ -483,8 +964,15 @@ impl ApiSender {
                 info!("Offline queue fully drained");
             }
         } else {
-            // Rewrite with remaining batches
-            let remaining = lines[drained..].iter().fold(String::new(), |mut acc, l| {
+            // Rewrite with remaining batches, replacing a partially sent one
+            let mut remaining_lines = lines[drained..].iter();
+            let mut remaining = String::new();
+            if let Some(partial) = partial {
+                remaining_lines.next();
+                remaining.push_str(&partial);
+                remaining.push('\n');
+            }
+            let remaining = remaining_lines.fold(remaining, |mut acc, l| {
                 acc.push_str(l);
                 acc.push('\n');
                 acc
@@ -501,16 +989,106 @@ impl ApiSender {
     }
 }
 
+/// Append a heartbeat to a dry run capture file, one JSON object per line.
+pub(crate) fn capture_payload(path: &Path, payload: &HeartbeatPayload) -> Result<(), SendError> {
+    let append = || -> io::Result<()> {
+        if let Some(parent) = path.parent()
+            && !parent.as_os_str().is_empty()
+        {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)?;
+        writeln!(file, "{}", serde_json::to_string(payload)?)
+    };
+    append().map_err(|source| SendError::Capture {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+/// Read the heartbeats in a dry run capture file.
+fn read_capture(path: &Path) -> Result<Vec<HeartbeatPayload>> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    content
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| {
+            serde_json::from_str(line).with_context(|| {
+                format!("Invalid heartbeat on line {} of {}", i + 1, path.display())
+            })
+        })
+        .collect()
+}
+
+impl<S: HeartbeatSender + ?Sized> HeartbeatSender for Box<S> {
+    fn send_heartbeat<'a>(
+        &'a self,
+        heartbeat: &'a Heartbeat,
+    ) -> BoxFuture<'a, Result<(), SendError>> {
+        (**self).send_heartbeat(heartbeat)
+    }
+
+    fn flush(&self) -> BoxFuture<'_, Result<(), SendError>> {
+        (**self).flush()
+    }
+}
+
 impl HeartbeatSender for ApiSender {
-    fn send_heartbeat<'a>(&'a self, heartbeat: &'a Heartbeat) -> BoxFuture<'a, Result<()>> {
+    fn send_heartbeat<'a>(
+        &'a self,
+        heartbeat: &'a Heartbeat,
+    ) -> BoxFuture<'a, Result<(), SendError>> {
         Box::pin(async move { self.buffer_heartbeat(heartbeat).await })
     }
 
-    fn flush(&self) -> BoxFuture<'_, Result<()>> {
+    fn flush(&self) -> BoxFuture<'_, Result<(), SendError>> {
         Box::pin(async move { self.flush_buffer().await })
     }
 }
 
+/// A sender that keeps every heartbeat it gets, for tests.
+///
+/// Clones share the list, so a test can keep one and hand the other to the
+/// sender under test.
+#[cfg(test)]
+#[derive(Debug, Clone, Default)]
+pub(crate) struct RecordingSender(Arc<Mutex<Vec<Heartbeat>>>);
+
+#[cfg(test)]
+impl RecordingSender {
+    /// Heartbeats received so far.
+    pub(crate) fn sent(&self) -> Vec<Heartbeat> {
+        self.0
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// Projects of the heartbeats received so far.
+    pub(crate) fn projects(&self) -> Vec<Option<String>> {
+        self.sent().into_iter().map(|h| h.project).collect()
+    }
+}
+
+#[cfg(test)]
+impl HeartbeatSender for RecordingSender {
+    fn send_heartbeat<'a>(
+        &'a self,
+        heartbeat: &'a Heartbeat,
+    ) -> BoxFuture<'a, Result<(), SendError>> {
+        self.0
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(heartbeat.clone());
+        Box::pin(async { Ok(()) })
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -521,7 +1099,11 @@ mod tests {
             entity_type: "app".to_string(),
             category: category.to_string(),
             time,
+            project: None,
+            language: None,
+            branch: None,
             machine_name_id: Some("test-machine".to_string()),
+            kind: HeartbeatKind::FocusChange,
         }
     }
 
@@ -538,6 +1120,10 @@ mod tests {
         assert!(json.get("operating_system").is_none());
         assert!(json["time"].is_f64());
         assert!(json.get("plugin").is_none());
+        assert!(json.get("kind").is_none());
+        assert!(json.get("project").is_none());
+        assert!(json.get("language").is_none());
+        assert!(json.get("branch").is_none());
     }
 
     #[test]
@@ -579,6 +1165,71 @@ mod tests {
         assert_eq!(deserialized[1].entity, "code");
     }
 
+    #[test]
+    fn test_prioritize_sends_focus_changes_first() {
+        let periodic = |entity, time| HeartbeatPayload {
+            kind: HeartbeatKind::Periodic,
+            ..test_payload(entity, "coding", time)
+        };
+        let mut payloads = vec![
+            periodic("code", 100.0),
+            test_payload("firefox", "browsing", 200.0),
+            periodic("firefox", 300.0),
+            test_payload("code", "coding", 400.0),
+        ];
+
+        prioritize_payloads(&mut payloads);
+
+        let order: Vec<f64> = payloads.iter().map(|p| p.time).collect();
+        assert_eq!(order, vec![200.0, 400.0, 100.0, 300.0]);
+    }
+
+    #[test]
+    fn test_coalesce_drops_consecutive_duplicates() {
+        let payloads = vec![
+            test_payload("firefox", "browsing", 100.0),
+            test_payload("firefox", "browsing", 110.0),
+            test_payload("firefox", "browsing", 120.0),
+        ];
+
+        let coalesced = coalesce_payloads(payloads);
+        assert_eq!(coalesced.len(), 1);
+        assert!((coalesced[0].time - 100.0).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn test_coalesce_keeps_heartbeats_outside_window() {
+        let payloads = vec![
+            test_payload("firefox", "browsing", 100.0),
+            test_payload("firefox", "browsing", 100.0 + COALESCE_WINDOW_SECONDS),
+        ];
+
+        assert_eq!(coalesce_payloads(payloads).len(), 2);
+    }
+
+    #[test]
+    fn test_coalesce_keeps_entity_switches() {
+        let payloads = vec![
+            test_payload("firefox", "browsing", 100.0),
+            test_payload("code", "coding", 105.0),
+            test_payload("firefox", "browsing", 110.0),
+        ];
+
+        let coalesced = coalesce_payloads(payloads);
+        assert_eq!(coalesced.len(), 3);
+        assert_eq!(coalesced[2].entity, "firefox");
+    }
+
+    #[test]
+    fn test_coalesce_keeps_category_changes() {
+        let payloads = vec![
+            test_payload("firefox", "browsing", 100.0),
+            test_payload("firefox", "researching", 105.0),
+        ];
+
+        assert_eq!(coalesce_payloads(payloads).len(), 2);
+    }
+
     #[test]
     fn test_resolve_api_url_from_config() {
         let config = Config {
@@ -665,9 +1316,11 @@ mod tests {
             bulk_url: String::new(),
             api_key: String::new(),
             dry_run: false,
+            capture_path: None,
             buffer: Mutex::new(Vec::new()),
             queue_path: Some(queue_path.clone()),
-            error_log_count: AtomicU32::new(0),
+            cipher: None,
+            stats: Arc::default(),
         };
 
         let payloads = vec![
@@ -690,6 +1343,55 @@ mod tests {
         assert_eq!(batch[1].entity, "code");
     }
 
+    #[test]
+    fn test_persist_to_encrypted_queue() {
+        let dir = tempfile::tempdir().unwrap();
+        let queue_path = dir.path().join("queue.jsonl");
+        std::fs::write(&queue_path, "[]\n").unwrap();
+
+        let sender = ApiSender {
+            queue_path: Some(queue_path.clone()),
+            cipher: Some(Arc::new(
+                QueueCipher::new(&QueueCipher::generate_key()).unwrap(),
+            )),
+            ..ApiSender::offline()
+        };
+        sender.persist_to_queue(&[test_payload("secret-project", "coding", 1.0)]);
+
+        let content = std::fs::read_to_string(&queue_path).unwrap();
+        assert!(!content.contains("secret-project"));
+        let lines: Vec<&str> = content.lines().collect();
+        // Lines from before the queue was encrypted are still read
+        assert!(sender.open(lines[0]).unwrap().is_empty());
+        let batch = sender.open(lines[1]).unwrap();
+        assert_eq!(batch[0].entity, "secret-project");
+    }
+
+    #[tokio::test]
+    async fn test_drain_keeps_batches_sealed_with_another_key() {
+        let dir = tempfile::tempdir().unwrap();
+        let queue_path = dir.path().join("queue.jsonl");
+        let old_key = Arc::new(QueueCipher::new(&QueueCipher::generate_key()).unwrap());
+        let writer = ApiSender {
+            queue_path: Some(queue_path.clone()),
+            cipher: Some(old_key),
+            ..ApiSender::offline()
+        };
+        writer.persist_to_queue(&[test_payload("secret-project", "coding", 1.0)]);
+        let before = std::fs::read_to_string(&queue_path).unwrap();
+
+        let reader = ApiSender {
+            queue_path: Some(queue_path.clone()),
+            cipher: Some(Arc::new(
+                QueueCipher::new(&QueueCipher::generate_key()).unwrap(),
+            )),
+            ..ApiSender::offline()
+        };
+        reader.drain_queue().await;
+
+        assert_eq!(std::fs::read_to_string(&queue_path).unwrap(), before);
+    }
+
     #[test]
     fn test_persist_multiple_batches() {
         let dir = tempfile::tempdir().unwrap();
@@ -701,9 +1403,11 @@ mod tests {
             bulk_url: String::new(),
             api_key: String::new(),
             dry_run: false,
+            capture_path: None,
             buffer: Mutex::new(Vec::new()),
             queue_path: Some(queue_path.clone()),
-            error_log_count: AtomicU32::new(0),
+            cipher: None,
+            stats: Arc::default(),
         };
 
         // Persist two separate batches
@@ -736,9 +1440,11 @@ mod tests {
             bulk_url: String::new(),
             api_key: String::new(),
             dry_run: false,
+            capture_path: None,
             buffer: Mutex::new(Vec::new()),
             queue_path: Some(queue_path.clone()),
-            error_log_count: AtomicU32::new(0),
+            cipher: None,
+            stats: Arc::default(),
         };
 
         // This should be dropped because the file is already at max size
@@ -757,12 +1463,94 @@ mod tests {
             bulk_url: String::new(),
             api_key: String::new(),
             dry_run: false,
+            capture_path: None,
             buffer: Mutex::new(Vec::new()),
             queue_path: None,
-            error_log_count: AtomicU32::new(0),
+            cipher: None,
+            stats: Arc::default(),
         };
 
         // Should not panic
         sender.persist_to_queue(&[test_payload("test", "coding", 1.0)]);
     }
+
+    #[tokio::test]
+    async fn test_dry_run_capture_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let capture_path = dir.path().join("trial").join("capture.jsonl");
+
+        let sender = ApiSender {
+            dry_run: true,
+            capture_path: Some(capture_path.clone()),
+            ..ApiSender::offline()
+        };
+        sender
+            .buffer_payload(Arc::new(test_payload(
+                "firefox",
+                "browsing",
+                1_700_000_000.5,
+            )))
+            .await
+            .unwrap();
+        sender
+            .buffer_payload(Arc::new(test_payload("code", "coding", 1_700_000_060.0)))
+            .await
+            .unwrap();
+        assert!(sender.buffered().is_empty());
+
+        let captured = read_capture(&capture_path).unwrap();
+        assert_eq!(captured.len(), 2);
+        assert_eq!(captured[0].entity, "firefox");
+        assert!((captured[0].time - 1_700_000_000.5).abs() < f64::EPSILON);
+        assert_eq!(captured[1].machine_name_id.as_deref(), Some("test-machine"));
+
+        std::fs::write(&capture_path, "{\"entity\":\"code\"}\n").unwrap();
+        let err = read_capture(&capture_path).unwrap_err();
+        assert!(format!("{err:#}").contains("line 1"));
+    }
+
+    #[tokio::test]
+    async fn test_failed_flush_backs_off_to_queue() {
+        let dir = tempfile::tempdir().unwrap();
+        let queue_path = dir.path().join("queue-wakapi.jsonl");
+        // Nothing listens on the discard port
+        let sender = ApiSender {
+            heartbeat_url: "http://127.0.0.1:9/heartbeats".to_string(),
+            bulk_url: "http://127.0.0.1:9/heartbeats.bulk".to_string(),
+            queue_path: Some(queue_path.clone()),
+            cipher: None,
+            stats: Arc::new(DestinationStats::new("wakapi".to_string())),
+            ..ApiSender::offline()
+        };
+
+        sender
+            .buffer_payload(Arc::new(test_payload("code", "coding", 1.0)))
+            .await
+            .unwrap();
+        assert!(sender.flush_buffer().await.is_err());
+        let status = sender.stats.snapshot();
+        assert_eq!(status.failed_flushes, 1);
+        assert!(status.retry_in_seconds.is_some_and(|s| s > 55));
+
+        // While backing off, flushes go straight to the queue
+        sender
+            .buffer_payload(Arc::new(test_payload("firefox", "browsing", 2.0)))
+            .await
+            .unwrap();
+        let err = sender.flush_buffer().await.unwrap_err();
+        assert!(err.to_string().contains("wakapi is unreachable"));
+        let content = std::fs::read_to_string(&queue_path).unwrap();
+        assert_eq!(content.lines().count(), 2);
+        assert_eq!(sender.stats.snapshot().failed_flushes, 1);
+
+        // The delay doubles up to the maximum
+        assert_eq!(sender.stats.record_failure(), Duration::from_mins(2));
+        assert_eq!(sender.stats.record_failure(), Duration::from_mins(4));
+        for _ in 0..10 {
+            sender.stats.record_failure();
+        }
+        assert_eq!(sender.stats.record_failure(), RETRY_MAX_DELAY);
+        sender.stats.record_success();
+        assert_eq!(sender.stats.snapshot().retry_in_seconds, None);
+    }
 }
@@ -2,46 +2,190 @@
 //!
 //! Reads the API key from (in priority order):
 //! 1. `$WAKATIME_API_KEY` environment variable
-//! 2. `api_key` field in the `[settings]` section of `~/.wakatime.cfg`
+//! 2. `api_key` in the daemon config
+//! 3. The output of `api_key_cmd`, e.g. `pass show wakatime` (see [`load`])
+//! 4. The desktop keyring, with `api_key_keyring` (see [`load`])
+//! 5. `api_key` field in the `[settings]` section of `~/.wakatime.cfg`
 
 use std::path::Path;
+use std::process::Stdio;
+use std::sync::PoisonError;
+use std::sync::RwLock;
+use std::time::Duration;
 
 use anyhow::Context;
 use anyhow::Result;
+use tokio::process::Command;
 use tracing::debug;
 
+use crate::config::Config;
+
 /// Environment variable for API key override.
 const ENV_VAR: &str = "WAKATIME_API_KEY";
 
 /// Default config file name.
 const DEFAULT_CONFIG_FILE: &str = ".wakatime.cfg";
 
+/// Label of the API key in the keyring.
+#[cfg_attr(not(feature = "secret-service"), allow(dead_code))]
+const KEYRING_LABEL: &str = "WakaTime API key";
+
+/// How long `api_key_cmd` may take, e.g. waiting for a passphrase prompt.
+const COMMAND_TIMEOUT: Duration = Duration::from_mins(1);
+
+/// The API key read from the keyring by [`load`].
+static KEYRING_KEY: RwLock<Option<String>> = RwLock::new(None);
+
+/// The `api_key_cmd` run by [`load`] and the key it printed.
+static COMMAND_KEY: RwLock<Option<(String, String)>> = RwLock::new(None);
+
 /// Resolve the `WakaTime` API key.
 ///
-/// Checks `$WAKATIME_API_KEY` first, then falls back to parsing the wakatime
-/// config file. The `wakatime_config_path` argument overrides the default
-/// `~/.wakatime.cfg` location.
-pub fn resolve_api_key(wakatime_config_path: Option<&Path>) -> Result<String> {
-    // 1. Environment variable takes priority
-    if let Ok(key) = std::env::var(ENV_VAR) {
-        let key = key.trim().to_string();
-        if !key.is_empty() {
-            debug!("Using API key from ${ENV_VAR}");
-            return Ok(key);
-        }
+/// Checks the sources in the order listed in the module docs. The
+/// `wakatime_config_path` setting overrides the default `~/.wakatime.cfg`
+/// location.
+pub fn resolve_api_key(config: &Config) -> Result<String> {
+    let (key, source) = find_api_key(config)?;
+    debug!("Using API key from {source}");
+    Ok(key)
+}
+
+/// Where [`resolve_api_key`] finds the key: `$WAKATIME_API_KEY`, a config
+/// option, the keyring, or the path of the wakatime config file. `None` if
+/// there is no key.
+#[must_use]
+pub fn api_key_source(config: &Config) -> Option<String> {
+    find_api_key(config).ok().map(|(_, source)| source)
+}
+
+/// Run `api_key_cmd`, or read the API key from the keyring if
+/// `api_key_keyring` is set, unless an earlier source has the key or it is
+/// loaded already. A reload only runs the command again if it changed.
+pub async fn load(config: &Config) -> Result<()> {
+    if env_key().is_some() || non_empty(config.api_key.clone()).is_some() {
+        return Ok(());
+    }
+    if let Some(command) = &config.api_key_cmd {
+        return command_key(command).await.map(drop);
+    }
+    if !config.api_key_keyring || keyring_key().is_some() {
+        return Ok(());
     }
+    let key = fetch_keyring_key().await?;
+    *KEYRING_KEY.write().unwrap_or_else(PoisonError::into_inner) = Some(key);
+    Ok(())
+}
 
-    // 2. Parse wakatime config file
-    let config_path = if let Some(p) = wakatime_config_path {
+/// The API key from the keyring, once [`load`] has succeeded.
+fn keyring_key() -> Option<String> {
+    KEYRING_KEY
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .clone()
+}
+
+/// The key printed by `command`, once [`load`] has run it.
+fn cached_command_key(command: &str) -> Option<String> {
+    COMMAND_KEY
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .as_ref()
+        .filter(|(cached, _)| cached == command)
+        .map(|(_, key)| key.clone())
+}
+
+/// The key printed by `command`, running it unless it ran before.
+async fn command_key(command: &str) -> Result<String> {
+    if let Some(key) = cached_command_key(command) {
+        return Ok(key);
+    }
+    let key = run_api_key_cmd(command).await?;
+    *COMMAND_KEY.write().unwrap_or_else(PoisonError::into_inner) =
+        Some((command.to_string(), key.clone()));
+    Ok(key)
+}
+
+/// The key in `$WAKATIME_API_KEY`, if set.
+fn env_key() -> Option<String> {
+    non_empty(std::env::var(ENV_VAR).ok())
+}
+
+/// The API key and a description of where it came from.
+fn find_api_key(config: &Config) -> Result<(String, String)> {
+    if let Some(key) = env_key() {
+        return Ok((key, format!("${ENV_VAR}")));
+    }
+    if let Some(key) = non_empty(config.api_key.clone()) {
+        return Ok((key, "api_key".to_string()));
+    }
+    if let Some(command) = &config.api_key_cmd {
+        let key = cached_command_key(command).context("api_key_cmd has not run yet")?;
+        return Ok((key, "api_key_cmd".to_string()));
+    }
+    if config.api_key_keyring
+        && let Some(key) = keyring_key()
+    {
+        return Ok((key, "keyring".to_string()));
+    }
+
+    let config_path = if let Some(p) = config.wakatime_config_path.as_deref() {
         p.to_path_buf()
     } else {
         let home = dirs::home_dir().context("Could not determine home directory")?;
         home.join(DEFAULT_CONFIG_FILE)
     };
-
     let key = read_api_key_from_config(&config_path)?;
-    debug!("Using API key from {}", config_path.display());
-    Ok(key)
+    Ok((key, config_path.display().to_string()))
+}
+
+/// Trim a key, treating blank ones as unset.
+fn non_empty(key: Option<String>) -> Option<String> {
+    key.map(|key| key.trim().to_string())
+        .filter(|key| !key.is_empty())
+}
+
+/// Run `api_key_cmd` with `sh -c` and take the first line it prints. The
+/// command is killed after [`COMMAND_TIMEOUT`].
+async fn run_api_key_cmd(command: &str) -> Result<String> {
+    let output = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .stdin(Stdio::null())
+        .kill_on_drop(true)
+        .output();
+    let output = tokio::time::timeout(COMMAND_TIMEOUT, output)
+        .await
+        .map_err(|_| anyhow::anyhow!("api_key_cmd took longer than {COMMAND_TIMEOUT:?}"))?
+        .context("Failed to run api_key_cmd")?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "api_key_cmd failed ({}): {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    let stdout = String::from_utf8(output.stdout).context("api_key_cmd printed invalid UTF-8")?;
+    non_empty(stdout.lines().next().map(String::from)).context("api_key_cmd printed no API key")
+}
+
+#[cfg(feature = "secret-service")]
+async fn fetch_keyring_key() -> Result<String> {
+    let attributes = [("application", "wakatime-focusd"), ("key", "api-key")];
+    let key = crate::secret_service::lookup(KEYRING_LABEL, &attributes)
+        .await
+        .context("Failed to get the API key from the keyring")?
+        .context(
+            "No API key in the keyring; store it with `secret-tool store \
+             --label=\"WakaTime API key\" application wakatime-focusd key api-key`",
+        )?;
+    let key = String::from_utf8(key).context("The API key in the keyring isn't UTF-8")?;
+    non_empty(Some(key)).context("The API key in the keyring is empty")
+}
+
+#[cfg(not(feature = "secret-service"))]
+#[allow(clippy::unused_async)]
+async fn fetch_keyring_key() -> Result<String> {
+    anyhow::bail!("api_key_keyring needs the secret-service feature, which this build doesn't have")
 }
 
 /// Parse `api_key` from a wakatime INI config file.
@@ -228,7 +372,7 @@ api_key = also_wrong
     fn test_env_var_override() {
         // Set env var, resolve should use it
         unsafe { std::env::set_var(ENV_VAR, "env-key-123") };
-        let result = resolve_api_key(None);
+        let result = resolve_api_key(&Config::default());
         unsafe { std::env::remove_var(ENV_VAR) };
 
         assert_eq!(result.unwrap(), "env-key-123");
@@ -238,7 +382,7 @@ api_key = also_wrong
     fn test_env_var_empty_falls_through() {
         // Empty env var should not be used
         unsafe { std::env::set_var(ENV_VAR, "") };
-        let result = resolve_api_key(None);
+        let result = resolve_api_key(&Config::default());
         unsafe { std::env::remove_var(ENV_VAR) };
 
         // Should fail because it falls through to file reading
@@ -246,6 +390,41 @@ api_key = also_wrong
         assert!(result.is_err() || result.is_ok());
     }
 
+    #[tokio::test]
+    async fn test_run_api_key_cmd() {
+        assert_eq!(
+            run_api_key_cmd("echo ' cmd-key '").await.unwrap(),
+            "cmd-key"
+        );
+        assert_eq!(
+            run_api_key_cmd("printf 'cmd-key\\nsecond line'")
+                .await
+                .unwrap(),
+            "cmd-key"
+        );
+        assert!(run_api_key_cmd("true").await.is_err());
+        assert!(run_api_key_cmd("echo oops >&2; exit 3").await.is_err());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_api_key_cmd_times_out() {
+        let err = run_api_key_cmd("sleep 600").await.unwrap_err();
+        assert!(err.to_string().contains("took longer"), "{err}");
+    }
+
+    #[tokio::test]
+    async fn test_api_key_cmd_runs_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let runs = dir.path().join("runs");
+        let command = format!("echo run >> '{}'; echo cmd-key", runs.display());
+        assert_eq!(cached_command_key(&command), None);
+
+        assert_eq!(command_key(&command).await.unwrap(), "cmd-key");
+        assert_eq!(command_key(&command).await.unwrap(), "cmd-key");
+        assert_eq!(cached_command_key(&command).as_deref(), Some("cmd-key"));
+        assert_eq!(std::fs::read_to_string(&runs).unwrap(), "run\n");
+    }
+
     #[test]
     fn test_read_api_key_from_file() {
         let dir = tempfile::tempdir().unwrap();
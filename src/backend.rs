@@ -3,33 +3,75 @@
 //! This module provides a generic abstraction for detecting window focus changes
 //! across different window managers and desktop environments.
 
+#[cfg(any(
+    feature = "cosmic",
+    feature = "kde",
+    feature = "wlr-foreign-toplevel",
+    feature = "x11"
+))]
+mod channel;
+#[cfg(feature = "cosmic")]
 mod cosmic;
+#[cfg(feature = "hyprland")]
+pub mod fake_hyprland;
+#[cfg(feature = "gnome")]
 mod gnome;
+#[cfg(feature = "hyprland")]
 mod hyprland;
+#[cfg(feature = "kde")]
 mod kde;
+mod merge;
+mod mock;
+#[cfg(feature = "niri")]
 mod niri;
+mod stdin;
+#[cfg(feature = "sway")]
 mod sway;
+#[cfg(feature = "wlr-foreign-toplevel")]
 mod wlr_foreign_toplevel;
+#[cfg(feature = "x11")]
 mod x11;
 
 use std::env;
 use std::fmt;
+use std::path::Path;
+use std::path::PathBuf;
 
 use clap::ValueEnum;
+#[cfg(feature = "cosmic")]
 use cosmic::CosmicSource;
 use futures_util::future::BoxFuture;
+#[cfg(feature = "gnome")]
 use gnome::GnomeSource;
+#[cfg(feature = "hyprland")]
 use hyprland::HyprlandSource;
+#[cfg(feature = "hyprland")]
+#[doc(hidden)]
+pub use hyprland::replay_lines as replay_hyprland_lines;
+#[cfg(feature = "hyprland")]
+pub use hyprland::set_instance as set_hyprland_instance;
+#[cfg(feature = "kde")]
 use kde::KdeSource;
+pub(crate) use merge::MergedSource;
+use merge::TaggedSource;
+use mock::MockSource;
+pub use mock::set_speed as set_script_speed;
+#[cfg(feature = "niri")]
 use niri::NiriSource;
 use serde::Deserialize;
 use serde::Serialize;
+use stdin::StdinSource;
+#[cfg(feature = "sway")]
 use sway::SwaySource;
 use thiserror::Error;
 use tracing::info;
+#[cfg(feature = "wlr-foreign-toplevel")]
 use wlr_foreign_toplevel::WlrForeignToplevelSource;
+#[cfg(feature = "x11")]
 use x11::X11Source;
 
+use crate::title;
+
 /// Backend-agnostic focus event.
 #[derive(Debug, Clone)]
 pub struct FocusEvent {
@@ -41,6 +83,25 @@ pub struct FocusEvent {
 
     /// Window identifier (backend-specific).
     pub window_id: Option<String>,
+
+    /// URL of the active browser tab, if reported by the browser companion.
+    pub url: Option<String>,
+
+    /// Name of the workspace the window is on, for backends that report it.
+    pub workspace: Option<String>,
+
+    /// Name of the output (monitor) the window is on, for backends that
+    /// report it.
+    pub monitor: Option<String>,
+
+    /// Process that owns the window, for backends that report its PID.
+    pub process: Option<ProcessInfo>,
+
+    /// Whether the window is fullscreen, for backends that report it.
+    pub fullscreen: bool,
+
+    /// Backend that reported the event.
+    pub backend: Option<Backend>,
 }
 
 impl FocusEvent {
@@ -51,9 +112,64 @@ impl FocusEvent {
             app_class,
             title,
             window_id,
+            url: None,
+            workspace: None,
+            monitor: None,
+            process: None,
+            fullscreen: false,
+            backend: None,
         }
     }
 
+    /// Return this event with the active browser tab URL attached.
+    #[must_use]
+    pub fn with_url(mut self, url: Option<String>) -> Self {
+        self.url = url;
+        self
+    }
+
+    /// Return this event with the window's workspace attached.
+    #[must_use]
+    pub fn with_workspace(mut self, workspace: Option<String>) -> Self {
+        self.workspace = workspace;
+        self
+    }
+
+    /// Return this event with the window's output attached.
+    #[must_use]
+    pub fn with_monitor(mut self, monitor: Option<String>) -> Self {
+        self.monitor = monitor;
+        self
+    }
+
+    /// Return this event with the window's process attached.
+    #[must_use]
+    pub fn with_process(mut self, process: Option<ProcessInfo>) -> Self {
+        self.process = process;
+        self
+    }
+
+    /// Return this event with the window's fullscreen state attached.
+    #[must_use]
+    pub fn with_fullscreen(mut self, fullscreen: bool) -> Self {
+        self.fullscreen = fullscreen;
+        self
+    }
+
+    /// Return this event tagged with the backend that reported it.
+    #[must_use]
+    pub fn with_backend(mut self, backend: Backend) -> Self {
+        self.backend = Some(backend);
+        self
+    }
+
+    /// Return this event with its title sanitized (see [`title::sanitize`]).
+    #[must_use]
+    pub fn with_sanitized_title(mut self, max_chars: usize) -> Self {
+        self.title = self.title.map(|t| title::sanitize(&t, max_chars));
+        self
+    }
+
     /// Returns true if this represents an empty/no-focus state.
     #[must_use]
     pub fn is_empty(&self) -> bool {
@@ -61,6 +177,33 @@ impl FocusEvent {
     }
 }
 
+/// The process that owns a window, read from `/proc`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProcessInfo {
+    /// Process ID.
+    pub pid: u32,
+    /// Process name (`/proc/PID/comm`), e.g. `kitty`.
+    pub name: Option<String>,
+    /// Working directory (`/proc/PID/cwd`).
+    pub cwd: Option<PathBuf>,
+}
+
+impl ProcessInfo {
+    /// Look up process `pid`. Fields that can't be read, e.g. because the
+    /// process already exited, are `None`.
+    #[must_use]
+    pub fn from_pid(pid: u32) -> Self {
+        let proc_dir = PathBuf::from(format!("/proc/{pid}"));
+        Self {
+            pid,
+            name: std::fs::read_to_string(proc_dir.join("comm"))
+                .ok()
+                .map(|name| name.trim_end().to_string()),
+            cwd: std::fs::read_link(proc_dir.join("cwd")).ok(),
+        }
+    }
+}
+
 /// Trait for focus event sources.
 pub trait FocusSource: Send {
     /// Get the next focus event.
@@ -70,6 +213,84 @@ pub trait FocusSource: Send {
     fn next_event(&mut self) -> BoxFuture<'_, Result<FocusEvent, FocusError>>;
 }
 
+/// A source fed through a channel, for tests.
+///
+/// Returns [`FocusError::Exhausted`] once every sender is dropped.
+#[cfg(test)]
+pub(crate) struct ChannelSource(pub(crate) tokio::sync::mpsc::Receiver<FocusEvent>);
+
+#[cfg(test)]
+impl FocusSource for ChannelSource {
+    fn next_event(&mut self) -> BoxFuture<'_, Result<FocusEvent, FocusError>> {
+        Box::pin(async move { self.0.recv().await.ok_or(FocusError::Exhausted) })
+    }
+}
+
+/// What a backend reports about the focused window.
+///
+/// Config options that rely on something the backend doesn't report have no
+/// effect; `doctor` lists these so that doesn't come as a surprise.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct Capabilities {
+    /// Window titles.
+    pub titles: bool,
+    /// Window identifiers, telling windows of the same app apart.
+    pub window_ids: bool,
+    /// The workspace the window is on.
+    pub workspaces: bool,
+    /// The output (monitor) the window is on.
+    pub monitors: bool,
+    /// The process that owns the window.
+    pub processes: bool,
+    /// A new focus event when the focused window's title changes.
+    pub title_changes: bool,
+    /// Whether the window is fullscreen.
+    pub fullscreen: bool,
+}
+
+impl Capabilities {
+    /// One line per capability, naming the options that rely on it.
+    #[must_use]
+    pub fn describe(self) -> Vec<String> {
+        [
+            (
+                "Window titles",
+                self.titles,
+                "title_policy, category rules on titles, project_rules on titles, parse_terminal_titles, parse_editor_titles, meeting_mode",
+            ),
+            ("Window IDs", self.window_ids, "distinguish_windows"),
+            (
+                "Workspaces",
+                self.workspaces,
+                "workspace_projects, project_rules on workspaces",
+            ),
+            ("Monitors", self.monitors, ""),
+            (
+                "Window processes",
+                self.processes,
+                "parse_terminal_titles when the title has no directory",
+            ),
+            (
+                "Title change events",
+                self.title_changes,
+                "following terminal and editor titles without a focus change",
+            ),
+            ("Fullscreen state", self.fullscreen, "media_apps"),
+        ]
+        .into_iter()
+        .map(|(name, supported, used_by)| {
+            let supported = if supported { "yes" } else { "no" };
+            if used_by.is_empty() {
+                format!("{name}: {supported}")
+            } else {
+                format!("{name}: {supported} ({used_by})")
+            }
+        })
+        .collect()
+    }
+}
+
 /// Available backend types.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, ValueEnum)]
 #[serde(rename_all = "lowercase")]
@@ -84,17 +305,23 @@ pub enum Backend {
     /// GNOME Shell (Mutter).
     Gnome,
     /// KDE Plasma (`KWin`).
+    #[serde(alias = "kwin")]
+    #[value(alias = "kwin")]
     Kde,
     /// Niri compositor.
     Niri,
     /// COSMIC desktop.
     Cosmic,
     /// Generic Wayland via `wlr-foreign-toplevel-management` (River, Wayfire, labwc, etc.).
-    #[serde(rename = "wlr-foreign-toplevel")]
-    #[value(name = "wlr-foreign-toplevel")]
+    #[serde(rename = "wlr-foreign-toplevel", alias = "wlr")]
+    #[value(name = "wlr-foreign-toplevel", alias = "wlr")]
     WlrForeignToplevel,
     /// Generic X11 (fallback for any X11 window manager).
     X11,
+    /// Scripted events from a JSON Lines file (for tests and demos).
+    Mock,
+    /// `class,title` or JSON lines piped in on stdin.
+    Stdin,
 }
 
 impl fmt::Display for Backend {
@@ -109,114 +336,239 @@ impl fmt::Display for Backend {
             Self::Cosmic => write!(f, "cosmic"),
             Self::WlrForeignToplevel => write!(f, "wlr-foreign-toplevel"),
             Self::X11 => write!(f, "x11"),
+            Self::Mock => write!(f, "mock"),
+            Self::Stdin => write!(f, "stdin"),
         }
     }
 }
 
 impl Backend {
-    /// Resolve `Auto` to a concrete backend by checking the environment.
-    ///
-    /// Detection order prefers Wayland-native backends over the X11 fallback:
-    /// Hyprland → Sway → Niri → KDE → GNOME → X11
-    pub fn detect() -> Result<Self, FocusError> {
-        if env::var("HYPRLAND_INSTANCE_SIGNATURE").is_ok() || env::var("HYPRLAND_CMD").is_ok() {
-            info!("Detected Hyprland environment");
-            return Ok(Self::Hyprland);
-        }
-
-        if env::var("SWAYSOCK").is_ok() {
-            info!("Detected Sway environment");
-            return Ok(Self::Sway);
-        }
-
-        if env::var("NIRI_SOCKET").is_ok() {
-            info!("Detected Niri environment");
-            return Ok(Self::Niri);
-        }
+    /// Order in which auto-detection tries the backends: Wayland-native
+    /// backends first, then the generic Wayland protocol, then X11.
+    const DETECTION_ORDER: [Self; 8] = [
+        Self::Hyprland,
+        Self::Sway,
+        Self::Niri,
+        Self::Kde,
+        Self::Gnome,
+        Self::Cosmic,
+        Self::WlrForeignToplevel,
+        Self::X11,
+    ];
 
-        if let Ok(desktop) = env::var("XDG_CURRENT_DESKTOP") {
-            let desktop_upper = desktop.to_uppercase();
-            if desktop_upper.contains("KDE") {
-                info!("Detected KDE environment (XDG_CURRENT_DESKTOP={desktop})");
-                return Ok(Self::Kde);
-            }
-            if desktop_upper.contains("GNOME") {
-                info!("Detected GNOME environment (XDG_CURRENT_DESKTOP={desktop})");
-                return Ok(Self::Gnome);
-            }
-            if desktop_upper.contains("COSMIC") {
-                info!("Detected COSMIC environment (XDG_CURRENT_DESKTOP={desktop})");
-                return Ok(Self::Cosmic);
+    /// Whether the environment has what this backend needs. Always false
+    /// for `Auto`, `Mock` and `Stdin`, which don't depend on it.
+    #[must_use]
+    pub fn is_present(self) -> bool {
+        let desktop = || {
+            env::var("XDG_CURRENT_DESKTOP")
+                .unwrap_or_default()
+                .to_uppercase()
+        };
+        match self {
+            Self::Hyprland => {
+                env::var("HYPRLAND_INSTANCE_SIGNATURE").is_ok() || env::var("HYPRLAND_CMD").is_ok()
             }
+            Self::Sway => env::var("SWAYSOCK").is_ok(),
+            Self::Niri => env::var("NIRI_SOCKET").is_ok(),
+            Self::Kde => desktop().contains("KDE"),
+            Self::Gnome => desktop().contains("GNOME"),
+            Self::Cosmic => desktop().contains("COSMIC"),
+            Self::WlrForeignToplevel => env::var("WAYLAND_DISPLAY").is_ok(),
+            Self::X11 => env::var("DISPLAY").is_ok(),
+            Self::Auto | Self::Mock | Self::Stdin => false,
         }
+    }
 
-        if env::var("WAYLAND_DISPLAY").is_ok() {
-            info!("Detected Wayland session, trying wlr-foreign-toplevel protocol");
-            return Ok(Self::WlrForeignToplevel);
+    /// The backend to fail over to when `current` keeps failing: the next
+    /// one after it that auto-detection with `priority` would consider, or
+    /// `None` if no other backend's environment is present.
+    #[must_use]
+    pub fn failover(current: Self, priority: &[Self]) -> Option<Self> {
+        let mut candidates: Vec<Self> = Vec::new();
+        let preferred = priority
+            .iter()
+            .copied()
+            .filter(|backend| capabilities(*backend).is_some());
+        for backend in preferred.chain(Self::DETECTION_ORDER) {
+            if backend.is_present() && !candidates.contains(&backend) {
+                candidates.push(backend);
+            }
         }
+        let start = candidates
+            .iter()
+            .position(|backend| *backend == current)
+            .map_or(0, |i| i + 1);
+        let count = candidates.len();
+        candidates
+            .into_iter()
+            .cycle()
+            .skip(start)
+            .take(count)
+            .find(|backend| *backend != current)
+    }
 
-        if env::var("DISPLAY").is_ok() {
-            info!("Detected X11 environment (fallback)");
-            return Ok(Self::X11);
-        }
+    /// Resolve `Auto` to a concrete backend by checking the environment.
+    ///
+    /// Detection order prefers Wayland-native backends over the X11 fallback:
+    /// Hyprland → Sway → Niri → KDE → GNOME → COSMIC → wlr-foreign-toplevel
+    /// → X11
+    pub fn detect() -> Result<Self, FocusError> {
+        Self::detect_with_priority(&[])
+    }
 
-        Err(FocusError::NoBackendDetected)
+    /// Like [`Backend::detect`], but try the built-in backends in `priority`
+    /// first, in order.
+    pub fn detect_with_priority(priority: &[Self]) -> Result<Self, FocusError> {
+        let preferred = priority
+            .iter()
+            .copied()
+            .filter(|backend| capabilities(*backend).is_some());
+        let detected = preferred
+            .chain(Self::DETECTION_ORDER)
+            .find(|backend| backend.is_present())
+            .ok_or(FocusError::NoBackendDetected)?;
+        info!("Detected {detected} environment");
+        Ok(detected)
     }
 
-    /// Resolve this backend, running auto-detection if needed.
-    pub fn resolve(self) -> Result<Self, FocusError> {
+    /// Resolve this backend, running auto-detection with `priority` if
+    /// needed.
+    pub fn resolve(self, priority: &[Self]) -> Result<Self, FocusError> {
         match self {
-            Self::Auto => Self::detect(),
+            Self::Auto => Self::detect_with_priority(priority),
             concrete => Ok(concrete),
         }
     }
 }
 
-/// Connect to the appropriate backend and return a boxed `FocusSource`.
+/// Connect to the appropriate backend and return a boxed `FocusSource`
+/// whose events are tagged with the backend.
 pub async fn connect(backend: Backend) -> Result<Box<dyn FocusSource>, FocusError> {
-    let resolved = backend.resolve()?;
+    let resolved = backend.resolve(&[])?;
+    let source = connect_resolved(resolved).await?;
+    Ok(Box::new(TaggedSource::new(source, resolved)))
+}
 
+// Nothing is awaited when every backend feature is disabled
+#[allow(clippy::unused_async)]
+async fn connect_resolved(resolved: Backend) -> Result<Box<dyn FocusSource>, FocusError> {
     match resolved {
+        #[cfg(feature = "hyprland")]
         Backend::Hyprland => {
             let source = HyprlandSource::connect().await?;
             Ok(Box::new(source))
         }
+        #[cfg(feature = "sway")]
         Backend::Sway => {
             let source = SwaySource::connect().await?;
             Ok(Box::new(source))
         }
+        #[cfg(feature = "x11")]
         Backend::X11 => {
             let source = X11Source::connect().await?;
             Ok(Box::new(source))
         }
+        #[cfg(feature = "gnome")]
         Backend::Gnome => {
             let source = GnomeSource::connect().await?;
             Ok(Box::new(source))
         }
+        #[cfg(feature = "kde")]
         Backend::Kde => {
             let source = KdeSource::connect().await?;
             Ok(Box::new(source))
         }
+        #[cfg(feature = "niri")]
         Backend::Niri => {
             let source = NiriSource::connect().await?;
             Ok(Box::new(source))
         }
+        #[cfg(feature = "cosmic")]
         Backend::Cosmic => {
             let source = CosmicSource::connect().await?;
             Ok(Box::new(source))
         }
+        #[cfg(feature = "wlr-foreign-toplevel")]
         Backend::WlrForeignToplevel => {
             let source = WlrForeignToplevelSource::connect().await?;
             Ok(Box::new(source))
         }
+        Backend::Mock => Err(FocusError::ConnectionFailed(
+            "The mock backend requires a script (--script <file>)".to_string(),
+        )),
+        Backend::Stdin => Ok(Box::new(StdinSource::connect())),
         Backend::Auto => unreachable!("Auto should have been resolved"),
+        #[allow(unreachable_patterns)]
+        disabled => Err(FocusError::BackendDisabled(disabled)),
+    }
+}
+
+/// Connect like [`connect`], replaying `script` if the backend is `mock`.
+pub async fn connect_with_script(
+    backend: Backend,
+    script: Option<&Path>,
+) -> Result<Box<dyn FocusSource>, FocusError> {
+    match (backend, script) {
+        (Backend::Mock, Some(script)) => Ok(Box::new(TaggedSource::new(
+            Box::new(MockSource::connect(script).await?),
+            Backend::Mock,
+        ))),
+        _ => connect(backend).await,
+    }
+}
+
+/// Connect like [`connect_with_script`] to `backend` and each of `others`,
+/// merging their events into one stream.
+pub async fn connect_merged(
+    backend: Backend,
+    others: &[Backend],
+    script: Option<&Path>,
+) -> Result<Box<dyn FocusSource>, FocusError> {
+    let first = connect_with_script(backend, script).await?;
+    if others.is_empty() {
+        return Ok(first);
     }
+    let mut sources = vec![first];
+    for other in others {
+        sources.push(connect_with_script(*other, script).await?);
+    }
+    Ok(Box::new(MergedSource::new(sources)))
 }
 
-/// Get diagnostic information for the given backend.
+/// What the given backend reports, or `None` for `auto` and disabled
+/// backends.
 #[must_use]
-pub fn diagnostics(backend: Backend) -> Vec<String> {
+pub const fn capabilities(backend: Backend) -> Option<Capabilities> {
     match backend {
+        #[cfg(feature = "hyprland")]
+        Backend::Hyprland => Some(HyprlandSource::CAPABILITIES),
+        #[cfg(feature = "sway")]
+        Backend::Sway => Some(SwaySource::CAPABILITIES),
+        #[cfg(feature = "gnome")]
+        Backend::Gnome => Some(GnomeSource::CAPABILITIES),
+        #[cfg(feature = "kde")]
+        Backend::Kde => Some(KdeSource::CAPABILITIES),
+        #[cfg(feature = "niri")]
+        Backend::Niri => Some(NiriSource::CAPABILITIES),
+        #[cfg(feature = "cosmic")]
+        Backend::Cosmic => Some(CosmicSource::CAPABILITIES),
+        #[cfg(feature = "wlr-foreign-toplevel")]
+        Backend::WlrForeignToplevel => Some(WlrForeignToplevelSource::CAPABILITIES),
+        #[cfg(feature = "x11")]
+        Backend::X11 => Some(X11Source::CAPABILITIES),
+        Backend::Mock => Some(MockSource::CAPABILITIES),
+        Backend::Stdin => Some(StdinSource::CAPABILITIES),
+        #[allow(unreachable_patterns)]
+        _ => None,
+    }
+}
+
+/// Get diagnostic information for the given backend, followed by its
+/// [`Capabilities`].
+#[must_use]
+pub fn diagnostics(backend: Backend) -> Vec<String> {
+    let mut diags = match backend {
         Backend::Auto => {
             let mut diags = vec!["Backend: auto-detect".to_string()];
             match Backend::detect() {
@@ -228,15 +580,31 @@ pub fn diagnostics(backend: Backend) -> Vec<String> {
             }
             diags
         }
+        #[cfg(feature = "hyprland")]
         Backend::Hyprland => HyprlandSource::get_diagnostics(),
+        #[cfg(feature = "sway")]
         Backend::Sway => SwaySource::get_diagnostics(),
+        #[cfg(feature = "gnome")]
         Backend::Gnome => GnomeSource::get_diagnostics(),
+        #[cfg(feature = "kde")]
         Backend::Kde => KdeSource::get_diagnostics(),
+        #[cfg(feature = "niri")]
         Backend::Niri => NiriSource::get_diagnostics(),
+        #[cfg(feature = "cosmic")]
         Backend::Cosmic => CosmicSource::get_diagnostics(),
+        #[cfg(feature = "wlr-foreign-toplevel")]
         Backend::WlrForeignToplevel => WlrForeignToplevelSource::get_diagnostics(),
+        #[cfg(feature = "x11")]
         Backend::X11 => X11Source::get_diagnostics(),
+        Backend::Mock => MockSource::get_diagnostics(),
+        Backend::Stdin => StdinSource::get_diagnostics(),
+        #[allow(unreachable_patterns)]
+        disabled => vec![FocusError::BackendDisabled(disabled).to_string()],
+    };
+    if let Some(capabilities) = capabilities(backend) {
+        diags.extend(capabilities.describe());
     }
+    diags
 }
 
 /// Errors that can occur in focus detection.
@@ -253,4 +621,16 @@ pub enum FocusError {
 
     #[error("Could not detect a supported desktop environment")]
     NoBackendDetected,
+
+    #[error("Focus source has no more events")]
+    Exhausted,
+
+    #[error("No focus events for too long, reconnecting")]
+    Stalled,
+
+    #[error("Focus backend panicked, reconnecting")]
+    Panicked,
+
+    #[error("The {0} backend is not enabled in this build")]
+    BackendDisabled(Backend),
 }
@@ -0,0 +1,230 @@
+//! Bounded focus event channel with a drop-oldest overflow policy.
+//!
+//! Backends that run their own event loop (in a blocking thread or a D-Bus
+//! callback) hand events to the daemon through this channel. If the daemon
+//! stalls, the backend must never block — a stuck compositor connection is
+//! worse than a lost event. When the channel is full the *oldest* event is
+//! discarded: only the most recent focus state matters for heartbeats.
+//! Discarded events are counted in [`crate::metrics`].
+
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::PoisonError;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+
+use tokio::sync::Notify;
+use tracing::warn;
+
+use super::FocusEvent;
+use crate::metrics;
+
+/// Number of events buffered before the oldest is dropped.
+pub const EVENT_CHANNEL_CAPACITY: usize = 32;
+
+/// Create a new focus event channel with [`EVENT_CHANNEL_CAPACITY`].
+pub fn event_channel() -> (EventSender, EventReceiver) {
+    with_capacity(EVENT_CHANNEL_CAPACITY)
+}
+
+/// Create a new focus event channel holding at most `capacity` events.
+pub fn with_capacity(capacity: usize) -> (EventSender, EventReceiver) {
+    let shared = Arc::new(Shared {
+        queue: Mutex::new(VecDeque::with_capacity(capacity)),
+        capacity: capacity.max(1),
+        notify: Notify::new(),
+        senders: AtomicUsize::new(1),
+        receiver_alive: AtomicBool::new(true),
+        overflowing: AtomicBool::new(false),
+    });
+    (
+        EventSender {
+            shared: Arc::clone(&shared),
+        },
+        EventReceiver { shared },
+    )
+}
+
+/// Error returned when the receiving half has been dropped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReceiverDropped;
+
+#[derive(Debug)]
+struct Shared {
+    queue: Mutex<VecDeque<FocusEvent>>,
+    capacity: usize,
+    notify: Notify,
+    senders: AtomicUsize,
+    receiver_alive: AtomicBool,
+    /// Set while the channel is dropping events, so the warning is logged
+    /// once per overflow episode rather than once per event.
+    overflowing: AtomicBool,
+}
+
+/// Sending half of a focus event channel. Never blocks.
+#[derive(Debug)]
+pub struct EventSender {
+    shared: Arc<Shared>,
+}
+
+impl EventSender {
+    /// Queue an event, dropping the oldest buffered event if full.
+    ///
+    /// Safe to call from both async and blocking contexts.
+    pub fn send(&self, event: FocusEvent) -> Result<(), ReceiverDropped> {
+        if !self.shared.receiver_alive.load(Ordering::Acquire) {
+            return Err(ReceiverDropped);
+        }
+
+        {
+            let mut queue = self
+                .shared
+                .queue
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner);
+            if queue.len() >= self.shared.capacity {
+                queue.pop_front();
+                metrics::incr(&metrics::global().focus_events_dropped);
+                if !self.shared.overflowing.swap(true, Ordering::Relaxed) {
+                    warn!(
+                        "Focus event channel full ({} events), dropping oldest events",
+                        self.shared.capacity
+                    );
+                }
+            }
+            queue.push_back(event);
+        }
+
+        self.shared.notify.notify_one();
+        Ok(())
+    }
+}
+
+impl Clone for EventSender {
+    fn clone(&self) -> Self {
+        self.shared.senders.fetch_add(1, Ordering::AcqRel);
+        Self {
+            shared: Arc::clone(&self.shared),
+        }
+    }
+}
+
+impl Drop for EventSender {
+    fn drop(&mut self) {
+        if self.shared.senders.fetch_sub(1, Ordering::AcqRel) == 1 {
+            // Last sender gone: wake the receiver so it can observe closure
+            self.shared.notify.notify_one();
+        }
+    }
+}
+
+/// Receiving half of a focus event channel.
+#[derive(Debug)]
+pub struct EventReceiver {
+    shared: Arc<Shared>,
+}
+
+impl EventReceiver {
+    /// Receive the next event, or `None` once all senders are dropped and
+    /// the buffer is drained.
+    pub async fn recv(&mut self) -> Option<FocusEvent> {
+        loop {
+            {
+                let mut queue = self
+                    .shared
+                    .queue
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner);
+                if let Some(event) = queue.pop_front() {
+                    if queue.is_empty() {
+                        self.shared.overflowing.store(false, Ordering::Relaxed);
+                    }
+                    return Some(event);
+                }
+            }
+
+            if self.shared.senders.load(Ordering::Acquire) == 0 {
+                return None;
+            }
+
+            self.shared.notify.notified().await;
+        }
+    }
+}
+
+impl Drop for EventReceiver {
+    fn drop(&mut self) {
+        self.shared.receiver_alive.store(false, Ordering::Release);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(class: &str) -> FocusEvent {
+        FocusEvent::new(class.to_string(), None, None)
+    }
+
+    #[tokio::test]
+    async fn test_events_delivered_in_order() {
+        let (tx, mut rx) = with_capacity(4);
+        tx.send(event("a")).unwrap();
+        tx.send(event("b")).unwrap();
+
+        assert_eq!(rx.recv().await.unwrap().app_class, "a");
+        assert_eq!(rx.recv().await.unwrap().app_class, "b");
+    }
+
+    #[tokio::test]
+    async fn test_full_channel_drops_oldest() {
+        let (tx, mut rx) = with_capacity(2);
+        let dropped_before = metrics::global()
+            .focus_events_dropped
+            .load(Ordering::Relaxed);
+
+        tx.send(event("a")).unwrap();
+        tx.send(event("b")).unwrap();
+        tx.send(event("c")).unwrap();
+
+        assert_eq!(rx.recv().await.unwrap().app_class, "b");
+        assert_eq!(rx.recv().await.unwrap().app_class, "c");
+        assert!(
+            metrics::global()
+                .focus_events_dropped
+                .load(Ordering::Relaxed)
+                > dropped_before
+        );
+    }
+
+    #[tokio::test]
+    async fn test_recv_returns_none_after_senders_dropped() {
+        let (tx, mut rx) = with_capacity(2);
+        let tx2 = tx.clone();
+        tx.send(event("a")).unwrap();
+        drop(tx);
+        drop(tx2);
+
+        assert_eq!(rx.recv().await.unwrap().app_class, "a");
+        assert!(rx.recv().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_recv_wakes_on_send_from_thread() {
+        let (tx, mut rx) = with_capacity(2);
+        std::thread::spawn(move || {
+            tx.send(event("threaded")).unwrap();
+        });
+
+        assert_eq!(rx.recv().await.unwrap().app_class, "threaded");
+    }
+
+    #[test]
+    fn test_send_fails_after_receiver_dropped() {
+        let (tx, rx) = with_capacity(2);
+        drop(rx);
+        assert_eq!(tx.send(event("a")), Err(ReceiverDropped));
+    }
+}
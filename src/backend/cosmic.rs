@@ -6,7 +6,7 @@
 //! `Activated` flag for focus detection.
 //!
 //! The Wayland event loop is synchronous, so it runs in a dedicated thread
-//! via `spawn_blocking` and sends focus events through an `mpsc` channel.
+//! via `spawn_blocking` and sends focus events through a drop-oldest channel.
 
 use std::collections::HashMap;
 use std::env;
@@ -15,7 +15,6 @@ use cosmic_protocols::toplevel_info::v1::client::zcosmic_toplevel_handle_v1;
 use cosmic_protocols::toplevel_info::v1::client::zcosmic_toplevel_handle_v1::ZcosmicToplevelHandleV1;
 use cosmic_protocols::toplevel_info::v1::client::zcosmic_toplevel_info_v1;
 use cosmic_protocols::toplevel_info::v1::client::zcosmic_toplevel_info_v1::ZcosmicToplevelInfoV1;
-use tokio::sync::mpsc;
 use tracing::debug;
 use tracing::info;
 use tracing::trace;
@@ -32,9 +31,14 @@ use wayland_protocols::ext::foreign_toplevel_list::v1::client::ext_foreign_tople
 use wayland_protocols::ext::foreign_toplevel_list::v1::client::ext_foreign_toplevel_list_v1;
 use wayland_protocols::ext::foreign_toplevel_list::v1::client::ext_foreign_toplevel_list_v1::ExtForeignToplevelListV1;
 
+use super::Capabilities;
 use super::FocusError;
 use super::FocusEvent;
 use super::FocusSource;
+use super::channel;
+use super::channel::EventReceiver;
+use super::channel::EventSender;
+use crate::redact::sensitive;
 
 /// The `Activated` state value in the COSMIC toplevel protocol.
 const STATE_ACTIVATED: u32 = 2;
@@ -44,10 +48,21 @@ const STATE_ACTIVATED: u32 = 2;
 /// Runs a blocking Wayland event loop in a dedicated thread and receives
 /// focus events via a channel.
 pub struct CosmicSource {
-    rx: mpsc::Receiver<FocusEvent>,
+    rx: EventReceiver,
 }
 
 impl CosmicSource {
+    /// What this backend reports.
+    pub const CAPABILITIES: Capabilities = Capabilities {
+        titles: true,
+        window_ids: true,
+        workspaces: false,
+        monitors: false,
+        processes: false,
+        title_changes: false,
+        fullscreen: false,
+    };
+
     /// Connect to the Wayland display and bind the COSMIC toplevel protocols.
     #[allow(clippy::unused_async)]
     pub async fn connect() -> Result<Self, FocusError> {
@@ -57,7 +72,7 @@ impl CosmicSource {
 
         verify_protocols()?;
 
-        let (tx, rx) = mpsc::channel(32);
+        let (tx, rx) = channel::event_channel();
 
         tokio::task::spawn_blocking(move || {
             if let Err(e) = run_event_loop(&tx) {
@@ -156,7 +171,8 @@ impl WindowTracker {
 
             debug!(
                 "Focus changed: class={}, title={:?}, window_id={id}",
-                app_class, title
+                app_class,
+                sensitive(&title)
             );
 
             Some(FocusEvent::new(
@@ -187,11 +203,11 @@ struct ToplevelState {
     /// The bound `zcosmic_toplevel_info_v1` proxy, used to create cosmic
     /// handles via `get_cosmic_toplevel`.
     cosmic_info: ZcosmicToplevelInfoV1,
-    tx: mpsc::Sender<FocusEvent>,
+    tx: EventSender,
 }
 
 impl ToplevelState {
-    fn new(cosmic_info: ZcosmicToplevelInfoV1, tx: mpsc::Sender<FocusEvent>) -> Self {
+    fn new(cosmic_info: ZcosmicToplevelInfoV1, tx: EventSender) -> Self {
         Self {
             tracker: WindowTracker::new(),
             cosmic_info,
@@ -261,7 +277,7 @@ impl Dispatch<wl_registry::WlRegistry, GlobalListContents> for ToplevelState {
 }
 
 /// Run the blocking Wayland event loop.
-fn run_event_loop(tx: &mpsc::Sender<FocusEvent>) -> Result<(), FocusError> {
+fn run_event_loop(tx: &EventSender) -> Result<(), FocusError> {
     let conn = Connection::connect_to_env()
         .map_err(|e| FocusError::ConnectionFailed(format!("Wayland connect: {e}")))?;
 
@@ -367,7 +383,7 @@ impl Dispatch<ZcosmicToplevelHandleV1, ()> for ToplevelState {
 
         match event {
             zcosmic_toplevel_handle_v1::Event::Title { title } => {
-                trace!("Title changed for {id}: {title}");
+                trace!("Title changed for {id}: {}", sensitive(&title));
                 if let Some(w) = state.tracker.windows.get_mut(&id) {
                     w.title = title;
                 }
@@ -388,7 +404,7 @@ impl Dispatch<ZcosmicToplevelHandleV1, ()> for ToplevelState {
             zcosmic_toplevel_handle_v1::Event::Done => {
                 trace!("Done for {id}");
                 if let Some(event) = state.tracker.commit(id)
-                    && state.tx.blocking_send(event).is_err()
+                    && state.tx.send(event).is_err()
                 {
                     warn!("Receiver dropped, stopping event loop");
                 }
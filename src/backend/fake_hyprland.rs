@@ -0,0 +1,166 @@
+//! Fake Hyprland socket for integration tests and bug reports.
+//!
+//! [`FakeHyprland`] creates a socket2-compatible socket under
+//! `$XDG_RUNTIME_DIR/hypr/<signature>/` and replays a script of event lines
+//! to whoever connects. With `HYPRLAND_INSTANCE_SIGNATURE` pointing at it,
+//! the Hyprland backend runs against the script as if it were talking to the
+//! compositor, reconnects included. `wakatime-focusd fake-hyprland SCRIPT`
+//! serves one from the command line.
+//!
+//! A script is socket2 lines plus two directives:
+//!
+//! ```text
+//! # Comments and blank lines are skipped
+//! workspace>>3
+//! activewindow>>kitty,~/src/myproject
+//! activewindowv2>>0x1
+//! sleep 5000
+//! disconnect
+//! activewindow>>firefox,GitHub
+//! ```
+//!
+//! `sleep MS` waits before the next line. `disconnect` closes the
+//! connection, and the next client picks up the script after it. Once the
+//! script is done, the connection stays open until the client hangs up.
+//! Hyprland's request socket is not faked, so the active workspace is only
+//! known from `workspace>>` lines.
+
+use std::io;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use tokio::io::AsyncReadExt;
+use tokio::io::AsyncWriteExt;
+use tokio::net::UnixListener;
+use tokio::net::UnixStream;
+use tracing::debug;
+use tracing::info;
+
+use crate::redact::sensitive;
+
+/// One step of a fake Hyprland script.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScriptStep {
+    /// Send this event line.
+    Line(String),
+    /// Wait before the next step.
+    Sleep(Duration),
+    /// Close the connection and wait for the client to reconnect.
+    Disconnect,
+}
+
+/// Parse a fake Hyprland script.
+pub fn parse_script(content: &str) -> Result<Vec<ScriptStep>, String> {
+    content
+        .lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .map(|(number, line)| {
+            if line == "disconnect" {
+                return Ok(ScriptStep::Disconnect);
+            }
+            if let Some(ms) = line.strip_prefix("sleep ") {
+                return ms
+                    .trim()
+                    .parse()
+                    .map(|ms| ScriptStep::Sleep(Duration::from_millis(ms)))
+                    .map_err(|_| format!("line {number}: invalid sleep '{ms}'"));
+            }
+            if !line.contains(">>") {
+                return Err(format!("line {number}: expected EVENT>>DATA, got '{line}'"));
+            }
+            Ok(ScriptStep::Line(line.to_string()))
+        })
+        .collect()
+}
+
+/// A fake Hyprland socket2 server.
+///
+/// The socket file is removed when the server is dropped.
+pub struct FakeHyprland {
+    listener: UnixListener,
+    path: PathBuf,
+}
+
+impl FakeHyprland {
+    /// Create the socket for instance `signature` under `runtime_dir`,
+    /// replacing a stale one.
+    pub fn bind(runtime_dir: &Path, signature: &str) -> io::Result<Self> {
+        let dir = runtime_dir.join("hypr").join(signature);
+        std::fs::create_dir_all(&dir)?;
+        let path = dir.join(".socket2.sock");
+        if path.exists() {
+            std::fs::remove_file(&path)?;
+        }
+        let listener = UnixListener::bind(&path)?;
+        Ok(Self { listener, path })
+    }
+
+    /// Path of the socket.
+    #[must_use]
+    pub fn socket_path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Replay `steps` to connecting clients, returning once the script is
+    /// done and the last client has hung up.
+    pub async fn replay(&self, steps: &[ScriptStep]) -> io::Result<()> {
+        let mut stream = self.accept().await?;
+        for step in steps {
+            match step {
+                ScriptStep::Line(line) => {
+                    debug!("Fake Hyprland: {}", sensitive(line));
+                    stream.write_all(line.as_bytes()).await?;
+                    stream.write_all(b"\n").await?;
+                }
+                ScriptStep::Sleep(duration) => tokio::time::sleep(*duration).await,
+                ScriptStep::Disconnect => {
+                    info!("Fake Hyprland: disconnecting client");
+                    drop(stream);
+                    stream = self.accept().await?;
+                }
+            }
+        }
+
+        info!("Fake Hyprland: script done, waiting for the client to hang up");
+        let mut buf = [0u8; 256];
+        while stream.read(&mut buf).await? > 0 {}
+        Ok(())
+    }
+
+    async fn accept(&self) -> io::Result<UnixStream> {
+        let (stream, _) = self.listener.accept().await?;
+        info!("Fake Hyprland: client connected");
+        Ok(stream)
+    }
+}
+
+impl Drop for FakeHyprland {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_script() {
+        let script = "# header\n\nactivewindow>>kitty,~\nsleep 250\ndisconnect\n  workspace>>2  \n";
+        assert_eq!(
+            parse_script(script).unwrap(),
+            vec![
+                ScriptStep::Line("activewindow>>kitty,~".to_string()),
+                ScriptStep::Sleep(Duration::from_millis(250)),
+                ScriptStep::Disconnect,
+                ScriptStep::Line("workspace>>2".to_string()),
+            ]
+        );
+
+        assert!(parse_script("sleep soon").unwrap_err().contains("line 1"));
+        assert!(parse_script("# ok\nkitty").unwrap_err().contains("line 2"));
+    }
+}
@@ -15,9 +15,11 @@ use zbus::Connection;
 use zbus::proxy;
 use zbus::zvariant::OwnedValue;
 
+use super::Capabilities;
 use super::FocusError;
 use super::FocusEvent;
 use super::FocusSource;
+use crate::redact::sensitive;
 
 /// D-Bus proxy for `org.gnome.Shell.Introspect`.
 #[proxy(
@@ -42,6 +44,17 @@ pub struct GnomeSource {
 }
 
 impl GnomeSource {
+    /// What this backend reports.
+    pub const CAPABILITIES: Capabilities = Capabilities {
+        titles: true,
+        window_ids: true,
+        workspaces: false,
+        monitors: false,
+        processes: false,
+        title_changes: false,
+        fullscreen: false,
+    };
+
     /// Create a new GNOME Shell focus source.
     pub async fn connect() -> Result<Self, FocusError> {
         let connection = Connection::session()
@@ -105,7 +118,8 @@ impl FocusSource for GnomeSource {
                 self.last_focused_class = Some(event.app_class.clone());
                 debug!(
                     "Initial focus: class={}, title={:?}",
-                    event.app_class, event.title
+                    event.app_class,
+                    sensitive(&event.title)
                 );
                 return Ok(event);
             }
@@ -135,7 +149,9 @@ impl FocusSource for GnomeSource {
                         self.last_focused_class = Some(event.app_class.clone());
                         debug!(
                             "Focus changed: class={}, title={:?}, window_id={:?}",
-                            event.app_class, event.title, event.window_id
+                            event.app_class,
+                            sensitive(&event.title),
+                            event.window_id
                         );
                         return Ok(event);
                     }
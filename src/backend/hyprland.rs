@@ -1,13 +1,25 @@
 //! Hyprland IPC socket2 focus detection backend.
 //!
 //! Connects to Hyprland's socket2 event stream and parses activewindow/activewindowv2 events.
+//! Workspace and monitor changes are tracked too, so focus events carry the
+//! workspace and output they happened on, and `windowtitlev2` events for the
+//! focused window become focus events with the new title. Each focus event also
+//! carries the window's process, looked up by address in the request socket's
+//! `j/clients` reply.
 
 use std::env;
+use std::path::Path;
 use std::path::PathBuf;
+use std::sync::Mutex;
+use std::sync::PoisonError;
+use std::sync::atomic::AtomicU32;
+use std::sync::atomic::Ordering;
 use std::time::Duration;
 
 use futures_util::future::BoxFuture;
 use tokio::io::AsyncBufReadExt;
+use tokio::io::AsyncReadExt;
+use tokio::io::AsyncWriteExt;
 use tokio::io::BufReader;
 use tokio::net::UnixStream;
 use tracing::debug;
@@ -15,32 +27,91 @@ use tracing::info;
 use tracing::trace;
 use tracing::warn;
 
+use super::Capabilities;
 use super::FocusError;
 use super::FocusEvent;
 use super::FocusSource;
+use super::ProcessInfo;
+use crate::redact::sensitive;
+
+/// How long a focus change waits for its `activewindowv2` (window address).
+/// Hyprland sends it right after `activewindow`, so this only matters if it
+/// never comes.
+const ADDRESS_WAIT: Duration = Duration::from_millis(50);
+
+/// How long to wait for Hyprland to answer a request socket query.
+const QUERY_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Failed connections in a row after which other instances are looked for.
+/// A restarted Hyprland gets a new instance signature, so the one in our
+/// environment keeps pointing at the old, dead socket.
+const RESCAN_AFTER: u32 = 3;
+
+/// Connections to socket2 that failed in a row.
+static FAILED_CONNECTS: AtomicU32 = AtomicU32::new(0);
+
+/// Socket2 of the instance found by a rescan, used instead of
+/// `HYPRLAND_INSTANCE_SIGNATURE` from then on.
+static RESCANNED_SOCKET: Mutex<Option<PathBuf>> = Mutex::new(None);
+
+/// `hyprland_instance` value selecting the most recently started instance.
+const MOST_RECENT: &str = "most-recent";
+
+/// The `hyprland_instance` setting.
+static INSTANCE: Mutex<Option<String>> = Mutex::new(None);
+
+/// Select the Hyprland instance to connect to: a signature, `"most-recent"`,
+/// or `None` for `HYPRLAND_INSTANCE_SIGNATURE`.
+pub fn set_instance(instance: Option<String>) {
+    *INSTANCE.lock().unwrap_or_else(PoisonError::into_inner) = instance;
+}
+
+fn instance() -> Option<String> {
+    INSTANCE
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .clone()
+}
 
 /// Hyprland focus source implementation.
 pub struct HyprlandSource {
     reader: Option<BufReader<UnixStream>>,
+    /// Path of socket2; the request socket is next to it.
+    socket_path: PathBuf,
+    /// Bytes of the line being read. Only cleared once a line is consumed,
+    /// so a cancelled read keeps what it already got.
+    line: Vec<u8>,
     state: FocusState,
+    /// Focus event waiting for its process lookup. Kept across calls so
+    /// `next_event` stays cancel-safe: the event loop drops the pending
+    /// future whenever another branch fires.
+    ready: Option<FocusEvent>,
     backoff: Duration,
 }
 
 impl HyprlandSource {
+    /// What this backend reports. Title changes come from `windowtitlev2`.
+    pub const CAPABILITIES: Capabilities = Capabilities {
+        titles: true,
+        window_ids: true,
+        workspaces: true,
+        monitors: true,
+        processes: true,
+        title_changes: true,
+        fullscreen: false,
+    };
+
     /// Create a new Hyprland focus source.
     pub async fn connect() -> Result<Self, FocusError> {
-        let socket_path = get_socket2_path()?;
-        info!("Connecting to Hyprland socket2: {}", socket_path.display());
-
-        let stream = UnixStream::connect(&socket_path)
-            .await
-            .map_err(|e| FocusError::ConnectionFailed(e.to_string()))?;
-
+        let (socket_path, stream) = connect_socket2().await?;
         info!("Connected to Hyprland socket2");
 
         Ok(Self {
             reader: Some(BufReader::new(stream)),
-            state: FocusState::default(),
+            line: Vec::new(),
+            state: FocusState::default().with_active(active_workspace(&socket_path).await),
+            ready: None,
+            socket_path,
             backoff: Duration::from_millis(250),
         })
     }
@@ -62,6 +133,14 @@ impl HyprlandSource {
             return diags;
         }
 
+        diags.push(format!(
+            "Instances (newest first): {}",
+            list_instances(&hypr_dir)
+        ));
+        if let Some(instance) = instance() {
+            diags.push(format!("hyprland_instance = {instance:?}"));
+        }
+
         match env::var("HYPRLAND_INSTANCE_SIGNATURE") {
             Ok(v) => {
                 diags.push(format!("HYPRLAND_INSTANCE_SIGNATURE={v} (set)"));
@@ -80,6 +159,20 @@ impl HyprlandSource {
         diags
     }
 
+    /// Attach the window's process to the ready event and log it. The event
+    /// stays in `ready` until the lookup is done.
+    async fn finish(&mut self) -> Option<FocusEvent> {
+        let address = self.ready.as_ref()?.window_id.as_deref();
+        let process = match address {
+            Some(address) => window_pid(&self.socket_path, address)
+                .await
+                .map(ProcessInfo::from_pid),
+            None => None,
+        };
+        let focus_event = self.ready.take()?;
+        Some(log_focus(focus_event.with_process(process)))
+    }
+
     /// Attempt to reconnect to the Hyprland socket.
     async fn reconnect(&mut self) -> Result<(), FocusError> {
         const MAX_BACKOFF: Duration = Duration::from_secs(5);
@@ -91,13 +184,13 @@ impl HyprlandSource {
         // Exponential backoff with cap
         self.backoff = std::cmp::min(self.backoff * 2, MAX_BACKOFF);
 
-        let socket_path = get_socket2_path()?;
-        let stream = UnixStream::connect(&socket_path)
-            .await
-            .map_err(|e| FocusError::ConnectionFailed(e.to_string()))?;
-
+        let (socket_path, stream) = connect_socket2().await?;
         info!("Reconnected to Hyprland socket2");
         self.reader = Some(BufReader::new(stream));
+        if let Some(active) = active_workspace(&socket_path).await {
+            self.state = std::mem::take(&mut self.state).with_active(Some(active));
+        }
+        self.socket_path = socket_path;
         self.backoff = Duration::from_millis(250); // Reset backoff on success
 
         Ok(())
@@ -108,36 +201,58 @@ impl FocusSource for HyprlandSource {
     fn next_event(&mut self) -> BoxFuture<'_, Result<FocusEvent, FocusError>> {
         Box::pin(async move {
             loop {
+                if let Some(focus_event) = self.finish().await {
+                    return Ok(focus_event);
+                }
                 let Some(reader) = &mut self.reader else {
                     self.reconnect().await?;
                     continue;
                 };
 
-                let mut line = String::new();
-                match reader.read_line(&mut line).await {
+                let read = if self.state.pending.is_some() {
+                    let waited = tokio::time::timeout(
+                        ADDRESS_WAIT,
+                        reader.read_until(b'\n', &mut self.line),
+                    )
+                    .await;
+                    let Ok(read) = waited else {
+                        // No activewindowv2 came; go with the last known address
+                        self.ready = self.state.take_pending();
+                        continue;
+                    };
+                    read
+                } else {
+                    reader.read_until(b'\n', &mut self.line).await
+                };
+
+                match read {
                     Ok(0) => {
                         // EOF - socket closed
                         warn!("Socket2 stream ended (EOF)");
                         self.reader = None;
-                        self.reconnect().await?;
+                        self.line.clear();
+                        self.ready = self.state.take_pending();
+                        if self.ready.is_none() {
+                            self.reconnect().await?;
+                        }
                     }
                     Ok(_) => {
-                        trace!("Received line: {}", line.trim());
-                        let event = parse_event_line(&line);
-
-                        if let Some(focus_event) = self.state.update(event) {
-                            debug!(
-                                "Focus changed: class={}, title={:?}, window_id={:?}",
-                                focus_event.app_class, focus_event.title, focus_event.window_id
-                            );
-                            return Ok(focus_event);
-                        }
-                        // No event produced, read next line
+                        let focus_event = {
+                            let line = String::from_utf8_lossy(&self.line);
+                            trace!("Received line: {}", sensitive(line.trim()));
+                            self.state.update(parse_event_line(&line))
+                        };
+                        self.line.clear();
+                        self.ready = focus_event;
                     }
                     Err(e) => {
                         warn!("Read error: {}", e);
                         self.reader = None;
-                        self.reconnect().await?;
+                        self.line.clear();
+                        self.ready = self.state.take_pending();
+                        if self.ready.is_none() {
+                            self.reconnect().await?;
+                        }
                     }
                 }
             }
@@ -145,11 +260,185 @@ impl FocusSource for HyprlandSource {
     }
 }
 
+fn log_focus(focus_event: FocusEvent) -> FocusEvent {
+    debug!(
+        "Focus changed: class={}, title={:?}, window_id={:?}, workspace={:?}, monitor={:?}",
+        focus_event.app_class,
+        sensitive(&focus_event.title),
+        focus_event.window_id,
+        focus_event.workspace,
+        focus_event.monitor
+    );
+    if let Some(process) = &focus_event.process {
+        debug!(
+            "Window process: pid={} name={:?} cwd={:?}",
+            process.pid,
+            process.name,
+            sensitive(&process.cwd)
+        );
+    }
+    focus_event
+}
+
+/// The active workspace and the monitor it is on.
+#[derive(Debug, PartialEq, Eq)]
+struct ActiveWorkspace {
+    name: String,
+    monitor: Option<String>,
+}
+
+/// Ask Hyprland's request socket (next to socket2) for the active workspace,
+/// so focus events carry it before the first workspace change.
+async fn active_workspace(socket2_path: &Path) -> Option<ActiveWorkspace> {
+    let workspace = query(socket2_path, "j/activeworkspace")
+        .await
+        .and_then(|reply| parse_active_workspace(&reply));
+    debug!("Active workspace: {:?}", workspace);
+    workspace
+}
+
+/// Ask Hyprland's request socket for the PID of the window at `address`.
+async fn window_pid(socket2_path: &Path, address: &str) -> Option<u32> {
+    let pid = query(socket2_path, "j/clients")
+        .await
+        .and_then(|reply| parse_client_pid(&reply, address));
+    trace!("PID of window {address}: {pid:?}");
+    pid
+}
+
+/// Send `request` to Hyprland's request socket (next to socket2) and return
+/// the reply, or `None` if it fails or takes longer than [`QUERY_TIMEOUT`].
+async fn query(socket2_path: &Path, request: &str) -> Option<String> {
+    let query = async {
+        let mut stream = UnixStream::connect(socket2_path.with_file_name(".socket.sock"))
+            .await
+            .ok()?;
+        stream.write_all(request.as_bytes()).await.ok()?;
+        let mut reply = String::new();
+        stream.read_to_string(&mut reply).await.ok()?;
+        Some(reply)
+    };
+    tokio::time::timeout(QUERY_TIMEOUT, query)
+        .await
+        .ok()
+        .flatten()
+}
+
+/// Workspace and monitor names from the `j/activeworkspace` reply.
+fn parse_active_workspace(reply: &str) -> Option<ActiveWorkspace> {
+    let parsed: serde_json::Value = serde_json::from_str(reply).ok()?;
+    Some(ActiveWorkspace {
+        name: parsed.get("name")?.as_str()?.to_string(),
+        monitor: parsed
+            .get("monitor")
+            .and_then(serde_json::Value::as_str)
+            .map(str::to_string),
+    })
+}
+
+/// PID of the window at `address` from the `j/clients` reply.
+fn parse_client_pid(reply: &str, address: &str) -> Option<u32> {
+    let clients: Vec<serde_json::Value> = serde_json::from_str(reply).ok()?;
+    let client = clients.iter().find(|client| {
+        client
+            .get("address")
+            .and_then(serde_json::Value::as_str)
+            .is_some_and(|a| same_address(a, address))
+    })?;
+    // Hyprland reports -1 for windows without a process, e.g. XWayland ones
+    // it lost track of
+    u32::try_from(client.get("pid")?.as_i64()?).ok()
+}
+
+/// Connect to Hyprland's socket2.
+///
+/// After [`RESCAN_AFTER`] failures in a row, Hyprland has probably
+/// restarted under a new instance signature, so every other instance
+/// directory is tried, newest first. An instance pinned by signature is
+/// never swapped for another.
+async fn connect_socket2() -> Result<(PathBuf, UnixStream), FocusError> {
+    let socket_path = get_socket2_path()?;
+    info!("Connecting to Hyprland socket2: {}", socket_path.display());
+    let error = match UnixStream::connect(&socket_path).await {
+        Ok(stream) => {
+            FAILED_CONNECTS.store(0, Ordering::Relaxed);
+            return Ok((socket_path, stream));
+        }
+        Err(e) => e,
+    };
+
+    let failures = FAILED_CONNECTS.fetch_add(1, Ordering::Relaxed) + 1;
+    let pinned = matches!(instance(), Some(instance) if instance != MOST_RECENT);
+    if failures >= RESCAN_AFTER && !pinned {
+        debug!("{failures} failed connections in a row, looking for another Hyprland instance");
+        let hypr_dir = socket_path.parent().and_then(Path::parent);
+        for candidate in hypr_dir.map(instance_sockets).unwrap_or_default() {
+            if candidate == socket_path {
+                continue;
+            }
+            if let Ok(stream) = UnixStream::connect(&candidate).await {
+                info!(
+                    "Hyprland seems to have restarted, switching to {}",
+                    candidate.display()
+                );
+                FAILED_CONNECTS.store(0, Ordering::Relaxed);
+                *RESCANNED_SOCKET
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner) = Some(candidate.clone());
+                return Ok((candidate, stream));
+            }
+        }
+    }
+    Err(FocusError::ConnectionFailed(error.to_string()))
+}
+
+/// Socket2 paths of the instances in `hypr_dir`, newest first.
+fn instance_sockets(hypr_dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = std::fs::read_dir(hypr_dir) else {
+        return Vec::new();
+    };
+    let mut sockets: Vec<(PathBuf, std::time::SystemTime)> = entries
+        .filter_map(std::result::Result::ok)
+        .filter_map(|e| {
+            let path = e.path().join(".socket2.sock");
+            let mtime = path.metadata().ok()?.modified().ok()?;
+            Some((path, mtime))
+        })
+        .collect();
+    sockets.sort_by_key(|(_path, mtime)| std::cmp::Reverse(*mtime));
+    sockets.into_iter().map(|(path, _mtime)| path).collect()
+}
+
+/// Signatures of the instances in `hypr_dir`, newest first, for messages.
+fn list_instances(hypr_dir: &Path) -> String {
+    let signatures: Vec<_> = instance_sockets(hypr_dir)
+        .iter()
+        .filter_map(|path| path.parent()?.file_name())
+        .map(|name| name.to_string_lossy().into_owned())
+        .collect();
+    if signatures.is_empty() {
+        "none".to_string()
+    } else {
+        signatures.join(", ")
+    }
+}
+
 /// Get the path to Hyprland's socket2.
 ///
-/// First tries `HYPRLAND_INSTANCE_SIGNATURE` env var (for multi-instance setups),
+/// Uses the instance found after a restart if there was one. Otherwise, a
+/// `hyprland_instance` signature selects that instance, and
+/// `"most-recent"` the most recently started one. Without it, tries
+/// `HYPRLAND_INSTANCE_SIGNATURE` env var (for multi-instance setups),
 /// then falls back to discovering the most recently modified socket.
 fn get_socket2_path() -> Result<PathBuf, FocusError> {
+    let rescanned = RESCANNED_SOCKET
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .clone();
+    if let Some(path) = rescanned.filter(|path| path.exists()) {
+        return Ok(path);
+    }
+
     let xdg_runtime_dir = env::var("XDG_RUNTIME_DIR")
         .map_err(|_| FocusError::EnvVarNotSet("XDG_RUNTIME_DIR".to_string()))?;
 
@@ -159,35 +448,42 @@ fn get_socket2_path() -> Result<PathBuf, FocusError> {
         return Err(FocusError::SocketNotFound(hypr_dir.display().to_string()));
     }
 
-    if let Ok(sig) = env::var("HYPRLAND_INSTANCE_SIGNATURE") {
-        let path = hypr_dir.join(&sig).join(".socket2.sock");
-        if path.exists() {
-            info!(
-                "Using Hyprland socket from HYPRLAND_INSTANCE_SIGNATURE: {}",
-                path.display()
-            );
-            return Ok(path);
+    let instance = instance();
+    match instance.as_deref() {
+        Some(MOST_RECENT) => {}
+        Some(sig) => {
+            let path = hypr_dir.join(sig).join(".socket2.sock");
+            if path.exists() {
+                info!("Using Hyprland instance from config: {}", path.display());
+                return Ok(path);
+            }
+            return Err(FocusError::SocketNotFound(format!(
+                "Hyprland instance {sig} not found in {} (available: {})",
+                hypr_dir.display(),
+                list_instances(&hypr_dir)
+            )));
+        }
+        None => {
+            if let Ok(sig) = env::var("HYPRLAND_INSTANCE_SIGNATURE") {
+                let path = hypr_dir.join(&sig).join(".socket2.sock");
+                if path.exists() {
+                    info!(
+                        "Using Hyprland socket from HYPRLAND_INSTANCE_SIGNATURE: {}",
+                        path.display()
+                    );
+                    return Ok(path);
+                }
+                warn!(
+                    "HYPRLAND_INSTANCE_SIGNATURE set but socket not found: {}, falling back to discovery",
+                    sig
+                );
+            }
         }
-        warn!(
-            "HYPRLAND_INSTANCE_SIGNATURE set but socket not found: {}, falling back to discovery",
-            sig
-        );
     }
 
-    if let Ok(entries) = std::fs::read_dir(&hypr_dir) {
-        let sockets: Vec<(PathBuf, std::time::SystemTime)> = entries
-            .filter_map(std::result::Result::ok)
-            .filter_map(|e| {
-                let path = e.path().join(".socket2.sock");
-                let mtime = path.metadata().ok()?.modified().ok()?;
-                Some((path, mtime))
-            })
-            .collect();
-
-        if let Some((path, _mtime)) = sockets.into_iter().max_by_key(|(_p, mtime)| *mtime) {
-            info!("Discovered Hyprland socket via glob: {}", path.display());
-            return Ok(path);
-        }
+    if let Some(path) = instance_sockets(&hypr_dir).into_iter().next() {
+        info!("Discovered Hyprland socket via glob: {}", path.display());
+        return Ok(path);
     }
 
     Err(FocusError::SocketNotFound(format!(
@@ -196,13 +492,25 @@ fn get_socket2_path() -> Result<PathBuf, FocusError> {
     )))
 }
 
-/// Parsed IPC event from socket2.
-#[derive(Debug, Clone)]
-enum HyprlandEvent {
+/// Parsed IPC event from socket2, borrowing from the line it was read from.
+///
+/// Busy compositors emit many events we ignore, so parsing never allocates;
+/// strings are only copied once a focus change is actually emitted.
+#[derive(Debug, Clone, Copy)]
+enum HyprlandEvent<'a> {
     /// activewindow>>WINDOWCLASS,WINDOWTITLE
-    ActiveWindow { class: String, title: String },
+    ActiveWindow { class: &'a str, title: &'a str },
     /// activewindowv2>>WINDOWADDRESS
-    ActiveWindowV2 { address: String },
+    ActiveWindowV2 { address: &'a str },
+    /// workspace>>WORKSPACENAME
+    Workspace { name: &'a str },
+    /// focusedmon>>MONNAME,WORKSPACENAME
+    FocusedMonitor {
+        monitor: &'a str,
+        workspace: &'a str,
+    },
+    /// windowtitlev2>>WINDOWADDRESS,WINDOWTITLE
+    WindowTitle { address: &'a str, title: &'a str },
     /// Other events we don't care about.
     Other,
 }
@@ -210,12 +518,12 @@ enum HyprlandEvent {
 /// Parse a single line from the socket2 stream.
 ///
 /// Format: EVENT>>DATA\n
-fn parse_event_line(line: &str) -> HyprlandEvent {
+fn parse_event_line(line: &str) -> HyprlandEvent<'_> {
     let line = line.trim_end();
 
     // Split on first ">>" to get event name and data
     let Some((event_name, data)) = line.split_once(">>") else {
-        trace!("Ignoring malformed line (no >>): {}", line);
+        trace!("Ignoring malformed line (no >>): {}", sensitive(line));
         return HyprlandEvent::Other;
     };
 
@@ -223,20 +531,26 @@ fn parse_event_line(line: &str) -> HyprlandEvent {
         "activewindow" => {
             // Data format: WINDOWCLASS,WINDOWTITLE
             // IMPORTANT: Title can contain commas, so split on FIRST comma only
-            let (class, title) = if let Some((c, t)) = data.split_once(',') {
-                (c.to_string(), t.to_string())
-            } else {
-                // No comma means just class, no title
-                (data.to_string(), String::new())
-            };
+            // No comma means just class, no title
+            let (class, title) = data.split_once(',').unwrap_or((data, ""));
 
             HyprlandEvent::ActiveWindow { class, title }
         }
         "activewindowv2" => {
             // Data format: WINDOWADDRESS (e.g., "0xabc123" or just the hex part)
-            HyprlandEvent::ActiveWindowV2 {
-                address: data.to_string(),
-            }
+            HyprlandEvent::ActiveWindowV2 { address: data }
+        }
+        "workspace" => HyprlandEvent::Workspace { name: data },
+        "windowtitlev2" => {
+            // Data format: WINDOWADDRESS,WINDOWTITLE; the title can contain
+            // commas, the address can't
+            let (address, title) = data.split_once(',').unwrap_or((data, ""));
+            HyprlandEvent::WindowTitle { address, title }
+        }
+        "focusedmon" => {
+            // Data format: MONNAME,WORKSPACENAME
+            let (monitor, workspace) = data.split_once(',').unwrap_or(("", data));
+            HyprlandEvent::FocusedMonitor { monitor, workspace }
         }
         _ => {
             trace!("Ignoring event: {}", event_name);
@@ -245,51 +559,149 @@ fn parse_event_line(line: &str) -> HyprlandEvent {
     }
 }
 
+/// Run socket2 lines through the parser and focus state, returning the
+/// number of focus events produced. Exposed for benchmarks.
+#[doc(hidden)]
+pub fn replay_lines<'a>(lines: impl IntoIterator<Item = &'a str>) -> usize {
+    let mut state = FocusState::default();
+    let count = lines
+        .into_iter()
+        .filter_map(|line| state.update(parse_event_line(line)))
+        .count();
+    count + usize::from(state.take_pending().is_some())
+}
+
 /// State tracker for building complete focus events.
 ///
-/// Hyprland may emit `activewindowv2` (address) *after* `activewindow`
-/// (class/title), so the address attached to an event may be from the
-/// *previous* focus change. This is acceptable because `window_id` is not
-/// used downstream for anything critical — it's purely informational.
+/// Hyprland sends `activewindow` (class/title) and then `activewindowv2`
+/// (address) for every focus change. The event is held until its address
+/// arrives (for at most [`ADDRESS_WAIT`]), so `window_id` belongs to the
+/// window that was actually focused. That tells apart two windows with the
+/// same class and title, like two terminals in the same directory.
+///
+/// A title change without a focus change, like switching browser tabs, only
+/// comes as `windowtitlev2`; for the focused window it produces a focus
+/// event with the new title.
 #[derive(Debug, Default)]
 struct FocusState {
     current_address: Option<String>,
+    current_workspace: Option<String>,
+    current_monitor: Option<String>,
+    /// Class and title of the last focus event emitted.
+    current_class: Option<String>,
+    current_title: Option<String>,
+    /// Class and title waiting for their `activewindowv2`.
+    pending: Option<FocusEvent>,
 }
 
 impl FocusState {
+    /// This state with the workspace and monitor from a
+    /// `j/activeworkspace` query.
+    fn with_active(mut self, active: Option<ActiveWorkspace>) -> Self {
+        if let Some(active) = active {
+            self.current_workspace = Some(active.name);
+            if active.monitor.is_some() {
+                self.current_monitor = active.monitor;
+            }
+        }
+        self
+    }
+
     /// Update state and return a `FocusEvent` if we have enough info.
-    fn update(&mut self, event: HyprlandEvent) -> Option<FocusEvent> {
+    fn update(&mut self, event: HyprlandEvent<'_>) -> Option<FocusEvent> {
         match event {
             HyprlandEvent::ActiveWindow { class, title } => {
                 let class = class.trim();
 
-                // activewindow carries class/title; attach the latest known address
-                // from activewindowv2 if available.
                 if class.is_empty() {
                     // Empty class means no focus (e.g., switching to empty workspace)
                     self.current_address = None;
-                    None
-                } else {
-                    Some(FocusEvent::new(
-                        class.to_string(),
-                        if title.is_empty() { None } else { Some(title) },
-                        self.current_address.clone(),
-                    ))
+                    self.current_class = None;
+                    self.pending = None;
+                    return None;
                 }
+
+                let event = FocusEvent::new(
+                    class.to_string(),
+                    (!title.is_empty()).then(|| title.to_string()),
+                    None,
+                )
+                .with_workspace(self.current_workspace.clone())
+                .with_monitor(self.current_monitor.clone());
+                // An earlier event whose address never came is still
+                // emitted, with the latest known address
+                let previous = self.take_pending();
+                self.pending = Some(event);
+                previous
             }
             HyprlandEvent::ActiveWindowV2 { address } => {
-                // Update address for future events
-                self.current_address = if address.is_empty() {
-                    None
-                } else {
-                    Some(address)
-                };
-                // Don't emit here; activewindow already emitted
+                // Update address, reusing the allocation
+                if address.is_empty() {
+                    self.current_address = None;
+                } else if self.current_address.as_deref() != Some(address) {
+                    let current = self.current_address.get_or_insert_with(String::new);
+                    current.clear();
+                    current.push_str(address);
+                }
+                self.take_pending()
+            }
+            HyprlandEvent::Workspace { name } => {
+                replace_name(&mut self.current_workspace, name);
+                None
+            }
+            HyprlandEvent::FocusedMonitor { monitor, workspace } => {
+                replace_name(&mut self.current_monitor, monitor);
+                replace_name(&mut self.current_workspace, workspace);
                 None
             }
+            HyprlandEvent::WindowTitle { address, title } => self.retitle(address, title),
             HyprlandEvent::Other => None,
         }
     }
+
+    /// A focus event for a new title of the focused window. Titles of other
+    /// windows, and changes while a focus change is still held, are ignored.
+    fn retitle(&mut self, address: &str, title: &str) -> Option<FocusEvent> {
+        let focused = self
+            .current_address
+            .as_deref()
+            .is_some_and(|current| same_address(current, address));
+        if !focused || self.pending.is_some() {
+            return None;
+        }
+        let class = self.current_class.clone()?;
+        let title = (!title.is_empty()).then(|| title.to_string());
+        if title == self.current_title {
+            return None;
+        }
+        self.current_title.clone_from(&title);
+        Some(
+            FocusEvent::new(class, title, self.current_address.clone())
+                .with_workspace(self.current_workspace.clone())
+                .with_monitor(self.current_monitor.clone()),
+        )
+    }
+
+    /// Take the held event, with the latest known address.
+    fn take_pending(&mut self) -> Option<FocusEvent> {
+        let mut event = self.pending.take()?;
+        event.window_id.clone_from(&self.current_address);
+        self.current_class = Some(event.app_class.clone());
+        self.current_title.clone_from(&event.title);
+        Some(event)
+    }
+}
+
+/// Set `current` to `name`, or `None` if it is empty.
+fn replace_name(current: &mut Option<String>, name: &str) {
+    if current.as_deref() != Some(name) {
+        *current = (!name.is_empty()).then(|| name.to_string());
+    }
+}
+
+/// Whether two window addresses are the same, with or without `0x`.
+fn same_address(a: &str, b: &str) -> bool {
+    a.trim_start_matches("0x") == b.trim_start_matches("0x")
 }
 
 #[cfg(test)]
@@ -383,7 +795,7 @@ mod tests {
     #[test]
     fn test_parse_other_events() {
         assert!(matches!(
-            parse_event_line("workspace>>1"),
+            parse_event_line("workspacev2>>1,1"),
             HyprlandEvent::Other
         ));
         assert!(matches!(
@@ -396,6 +808,40 @@ mod tests {
         ));
     }
 
+    #[test]
+    fn test_parse_workspace_events() {
+        assert!(matches!(
+            parse_event_line("workspace>>3"),
+            HyprlandEvent::Workspace { name: "3" }
+        ));
+        assert!(matches!(
+            parse_event_line("focusedmon>>DP-1,client work"),
+            HyprlandEvent::FocusedMonitor {
+                monitor: "DP-1",
+                workspace: "client work"
+            }
+        ));
+        assert_eq!(
+            parse_active_workspace(r#"{"id": 3, "name": "3", "monitor": "DP-1"}"#),
+            Some(ActiveWorkspace {
+                name: "3".to_string(),
+                monitor: Some("DP-1".to_string()),
+            })
+        );
+        assert_eq!(parse_active_workspace("unknown request"), None);
+    }
+
+    #[test]
+    fn test_parse_client_pid() {
+        let clients = r#"[
+            {"address": "0x55d1a0", "class": "kitty", "pid": 4242},
+            {"address": "0x55d1b0", "class": "firefox", "pid": -1}
+        ]"#;
+        assert_eq!(parse_client_pid(clients, "55d1a0"), Some(4242));
+        assert_eq!(parse_client_pid(clients, "0x55d1b0"), None);
+        assert_eq!(parse_client_pid(clients, "0x1"), None);
+    }
+
     #[test]
     fn test_parse_malformed_line() {
         assert!(matches!(
@@ -422,13 +868,20 @@ mod tests {
         let mut state = FocusState::default();
 
         let event = HyprlandEvent::ActiveWindow {
-            class: "firefox".to_string(),
-            title: "Mozilla Firefox".to_string(),
+            class: "firefox",
+            title: "Mozilla Firefox",
         };
+        assert!(
+            state.update(event).is_none(),
+            "Should wait for the window address"
+        );
 
-        let focus = state.update(event).expect("Should produce focus event");
+        let focus = state
+            .update(HyprlandEvent::ActiveWindowV2 { address: "0xf1" })
+            .expect("Should produce focus event");
         assert_eq!(focus.app_class, "firefox");
         assert_eq!(focus.title, Some("Mozilla Firefox".to_string()));
+        assert_eq!(focus.window_id, Some("0xf1".to_string()));
     }
 
     #[test]
@@ -436,8 +889,8 @@ mod tests {
         let mut state = FocusState::default();
 
         let event = HyprlandEvent::ActiveWindow {
-            class: String::new(),
-            title: String::new(),
+            class: "",
+            title: "",
         };
 
         assert!(
@@ -451,8 +904,8 @@ mod tests {
         let mut state = FocusState::default();
 
         let event = HyprlandEvent::ActiveWindow {
-            class: "   ".to_string(),
-            title: "title".to_string(),
+            class: "   ",
+            title: "title",
         };
 
         assert!(
@@ -462,21 +915,83 @@ mod tests {
     }
 
     #[test]
-    fn test_focus_state_v2_updates_address() {
+    fn test_focus_state_same_class_windows_differ() {
+        let lines = [
+            "activewindow>>kitty,~/src/project",
+            "activewindowv2>>0xa",
+            "activewindow>>kitty,~/src/project",
+            "activewindowv2>>0xb",
+        ];
         let mut state = FocusState::default();
+        let ids: Vec<_> = lines
+            .into_iter()
+            .filter_map(|line| state.update(parse_event_line(line)))
+            .map(|focus| focus.window_id)
+            .collect();
+        assert_eq!(ids, [Some("0xa".to_string()), Some("0xb".to_string())]);
+    }
 
-        // First, get a v2 event
-        let v2_event = HyprlandEvent::ActiveWindowV2 {
-            address: "0xabc123".to_string(),
-        };
-        assert!(state.update(v2_event).is_none());
+    #[test]
+    fn test_focus_state_title_change() {
+        let lines = [
+            "activewindow>>firefox,GitHub",
+            "activewindowv2>>f1",
+            // Another window's title changing is not a focus change
+            "windowtitlev2>>a2,make: done",
+            // Switching tabs only changes the title
+            "windowtitlev2>>f1,Docs, Rust",
+            "windowtitlev2>>f1,Docs, Rust",
+        ];
+        let mut state = FocusState::default();
+        let titles: Vec<_> = lines
+            .into_iter()
+            .filter_map(|line| state.update(parse_event_line(line)))
+            .map(|focus| (focus.app_class, focus.title.unwrap(), focus.window_id))
+            .collect();
+        assert_eq!(
+            titles,
+            [
+                (
+                    "firefox".to_string(),
+                    "GitHub".to_string(),
+                    Some("f1".to_string())
+                ),
+                (
+                    "firefox".to_string(),
+                    "Docs, Rust".to_string(),
+                    Some("f1".to_string())
+                ),
+            ]
+        );
+    }
 
-        // Then an activewindow event should include the address
-        let event = HyprlandEvent::ActiveWindow {
-            class: "code".to_string(),
-            title: "main.rs".to_string(),
-        };
-        let focus = state.update(event).expect("Should produce focus event");
+    #[test]
+    fn test_focus_state_missing_address_keeps_event() {
+        let mut state = FocusState::default();
+        assert!(
+            state
+                .update(HyprlandEvent::ActiveWindowV2 {
+                    address: "0xabc123"
+                })
+                .is_none()
+        );
+        assert!(
+            state
+                .update(HyprlandEvent::ActiveWindow {
+                    class: "code",
+                    title: "main.rs",
+                })
+                .is_none()
+        );
+
+        // No activewindowv2 came; the held event goes out with the last address
+        let focus = state
+            .update(HyprlandEvent::ActiveWindow {
+                class: "code",
+                title: "lib.rs",
+            })
+            .expect("Should produce focus event");
+        assert_eq!(focus.title, Some("main.rs".to_string()));
         assert_eq!(focus.window_id, Some("0xabc123".to_string()));
     }
 
@@ -486,28 +1001,61 @@ mod tests {
 
         assert!(
             state
-                .update(HyprlandEvent::ActiveWindowV2 {
-                    address: "0xold".to_string()
-                })
+                .update(HyprlandEvent::ActiveWindowV2 { address: "0xold" })
                 .is_none()
         );
 
         assert!(
             state
                 .update(HyprlandEvent::ActiveWindow {
-                    class: String::new(),
-                    title: String::new()
+                    class: "",
+                    title: ""
                 })
                 .is_none()
         );
 
+        state.update(HyprlandEvent::ActiveWindow {
+            class: "code",
+            title: "main.rs",
+        });
         let next_focus = state
             .update(HyprlandEvent::ActiveWindow {
-                class: "code".to_string(),
-                title: "main.rs".to_string(),
+                class: "code",
+                title: "lib.rs",
             })
             .expect("Should produce focus event");
 
         assert_eq!(next_focus.window_id, None);
     }
+
+    #[test]
+    fn test_focus_state_tracks_workspace() {
+        let mut state = FocusState::default();
+
+        state.update(HyprlandEvent::Workspace { name: "3" });
+        state.update(HyprlandEvent::ActiveWindow {
+            class: "kitty",
+            title: "~",
+        });
+        let focus = state
+            .update(HyprlandEvent::ActiveWindowV2 { address: "0xa" })
+            .expect("Should produce focus event");
+        assert_eq!(focus.workspace.as_deref(), Some("3"));
+
+        assert_eq!(focus.monitor, None);
+
+        state.update(HyprlandEvent::FocusedMonitor {
+            monitor: "HDMI-A-1",
+            workspace: "web",
+        });
+        state.update(HyprlandEvent::ActiveWindow {
+            class: "firefox",
+            title: "GitHub",
+        });
+        let focus = state
+            .update(HyprlandEvent::ActiveWindowV2 { address: "0xb" })
+            .expect("Should produce focus event");
+        assert_eq!(focus.workspace.as_deref(), Some("web"));
+        assert_eq!(focus.monitor.as_deref(), Some("HDMI-A-1"));
+    }
 }
@@ -8,7 +8,6 @@ use std::env;
 use std::path::PathBuf;
 
 use futures_util::future::BoxFuture;
-use tokio::sync::mpsc;
 use tracing::debug;
 use tracing::info;
 use tracing::warn;
@@ -17,9 +16,14 @@ use zbus::connection::Builder;
 use zbus::proxy;
 use zbus::zvariant::ObjectPath;
 
+use super::Capabilities;
 use super::FocusError;
 use super::FocusEvent;
 use super::FocusSource;
+use super::channel;
+use super::channel::EventReceiver;
+use super::channel::EventSender;
+use crate::redact::sensitive;
 
 /// D-Bus service name for receiving `KWin` script callbacks.
 const DBUS_SERVICE_NAME: &str = "org.wakatime.focusd";
@@ -72,12 +76,12 @@ trait KWinScript {
 
 /// D-Bus object that receives focus change callbacks from the `KWin` script.
 struct FocusReceiver {
-    tx: mpsc::Sender<FocusEvent>,
+    tx: EventSender,
 }
 
 #[zbus::interface(name = "org.wakatime.focusd.FocusReceiver")]
 impl FocusReceiver {
-    async fn focus_changed(&self, class: String, title: String, window_id: String) {
+    fn focus_changed(&self, class: String, title: String, window_id: String) {
         let title = if title.is_empty() { None } else { Some(title) };
         let window_id = if window_id.is_empty() {
             None
@@ -85,7 +89,7 @@ impl FocusReceiver {
             Some(window_id)
         };
         let event = FocusEvent::new(class, title, window_id);
-        if self.tx.send(event).await.is_err() {
+        if self.tx.send(event).is_err() {
             warn!("Focus event channel closed");
         }
     }
@@ -93,7 +97,7 @@ impl FocusReceiver {
 
 /// KDE Plasma focus source implementation.
 pub struct KdeSource {
-    rx: mpsc::Receiver<FocusEvent>,
+    rx: EventReceiver,
     // Hold connection to keep the D-Bus service alive.
     // Not read directly but must stay alive for the D-Bus service.
     #[allow(dead_code)]
@@ -102,9 +106,20 @@ pub struct KdeSource {
 }
 
 impl KdeSource {
+    /// What this backend reports.
+    pub const CAPABILITIES: Capabilities = Capabilities {
+        titles: true,
+        window_ids: true,
+        workspaces: false,
+        monitors: false,
+        processes: false,
+        title_changes: false,
+        fullscreen: false,
+    };
+
     /// Create a new KDE focus source.
     pub async fn connect() -> Result<Self, FocusError> {
-        let (tx, rx) = mpsc::channel(32);
+        let (tx, rx) = channel::event_channel();
 
         // Register our D-Bus service to receive callbacks
         let connection = Builder::session()
@@ -207,7 +222,9 @@ impl FocusSource for KdeSource {
 
                 debug!(
                     "Focus changed: class={}, title={:?}, window_id={:?}",
-                    event.app_class, event.title, event.window_id
+                    event.app_class,
+                    sensitive(&event.title),
+                    event.window_id
                 );
 
                 return Ok(event);
@@ -0,0 +1,138 @@
+//! Tagging events with their backend, and running several backends at once.
+//!
+//! For nested sessions, e.g. Sway running in a Hyprland window, each backend
+//! in `merge_backends` runs in a task of its own and their events are merged
+//! into one stream in the order they arrive. If any of them fails, the merged
+//! source reports its error and the daemon reconnects all of them.
+//! Wrapping a single source in a [`MergedSource`] makes its reads
+//! cancel-safe, since the task keeps reading while callers give up.
+
+use futures_util::future::BoxFuture;
+use tokio::sync::mpsc;
+use tokio::task::JoinSet;
+
+use super::Backend;
+use super::FocusError;
+use super::FocusEvent;
+use super::FocusSource;
+
+/// Events waiting to be read from a merged source.
+const QUEUE_SIZE: usize = 16;
+
+/// Focus source that tags each event with the backend it came from.
+pub struct TaggedSource {
+    inner: Box<dyn FocusSource>,
+    backend: Backend,
+}
+
+impl TaggedSource {
+    /// Tag events from `inner` with `backend`.
+    #[must_use]
+    pub fn new(inner: Box<dyn FocusSource>, backend: Backend) -> Self {
+        Self { inner, backend }
+    }
+}
+
+impl FocusSource for TaggedSource {
+    fn next_event(&mut self) -> BoxFuture<'_, Result<FocusEvent, FocusError>> {
+        Box::pin(async move {
+            let event = self.inner.next_event().await?;
+            Ok(event.with_backend(self.backend))
+        })
+    }
+}
+
+/// Focus source merging the events of several others.
+pub struct MergedSource {
+    events: mpsc::Receiver<Result<FocusEvent, FocusError>>,
+    /// Aborted when the source is dropped.
+    _tasks: JoinSet<()>,
+}
+
+impl MergedSource {
+    /// Start reading events from each of `sources`.
+    #[must_use]
+    pub fn new(sources: Vec<Box<dyn FocusSource>>) -> Self {
+        let (tx, events) = mpsc::channel(QUEUE_SIZE);
+        let mut tasks = JoinSet::new();
+        for mut source in sources {
+            let tx = tx.clone();
+            tasks.spawn(async move {
+                loop {
+                    let result = source.next_event().await;
+                    let failed = result.is_err();
+                    if tx.send(result).await.is_err() || failed {
+                        return;
+                    }
+                }
+            });
+        }
+        Self {
+            events,
+            _tasks: tasks,
+        }
+    }
+}
+
+impl FocusSource for MergedSource {
+    fn next_event(&mut self) -> BoxFuture<'_, Result<FocusEvent, FocusError>> {
+        Box::pin(async move {
+            // Every task sends its error before it ends
+            self.events
+                .recv()
+                .await
+                .unwrap_or(Err(FocusError::Exhausted))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+
+    use super::*;
+
+    /// Source returning a fixed list of events, then waiting forever.
+    struct ListSource(VecDeque<FocusEvent>);
+
+    impl FocusSource for ListSource {
+        fn next_event(&mut self) -> BoxFuture<'_, Result<FocusEvent, FocusError>> {
+            Box::pin(async move {
+                match self.0.pop_front() {
+                    Some(event) => Ok(event),
+                    None => std::future::pending().await,
+                }
+            })
+        }
+    }
+
+    fn tagged(backend: Backend, class: &str) -> Box<dyn FocusSource> {
+        let event = FocusEvent::new(class.to_string(), None, None);
+        Box::new(TaggedSource::new(
+            Box::new(ListSource(VecDeque::from([event]))),
+            backend,
+        ))
+    }
+
+    #[tokio::test]
+    async fn test_merged_events_keep_their_backend() {
+        let mut merged = MergedSource::new(vec![
+            tagged(Backend::Hyprland, "sway"),
+            tagged(Backend::Sway, "kitty"),
+        ]);
+
+        let mut events = Vec::new();
+        for _ in 0..2 {
+            let event = merged.next_event().await.unwrap();
+            events.push((event.app_class, event.backend));
+        }
+        events.sort_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(
+            events,
+            vec![
+                ("kitty".to_string(), Some(Backend::Sway)),
+                ("sway".to_string(), Some(Backend::Hyprland)),
+            ]
+        );
+    }
+}
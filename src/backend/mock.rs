@@ -0,0 +1,222 @@
+//! Scripted focus backend for tests and demos.
+//!
+//! Replays focus events from a JSON Lines script instead of talking to a
+//! compositor. Each line is one event, emitted `delay_ms` after the previous
+//! one:
+//!
+//! ```json
+//! {"delay_ms": 0, "app_class": "firefox", "title": "GitHub", "url": "https://github.com/"}
+//! {"delay_ms": 5000, "app_class": "kitty", "title": "~/src/myproject", "workspace": "3"}
+//! ```
+//!
+//! Blank lines and lines starting with `#` are ignored. Once the script is
+//! exhausted, the source reports [`FocusError::Exhausted`] and the daemon
+//! exits.
+//!
+//! Delays are replayed at real speed unless [`set_speed`] (`--script-speed`)
+//! says otherwise, so a day's recording can run through the pipeline in
+//! seconds.
+
+use std::collections::VecDeque;
+use std::path::Path;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+use futures_util::future::BoxFuture;
+use serde::Deserialize;
+use tokio::time::Instant;
+use tracing::debug;
+use tracing::info;
+
+use super::Capabilities;
+use super::FocusError;
+use super::FocusEvent;
+use super::FocusSource;
+use crate::redact::sensitive;
+
+/// Replay speed factor, as the bits of an `f64`; 1.0 is real speed.
+static SPEED: AtomicU64 = AtomicU64::new(1.0f64.to_bits());
+
+/// Replay scripts `speed` times faster than recorded; 0 replays them without
+/// delays.
+pub fn set_speed(speed: f64) {
+    SPEED.store(speed.to_bits(), Ordering::Relaxed);
+}
+
+/// `delay_ms` scaled by the replay `speed`.
+fn scaled_delay(delay_ms: u64, speed: f64) -> Duration {
+    let delay = Duration::from_millis(delay_ms);
+    if speed > 0.0 {
+        delay.div_f64(speed)
+    } else {
+        Duration::ZERO
+    }
+}
+
+/// One line of a mock script.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ScriptedEvent {
+    /// Milliseconds to wait before emitting this event.
+    #[serde(default)]
+    delay_ms: u64,
+    app_class: String,
+    #[serde(default)]
+    title: Option<String>,
+    #[serde(default)]
+    window_id: Option<String>,
+    #[serde(default)]
+    url: Option<String>,
+    #[serde(default)]
+    workspace: Option<String>,
+    #[serde(default)]
+    monitor: Option<String>,
+    #[serde(default)]
+    fullscreen: bool,
+}
+
+/// Mock focus source replaying a script.
+pub struct MockSource {
+    events: VecDeque<ScriptedEvent>,
+    /// When the next event is due, once its delay has started.
+    ///
+    /// Kept across calls so `next_event` stays cancel-safe: the event loop
+    /// drops the pending future whenever another branch fires.
+    next_due: Option<Instant>,
+}
+
+impl MockSource {
+    /// What this backend reports: scripts can set everything but the
+    /// window's process.
+    pub const CAPABILITIES: Capabilities = Capabilities {
+        titles: true,
+        window_ids: true,
+        workspaces: true,
+        monitors: true,
+        processes: false,
+        title_changes: true,
+        fullscreen: true,
+    };
+
+    /// Load a mock source from the script at `path`.
+    pub async fn connect(path: &Path) -> Result<Self, FocusError> {
+        let script = tokio::fs::read_to_string(path).await.map_err(|e| {
+            FocusError::ConnectionFailed(format!(
+                "Failed to read mock script {}: {e}",
+                path.display()
+            ))
+        })?;
+        let source = Self::from_script(&script).map_err(|e| {
+            FocusError::ConnectionFailed(format!("Invalid mock script {}: {e}", path.display()))
+        })?;
+
+        info!(
+            "Loaded mock script {} ({} events)",
+            path.display(),
+            source.events.len()
+        );
+        Ok(source)
+    }
+
+    /// Parse a mock script. Errors name the offending line.
+    fn from_script(script: &str) -> Result<Self, String> {
+        let mut events = VecDeque::new();
+        for (i, line) in script.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let event = serde_json::from_str(line).map_err(|e| format!("line {}: {e}", i + 1))?;
+            events.push_back(event);
+        }
+        Ok(Self {
+            events,
+            next_due: None,
+        })
+    }
+
+    /// Get diagnostic information for the mock backend.
+    pub fn get_diagnostics() -> Vec<String> {
+        vec!["Replays focus events from the file given with --script".to_string()]
+    }
+}
+
+impl FocusSource for MockSource {
+    fn next_event(&mut self) -> BoxFuture<'_, Result<FocusEvent, FocusError>> {
+        Box::pin(async move {
+            let Some(delay_ms) = self.events.front().map(|e| e.delay_ms) else {
+                info!("Mock script finished");
+                return Err(FocusError::Exhausted);
+            };
+
+            let due = *self.next_due.get_or_insert_with(|| {
+                let speed = f64::from_bits(SPEED.load(Ordering::Relaxed));
+                Instant::now() + scaled_delay(delay_ms, speed)
+            });
+            tokio::time::sleep_until(due).await;
+
+            self.next_due = None;
+            let scripted = self.events.pop_front().expect("front event checked above");
+            let event = FocusEvent::new(scripted.app_class, scripted.title, scripted.window_id)
+                .with_url(scripted.url)
+                .with_workspace(scripted.workspace)
+                .with_monitor(scripted.monitor)
+                .with_fullscreen(scripted.fullscreen);
+            debug!(
+                "Mock focus: class={}, title={:?}",
+                event.app_class,
+                sensitive(&event.title)
+            );
+            Ok(event)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scaled_delay() {
+        assert_eq!(scaled_delay(5000, 1.0), Duration::from_secs(5));
+        assert_eq!(scaled_delay(5000, 10.0), Duration::from_millis(500));
+        assert_eq!(scaled_delay(5000, 0.5), Duration::from_secs(10));
+        assert_eq!(scaled_delay(5000, 0.0), Duration::ZERO);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_replays_script_on_schedule() {
+        let script = r#"
+# focus the browser, then the terminal five seconds later
+{"app_class": "firefox", "title": "GitHub", "url": "https://github.com/"}
+
+{"delay_ms": 5000, "app_class": "kitty"}
+"#;
+        let mut source = MockSource::from_script(script).unwrap();
+        let start = Instant::now();
+
+        let first = source.next_event().await.unwrap();
+        assert_eq!(first.app_class, "firefox");
+        assert_eq!(first.url.as_deref(), Some("https://github.com/"));
+        assert_eq!(start.elapsed(), Duration::ZERO);
+
+        let second = source.next_event().await.unwrap();
+        assert_eq!(second.app_class, "kitty");
+        assert_eq!(second.title, None);
+        assert_eq!(start.elapsed(), Duration::from_secs(5));
+
+        assert!(matches!(
+            source.next_event().await,
+            Err(FocusError::Exhausted)
+        ));
+    }
+
+    #[test]
+    fn test_invalid_script_names_line() {
+        let err = MockSource::from_script("{\"app_class\": \"kitty\"}\n{\"class\": 1}")
+            .err()
+            .unwrap();
+        assert!(err.starts_with("line 2:"), "{err}");
+    }
+}
@@ -20,9 +20,11 @@ use tracing::info;
 use tracing::trace;
 use tracing::warn;
 
+use super::Capabilities;
 use super::FocusError;
 use super::FocusEvent;
 use super::FocusSource;
+use crate::redact::sensitive;
 
 /// Environment variable for the Niri socket path.
 const SOCKET_PATH_ENV: &str = "NIRI_SOCKET";
@@ -43,6 +45,17 @@ struct WindowInfo {
 }
 
 impl NiriSource {
+    /// What this backend reports.
+    pub const CAPABILITIES: Capabilities = Capabilities {
+        titles: true,
+        window_ids: true,
+        workspaces: false,
+        monitors: false,
+        processes: false,
+        title_changes: false,
+        fullscreen: false,
+    };
+
     /// Create a new Niri focus source.
     pub async fn connect() -> Result<Self, FocusError> {
         let socket_path = get_socket_path()?;
@@ -122,7 +135,7 @@ impl FocusSource for NiriSource {
                         self.reader = None;
                     }
                     Ok(_) => {
-                        trace!("Niri event: {}", line.trim());
+                        trace!("Niri event: {}", sensitive(line.trim()));
 
                         match serde_json::from_str::<NiriEvent>(line.trim()) {
                             Ok(event) => {
@@ -172,7 +185,8 @@ impl NiriSource {
 
                 debug!(
                     "Focus changed: class={}, title={:?}, window_id={id}",
-                    app_id, title
+                    app_id,
+                    sensitive(&title)
                 );
 
                 Some(FocusEvent::new(app_id.clone(), title, Some(id.to_string())))
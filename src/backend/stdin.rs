@@ -0,0 +1,165 @@
+//! Focus events piped in on standard input.
+//!
+//! For window managers without a native backend, and for scripts: each line
+//! on stdin is one focus change, either `class,title` or a JSON object with
+//! the same fields as a mock script line, minus the delay:
+//!
+//! ```text
+//! kitty,~/src/myproject
+//! {"app_class": "firefox", "title": "GitHub", "url": "https://github.com/"}
+//! ```
+//!
+//! Events are emitted as the lines arrive. Blank lines and lines starting
+//! with `#` are ignored, and malformed lines are logged and skipped. At end
+//! of input, the source reports [`FocusError::Exhausted`] and the daemon
+//! exits.
+
+use futures_util::future::BoxFuture;
+use serde::Deserialize;
+use tokio::io::AsyncBufReadExt;
+use tokio::io::BufReader;
+use tokio::io::Lines;
+use tokio::io::Stdin;
+use tracing::debug;
+use tracing::info;
+use tracing::warn;
+
+use super::Capabilities;
+use super::FocusError;
+use super::FocusEvent;
+use super::FocusSource;
+use crate::redact::sensitive;
+
+/// A JSON line on stdin.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PipedEvent {
+    app_class: String,
+    #[serde(default)]
+    title: Option<String>,
+    #[serde(default)]
+    window_id: Option<String>,
+    #[serde(default)]
+    url: Option<String>,
+    #[serde(default)]
+    workspace: Option<String>,
+    #[serde(default)]
+    monitor: Option<String>,
+    #[serde(default)]
+    fullscreen: bool,
+}
+
+/// Focus source reading standard input.
+pub struct StdinSource {
+    lines: Lines<BufReader<Stdin>>,
+}
+
+impl StdinSource {
+    /// What this backend reports: JSON lines can set everything but the
+    /// window's process.
+    pub const CAPABILITIES: Capabilities = Capabilities {
+        titles: true,
+        window_ids: true,
+        workspaces: true,
+        monitors: true,
+        processes: false,
+        title_changes: true,
+        fullscreen: true,
+    };
+
+    /// Start reading standard input.
+    #[must_use]
+    pub fn connect() -> Self {
+        info!("Reading focus events from stdin");
+        Self {
+            lines: BufReader::new(tokio::io::stdin()).lines(),
+        }
+    }
+
+    /// Get diagnostic information for the stdin backend.
+    pub fn get_diagnostics() -> Vec<String> {
+        vec!["Reads `class,title` or JSON focus events from stdin, one per line".to_string()]
+    }
+}
+
+/// Parse a line of input; `Ok(None)` for blank lines and comments.
+fn parse_line(line: &str) -> Result<Option<FocusEvent>, String> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return Ok(None);
+    }
+    let event = if line.starts_with('{') {
+        let piped: PipedEvent = serde_json::from_str(line).map_err(|e| e.to_string())?;
+        FocusEvent::new(piped.app_class, piped.title, piped.window_id)
+            .with_url(piped.url)
+            .with_workspace(piped.workspace)
+            .with_monitor(piped.monitor)
+            .with_fullscreen(piped.fullscreen)
+    } else {
+        let (class, title) = line.split_once(',').unwrap_or((line, ""));
+        let title = title.trim();
+        FocusEvent::new(
+            class.trim().to_string(),
+            (!title.is_empty()).then(|| title.to_string()),
+            None,
+        )
+    };
+    if event.app_class.is_empty() {
+        return Err("missing app class".to_string());
+    }
+    Ok(Some(event))
+}
+
+impl FocusSource for StdinSource {
+    fn next_event(&mut self) -> BoxFuture<'_, Result<FocusEvent, FocusError>> {
+        Box::pin(async move {
+            loop {
+                let line = self
+                    .lines
+                    .next_line()
+                    .await
+                    .map_err(|e| FocusError::ConnectionFailed(format!("Reading stdin: {e}")))?;
+                let Some(line) = line else {
+                    info!("End of stdin");
+                    return Err(FocusError::Exhausted);
+                };
+                match parse_line(&line) {
+                    Ok(Some(event)) => {
+                        debug!(
+                            "Stdin focus: class={}, title={:?}",
+                            event.app_class,
+                            sensitive(&event.title)
+                        );
+                        return Ok(event);
+                    }
+                    Ok(None) => {}
+                    Err(e) => warn!("Skipping malformed stdin line: {e}"),
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_line() {
+        let event = parse_line("kitty,~/src/a, b").unwrap().unwrap();
+        assert_eq!(event.app_class, "kitty");
+        assert_eq!(event.title.as_deref(), Some("~/src/a, b"));
+
+        let event = parse_line("firefox").unwrap().unwrap();
+        assert_eq!(event.title, None);
+
+        let event = parse_line(r#"{"app_class": "kitty", "workspace": "3"}"#)
+            .unwrap()
+            .unwrap();
+        assert_eq!(event.workspace.as_deref(), Some("3"));
+
+        assert!(parse_line("  # comment").unwrap().is_none());
+        assert!(parse_line(",untitled").is_err());
+        assert!(parse_line(r#"{"class": "kitty"}"#).is_err());
+    }
+}
@@ -16,9 +16,11 @@ use tracing::info;
 use tracing::trace;
 use tracing::warn;
 
+use super::Capabilities;
 use super::FocusError;
 use super::FocusEvent;
 use super::FocusSource;
+use crate::redact::sensitive;
 
 /// IPC magic string for the i3/sway protocol.
 const IPC_MAGIC: &[u8] = b"i3-ipc";
@@ -39,6 +41,17 @@ pub struct SwaySource {
 }
 
 impl SwaySource {
+    /// What this backend reports.
+    pub const CAPABILITIES: Capabilities = Capabilities {
+        titles: true,
+        window_ids: true,
+        workspaces: false,
+        monitors: false,
+        processes: false,
+        title_changes: false,
+        fullscreen: false,
+    };
+
     /// Create a new Sway focus source and subscribe to window events.
     pub async fn connect() -> Result<Self, FocusError> {
         let socket_path = get_socket_path()?;
@@ -194,12 +207,14 @@ impl FocusSource for SwaySource {
                         }
 
                         let payload_str = String::from_utf8_lossy(&payload);
-                        trace!("Window event: {payload_str}");
+                        trace!("Window event: {}", sensitive(&payload_str));
 
                         if let Some(event) = parse_window_event(&payload_str) {
                             debug!(
                                 "Focus changed: class={}, title={:?}, window_id={:?}",
-                                event.app_class, event.title, event.window_id
+                                event.app_class,
+                                sensitive(&event.title),
+                                event.window_id
                             );
                             return Ok(event);
                         }
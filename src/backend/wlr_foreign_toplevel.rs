@@ -6,13 +6,12 @@
 //! `Activated` flag to detect focus changes.
 //!
 //! The Wayland event loop is synchronous, so it runs in a dedicated thread
-//! via `spawn_blocking` and sends focus events through an `mpsc` channel.
+//! via `spawn_blocking` and sends focus events through a drop-oldest channel.
 
 use std::collections::HashMap;
 use std::env;
 
 use futures_util::future::BoxFuture;
-use tokio::sync::mpsc;
 use tracing::debug;
 use tracing::info;
 use tracing::trace;
@@ -29,9 +28,14 @@ use wayland_protocols_wlr::foreign_toplevel::v1::client::zwlr_foreign_toplevel_h
 use wayland_protocols_wlr::foreign_toplevel::v1::client::zwlr_foreign_toplevel_manager_v1;
 use wayland_protocols_wlr::foreign_toplevel::v1::client::zwlr_foreign_toplevel_manager_v1::ZwlrForeignToplevelManagerV1;
 
+use super::Capabilities;
 use super::FocusError;
 use super::FocusEvent;
 use super::FocusSource;
+use super::channel;
+use super::channel::EventReceiver;
+use super::channel::EventSender;
+use crate::redact::sensitive;
 
 /// The `Activated` state value in the wlr-foreign-toplevel protocol.
 const STATE_ACTIVATED: u32 = 2;
@@ -41,10 +45,21 @@ const STATE_ACTIVATED: u32 = 2;
 /// Runs a blocking Wayland event loop in a dedicated thread and receives
 /// focus events via a channel.
 pub struct WlrForeignToplevelSource {
-    rx: mpsc::Receiver<FocusEvent>,
+    rx: EventReceiver,
 }
 
 impl WlrForeignToplevelSource {
+    /// What this backend reports.
+    pub const CAPABILITIES: Capabilities = Capabilities {
+        titles: true,
+        window_ids: true,
+        workspaces: false,
+        monitors: false,
+        processes: false,
+        title_changes: false,
+        fullscreen: false,
+    };
+
     /// Connect to the Wayland display and bind the foreign-toplevel-manager.
     #[allow(clippy::unused_async)]
     pub async fn connect() -> Result<Self, FocusError> {
@@ -56,7 +71,7 @@ impl WlrForeignToplevelSource {
         // the long-lived thread.
         verify_protocol()?;
 
-        let (tx, rx) = mpsc::channel(32);
+        let (tx, rx) = channel::event_channel();
 
         tokio::task::spawn_blocking(move || {
             if let Err(e) = run_event_loop(&tx) {
@@ -110,11 +125,11 @@ struct ToplevelState {
     windows: HashMap<u32, WindowData>,
     /// The ID of the currently focused toplevel (after `Done`).
     focused_id: Option<u32>,
-    tx: mpsc::Sender<FocusEvent>,
+    tx: EventSender,
 }
 
 impl ToplevelState {
-    fn new(tx: mpsc::Sender<FocusEvent>) -> Self {
+    fn new(tx: EventSender) -> Self {
         Self {
             windows: HashMap::new(),
             focused_id: None,
@@ -147,7 +162,8 @@ impl ToplevelState {
 
             debug!(
                 "Focus changed: class={}, title={:?}, window_id={id}",
-                app_class, title
+                app_class,
+                sensitive(&title)
             );
 
             Some(FocusEvent::new(
@@ -173,6 +189,9 @@ impl ToplevelState {
     }
 }
 
+/// Interface of the standard `ext-foreign-toplevel-list-v1` protocol.
+const EXT_FOREIGN_TOPLEVEL_LIST: &str = "ext_foreign_toplevel_list_v1";
+
 /// Connect to the Wayland display and verify the protocol global exists.
 fn verify_protocol() -> Result<(), FocusError> {
     let conn = Connection::connect_to_env()
@@ -182,16 +201,24 @@ fn verify_protocol() -> Result<(), FocusError> {
     let (globals, _event_queue) = registry_queue_init::<ProbeState>(&conn)
         .map_err(|e| FocusError::ConnectionFailed(format!("Registry init: {e}")))?;
 
-    let has_manager = globals
+    let interfaces: Vec<String> = globals
         .contents()
         .clone_list()
-        .iter()
-        .any(|g| g.interface == ZwlrForeignToplevelManagerV1::interface().name);
-
-    if !has_manager {
-        return Err(FocusError::ConnectionFailed(
-            "Compositor does not support wlr-foreign-toplevel-management".to_string(),
-        ));
+        .into_iter()
+        .map(|g| g.interface)
+        .collect();
+    let has = |name: &str| interfaces.iter().any(|interface| interface == name);
+
+    if !has(ZwlrForeignToplevelManagerV1::interface().name) {
+        // The standard toplevel list names the windows but not which one is
+        // focused, so on its own it can't drive a backend
+        let reason = if has(EXT_FOREIGN_TOPLEVEL_LIST) {
+            "Compositor does not support wlr-foreign-toplevel-management; \
+             it offers ext-foreign-toplevel-list-v1, which does not report focus"
+        } else {
+            "Compositor does not support wlr-foreign-toplevel-management"
+        };
+        return Err(FocusError::ConnectionFailed(reason.to_string()));
     }
 
     Ok(())
@@ -225,7 +252,7 @@ impl Dispatch<wl_registry::WlRegistry, GlobalListContents> for ToplevelState {
 }
 
 /// Run the blocking Wayland event loop.
-fn run_event_loop(tx: &mpsc::Sender<FocusEvent>) -> Result<(), FocusError> {
+fn run_event_loop(tx: &EventSender) -> Result<(), FocusError> {
     let conn = Connection::connect_to_env()
         .map_err(|e| FocusError::ConnectionFailed(format!("Wayland connect: {e}")))?;
 
@@ -294,7 +321,7 @@ impl Dispatch<ZwlrForeignToplevelHandleV1, ()> for ToplevelState {
 
         match event {
             zwlr_foreign_toplevel_handle_v1::Event::Title { title } => {
-                trace!("Title changed for {id}: {title}");
+                trace!("Title changed for {id}: {}", sensitive(&title));
                 if let Some(w) = state.windows.get_mut(&id) {
                     w.title = title;
                 }
@@ -315,7 +342,7 @@ impl Dispatch<ZwlrForeignToplevelHandleV1, ()> for ToplevelState {
             zwlr_foreign_toplevel_handle_v1::Event::Done => {
                 trace!("Done for {id}");
                 if let Some(event) = state.commit(id)
-                    && state.tx.blocking_send(event).is_err()
+                    && state.tx.send(event).is_err()
                 {
                     warn!("Receiver dropped, stopping event loop");
                 }
@@ -339,8 +366,8 @@ fn parse_states(raw: &[u8]) -> impl Iterator<Item = u32> + '_ {
 mod tests {
     use super::*;
 
-    fn make_state() -> (ToplevelState, mpsc::Receiver<FocusEvent>) {
-        let (tx, rx) = mpsc::channel(16);
+    fn make_state() -> (ToplevelState, EventReceiver) {
+        let (tx, rx) = channel::with_capacity(16);
         (ToplevelState::new(tx), rx)
     }
 
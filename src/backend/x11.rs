@@ -7,7 +7,6 @@
 use std::env;
 
 use futures_util::future::BoxFuture;
-use tokio::sync::mpsc;
 use tracing::debug;
 use tracing::info;
 use tracing::trace;
@@ -23,19 +22,35 @@ use x11rb::protocol::xproto::EventMask;
 use x11rb::protocol::xproto::Window;
 use x11rb::rust_connection::RustConnection;
 
+use super::Capabilities;
 use super::FocusError;
 use super::FocusEvent;
 use super::FocusSource;
+use super::channel;
+use super::channel::EventReceiver;
+use super::channel::EventSender;
+use crate::redact::sensitive;
 
 /// X11 focus source implementation.
 ///
 /// Runs the blocking X11 event loop in a dedicated thread and receives
 /// focus events via a channel.
 pub struct X11Source {
-    rx: mpsc::Receiver<FocusEvent>,
+    rx: EventReceiver,
 }
 
 impl X11Source {
+    /// What this backend reports.
+    pub const CAPABILITIES: Capabilities = Capabilities {
+        titles: true,
+        window_ids: true,
+        workspaces: false,
+        monitors: false,
+        processes: false,
+        title_changes: false,
+        fullscreen: false,
+    };
+
     /// Create a new X11 focus source.
     #[allow(clippy::unused_async)]
     pub async fn connect() -> Result<Self, FocusError> {
@@ -44,7 +59,7 @@ impl X11Source {
             return Err(FocusError::EnvVarNotSet("DISPLAY".to_string()));
         }
 
-        let (tx, rx) = mpsc::channel(32);
+        let (tx, rx) = channel::event_channel();
 
         // Spawn the blocking X11 event loop in a dedicated thread
         tokio::task::spawn_blocking(move || {
@@ -82,7 +97,7 @@ impl FocusSource for X11Source {
 }
 
 /// Run the blocking X11 event loop, sending focus events to the channel.
-fn run_x11_event_loop(tx: &mpsc::Sender<FocusEvent>) -> Result<(), FocusError> {
+fn run_x11_event_loop(tx: &EventSender) -> Result<(), FocusError> {
     let (conn, screen_num) = x11rb::connect(None)
         .map_err(|e| FocusError::ConnectionFailed(format!("X11 connect failed: {e}")))?;
 
@@ -107,9 +122,10 @@ fn run_x11_event_loop(tx: &mpsc::Sender<FocusEvent>) -> Result<(), FocusError> {
     {
         debug!(
             "Initial focus: class={}, title={:?}",
-            event.app_class, event.title
+            event.app_class,
+            sensitive(&event.title)
         );
-        if tx.blocking_send(event).is_err() {
+        if tx.send(event).is_err() {
             return Ok(());
         }
     }
@@ -131,9 +147,11 @@ fn run_x11_event_loop(tx: &mpsc::Sender<FocusEvent>) -> Result<(), FocusError> {
             {
                 debug!(
                     "Focus changed: class={}, title={:?}, window_id={:?}",
-                    focus_event.app_class, focus_event.title, focus_event.window_id
+                    focus_event.app_class,
+                    sensitive(&focus_event.title),
+                    focus_event.window_id
                 );
-                if tx.blocking_send(focus_event).is_err() {
+                if tx.send(focus_event).is_err() {
                     // Receiver dropped, shut down
                     return Ok(());
                 }
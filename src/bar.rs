@@ -0,0 +1,439 @@
+//! Status bar summary.
+//!
+//! Bars like Waybar poll every second or so, which is too often for the
+//! `WakaTime` API. [`BarState`] keeps a running tally instead: today's active
+//! time, overall, per workspace and per app, the current entity, and whether
+//! heartbeats are getting through. [`BarSender`] feeds it from the heartbeats
+//! that leave the daemon, and the control socket's `bar` request reads it
+//! without any I/O.
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::PoisonError;
+
+use futures_util::future::BoxFuture;
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::api::HeartbeatSender;
+use crate::api::SendError;
+use crate::domain::Heartbeat;
+use crate::domain::unix_time_now;
+use crate::vacation::Date;
+
+/// Longest gap between heartbeats still counted as active time, matching
+/// `WakaTime`'s default keystroke timeout.
+const ACTIVITY_TIMEOUT_SECONDS: f64 = 15.0 * 60.0;
+
+/// Entities kept in an app's breakdown. Time on further entities (a long
+/// day of window titles) still counts towards the app.
+const MAX_ENTITIES_PER_APP: usize = 100;
+
+/// Whether heartbeats are reaching `WakaTime`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SendHealth {
+    /// The last upload succeeded, or nothing was uploaded yet.
+    #[default]
+    Ok,
+    /// The last upload failed; heartbeats are queued for a retry.
+    Queued,
+}
+
+/// Answer to the control socket's `bar` request.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BarStatus {
+    /// Active time today (local date), in seconds.
+    pub today_seconds: u64,
+    /// Entity of the last heartbeat, unless it is older than the activity
+    /// timeout.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub entity: Option<String>,
+    /// Category of that heartbeat.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub category: Option<String>,
+    /// Whether heartbeats are getting through.
+    pub health: SendHealth,
+    /// Whether heartbeats are paused (`pause`, holidays, or an inhibitor).
+    #[serde(default)]
+    pub paused: bool,
+}
+
+#[derive(Debug, Default)]
+struct Tally {
+    day: Option<Date>,
+    today_seconds: f64,
+    workspace_seconds: BTreeMap<String, f64>,
+    apps: BTreeMap<String, AppTally>,
+    last: Option<Last>,
+    health: SendHealth,
+}
+
+/// Today's time in one app, and on its entities.
+#[derive(Debug, Default)]
+struct AppTally {
+    seconds: f64,
+    entities: BTreeMap<String, f64>,
+}
+
+impl AppTally {
+    fn add(&mut self, entity: &str, seconds: f64) {
+        self.seconds += seconds;
+        if let Some(total) = self.entities.get_mut(entity) {
+            *total += seconds;
+        } else if self.entities.len() < MAX_ENTITIES_PER_APP {
+            self.entities.insert(entity.to_string(), seconds);
+        }
+    }
+}
+
+/// Active time today in one app, with a breakdown by entity.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AppTotal {
+    /// App class.
+    pub app: String,
+    /// Active time in the app, in seconds.
+    pub seconds: u64,
+    /// Active time per entity (titles, files, domains), longest first.
+    /// Empty when the app's only entity is the app itself.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub entities: Vec<EntityTotal>,
+}
+
+/// Active time today on one entity.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EntityTotal {
+    /// Entity, as sent.
+    pub entity: String,
+    /// Active time, in seconds.
+    pub seconds: u64,
+}
+
+/// The most recent heartbeat counted.
+#[derive(Debug)]
+struct Last {
+    time: f64,
+    entity: String,
+    app: String,
+    category: String,
+    workspace: Option<String>,
+}
+
+/// Running totals for [`BarStatus`].
+#[derive(Debug, Default)]
+pub struct BarState {
+    tally: Mutex<Tally>,
+}
+
+impl BarState {
+    /// Create an empty tally.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Count a heartbeat that was handed to the upstream sender.
+    ///
+    /// The time since the previous heartbeat counts as active unless it
+    /// exceeds the activity timeout, and goes to the previous heartbeat's
+    /// workspace, app and entity. Totals restart on a new local date.
+    pub fn record_heartbeat(&self, heartbeat: &Heartbeat) {
+        let mut tally = self.lock();
+        let day = Date::local(heartbeat.time);
+        if day != tally.day {
+            tally.day = day;
+            tally.today_seconds = 0.0;
+            tally.workspace_seconds.clear();
+            tally.apps.clear();
+            tally.last = None;
+        }
+        if let Some(last) = tally.last.take() {
+            let gap = heartbeat.time - last.time;
+            if gap > 0.0 && gap <= ACTIVITY_TIMEOUT_SECONDS {
+                tally.today_seconds += gap;
+                if let Some(ref workspace) = last.workspace {
+                    *tally
+                        .workspace_seconds
+                        .entry(workspace.clone())
+                        .or_default() += gap;
+                }
+                tally
+                    .apps
+                    .entry(last.app.clone())
+                    .or_default()
+                    .add(&last.entity, gap);
+            }
+            if gap < 0.0 {
+                // An older heartbeat (e.g. a retry) doesn't move the clock back
+                tally.last = Some(last);
+                return;
+            }
+        }
+        tally.last = Some(Last {
+            time: heartbeat.time,
+            entity: heartbeat.entity.as_str().to_string(),
+            app: heartbeat.source.app_class.clone(),
+            category: heartbeat.category.as_str().to_string(),
+            workspace: heartbeat.source.workspace.clone(),
+        });
+    }
+
+    /// Stop counting from the last heartbeat, so a gap in tracking isn't
+    /// added to today's total.
+    pub fn record_gap(&self) {
+        self.lock().last = None;
+    }
+
+    /// Record the outcome of an upload.
+    pub fn record_flush(&self, ok: bool) {
+        self.lock().health = if ok {
+            SendHealth::Ok
+        } else {
+            SendHealth::Queued
+        };
+    }
+
+    /// Current summary. `paused` is filled in by the caller.
+    #[must_use]
+    pub fn status(&self, paused: bool) -> BarStatus {
+        let now = unix_time_now();
+        let tally = self.lock();
+        let today = tally.day.is_some() && tally.day == Date::local(now);
+        let current = tally
+            .last
+            .as_ref()
+            .filter(|last| today && now - last.time <= ACTIVITY_TIMEOUT_SECONDS);
+        BarStatus {
+            today_seconds: if today {
+                whole_seconds(tally.today_seconds)
+            } else {
+                0
+            },
+            entity: current.map(|last| last.entity.clone()),
+            category: current.map(|last| last.category.clone()),
+            health: tally.health,
+            paused,
+        }
+    }
+
+    /// Active time today per workspace, in seconds, for backends that report
+    /// workspaces.
+    #[must_use]
+    pub fn workspace_seconds(&self) -> BTreeMap<String, u64> {
+        let tally = self.lock();
+        if tally.day.is_none() || tally.day != Date::local(unix_time_now()) {
+            return BTreeMap::new();
+        }
+        tally
+            .workspace_seconds
+            .iter()
+            .map(|(workspace, seconds)| (workspace.clone(), whole_seconds(*seconds)))
+            .collect()
+    }
+
+    /// Active time today per app, longest first, each with its entities.
+    ///
+    /// Title-level entities roll up into their app, so a day of window
+    /// titles stays a short list with the detail one level down.
+    #[must_use]
+    pub fn app_totals(&self) -> Vec<AppTotal> {
+        let tally = self.lock();
+        if tally.day.is_none() || tally.day != Date::local(unix_time_now()) {
+            return Vec::new();
+        }
+        let mut totals: Vec<_> = tally
+            .apps
+            .iter()
+            .map(|(app, app_tally)| {
+                let mut entities: Vec<_> = app_tally
+                    .entities
+                    .iter()
+                    .map(|(entity, seconds)| EntityTotal {
+                        entity: entity.clone(),
+                        seconds: whole_seconds(*seconds),
+                    })
+                    .collect();
+                if matches!(entities.as_slice(), [only] if only.entity == *app) {
+                    entities.clear();
+                }
+                entities.sort_by_key(|total| std::cmp::Reverse(total.seconds));
+                AppTotal {
+                    app: app.clone(),
+                    seconds: whole_seconds(app_tally.seconds),
+                    entities,
+                }
+            })
+            .collect();
+        totals.sort_by_key(|total| std::cmp::Reverse(total.seconds));
+        totals
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Tally> {
+        self.tally.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Whole seconds are enough for display.
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+fn whole_seconds(seconds: f64) -> u64 {
+    seconds as u64
+}
+
+/// A [`HeartbeatSender`] wrapper that feeds a [`BarState`].
+pub struct BarSender<S> {
+    inner: S,
+    state: Arc<BarState>,
+}
+
+impl<S> BarSender<S> {
+    /// Wrap `inner`, recording what it sends in `state`.
+    pub fn new(inner: S, state: Arc<BarState>) -> Self {
+        Self { inner, state }
+    }
+}
+
+impl<S: HeartbeatSender + Sync> HeartbeatSender for BarSender<S> {
+    fn send_heartbeat<'a>(
+        &'a self,
+        heartbeat: &'a Heartbeat,
+    ) -> BoxFuture<'a, Result<(), SendError>> {
+        Box::pin(async move {
+            let result = self.inner.send_heartbeat(heartbeat).await;
+            if result.is_ok() {
+                self.state.record_heartbeat(heartbeat);
+            }
+            result
+        })
+    }
+
+    fn flush(&self) -> BoxFuture<'_, Result<(), SendError>> {
+        Box::pin(async move {
+            let result = self.inner.flush().await;
+            self.state.record_flush(result.is_ok());
+            result
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::FocusEvent;
+    use crate::domain::Category;
+    use crate::domain::Entity;
+
+    /// Hours from local midnight in every time zone.
+    const T0: f64 = 1_700_000_000.0;
+
+    fn heartbeat(entity: &str, time: f64) -> Heartbeat {
+        Heartbeat::new(
+            Entity::new(entity),
+            Category::Coding,
+            FocusEvent::new(entity.to_string(), None, None),
+        )
+        .at(time)
+    }
+
+    #[test]
+    fn test_today_total_skips_long_gaps() {
+        let state = BarState::new();
+        state.record_heartbeat(&heartbeat("code", T0));
+        state.record_heartbeat(&heartbeat("code", T0 + 120.0));
+        // Idle for longer than the timeout
+        state.record_heartbeat(&heartbeat("firefox", T0 + 1200.0));
+        state.record_heartbeat(&heartbeat("firefox", T0 + 1260.0));
+        // A late retry doesn't count twice
+        state.record_heartbeat(&heartbeat("code", T0 + 60.0));
+
+        let tally = state.lock();
+        assert!((tally.today_seconds - 180.0).abs() < f64::EPSILON);
+        assert_eq!(tally.last.as_ref().unwrap().entity, "firefox");
+    }
+
+    #[test]
+    fn test_time_per_workspace() {
+        let on = |workspace: &str, time: f64| Heartbeat {
+            source: FocusEvent::new("code".to_string(), None, None)
+                .with_workspace(Some(workspace.to_string())),
+            ..heartbeat("code", time)
+        };
+        let state = BarState::new();
+        let now = unix_time_now();
+        state.record_heartbeat(&on("1", now - 300.0));
+        state.record_heartbeat(&on("2", now - 240.0));
+        state.record_heartbeat(&on("2", now - 120.0));
+        state.record_heartbeat(&heartbeat("code", now - 60.0));
+        state.record_heartbeat(&on("1", now));
+
+        assert_eq!(
+            state.workspace_seconds(),
+            BTreeMap::from([("1".to_string(), 60), ("2".to_string(), 180)])
+        );
+    }
+
+    #[test]
+    fn test_time_per_app() {
+        let titled = |app: &str, title: &str, time: f64| Heartbeat {
+            source: FocusEvent::new(app.to_string(), Some(title.to_string()), None),
+            ..heartbeat(title, time)
+        };
+        let state = BarState::new();
+        let now = unix_time_now();
+        state.record_heartbeat(&titled("firefox", "docs", now - 300.0));
+        state.record_heartbeat(&titled("firefox", "issue", now - 240.0));
+        state.record_heartbeat(&titled("firefox", "docs", now - 120.0));
+        state.record_heartbeat(&heartbeat("code", now - 60.0));
+        state.record_heartbeat(&heartbeat("code", now));
+
+        let totals = state.app_totals();
+        let apps: Vec<_> = totals
+            .iter()
+            .map(|total| (total.app.as_str(), total.seconds))
+            .collect();
+        assert_eq!(apps, [("firefox", 240), ("code", 60)]);
+        let entities: Vec<_> = totals[0]
+            .entities
+            .iter()
+            .map(|total| (total.entity.as_str(), total.seconds))
+            .collect();
+        assert_eq!(entities, [("docs", 120), ("issue", 120)]);
+        // An app without titles has nothing to break down
+        assert!(totals[1].entities.is_empty());
+    }
+
+    #[test]
+    fn test_gap_is_not_counted() {
+        let state = BarState::new();
+        state.record_heartbeat(&heartbeat("code", T0));
+        state.record_heartbeat(&heartbeat("code", T0 + 60.0));
+        state.record_gap();
+        state.record_heartbeat(&heartbeat("code", T0 + 300.0));
+
+        assert!((state.lock().today_seconds - 60.0).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn test_status_reports_current_entity() {
+        let state = BarState::new();
+        let now = unix_time_now();
+        state.record_heartbeat(&heartbeat("code", now - ACTIVITY_TIMEOUT_SECONDS - 60.0));
+        assert_eq!(state.status(false).entity, None);
+
+        state.record_heartbeat(&heartbeat("firefox", now));
+        let status = state.status(true);
+        assert_eq!(status.entity.as_deref(), Some("firefox"));
+        assert_eq!(status.category.as_deref(), Some("coding"));
+        assert!(status.paused);
+    }
+
+    #[test]
+    fn test_health_follows_flushes() {
+        let state = BarState::new();
+        assert_eq!(state.status(false).health, SendHealth::Ok);
+        state.record_flush(false);
+        assert_eq!(state.status(true).health, SendHealth::Queued);
+        state.record_flush(true);
+        assert_eq!(state.status(false).health, SendHealth::Ok);
+    }
+}
@@ -0,0 +1,373 @@
+//! Browser companion integration.
+//!
+//! An optional browser extension reports the active tab over the control
+//! socket (see [`crate::control`]). Reports are kept in [`BrowserTabs`] and
+//! merged into the focus stream by [`BrowserAwareSource`]: while a configured
+//! browser class is focused, its focus events carry the tab URL, so browser
+//! time is attributed to the website rather than to the browser as a whole.
+//! Tab reports received while another app is focused are remembered but
+//! never produce heartbeats on their own.
+//!
+//! Without the extension, `browser_title_patterns` can pick the site out of
+//! the window title instead. `browser_mode` decides whether browser time goes
+//! to the app, the domain, or the page URL.
+
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::PoisonError;
+
+use futures_util::future::BoxFuture;
+use serde::Deserialize;
+use serde::Serialize;
+use tokio::sync::Notify;
+use tracing::debug;
+
+use crate::backend::FocusError;
+use crate::backend::FocusEvent;
+use crate::backend::FocusSource;
+use crate::backend::MergedSource;
+use crate::redact::sensitive;
+
+/// What browser heartbeats are attributed to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BrowserMode {
+    /// The browser app, like any other app.
+    App,
+    /// The website's domain (default).
+    #[default]
+    Domain,
+    /// The page URL, without query string or fragment.
+    Url,
+}
+
+/// Latest active-tab report from the browser companion.
+#[derive(Debug, Default)]
+pub struct BrowserTabs {
+    /// URL of the active tab, or `None` if cleared.
+    url: Mutex<Option<String>>,
+
+    /// Signalled whenever the active tab changes.
+    changed: Notify,
+}
+
+impl BrowserTabs {
+    /// Create an empty tab state.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record the active tab URL (`None` clears it, e.g. on a private window).
+    pub fn report(&self, url: Option<String>) {
+        let mut current = self.url.lock().unwrap_or_else(PoisonError::into_inner);
+        if *current == url {
+            return;
+        }
+        debug!("Browser tab changed: {:?}", sensitive(&url));
+        *current = url;
+        drop(current);
+        self.changed.notify_one();
+    }
+
+    /// The currently reported tab URL.
+    #[must_use]
+    pub fn current(&self) -> Option<String> {
+        self.url
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// Wait until the reported tab changes.
+    async fn changed(&self) {
+        self.changed.notified().await;
+    }
+}
+
+/// Extract the host of a URL (without port or user info).
+///
+/// Returns `None` for URLs without a host, such as `about:blank` or `file://`.
+#[must_use]
+pub fn url_domain(url: &str) -> Option<String> {
+    let (_, rest) = url.split_once("://")?;
+    let authority = rest.split(['/', '?', '#']).next()?;
+    let host_port = authority.rsplit_once('@').map_or(authority, |(_, h)| h);
+    let host = if host_port.starts_with('[') {
+        // IPv6 literal: keep the brackets, drop the port
+        host_port.split_inclusive(']').next()?
+    } else {
+        host_port.split(':').next()?
+    };
+
+    if host.is_empty() {
+        None
+    } else {
+        Some(host.to_ascii_lowercase())
+    }
+}
+
+/// The page a URL points to, without user info, query string, or fragment.
+///
+/// Returns `None` for URLs without a host, like [`url_domain`].
+#[must_use]
+pub fn url_page(url: &str) -> Option<String> {
+    let domain = url_domain(url)?;
+    let (scheme, rest) = url.split_once("://")?;
+    let rest = rest.split(['?', '#']).next().unwrap_or_default();
+    let (authority, path) = rest.split_at(rest.find('/').unwrap_or(rest.len()));
+    let port = authority
+        .rsplit_once('@')
+        .map_or(authority, |(_, host)| host)
+        .rsplit_once(':')
+        .filter(|(_, port)| port.chars().all(|c| c.is_ascii_digit()))
+        .map(|(_, port)| format!(":{port}"))
+        .unwrap_or_default();
+    let path = if path.is_empty() { "/" } else { path };
+    Some(format!(
+        "{}://{domain}{port}{path}",
+        scheme.to_ascii_lowercase()
+    ))
+}
+
+/// A bare domain shown in a window title, like `github.com`.
+#[must_use]
+pub fn bare_domain(text: &str) -> Option<String> {
+    let text = text.trim().trim_end_matches('/');
+    let valid = text.contains('.')
+        && !text.starts_with('.')
+        && text
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '-');
+    valid.then(|| text.to_ascii_lowercase())
+}
+
+/// A [`FocusSource`] wrapper that attaches browser tab URLs to focus events.
+pub struct BrowserAwareSource {
+    /// The wrapped source, read in a task of its own: a tab report winning
+    /// the race must not cancel a read that is halfway done.
+    inner: MergedSource,
+    tabs: Arc<BrowserTabs>,
+    browser_classes: Vec<String>,
+    /// Most recent event emitted, re-emitted when the tab changes.
+    last_event: Option<FocusEvent>,
+}
+
+impl BrowserAwareSource {
+    /// Wrap `inner`, treating `browser_classes` (case-insensitive) as browsers.
+    #[must_use]
+    pub fn new(
+        inner: Box<dyn FocusSource>,
+        tabs: Arc<BrowserTabs>,
+        browser_classes: &[String],
+    ) -> Self {
+        Self {
+            inner: MergedSource::new(vec![inner]),
+            tabs,
+            browser_classes: browser_classes.to_vec(),
+            last_event: None,
+        }
+    }
+
+    fn is_browser(&self, app_class: &str) -> bool {
+        self.browser_classes
+            .iter()
+            .any(|b| b.eq_ignore_ascii_case(app_class))
+    }
+
+    /// Attach the current tab URL if the event is for a browser.
+    fn with_tab(&self, event: FocusEvent) -> FocusEvent {
+        if self.is_browser(&event.app_class) {
+            let url = self.tabs.current();
+            event.with_url(url)
+        } else {
+            event
+        }
+    }
+}
+
+impl FocusSource for BrowserAwareSource {
+    fn next_event(&mut self) -> BoxFuture<'_, Result<FocusEvent, FocusError>> {
+        Box::pin(async move {
+            loop {
+                tokio::select! {
+                    event = self.inner.next_event() => {
+                        let event = self.with_tab(event?);
+                        self.last_event = Some(event.clone());
+                        return Ok(event);
+                    }
+                    () = self.tabs.changed() => {
+                        // Skip stale notifications for a tab already attached
+                        if let Some(ref last) = self.last_event
+                            && self.is_browser(&last.app_class)
+                            && last.url != self.tabs.current()
+                        {
+                            let event = self.with_tab(last.clone());
+                            self.last_event = Some(event.clone());
+                            return Ok(event);
+                        }
+                    }
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::sync::mpsc;
+
+    use super::*;
+    use crate::backend::ChannelSource;
+
+    fn event(class: &str) -> FocusEvent {
+        FocusEvent::new(class.to_string(), None, None)
+    }
+
+    fn wrapped() -> (
+        BrowserAwareSource,
+        mpsc::Sender<FocusEvent>,
+        Arc<BrowserTabs>,
+    ) {
+        let (tx, rx) = mpsc::channel(8);
+        let tabs = Arc::new(BrowserTabs::new());
+        let source = BrowserAwareSource::new(
+            Box::new(ChannelSource(rx)),
+            Arc::clone(&tabs),
+            &["firefox".to_string()],
+        );
+        (source, tx, tabs)
+    }
+
+    /// Source that takes a class, then waits for the gate before returning
+    /// its event.
+    struct GatedSource {
+        events: mpsc::Receiver<FocusEvent>,
+        gate: mpsc::Receiver<()>,
+    }
+
+    impl FocusSource for GatedSource {
+        fn next_event(&mut self) -> BoxFuture<'_, Result<FocusEvent, FocusError>> {
+            Box::pin(async move {
+                let event = self.events.recv().await.ok_or(FocusError::Exhausted)?;
+                self.gate.recv().await.ok_or(FocusError::Exhausted)?;
+                Ok(event)
+            })
+        }
+    }
+
+    #[test]
+    fn test_url_page() {
+        assert_eq!(
+            url_page("https://user:pw@GitHub.com/acme/widgets?tab=readme#top").as_deref(),
+            Some("https://github.com/acme/widgets")
+        );
+        assert_eq!(
+            url_page("http://localhost:8080").as_deref(),
+            Some("http://localhost:8080/")
+        );
+        assert_eq!(url_page("about:blank"), None);
+    }
+
+    #[test]
+    fn test_bare_domain() {
+        assert_eq!(bare_domain(" GitHub.com ").as_deref(), Some("github.com"));
+        assert_eq!(bare_domain("docs.rs/").as_deref(), Some("docs.rs"));
+        assert_eq!(bare_domain("New Tab"), None);
+        assert_eq!(bare_domain("localhost"), None);
+    }
+
+    #[test]
+    fn test_url_domain() {
+        assert_eq!(
+            url_domain("https://github.com/rust-lang/rust").as_deref(),
+            Some("github.com")
+        );
+        assert_eq!(
+            url_domain("http://user:pw@Example.COM:8080/x?y#z").as_deref(),
+            Some("example.com")
+        );
+        assert_eq!(url_domain("http://[::1]:3000/").as_deref(), Some("[::1]"));
+        assert_eq!(url_domain("https://docs.rs").as_deref(), Some("docs.rs"));
+        assert_eq!(url_domain("about:blank"), None);
+        assert_eq!(url_domain("file:///home/user/index.html"), None);
+    }
+
+    #[tokio::test]
+    async fn test_url_attached_only_to_browser_events() {
+        let (mut source, tx, tabs) = wrapped();
+        tabs.report(Some("https://github.com/".to_string()));
+
+        tx.send(event("firefox")).await.unwrap();
+        tx.send(event("code")).await.unwrap();
+
+        let browser = source.next_event().await.unwrap();
+        assert_eq!(browser.url.as_deref(), Some("https://github.com/"));
+        let editor = source.next_event().await.unwrap();
+        assert!(editor.url.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_tab_change_reemits_focused_browser() {
+        let (mut source, tx, tabs) = wrapped();
+        tx.send(event("firefox")).await.unwrap();
+        let first = source.next_event().await.unwrap();
+        assert!(first.url.is_none());
+
+        tabs.report(Some("https://docs.rs/".to_string()));
+        let updated = source.next_event().await.unwrap();
+        assert_eq!(updated.app_class, "firefox");
+        assert_eq!(updated.url.as_deref(), Some("https://docs.rs/"));
+    }
+
+    #[tokio::test]
+    async fn test_tab_change_keeps_inner_read_in_progress() {
+        let (tx, events) = mpsc::channel(8);
+        let (open, gate) = mpsc::channel(8);
+        let tabs = Arc::new(BrowserTabs::new());
+        let mut source = BrowserAwareSource::new(
+            Box::new(GatedSource { events, gate }),
+            Arc::clone(&tabs),
+            &["firefox".to_string()],
+        );
+        let wait = Duration::from_millis(100);
+        tx.send(event("firefox")).await.unwrap();
+        open.send(()).await.unwrap();
+        source.next_event().await.unwrap();
+
+        // The inner source has taken "code" and waits at the gate
+        tx.send(event("code")).await.unwrap();
+        assert!(
+            tokio::time::timeout(wait, source.next_event())
+                .await
+                .is_err()
+        );
+        tabs.report(Some("https://docs.rs/".to_string()));
+        let updated = source.next_event().await.unwrap();
+        assert_eq!(updated.app_class, "firefox");
+
+        open.send(()).await.unwrap();
+        let next = tokio::time::timeout(wait, source.next_event())
+            .await
+            .expect("the read in progress was lost")
+            .unwrap();
+        assert_eq!(next.app_class, "code");
+    }
+
+    #[tokio::test]
+    async fn test_tab_change_ignored_when_browser_not_focused() {
+        let (mut source, tx, tabs) = wrapped();
+        tx.send(event("code")).await.unwrap();
+        source.next_event().await.unwrap();
+
+        tabs.report(Some("https://docs.rs/".to_string()));
+        tx.send(event("kitty")).await.unwrap();
+
+        // The tab report produced nothing; the next event is the real one
+        let next = source.next_event().await.unwrap();
+        assert_eq!(next.app_class, "kitty");
+        assert!(next.url.is_none());
+    }
+}
@@ -1,25 +1,218 @@
 //! Configuration loading and defaults for wakatime-focusd.
 
+use std::collections::BTreeMap;
+use std::io;
 use std::path::Path;
 use std::path::PathBuf;
 
-use anyhow::Context;
-use anyhow::Result;
+use regex::RegexBuilder;
 use serde::Deserialize;
 use serde::Serialize;
+use serde::de::IntoDeserializer;
+use thiserror::Error;
+use toml_edit::DocumentMut;
+use toml_edit::Item;
+use toml_edit::Key;
+use tracing::warn;
 
 use crate::backend::Backend;
+use crate::browser::BrowserMode;
 use crate::domain::Category;
+use crate::idle::IdleBackend;
+use crate::repo::ProjectNaming;
+use crate::screencast::ScreenShareMode;
+use crate::timezone::Zone;
+use crate::vacation::Date;
 
-/// Title handling strategy when `track_titles` is enabled.
-#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+/// Errors loading or checking a configuration.
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    /// The config file could not be read.
+    #[error("Failed to read config file: {}", path.display())]
+    Read {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+
+    /// The config file is not valid TOML or has invalid values.
+    #[error("Failed to parse config file: {}", path.display())]
+    Parse {
+        path: PathBuf,
+        #[source]
+        source: toml::de::Error,
+    },
+
+    /// The config could not be written out as TOML.
+    #[error("Failed to serialize config")]
+    Serialize(#[from] toml::ser::Error),
+
+    /// A category rule or meeting pattern is not a valid regex.
+    #[error("Invalid regex '{pattern}'")]
+    InvalidRegex {
+        pattern: String,
+        #[source]
+        source: regex::Error,
+    },
+
+    /// A `[[destinations]]` name can't be used in a file name.
+    #[error("Destination name {0:?} must be letters, digits, '-' or '_'")]
+    InvalidDestinationName(String),
+
+    /// Two `[[destinations]]` share a name.
+    #[error("Destination name {0:?} is used more than once")]
+    DuplicateDestination(String),
+
+    /// `startup_grace_seconds` is over [`MAX_STARTUP_GRACE_SECONDS`].
+    #[error("startup_grace_seconds = {0} is over the maximum of {MAX_STARTUP_GRACE_SECONDS}")]
+    StartupGraceTooLong(u64),
+
+    /// A project rule has no pattern to match.
+    #[error("Project rule needs an app_class, title, or workspace pattern")]
+    EmptyProjectRule,
+}
+
+/// How much of an app's window title goes into its heartbeat entity.
+///
+/// Titles are appended to the class as `"Class — Title"`.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
 #[serde(rename_all = "lowercase")]
-pub enum TitleStrategy {
-    /// Ignore window titles entirely (default).
+pub enum TitlePolicy {
+    /// Send the app class only (default).
     #[default]
-    Ignore,
-    /// Append title to class: "Class — Title".
-    Append,
+    Off,
+    /// Append the title lowercased, with numbers masked.
+    Normalized,
+    /// Append the title as shown.
+    Full,
+    /// Append an unkeyed hash of the title.
+    Hashed,
+}
+
+/// How much entity detail a destination receives.
+///
+/// Each level keeps what the ones before it keep: `title` keeps app entities
+/// as built (with their title policy), `domain` also keeps website domains,
+/// and `file` also keeps editor files and page URLs, which `domain` cuts
+/// down to their domain. Other heartbeats with more detail than the
+/// level allows are sent as `app` heartbeats for the app class.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum EntityGranularity {
+    /// App classes only.
+    Class,
+    /// App classes with window titles.
+    Title,
+    /// Also website domains of browser tabs.
+    Domain,
+    /// Also files open in editors and page URLs: everything as built
+    /// (default).
+    #[default]
+    File,
+}
+
+/// A step of the `default_category` chain.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(try_from = "String", into = "String")]
+pub enum CategoryStep {
+    /// The main category of the app's desktop entry (`"desktop_entry"`).
+    DesktopEntry,
+    /// The `use_presets` packs and AI tool rules (`"presets"`).
+    Presets,
+    /// A fixed category, which ends the chain.
+    Category(Category),
+}
+
+impl TryFrom<String> for CategoryStep {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        match value.as_str() {
+            "desktop_entry" => Ok(Self::DesktopEntry),
+            "presets" => Ok(Self::Presets),
+            other => Category::deserialize(other.into_deserializer())
+                .map(Self::Category)
+                .map_err(|_: serde::de::value::Error| {
+                    format!("unknown category or step \"{other}\"")
+                }),
+        }
+    }
+}
+
+impl From<CategoryStep> for String {
+    fn from(step: CategoryStep) -> Self {
+        match step {
+            CategoryStep::DesktopEntry => "desktop_entry".to_string(),
+            CategoryStep::Presets => "presets".to_string(),
+            CategoryStep::Category(category) => category.as_str().replace(' ', "_"),
+        }
+    }
+}
+
+/// Where the category comes from for apps no `category_rules` entry
+/// matches: steps tried in order until one decides.
+///
+/// A single category, as in `default_category = "coding"`, means
+/// `["presets", "coding"]`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(from = "CategoryChainRepr", into = "Vec<CategoryStep>")]
+pub struct CategoryChain(Vec<CategoryStep>);
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum CategoryChainRepr {
+    One(CategoryStep),
+    Chain(Vec<CategoryStep>),
+}
+
+impl From<CategoryChainRepr> for CategoryChain {
+    fn from(repr: CategoryChainRepr) -> Self {
+        match repr {
+            CategoryChainRepr::One(step @ CategoryStep::Category(_)) => {
+                Self(vec![CategoryStep::Presets, step])
+            }
+            CategoryChainRepr::One(step) => Self(vec![step]),
+            CategoryChainRepr::Chain(steps) => Self(steps),
+        }
+    }
+}
+
+impl From<CategoryChain> for Vec<CategoryStep> {
+    fn from(chain: CategoryChain) -> Self {
+        chain.0
+    }
+}
+
+impl Default for CategoryChain {
+    fn default() -> Self {
+        Category::default().into()
+    }
+}
+
+impl From<Category> for CategoryChain {
+    fn from(category: Category) -> Self {
+        CategoryChainRepr::One(CategoryStep::Category(category)).into()
+    }
+}
+
+impl CategoryChain {
+    /// The steps, in order.
+    #[must_use]
+    pub fn steps(&self) -> &[CategoryStep] {
+        &self.0
+    }
+
+    /// The fixed category ending the chain (default: coding).
+    #[must_use]
+    pub fn fallback(&self) -> Category {
+        self.0
+            .iter()
+            .find_map(|step| match step {
+                CategoryStep::Category(category) => Some(*category),
+                _ => None,
+            })
+            .unwrap_or_default()
+    }
 }
 
 /// Category rule for pattern-based category assignment.
@@ -35,31 +228,249 @@ pub struct CategoryRule {
     pub category: Category,
 }
 
+/// Project rule for pattern-based project assignment.
+///
+/// Each pattern is a case-insensitive regex matching anywhere in its field.
+/// A rule applies when all of its patterns match; a rule without any
+/// pattern never applies.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProjectRule {
+    /// Regex pattern to match `app_class`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub app_class: Option<String>,
+    /// Regex pattern to match the window title.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    /// Regex pattern to match the workspace name.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub workspace: Option<String>,
+    /// Project to assign when the patterns match.
+    pub project: String,
+}
+
+/// Per-app settings, keyed by app class in `[apps."<class>"]` tables.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(default)]
+pub struct AppConfig {
+    /// Skip heartbeats for this app because its own `WakaTime` plugin
+    /// already reports them (default: true for editors with known plugins).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub defer_to_plugin: Option<bool>,
+    /// Title policy for this app, overriding the global `title_policy`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title_policy: Option<TitlePolicy>,
+    /// Language reported for this app when none is derived from its title.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+}
+
+/// Fleet mode settings, in the `[fleet]` table.
+///
+/// A machine either serves (`listen`), sending heartbeats for the machines in
+/// `clients`, or forwards its own heartbeats to a `server`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(default)]
+pub struct FleetConfig {
+    /// Address to accept forwarded heartbeats on, e.g. `"127.0.0.1:9750"`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub listen: Option<String>,
+    /// Tokens of the machines allowed to forward, keyed by machine name.
+    #[serde(skip_serializing)]
+    pub clients: BTreeMap<String, String>,
+    /// Fleet server to forward heartbeats to instead of the `WakaTime` API.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub server: Option<String>,
+    /// Token this machine authenticates to the server with.
+    #[serde(skip_serializing)]
+    pub token: Option<String>,
+    /// Machine name reported to the server (default: hostname).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub machine: Option<String>,
+    /// On the server, send heartbeats only from the machine in use.
+    pub handoff: bool,
+    /// Allow a `listen` address other than loopback, even though tokens and
+    /// heartbeats travel unencrypted.
+    pub insecure_listen: bool,
+}
+
+/// An extra server that receives a copy of every heartbeat, in a
+/// `[[destinations]]` table.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Destination {
+    /// Name used in logs, status, and the queue file name.
+    pub name: String,
+    /// API base URL, e.g. `"https://wakapi.example.com/api"`.
+    pub api_url: String,
+    /// API key for this server.
+    #[serde(skip_serializing)]
+    pub api_key: String,
+    /// Entity detail this server receives (default: everything).
+    #[serde(default)]
+    pub entity_granularity: EntityGranularity,
+}
+
+/// Default app classes treated as web browsers.
+fn default_browser_classes() -> Vec<String> {
+    [
+        "firefox",
+        "chromium",
+        "google-chrome",
+        "brave-browser",
+        "zen",
+        "librewolf",
+    ]
+    .into_iter()
+    .map(String::from)
+    .collect()
+}
+
+/// Default app classes treated as editors without a `WakaTime` plugin.
+fn default_editor_classes() -> Vec<String> {
+    [
+        "dev.zed.Zed",
+        "sublime_text",
+        "org.kde.kate",
+        "org.kde.kwrite",
+        "org.gnome.TextEditor",
+        "gedit",
+        "geany",
+        "mousepad",
+        "lapce",
+    ]
+    .into_iter()
+    .map(String::from)
+    .collect()
+}
+
+/// Default commands identifying AI coding tools in terminal titles.
+fn default_ai_terminal_commands() -> Vec<String> {
+    ["claude", "aider", "codex", "gemini", "opencode", "goose"]
+        .into_iter()
+        .map(String::from)
+        .collect()
+}
+
+/// Default patterns identifying video-call windows.
+fn default_meeting_patterns() -> Vec<String> {
+    [
+        r"^zoom meeting|^zoom webinar",
+        r"^meet - [a-z]{3}-[a-z]{4}-[a-z]{3}|meet\.google\.com/[a-z]{3}-",
+        r"jitsi meet|meet\.jit\.si/",
+        r"^(meeting|call) .*\| microsoft teams",
+    ]
+    .into_iter()
+    .map(String::from)
+    .collect()
+}
+
+/// Default app classes treated as terminal emulators.
+fn default_terminal_classes() -> Vec<String> {
+    [
+        "kitty",
+        "alacritty",
+        "foot",
+        "org.wezfurlong.wezterm",
+        "com.mitchellh.ghostty",
+        "org.kde.konsole",
+        "org.gnome.Console",
+        "gnome-terminal-server",
+        "xterm",
+    ]
+    .into_iter()
+    .map(String::from)
+    .collect()
+}
+
 /// Main configuration for wakatime-focusd.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(default)]
+#[allow(clippy::struct_excessive_bools)]
 pub struct Config {
+    /// Config file format version (default: [`CONFIG_VERSION`]).
+    pub config_version: u32,
+
     /// Which backend to use for focus detection (default: auto).
     pub backend: Backend,
 
+    /// Backends auto-detection tries first, in order (default: none).
+    pub backend_priority: Vec<Backend>,
+
+    /// Hyprland instance to connect to: a signature or `"most-recent"`
+    /// (default: `$HYPRLAND_INSTANCE_SIGNATURE`).
+    pub hyprland_instance: Option<String>,
+
+    /// Backends to run alongside `backend`, merging their focus events
+    /// (default: none).
+    pub merge_backends: Vec<Backend>,
+
     /// Interval between heartbeats in seconds (default: 120).
     pub heartbeat_interval_seconds: u64,
 
     /// Minimum seconds before resending heartbeat for same entity (default: 120).
     pub min_entity_resend_seconds: u64,
 
-    /// Whether to include window titles in tracking (default: false).
-    pub track_titles: bool,
+    /// Upper bound in seconds for the random delay of the first periodic
+    /// heartbeat after a resume (default: 15).
+    pub periodic_jitter_seconds: u64,
+
+    /// Adapt the periodic heartbeat interval to focus behavior (default: false).
+    pub adaptive_interval: bool,
+
+    /// Lower bound for the adaptive heartbeat interval in seconds (default: 30).
+    pub adaptive_min_interval_seconds: u64,
+
+    /// Upper bound for the adaptive heartbeat interval in seconds (default: 300).
+    pub adaptive_max_interval_seconds: u64,
+
+    /// Send a final heartbeat for the previous entity when focus leaves it
+    /// (default: false).
+    pub send_leave_heartbeats: bool,
+
+    /// Send a heartbeat right away when focus moves between windows of the
+    /// same entity (default: false).
+    pub distinguish_windows: bool,
+
+    /// Only send periodic heartbeats if there was activity since the last
+    /// heartbeat (default: false).
+    pub periodic_requires_activity: bool,
+
+    /// Drop a focus event identical to the previous one within this many
+    /// milliseconds (default: 250, 0 = off).
+    pub focus_dedup_window_ms: u64,
+
+    /// How much of window titles to include in entities, unless overridden
+    /// per app (default: "off").
+    pub title_policy: TitlePolicy,
+
+    /// Hide window titles, URLs, and entities from logs (default: false).
+    pub redact_logs: bool,
+
+    /// Maximum window title length in characters; longer titles are
+    /// truncated with an ellipsis (default: 200, 0 = unlimited).
+    pub max_title_length: usize,
 
-    /// How to handle titles when `track_titles` is true.
-    pub title_strategy: TitleStrategy,
+    /// Category for apps that don't match any rule, or a chain of steps
+    /// to try in order (default: "coding", i.e. presets, then coding).
+    pub default_category: CategoryChain,
 
-    /// Default category for apps that don't match any rule (default: "coding").
-    pub default_category: Category,
+    /// Categories by exact app class (case-insensitive), checked before
+    /// `category_rules`.
+    pub categories: BTreeMap<String, Category>,
 
     /// Category rules evaluated in order (first match wins).
     pub category_rules: Vec<CategoryRule>,
 
+    /// Built-in rule presets evaluated after `category_rules`
+    /// (e.g. `["default"]`).
+    pub use_presets: Vec<String>,
+
+    /// Categorize AI coding tools as "ai coding" (default: true).
+    pub detect_ai_tools: bool,
+
+    /// Commands that mark a terminal as running an AI coding tool when its
+    /// title shows one as the running command.
+    pub ai_terminal_commands: Vec<String>,
+
     /// Optional allowlist of app classes to track.
     /// If set, only these classes generate heartbeats.
     pub app_allowlist: Option<Vec<String>>,
@@ -68,10 +479,95 @@ pub struct Config {
     /// Always excluded even if in allowlist.
     pub app_denylist: Option<Vec<String>>,
 
+    /// Count time in apps excluded by `app_allowlist` or `app_denylist` as
+    /// an anonymous "Hidden" entity, without their class or title
+    /// (default: false).
+    pub track_hidden_apps: bool,
+
+    /// App classes treated as web browsers by the browser companion.
+    pub browser_classes: Vec<String>,
+
+    /// What browser heartbeats are attributed to (default: "domain").
+    pub browser_mode: BrowserMode,
+
+    /// Regexes picking the site out of window titles, by browser app class.
+    /// The first capture group is a URL or a domain.
+    pub browser_title_patterns: BTreeMap<String, String>,
+
+    /// Derive the project and language from terminal window titles
+    /// (default: false).
+    pub parse_terminal_titles: bool,
+
+    /// App classes treated as terminal emulators for title parsing.
+    pub terminal_classes: Vec<String>,
+
+    /// How projects of git repositories are named (default: "directory").
+    pub git_project_name: ProjectNaming,
+
+    /// Send the checked-out branch of git repository projects
+    /// (default: true).
+    pub git_branch: bool,
+
+    /// Projects by workspace name, for heartbeats from windows on that
+    /// workspace (Hyprland only).
+    pub workspace_projects: BTreeMap<String, String>,
+
+    /// Project rules evaluated in order (first match wins), overriding
+    /// projects derived from window titles.
+    pub project_rules: Vec<ProjectRule>,
+
+    /// Derive file, project, and language from editor window titles
+    /// (default: false).
+    pub parse_editor_titles: bool,
+
+    /// App classes treated as editors for title parsing.
+    pub editor_classes: Vec<String>,
+
+    /// Detect video calls and switch to meeting mode (default: false).
+    pub meeting_mode: bool,
+
+    /// Patterns identifying video calls (case-insensitive regexes matched
+    /// against the app class, window title, and browser tab URL).
+    pub meeting_patterns: Vec<String>,
+
+    /// Leave window titles out of heartbeats during meetings (default: false).
+    pub meeting_hide_titles: bool,
+
+    /// App classes whose media playback counts as activity while the session
+    /// is idle (but not locked).
+    pub media_apps: Vec<String>,
+
+    /// What to do while the screen is shared or recorded (default: "off").
+    pub screen_share_mode: ScreenShareMode,
+
     /// `WakaTime` API base URL (default: <https://api.wakatime.com/api>).
     /// Also read from `api_url` in `~/.wakatime.cfg` if not set here.
     pub api_url: Option<String>,
 
+    /// `WakaTime` API key. `$WAKATIME_API_KEY` takes precedence.
+    pub api_key: Option<String>,
+
+    /// Shell command printing the API key, e.g. `pass show wakatime`.
+    pub api_key_cmd: Option<String>,
+
+    /// Read the API key from the desktop keyring (default: false).
+    pub api_key_keyring: bool,
+
+    /// Extra servers that receive a copy of every heartbeat, each with its
+    /// own offline queue and retry schedule.
+    pub destinations: Vec<Destination>,
+
+    /// Entity detail the `WakaTime` API receives (default: everything).
+    pub entity_granularity: EntityGranularity,
+
+    /// Add the compositor, distribution and kernel to the User-Agent of
+    /// heartbeat requests (default: false).
+    pub report_environment: bool,
+
+    /// Encrypt the offline queues with a key kept in the desktop keyring
+    /// (default: false).
+    pub encrypt_queue: bool,
+
     /// Path to wakatime config file (`~/.wakatime.cfg`).
     /// Used to read the API key and `api_url`.
     pub wakatime_config_path: Option<PathBuf>,
@@ -79,44 +575,206 @@ pub struct Config {
     /// Dry run mode: log commands instead of executing.
     pub dry_run: bool,
 
+    /// In dry run mode, append the heartbeats that would have been sent to
+    /// this file, for `wakatime-focusd import` to send later.
+    pub dry_run_capture: Option<PathBuf>,
+
     /// Idle check interval in seconds (default: 10).
     pub idle_check_interval_seconds: u64,
+
+    /// Idle sources to try, in order, falling back to the next when one is
+    /// unavailable (default: wayland, logind, screensaver).
+    pub idle_sources: Vec<IdleBackend>,
+
+    /// Seconds without activity after which the session counts as idle,
+    /// whatever the idle source says (default: unset).
+    pub idle_timeout_seconds: Option<u64>,
+
+    /// logind session whose idle state gates heartbeats (default: detected).
+    pub logind_session: Option<String>,
+
+    /// Skip heartbeats while the session is idle or locked (default: true).
+    pub idle_gating: bool,
+
+    /// Skip heartbeats while the session is locked, even when it isn't idle
+    /// (default: true).
+    pub pause_when_locked: bool,
+
+    /// Reduce wakeups while `UPower` reports the system is on battery
+    /// (default: true).
+    pub battery_aware: bool,
+
+    /// Idle check interval in seconds while on battery (default: 60).
+    pub battery_idle_check_interval_seconds: u64,
+
+    /// Minimum seconds between heartbeat uploads while on battery
+    /// (default: 600).
+    pub battery_flush_interval_seconds: u64,
+
+    /// After the backend was disconnected for at least this many seconds,
+    /// treat the focus state from before as stale (default: 60).
+    pub backend_reset_gap_seconds: u64,
+
+    /// Restart subsystems that stop working (default: true).
+    pub watchdog: bool,
+
+    /// Reconnect the backend after this many minutes without focus events
+    /// while the session is active; 0 disables (default: 60).
+    pub watchdog_backend_silence_minutes: u64,
+
+    /// Errors of one kind logged in a row before the rest are thinned out
+    /// (default: 5).
+    pub error_log_burst: u32,
+
+    /// After the burst, log every Nth error of the kind; 0 logs none
+    /// (default: 10).
+    pub error_log_every: u32,
+
+    /// Seconds between summaries of the errors that weren't logged
+    /// (default: 300).
+    pub error_log_summary_seconds: u64,
+
+    /// Days on which no heartbeats are sent, as `YYYY-MM-DD` local dates.
+    pub holidays: Vec<Date>,
+
+    /// IANA time zone for local dates; `None` uses the system zone.
+    pub timezone: Option<Zone>,
+
+    /// Pause heartbeats while this file exists (`~` is expanded).
+    pub inhibit_file: Option<PathBuf>,
+
+    /// Pause heartbeats while the desktop's "do not disturb" mode is on
+    /// (default: false).
+    pub inhibit_on_dnd: bool,
+
+    /// Seconds after the daemon starts during which focus is followed but no
+    /// heartbeats are sent (default: 0, at most
+    /// [`MAX_STARTUP_GRACE_SECONDS`]).
+    pub startup_grace_seconds: u64,
+
+    /// Per-app settings, keyed by app class (case-insensitive).
+    pub apps: BTreeMap<String, AppConfig>,
+
+    /// Fleet mode: send heartbeats for several machines from one daemon.
+    pub fleet: FleetConfig,
 }
 
 impl Default for Config {
     fn default() -> Self {
         Self {
+            config_version: CONFIG_VERSION,
             backend: Backend::default(),
+            backend_priority: Vec::new(),
+            hyprland_instance: None,
+            merge_backends: Vec::new(),
             heartbeat_interval_seconds: 120,
             min_entity_resend_seconds: 120,
-            track_titles: false,
-            title_strategy: TitleStrategy::default(),
-            default_category: Category::default(),
+            periodic_jitter_seconds: 15,
+            adaptive_interval: false,
+            adaptive_min_interval_seconds: 30,
+            adaptive_max_interval_seconds: 300,
+            send_leave_heartbeats: false,
+            distinguish_windows: false,
+            periodic_requires_activity: false,
+            focus_dedup_window_ms: 250,
+            title_policy: TitlePolicy::default(),
+            redact_logs: false,
+            max_title_length: 200,
+            default_category: CategoryChain::default(),
+            categories: BTreeMap::new(),
             category_rules: Vec::new(),
+            use_presets: Vec::new(),
+            detect_ai_tools: true,
+            ai_terminal_commands: default_ai_terminal_commands(),
             app_allowlist: None,
             app_denylist: None,
+            track_hidden_apps: false,
+            browser_classes: default_browser_classes(),
+            browser_mode: BrowserMode::default(),
+            browser_title_patterns: BTreeMap::new(),
+            parse_terminal_titles: false,
+            terminal_classes: default_terminal_classes(),
+            git_project_name: ProjectNaming::default(),
+            git_branch: true,
+            workspace_projects: BTreeMap::new(),
+            project_rules: Vec::new(),
+            parse_editor_titles: false,
+            editor_classes: default_editor_classes(),
+            meeting_mode: false,
+            meeting_patterns: default_meeting_patterns(),
+            meeting_hide_titles: false,
+            media_apps: Vec::new(),
+            screen_share_mode: ScreenShareMode::default(),
             api_url: None,
+            api_key: None,
+            api_key_cmd: None,
+            api_key_keyring: false,
+            destinations: Vec::new(),
+            entity_granularity: EntityGranularity::default(),
+            report_environment: false,
+            encrypt_queue: false,
             wakatime_config_path: None,
             dry_run: false,
+            dry_run_capture: None,
             idle_check_interval_seconds: 10,
+            idle_sources: IdleBackend::DEFAULT_CHAIN.to_vec(),
+            idle_timeout_seconds: None,
+            logind_session: None,
+            idle_gating: true,
+            pause_when_locked: true,
+            battery_aware: true,
+            battery_idle_check_interval_seconds: 60,
+            battery_flush_interval_seconds: 600,
+            backend_reset_gap_seconds: 60,
+            watchdog: true,
+            watchdog_backend_silence_minutes: 60,
+            error_log_burst: 5,
+            error_log_every: 10,
+            error_log_summary_seconds: 300,
+            holidays: Vec::new(),
+            timezone: None,
+            inhibit_file: None,
+            inhibit_on_dnd: false,
+            startup_grace_seconds: 0,
+            apps: BTreeMap::new(),
+            fleet: FleetConfig::default(),
         }
     }
 }
 
-impl Config {
-    /// Return the default config file content with comments.
-    ///
-    /// Optional fields are commented out so the file is safe to write as-is.
-    #[must_use]
-    pub fn template() -> &'static str {
-        r#"# wakatime-focusd configuration
+/// Default config file content (see [`Config::template`]).
+const CONFIG_TEMPLATE: &str = r#"# wakatime-focusd configuration
 # Location: ~/.config/wakatime-focusd/config.toml
 
+# Config file format version, updated by `wakatime-focusd config migrate`
+config_version = 2
+
 # Backend for focus detection (default: "auto")
-# Options: auto, hyprland, sway, gnome, kde, niri, cosmic, wlr-foreign-toplevel, x11
+# Options: auto, hyprland, sway, gnome, kde (or kwin), niri, cosmic,
+# wlr-foreign-toplevel (or wlr), x11
 # "auto" detects your desktop environment automatically.
 # backend = "auto"
 
+# Backends to try first when auto-detecting, in order (default: none)
+# For setups where several compositors' variables are set, e.g. a nested
+# session. Backends whose environment is missing, or that aren't built in,
+# are skipped; the usual detection order follows.
+# backend_priority = ["sway", "hyprland"]
+
+# Hyprland instance to connect to (default: $HYPRLAND_INSTANCE_SIGNATURE)
+# With several instances running, e.g. nested sessions or several seats, set
+# an instance signature (a directory in $XDG_RUNTIME_DIR/hypr/) or
+# "most-recent" for the most recently started one. `wakatime-focusd doctor`
+# lists the instances. Like `backend`, changing it needs a restart.
+# hyprland_instance = "most-recent"
+
+# Backends to run alongside `backend`, merging their focus events (default:
+# none). For nested sessions, e.g. Sway in a Hyprland window. Every event is
+# tagged with the backend that reported it, so app_denylist entries like
+# "hyprland:sway" skip a class only when that backend reports it. Changing it
+# needs a restart.
+# merge_backends = ["sway"]
+
 # Heartbeat interval in seconds (default: 120)
 # How often to send heartbeats for the same focused app.
 heartbeat_interval_seconds = 120
@@ -125,22 +783,86 @@ heartbeat_interval_seconds = 120
 # Usually the same as heartbeat_interval_seconds.
 min_entity_resend_seconds = 120
 
-# Whether to include window titles in tracking (default: false)
+# Periodic heartbeat jitter in seconds (default: 15)
+# After a resume from suspend, the first periodic heartbeat (and the upload of
+# buffered and queued heartbeats that comes with it) waits a random delay of up
+# to this many seconds instead of firing right away. Set to 0 to disable.
+periodic_jitter_seconds = 15
+
+# Adaptive heartbeat interval (default: false)
+# Starts at heartbeat_interval_seconds, halves while the same app stays focused
+# (tighter duration accuracy) and doubles when focus keeps switching, bounded by
+# adaptive_min_interval_seconds and adaptive_max_interval_seconds.
+# When enabled, the interval also replaces min_entity_resend_seconds.
+adaptive_interval = false
+adaptive_min_interval_seconds = 30
+adaptive_max_interval_seconds = 300
+
+# Send a final heartbeat for the previous app when focus moves away (default: false)
+# Improves duration accuracy for the last minutes spent in an app before
+# switching. Skipped if the previous app's last heartbeat was under a minute ago.
+send_leave_heartbeats = false
+
+# Treat a switch between windows of the same app as a focus change (default: false)
+# Two terminals with the same title make the same entity, so switching between
+# them is normally throttled like staying put. With this, the switch sends a
+# heartbeat right away.
+distinguish_windows = false
+
+# Only send periodic heartbeats after user activity (default: false)
+# When enabled, a periodic heartbeat for the same app is sent only if there was
+# activity since the previous heartbeat: keyboard or pointer input (on
+# compositors with ext-idle-notify-v1), a focus or title change, or returning
+# from idle. Leaving an app focused without touching it no longer counts as time.
+periodic_requires_activity = false
+
+# Duplicate focus event window in milliseconds (default: 250, 0 = off)
+# Some compositors report the same focus more than once in quick succession.
+# An event with the same app, title, and window as the previous one within
+# this window is dropped.
+focus_dedup_window_ms = 250
+
+# How much of window titles to include in entities (default: "off")
+# Options: "off" | "normalized" | "full" | "hashed"
+# "full" creates entities like "Class — Title" (high cardinality warning).
+# "normalized" lowercases the title and masks numbers ("invoice #-# - mail"),
+# and "hashed" sends a hash instead of the text. The hash has no key, so common
+# titles can be guessed back from it. Override it per app with `title_policy`
+# in [apps."<class>"].
 # WARNING: Titles may contain sensitive information (file paths, URLs, etc.)
-track_titles = false
+title_policy = "off"
+
+# Hide window titles, URLs, and entities from logs (default: false)
+# Independent of what is sent to WakaTime: even with title_policy = "off",
+# debug and trace logs show titles, and they end up in journald.
+redact_logs = false
 
-# How to handle titles when track_titles is true (default: "ignore")
-# Options: "ignore" | "append"
-# "append" creates entities like "Class — Title" (high cardinality warning)
-title_strategy = "ignore"
+# Maximum window title length in characters (default: 200, 0 = unlimited)
+# Titles are Unicode-normalized, stripped of control characters, and truncated
+# with an ellipsis before they are used in entities or logs.
+max_title_length = 200
 
 # Default category for heartbeats when no rule matches (default: "coding")
 # Valid options: coding, building, indexing, debugging, browsing, running tests,
 # writing tests, manual testing, writing docs, code reviewing, communicating,
 # notes, researching, learning, designing, ai coding
 # See: https://wakatime.com/developers#heartbeats
+# Can also be a chain of steps tried in order until one decides:
+# "desktop_entry" maps the Categories of the app's .desktop file (Development,
+# WebBrowser, Chat, Graphics, Office, ...), "presets" applies use_presets and
+# AI tool detection, and a category ends the chain. A single category means
+# ["presets", category]. Run with --log-level debug to see which step decided.
+# default_category = ["desktop_entry", "presets", "coding"]
 default_category = "coding"
 
+# Categories by app class (default: none)
+# The app class must match exactly (ignoring case); no regex needed. Checked
+# before category_rules. Run with --print-events to see app classes.
+# [categories]
+# firefox = "browsing"
+# slack = "communicating"
+# "org.gnome.Nautilus" = "browsing"
+
 # Category rules - first match wins (case-insensitive regex, substring match).
 # Patterns match anywhere in the app class. Use ^...$ anchors for exact matches,
 # e.g. "^code$" matches only "code", not "unicode-input".
@@ -160,48 +882,478 @@ default_category = "coding"
 # pattern = "figma|inkscape|gimp"
 # category = "designing"
 
+# Built-in rule presets, evaluated after category_rules (default: none)
+# "default" includes every pack: ai (ai coding), ides (coding), browsers
+# (browsing), chat (communicating), design (designing). Packs can be enabled
+# individually and pinned to a version, e.g. ["browsers@1", "chat"].
+# use_presets = ["default"]
+
+# Detect AI coding tools (default: true)
+# Apps like Cursor, Claude, and ChatGPT web apps, and terminals whose title
+# shows one of ai_terminal_commands running, are categorized as "ai coding".
+# category_rules still take precedence.
+detect_ai_tools = true
+# ai_terminal_commands = ["claude", "aider", "codex", "gemini", "opencode", "goose"]
+
 # Optional: Only track these app classes (empty = track all)
 # app_allowlist = ["code", "codium", "nvim", "vim", "emacs"]
 
 # Optional: Never track these app classes
 # app_denylist = ["slack", "discord", "spotify"]
 
+# Count time in apps excluded above as an anonymous "Hidden" entity
+# (default: false). Their class and title are never sent, but your total
+# active time stays accurate.
+track_hidden_apps = false
+
+# App classes treated as web browsers by the browser companion
+# When the browser extension reports the active tab over the control socket,
+# heartbeats for these apps use the tab's domain (e.g. "github.com") instead.
+# browser_classes = ["firefox", "chromium", "google-chrome", "brave-browser", "zen", "librewolf"]
+
+# What browser time is attributed to (default: "domain")
+# Options: "app" | "domain" | "url"
+# "domain" sends the site's domain when it is known, "url" the page URL
+# without query string or fragment (or the domain when only that is known),
+# and "app" the browser like any other app.
+browser_mode = "domain"
+
+# Derive the project from terminal window titles (default: false)
+# Terminal titles like "user@host: ~/src/myproject" or "cargo test - ~/src/myproject"
+# are parsed for the working directory; the enclosing git repository (or the
+# directory name) becomes the heartbeat's project. A file the running command
+# was given, as in "nvim main.rs", sets the language. Works with any title
+# tracking setting — the title itself is never sent. On Hyprland, a title
+# without a directory falls back to the terminal process's own working
+# directory.
+parse_terminal_titles = false
+# terminal_classes = ["kitty", "alacritty", "foot", "org.wezfurlong.wezterm", "com.mitchellh.ghostty", "org.kde.konsole", "org.gnome.Console", "gnome-terminal-server", "xterm"]
+
+# How git repository projects are named (default: "directory")
+# "directory" uses the repository's directory name; "remote" uses the owner/name
+# slug of its origin remote (e.g. "acme/widgets"), so checkouts in differently
+# named directories count as one project.
+git_project_name = "directory"
+
+# Send the branch of git repository projects (default: true)
+# When a terminal's working directory, or the working directory of an editor
+# whose title names the same project, is in a git repository, heartbeats carry
+# its checked-out branch. HEAD is read again at most every 30 seconds.
+git_branch = true
+
+# Derive file, project, and language from editor window titles (default: false)
+# For editors without a WakaTime plugin, titles like "main.rs - myproject - Zed"
+# produce file heartbeats (entity "main.rs", language "Rust", project
+# "myproject"). Note that this sends file names to WakaTime.
+parse_editor_titles = false
+# editor_classes = ["dev.zed.Zed", "sublime_text", "org.kde.kate", "org.kde.kwrite", "org.gnome.TextEditor", "gedit", "geany", "mousepad", "lapce"]
+
+# Meeting mode (default: false)
+# While a video call (Zoom, Google Meet, Jitsi, Teams) is focused, heartbeats
+# are categorized as "communicating" and sent even when the session is idle,
+# since nobody types during a call. meeting_patterns are case-insensitive
+# regexes matched against the app class, window title, and browser tab URL.
+meeting_mode = false
+# meeting_patterns = ["^zoom meeting|^zoom webinar", "^meet - [a-z]{3}-[a-z]{4}-[a-z]{3}|meet\\.google\\.com/[a-z]{3}-", "jitsi meet|meet\\.jit\\.si/", "^(meeting|call) .*\\| microsoft teams"]
+# Leave window titles out of heartbeats during meetings (default: false)
+meeting_hide_titles = false
+
+# Media apps (default: none)
+# Watching a video involves no input, so the session goes idle. For these app
+# classes, heartbeats continue while idle (not while locked) as long as an MPRIS
+# player is playing in the focused window, or any player while the window is
+# fullscreen.
+# media_apps = ["mpv", "firefox"]
+
+# What to do while the screen is shared or recorded (default: "off")
+# Options: "off" | "hide_titles" | "pause"
+# "hide_titles" sends app-level heartbeats without titles, URLs, or projects;
+# "pause" stops sending heartbeats. Detection polls PipeWire with pw-dump
+# every idle_check_interval_seconds.
+screen_share_mode = "off"
+
 # WakaTime API base URL (optional)
 # Default: https://api.wakatime.com/api
 # Also read from api_url in ~/.wakatime.cfg if not set here.
 # For self-hosted Wakapi: use your instance URL (e.g. "https://wakapi.example.com/api")
 # api_url = "https://api.wakatime.com/api"
 
+# WakaTime API key (optional)
+# Checked in this order: the $WAKATIME_API_KEY environment variable, api_key,
+# the output of api_key_cmd (run with sh -c), the desktop keyring when
+# api_key_keyring is set, and finally api_key in ~/.wakatime.cfg. The command
+# runs once at startup, and again only when a reload changes it; it may take a
+# minute, e.g. for a passphrase prompt. Store the key in the keyring with:
+#   secret-tool store --label="WakaTime API key" application wakatime-focusd key api-key
+# api_key = "waka_..."
+# api_key_cmd = "pass show wakatime"
+api_key_keyring = false
+
 # Path to wakatime config file (optional, default: ~/.wakatime.cfg)
 # Used to read the API key and api_url.
-# The API key can also be set via the $WAKATIME_API_KEY environment variable.
 # wakatime_config_path = "/home/user/.wakatime.cfg"
 
+# Report the environment to WakaTime (default: false)
+# Adds the compositor and its version, the distribution, and the kernel to the
+# User-Agent of heartbeat requests, e.g.
+# "(Linux-6.11.4; Fedora Linux 41) Hyprland/0.45.2 wakatime-focusd/1.0.0".
+# WakaTime shows the compositor as the editor and the rest as the operating
+# system, which helps tell machines apart. Detected once at startup.
+report_environment = false
+
+# Encrypt the offline queues (default: false)
+# Heartbeats waiting in the offline queue files are encrypted with a key kept
+# in the desktop keyring (GNOME Keyring, KWallet, KeePassXC, ...), created there
+# on first use. The keyring must be unlocked when the daemon starts. Batches
+# queued before this was turned on are still sent.
+encrypt_queue = false
+
 # Idle check interval in seconds (default: 10)
-# How often to poll systemd-logind for idle state.
+# How often to poll systemd-logind for idle state. Idle changes normally
+# arrive as D-Bus signals, and then polling only runs every 5 minutes in case
+# one was lost; this interval applies when signals aren't available.
 idle_check_interval_seconds = 10
 
+# Idle sources to try, in order (default: ["wayland", "logind", "screensaver"])
+# The first one that works gates heartbeats; when it is unavailable or stops
+# working, the next one takes over. "logind" is systemd-logind's IdleHint and
+# LockedHint, "wayland" the compositor's ext-idle-notify-v1 protocol, and
+# "screensaver" the org.freedesktop.ScreenSaver service. Only logind reports
+# the lock state; put it first to pause on lock where the compositor also has
+# ext-idle-notify-v1. An empty list leaves only idle_timeout_seconds.
+# idle_sources = ["wayland", "logind", "screensaver"]
+
+# Idle after this many seconds without activity (default: unset)
+# logind and the screensaver follow the desktop's idle settings, which may not
+# suit time tracking. When set, the daemon also counts the session as idle once
+# it has seen no focus or title change, and the idle source no return from
+# idle, for this long. It is also when the wayland idle source reports idle,
+# which is otherwise after 300 seconds without input.
+# idle_timeout_seconds = 300
+
+# logind session to watch for idle state (default: detected)
+# Without it, the daemon uses $XDG_SESSION_ID, then the session that owns the
+# compositor's socket, then logind's guess. On a machine with several seats
+# or sessions, set the session ID from `loginctl list-sessions` if the wrong
+# one is picked.
+# logind_session = "3"
+
+# Skip heartbeats while the session is idle or locked (default: true)
+# With false, idle sources still run but every heartbeat is sent; handy for
+# finding out why heartbeats stop. `wakatime-focusd idle disable` and
+# `idle enable` switch it in the running daemon without a restart.
+idle_gating = true
+
+# Skip heartbeats while the session is locked (default: true)
+# Checked separately from idle, through logind's LockedHint and the session's
+# Lock and Unlock signals: a session can be locked without logind counting it
+# as idle. Heartbeats during a call are still sent.
+pause_when_locked = true
+
+# Battery-aware operation (default: true)
+# While UPower reports the system is on battery, idle state is polled less
+# often and buffered heartbeats are uploaded in fewer, larger batches.
+# Heartbeat timestamps are unaffected.
+battery_aware = true
+battery_idle_check_interval_seconds = 60
+battery_flush_interval_seconds = 600
+
+# Backend disconnects (default: 60)
+# When the backend comes back after being disconnected for at least this many
+# seconds (e.g. a compositor crash), the browser tab and the current entity
+# from before are forgotten, and the gap is not counted as active time.
+backend_reset_gap_seconds = 60
+
+# Self-diagnostics (default: true)
+# Once a minute, check that the backend still delivers focus events, the idle
+# monitor still polls, and heartbeats still go out, and restart whatever
+# stopped working. Incidents are listed in `wakatime-focusd service status`.
+# A panic in the backend, idle monitor, or control socket restarts it with
+# backoff either way; a panicking sender is restarted by the next check.
+watchdog = true
+# Reconnect the backend after this long without focus events while the
+# session is active (0 to disable).
+watchdog_backend_silence_minutes = 60
+
+# Repeated errors (default: 5, every 10th, summary every 300 seconds)
+# When the API, the backend connection, or idle polling keeps failing, the
+# first few errors are logged, then only every Nth; the count of the ones
+# left out is logged periodically and when things work again.
+error_log_burst = 5
+error_log_every = 10
+error_log_summary_seconds = 300
+
+# Days off (default: none)
+# No heartbeats are sent on these local dates. To pause ad hoc instead, run
+# `wakatime-focusd pause --until 2025-01-05`.
+holidays = []
+
+# Time zone for local dates (default: the system zone)
+# Holidays, `pause --until`, and the status bar's daily total go by this zone,
+# including its DST changes. Use an IANA name:
+# timezone = "Europe/Berlin"
+
+# Inhibitors (optional)
+# Pause heartbeats while a file exists, so scripts can stop tracking by
+# creating it and resume by removing it. It is checked every
+# idle_check_interval_seconds.
+# inhibit_file = "~/.cache/wakatime-focusd/paused"
+# Pause heartbeats while "do not disturb" is on. Read from the notification
+# server's Inhibited D-Bus property (KDE Plasma and others; not GNOME).
+inhibit_on_dnd = false
+# Don't send heartbeats for this many seconds after the daemon starts (default:
+# 0, at most 86400). Session restore and autostarted apps grab focus at login;
+# focus is still followed, so the window in use afterwards is reported with the
+# next periodic heartbeat. Reloads don't start the grace period again.
+startup_grace_seconds = 0
+
 # Dry run mode: log commands instead of executing (default: false)
 dry_run = false
-"#
+# Record the heartbeats a dry run would have sent, with their real timestamps,
+# to send them later with `wakatime-focusd import <FILE>`. Handy for trying
+# the daemon out for a while before anything reaches WakaTime.
+# dry_run_capture = "/home/you/wakatime-trial.jsonl"
+
+# Entity detail sent to WakaTime (default: "file")
+# Options: "class" | "title" | "domain" | "file"
+# "class" sends app classes only, "title" adds window titles (as title_policy
+# allows), "domain" adds browser tab domains, and "file" adds editor files
+# and page URLs, i.e. everything. Set it per destination too, e.g. to keep WakaTime at
+# "class" while a private Wakapi gets the details.
+# entity_granularity = "file"
+
+# Extra destinations (optional)
+# Send a copy of every heartbeat to more servers, e.g. a self-hosted Wakapi
+# next to WakaTime. Each has its own offline queue and retries on its own
+# schedule, so one being down doesn't hold up the others.
+# [[destinations]]
+# name = "wakapi"
+# api_url = "https://wakapi.example.com/api"
+# api_key = "your-wakapi-key"
+# entity_granularity = "file"
+
+# Per-app settings, keyed by app class
+# defer_to_plugin skips heartbeats for apps whose own WakaTime plugin already
+# reports them, so their time isn't counted twice. It defaults to true for
+# VS Code, VSCodium, JetBrains IDEs, Android Studio, and Neovide; set it to
+# false if you don't use the plugin there, or to true for other apps.
+# title_policy overrides the global title_policy for the app. language is sent
+# with the app's heartbeats when none is derived from the window title.
+# [apps.code]
+# defer_to_plugin = false
+# title_policy = "full"
+#
+# [apps.firefox]
+# title_policy = "hashed"
+#
+# [apps.rstudio]
+# language = "R"
+
+# Workspace projects (optional, Hyprland only)
+# Heartbeats from windows on these workspaces carry the project, unless one is
+# pinned with `project set`. To bind a workspace without editing this file,
+# run `wakatime-focusd workspace bind 3 client-x`; those bindings are saved
+# and take precedence over these.
+# [workspace_projects]
+# "3" = "client-x"
+
+# Browser title patterns (default: none)
+# Without the browser extension, pick the site out of window titles: a
+# case-insensitive regex per browser app class, whose first capture group is a
+# URL or a domain. Useful with add-ons that put the URL in the title.
+# [browser_title_patterns]
+# firefox = "^.* - (https?://\\S+) — Mozilla Firefox$"
+# chromium = "\\(([a-z0-9.-]+\\.[a-z]+)\\) - Chromium$"
+
+# Project rules - first match wins (case-insensitive regex, substring match).
+# A rule matches when all of its app_class, title, and workspace patterns
+# match. Its project replaces one derived from the window title; workspace
+# projects and `project set` still take precedence.
+# [[project_rules]]
+# title = "client-x|acme"
+# project = "client-x"
+#
+# [[project_rules]]
+# app_class = "^obsidian$"
+# workspace = "^notes$"
+# project = "notes"
+
+# Fleet mode (optional)
+# Track several machines as one timeline: one daemon sends heartbeats to
+# WakaTime for all of them, and the others forward theirs to it. There is no
+# transport encryption, so keep the connection on a trusted network, VPN, or
+# SSH tunnel.
+#
+# On the server (run the daemon, or `wakatime-focusd serve` without tracking):
+# [fleet]
+# listen = "127.0.0.1:9750"
+# clients = { laptop = "long-random-token", devbox = "another-token" }
+# Listening on anything but a loopback address, e.g. "0.0.0.0:9750" on a VPN,
+# needs this opt-in, since tokens and heartbeats travel unencrypted
+# (default: false).
+# insecure_listen = true
+# Send heartbeats only from the machine in use (default: false). The machine
+# where windows were switched last takes over; the others' heartbeats are
+# dropped until it goes five minutes without a switch. The server's own
+# heartbeats take part too, so a laptop left awake next to the desktop
+# doesn't count the same hours twice.
+# handoff = true
+#
+# On each client:
+# [fleet]
+# server = "desktop.lan:9750"
+# token = "long-random-token"
+# machine = "laptop"  # default: hostname
+"#;
+
+/// Current config file format version, written as `config_version`.
+///
+/// Files without it are version 1. Each version past 1 has a step in
+/// [`MIGRATIONS`].
+pub const CONFIG_VERSION: u32 = 2;
+
+/// Longest startup grace period; longer settings are cut down to it.
+pub const MAX_STARTUP_GRACE_SECONDS: u64 = 24 * 60 * 60;
+
+/// Steps that bring a config file up one version, starting from version 1.
+/// Each rewrites deprecated options and records what it changed.
+const MIGRATIONS: &[fn(&mut DocumentMut, &mut Vec<String>)] = &[migrate_title_policy];
+
+/// A config file brought up to [`CONFIG_VERSION`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Migration {
+    /// Version of the file before migrating.
+    pub from_version: u32,
+    /// What was rewritten, one message per deprecated option.
+    pub changes: Vec<String>,
+    /// The migrated file, with comments and formatting kept.
+    pub content: String,
+}
+
+impl Migration {
+    /// Migrate config file `content`. Content that is already current comes
+    /// back as is.
+    pub fn apply(content: &str) -> Result<Self, toml::de::Error> {
+        let table: toml::Table = toml::from_str(content)?;
+        let from_version = table
+            .get("config_version")
+            .and_then(toml::Value::as_integer)
+            .map_or(1, |version| u32::try_from(version).unwrap_or(0));
+        let mut migration = Self {
+            from_version,
+            changes: Vec::new(),
+            content: content.to_string(),
+        };
+        let Some(steps) = MIGRATIONS.get(from_version.saturating_sub(1) as usize..) else {
+            return Ok(migration);
+        };
+        if steps.is_empty() {
+            return Ok(migration);
+        }
+        // toml already accepted the content, so this only fails on syntax
+        // newer than toml_edit knows; such a file is left alone
+        let Ok(mut doc) = content.parse::<DocumentMut>() else {
+            return Ok(migration);
+        };
+        for step in steps {
+            step(&mut doc, &mut migration.changes);
+        }
+        if doc.contains_key("config_version") {
+            doc["config_version"] = toml_edit::value(i64::from(CONFIG_VERSION));
+        } else {
+            let mut key = Key::new("config_version");
+            key.leaf_decor_mut().set_prefix(
+                "\n# Config file format version, updated by `wakatime-focusd config migrate`\n",
+            );
+            doc.insert_formatted(&key, toml_edit::value(i64::from(CONFIG_VERSION)));
+        }
+        migration.content = doc.to_string();
+        Ok(migration)
+    }
+
+    /// Whether the file needs rewriting.
+    #[must_use]
+    pub fn is_needed(&self) -> bool {
+        self.from_version < CONFIG_VERSION
+    }
+}
+
+/// Version 2: `title_policy` replaced `track_titles` and `title_strategy`.
+/// An explicit `title_policy` wins over them.
+fn migrate_title_policy(doc: &mut DocumentMut, changes: &mut Vec<String>) {
+    let track_titles = doc.remove_entry("track_titles");
+    let title_strategy = doc.remove("title_strategy");
+    if track_titles.is_none() && title_strategy.is_none() {
+        return;
+    }
+    changes.push(
+        "`track_titles` and `title_strategy` are deprecated, use `title_policy` instead"
+            .to_string(),
+    );
+    let Some((old_key, track_titles)) = track_titles else {
+        return;
+    };
+    let full = track_titles.as_bool() == Some(true)
+        && title_strategy.as_ref().and_then(Item::as_str) == Some("append");
+    if full && !doc.contains_key("title_policy") {
+        // Keep the comments above the old option
+        let mut key = Key::new("title_policy");
+        *key.leaf_decor_mut() = old_key.leaf_decor().clone();
+        doc.insert_formatted(&key, toml_edit::value("full"));
+    }
+}
+
+impl Config {
+    /// Return the default config file content with comments.
+    ///
+    /// Optional fields are commented out so the file is safe to write as-is.
+    #[must_use]
+    pub fn template() -> &'static str {
+        CONFIG_TEMPLATE
     }
 
     /// Serialize the resolved config to TOML.
-    pub fn dump(&self) -> Result<String> {
-        toml::to_string_pretty(self).context("Failed to serialize config")
+    pub fn dump(&self) -> Result<String, ConfigError> {
+        Ok(toml::to_string_pretty(self)?)
     }
 
     /// Load configuration from a file path.
-    pub fn load(path: &Path) -> Result<Self> {
-        let content = std::fs::read_to_string(path)
-            .with_context(|| format!("Failed to read config file: {}", path.display()))?;
-        let config: Config = toml::from_str(&content)
-            .with_context(|| format!("Failed to parse config file: {}", path.display()))?;
-        Ok(config)
+    ///
+    /// Deprecated options are migrated in memory with a warning; `config
+    /// migrate` rewrites the file.
+    pub fn load(path: &Path) -> Result<Self, ConfigError> {
+        let content = std::fs::read_to_string(path).map_err(|source| ConfigError::Read {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        let parse_error = |source| ConfigError::Parse {
+            path: path.to_path_buf(),
+            source,
+        };
+        let migration = Migration::apply(&content).map_err(parse_error)?;
+        if migration.from_version > CONFIG_VERSION {
+            warn!(
+                "Config file version {} is newer than this build supports ({CONFIG_VERSION}); unknown options are ignored",
+                migration.from_version
+            );
+        }
+        let content = if migration.changes.is_empty() {
+            content
+        } else {
+            for change in &migration.changes {
+                warn!("{change}");
+            }
+            warn!(
+                "Run `wakatime-focusd config migrate` to update {}",
+                path.display()
+            );
+            migration.content
+        };
+        toml::from_str(&content).map_err(parse_error)
     }
 
     /// Load configuration from the default path, or return defaults if not found.
-    pub fn load_or_default(path: Option<&Path>) -> Result<Self> {
+    pub fn load_or_default(path: Option<&Path>) -> Result<Self, ConfigError> {
         if let Some(p) = path {
             return Self::load(p);
         }
@@ -216,6 +1368,50 @@ dry_run = false
 
         Ok(Self::default())
     }
+
+    /// Check settings that loading accepts but the daemon can't use.
+    ///
+    /// The daemon skips invalid regexes with a warning; this reports the
+    /// first one instead, for callers that would rather fail.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let patterns = self
+            .category_rules
+            .iter()
+            .map(|rule| &rule.pattern)
+            .chain(&self.meeting_patterns);
+        for pattern in patterns {
+            RegexBuilder::new(pattern)
+                .case_insensitive(true)
+                .build()
+                .map_err(|source| ConfigError::InvalidRegex {
+                    pattern: pattern.clone(),
+                    source,
+                })?;
+        }
+        if self.startup_grace_seconds > MAX_STARTUP_GRACE_SECONDS {
+            return Err(ConfigError::StartupGraceTooLong(self.startup_grace_seconds));
+        }
+        self.validate_destinations()
+    }
+
+    /// Destination names end up in file names, so keep them plain and
+    /// unique.
+    pub fn validate_destinations(&self) -> Result<(), ConfigError> {
+        for (i, destination) in self.destinations.iter().enumerate() {
+            let name = &destination.name;
+            if name.is_empty()
+                || !name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+            {
+                return Err(ConfigError::InvalidDestinationName(name.clone()));
+            }
+            if self.destinations[..i].iter().any(|d| d.name == *name) {
+                return Err(ConfigError::DuplicateDestination(name.clone()));
+            }
+        }
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -226,12 +1422,62 @@ mod tests {
     fn test_default_config() {
         let config = Config::default();
         assert_eq!(config.backend, Backend::Auto);
+        assert!(config.backend_priority.is_empty());
+        assert_eq!(config.hyprland_instance, None);
+        assert!(config.merge_backends.is_empty());
         assert_eq!(config.heartbeat_interval_seconds, 120);
+        assert_eq!(config.periodic_jitter_seconds, 15);
         assert_eq!(config.min_entity_resend_seconds, 120);
-        assert!(!config.track_titles);
-        assert_eq!(config.default_category, Category::Coding);
+        assert!(!config.send_leave_heartbeats);
+        assert!(!config.distinguish_windows);
+        assert!(!config.periodic_requires_activity);
+        assert_eq!(config.focus_dedup_window_ms, 250);
+        assert!(!config.parse_terminal_titles);
+        assert_eq!(config.git_project_name, ProjectNaming::Directory);
+        assert!(config.git_branch);
+        assert!(config.workspace_projects.is_empty());
+        assert!(config.project_rules.is_empty());
+        assert!(!config.parse_editor_titles);
+        assert!(!config.meeting_mode);
+        assert!(!config.meeting_hide_titles);
+        assert!(config.media_apps.is_empty());
+        assert_eq!(config.browser_mode, BrowserMode::Domain);
+        assert!(config.browser_title_patterns.is_empty());
+        assert_eq!(config.screen_share_mode, ScreenShareMode::Off);
+        assert_eq!(config.title_policy, TitlePolicy::Off);
+        assert_eq!(config.max_title_length, 200);
+        assert!(!config.redact_logs);
+        assert_eq!(config.default_category.fallback(), Category::Coding);
+        assert!(config.categories.is_empty());
         assert!(config.category_rules.is_empty());
+        assert!(config.use_presets.is_empty());
+        assert!(config.detect_ai_tools);
         assert!(!config.dry_run);
+        assert_eq!(config.dry_run_capture, None);
+        assert!(!config.report_environment);
+        assert!(!config.encrypt_queue);
+        assert_eq!(config.api_key, None);
+        assert_eq!(config.api_key_cmd, None);
+        assert!(!config.api_key_keyring);
+        assert_eq!(config.startup_grace_seconds, 0);
+        assert!(!config.track_hidden_apps);
+        assert_eq!(config.idle_sources, IdleBackend::DEFAULT_CHAIN);
+        assert_eq!(config.idle_timeout_seconds, None);
+        assert_eq!(config.logind_session, None);
+        assert!(config.idle_gating);
+        assert!(config.pause_when_locked);
+        assert!(config.battery_aware);
+        assert_eq!(config.battery_idle_check_interval_seconds, 60);
+        assert_eq!(config.battery_flush_interval_seconds, 600);
+        assert!(config.watchdog);
+        assert_eq!(config.watchdog_backend_silence_minutes, 60);
+        assert_eq!(config.error_log_burst, 5);
+        assert_eq!(config.error_log_every, 10);
+        assert_eq!(config.error_log_summary_seconds, 300);
+        assert!(config.holidays.is_empty());
+        assert!(config.timezone.is_none());
+        assert_eq!(config.inhibit_file, None);
+        assert!(!config.inhibit_on_dnd);
     }
 
     #[test]
@@ -242,14 +1488,22 @@ mod tests {
         "#;
         let config: Config = toml::from_str(toml_str).unwrap();
         assert_eq!(config.backend, Backend::Sway);
+
+        let config: Config = toml::from_str(r#"backend = "kwin""#).unwrap();
+        assert_eq!(config.backend, Backend::Kde);
+
+        let config: Config = toml::from_str(r#"backend_priority = ["wlr", "x11"]"#).unwrap();
+        assert_eq!(
+            config.backend_priority,
+            [Backend::WlrForeignToplevel, Backend::X11]
+        );
     }
 
     #[test]
     fn test_parse_toml_with_category_rules() {
         let toml_str = r#"
             heartbeat_interval_seconds = 60
-            track_titles = true
-            title_strategy = "append"
+            title_policy = "full"
             default_category = "browsing"
             app_denylist = ["spotify"]
             dry_run = true
@@ -265,9 +1519,14 @@ mod tests {
 
         let config: Config = toml::from_str(toml_str).unwrap();
         assert_eq!(config.heartbeat_interval_seconds, 60);
-        assert!(config.track_titles);
-        assert_eq!(config.title_strategy, TitleStrategy::Append);
-        assert_eq!(config.default_category, Category::Browsing);
+        assert_eq!(config.title_policy, TitlePolicy::Full);
+        assert_eq!(
+            config.default_category.steps(),
+            [
+                CategoryStep::Presets,
+                CategoryStep::Category(Category::Browsing)
+            ]
+        );
         assert_eq!(config.category_rules.len(), 2);
         assert_eq!(config.category_rules[0].pattern, "firefox|chromium");
         assert_eq!(config.category_rules[0].category, Category::Browsing);
@@ -277,6 +1536,99 @@ mod tests {
         assert_eq!(config.app_denylist, Some(vec!["spotify".to_string()]));
     }
 
+    #[test]
+    fn test_parse_project_rules() {
+        let toml_str = r#"
+            [[project_rules]]
+            title = "client-x"
+            project = "client-x"
+
+            [[project_rules]]
+            app_class = "^obsidian$"
+            workspace = "^notes$"
+            project = "notes"
+        "#;
+
+        let config: Config = toml::from_str(toml_str).unwrap();
+        assert_eq!(config.project_rules.len(), 2);
+        assert_eq!(config.project_rules[0].title.as_deref(), Some("client-x"));
+        assert_eq!(config.project_rules[0].app_class, None);
+        assert_eq!(
+            config.project_rules[1].workspace.as_deref(),
+            Some("^notes$")
+        );
+        assert_eq!(config.project_rules[1].project, "notes");
+    }
+
+    #[test]
+    fn test_error_kinds() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        assert!(matches!(Config::load(&path), Err(ConfigError::Read { .. })));
+        std::fs::write(&path, "heartbeat_interval_seconds = \"often\"").unwrap();
+        assert!(matches!(
+            Config::load(&path),
+            Err(ConfigError::Parse { .. })
+        ));
+
+        let config = Config {
+            category_rules: vec![CategoryRule {
+                pattern: "firefox(".to_string(),
+                category: Category::Browsing,
+            }],
+            ..Config::default()
+        };
+        assert!(matches!(
+            config.validate(),
+            Err(ConfigError::InvalidRegex { pattern, .. }) if pattern == "firefox("
+        ));
+        let config = Config {
+            startup_grace_seconds: u64::MAX,
+            ..Config::default()
+        };
+        assert!(matches!(
+            config.validate(),
+            Err(ConfigError::StartupGraceTooLong(u64::MAX))
+        ));
+        assert!(Config::default().validate().is_ok());
+    }
+
+    #[test]
+    fn test_parse_categories_map() {
+        let config: Config = toml::from_str(
+            r#"
+            [categories]
+            firefox = "browsing"
+            "org.gnome.Nautilus" = "ai_coding"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.categories["firefox"], Category::Browsing);
+        assert_eq!(config.categories["org.gnome.Nautilus"], Category::AiCoding);
+
+        let reparsed: Config = toml::from_str(&config.dump().unwrap()).unwrap();
+        assert_eq!(reparsed.categories, config.categories);
+        assert!(toml::from_str::<Config>("[categories]\nfirefox = \"surfing\"").is_err());
+    }
+
+    #[test]
+    fn test_parse_default_category_chain() {
+        let config: Config =
+            toml::from_str(r#"default_category = ["desktop_entry", "running_tests"]"#).unwrap();
+        assert_eq!(
+            config.default_category.steps(),
+            [
+                CategoryStep::DesktopEntry,
+                CategoryStep::Category(Category::RunningTests)
+            ]
+        );
+        assert_eq!(config.default_category.fallback(), Category::RunningTests);
+
+        let reparsed: Config = toml::from_str(&config.dump().unwrap()).unwrap();
+        assert_eq!(reparsed.default_category, config.default_category);
+        assert!(toml::from_str::<Config>(r#"default_category = ["nope"]"#).is_err());
+    }
+
     #[test]
     fn test_template_is_valid_toml() {
         let config: Config = toml::from_str(Config::template()).unwrap();
@@ -296,7 +1648,7 @@ mod tests {
         );
         assert_eq!(reloaded.backend, config.backend);
         assert_eq!(reloaded.dry_run, config.dry_run);
-        assert_eq!(reloaded.track_titles, config.track_titles);
+        assert_eq!(reloaded.title_policy, config.title_policy);
     }
 
     #[test]
@@ -313,4 +1665,49 @@ mod tests {
         assert!(reloaded.dry_run);
         assert_eq!(reloaded.heartbeat_interval_seconds, 60);
     }
+
+    fn migrated(content: &str) -> Config {
+        toml::from_str(&Migration::apply(content).unwrap().content).unwrap()
+    }
+
+    #[test]
+    fn test_track_titles_migrates_to_title_policy() {
+        let legacy = "track_titles = true\ntitle_strategy = \"append\"\n";
+        assert_eq!(migrated(legacy).title_policy, TitlePolicy::Full);
+
+        let ignored = "track_titles = true\n";
+        assert_eq!(migrated(ignored).title_policy, TitlePolicy::Off);
+
+        let explicit =
+            "track_titles = true\ntitle_strategy = \"append\"\ntitle_policy = \"hashed\"\n";
+        assert_eq!(migrated(explicit).title_policy, TitlePolicy::Hashed);
+    }
+
+    #[test]
+    fn test_migration_keeps_comments_and_stamps_version() {
+        let legacy = "# Mine\nheartbeat_interval_seconds = 60 # often\ntrack_titles = false\n\n[apps.kitty]\ntitle_policy = \"full\"\n";
+        let migration = Migration::apply(legacy).unwrap();
+        assert!(migration.is_needed());
+        assert_eq!(migration.changes.len(), 1);
+        assert!(
+            migration
+                .content
+                .starts_with("# Mine\nheartbeat_interval_seconds = 60 # often\n")
+        );
+        assert!(!migration.content.contains("track_titles"));
+
+        let current = Migration::apply(&migration.content).unwrap();
+        assert_eq!(current.from_version, CONFIG_VERSION);
+        assert!(!current.is_needed());
+        assert_eq!(current.content, migration.content);
+        let config: Config = toml::from_str(&current.content).unwrap();
+        assert_eq!(config.heartbeat_interval_seconds, 60);
+        assert_eq!(config.apps["kitty"].title_policy, Some(TitlePolicy::Full));
+
+        // A file without deprecated options only gets the version
+        let plain = Migration::apply("heartbeat_interval_seconds = 60\n").unwrap();
+        assert!(plain.is_needed());
+        assert!(plain.changes.is_empty());
+        assert!(plain.content.contains("config_version = 2"));
+    }
 }
@@ -0,0 +1,1041 @@
+//! Local control socket for querying the running daemon.
+//!
+//! The daemon listens on a Unix socket under `$XDG_RUNTIME_DIR`. The protocol
+//! is newline-delimited JSON: each request line is a [`Request`], answered by
+//! exactly one [`Response`] line. Clients such as `wakatime-focusd service
+//! status` use [`query`] to talk to it. [`Request::Tail`] is the exception:
+//! it turns the connection into a stream of [`Response::Activity`] lines,
+//! read with [`tail`].
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::fs::TryLockError;
+use std::io;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Context;
+use anyhow::Result;
+use serde::Deserialize;
+use serde::Serialize;
+use tokio::io::AsyncBufReadExt;
+use tokio::io::AsyncWriteExt;
+use tokio::io::BufReader;
+use tokio::io::Lines;
+use tokio::net::UnixListener;
+use tokio::net::UnixStream;
+use tokio::net::unix::OwnedReadHalf;
+use tokio::net::unix::OwnedWriteHalf;
+use tokio::sync::broadcast::error::RecvError;
+use tokio_util::sync::CancellationToken;
+use tracing::debug;
+use tracing::info;
+use tracing::warn;
+
+use crate::activity;
+use crate::activity::ActivityEntry;
+use crate::activity::ActivityKind;
+use crate::annotation;
+use crate::annotation::Annotation;
+use crate::api::DestinationStatus;
+use crate::bar::AppTotal;
+use crate::bar::BarState;
+use crate::bar::BarStatus;
+use crate::browser::BrowserTabs;
+use crate::fanout::Destinations;
+use crate::fleet::FleetStats;
+use crate::fleet::Handoff;
+use crate::fleet::RemoteMachine;
+use crate::idle;
+use crate::inhibit::Inhibitor;
+use crate::metrics;
+use crate::metrics::MetricsSnapshot;
+use crate::project::PinnedProject;
+use crate::project::ProjectPin;
+use crate::rules;
+use crate::rules::RuleEffect;
+use crate::rules::TemporaryRule;
+use crate::screencast::ScreenShareMonitor;
+use crate::throttle;
+use crate::throttle::BypassStatus;
+use crate::vacation::Date;
+use crate::vacation::Pause;
+use crate::vacation::PauseState;
+use crate::watchdog::Incident;
+use crate::watchdog::Subsystem;
+use crate::watchdog::Watchdog;
+use crate::workspace::WorkspaceProjects;
+
+/// Socket file name inside the runtime directory.
+const SOCKET_NAME: &str = "control.sock";
+
+/// Instance lock file name, next to the socket.
+const LOCK_NAME: &str = "daemon.lock";
+
+/// How long a client waits for the daemon to answer.
+const QUERY_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How long `--replace` waits for the running daemon to release the socket.
+const TAKEOVER_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How often `--replace` checks whether the socket was released.
+const TAKEOVER_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Return the default control socket path.
+///
+/// `$XDG_RUNTIME_DIR/wakatime-focusd/control.sock`, or `None` if there is no
+/// runtime directory.
+#[must_use]
+pub fn default_socket_path() -> Option<PathBuf> {
+    dirs::runtime_dir().map(|dir| dir.join("wakatime-focusd").join(SOCKET_NAME))
+}
+
+/// Return the default instance lock path.
+///
+/// Next to the control socket, or in the state directory if there is no
+/// runtime directory, so a second daemon is refused even then.
+#[must_use]
+pub fn default_lock_path() -> Option<PathBuf> {
+    dirs::runtime_dir()
+        .or_else(dirs::state_dir)
+        .map(|dir| dir.join("wakatime-focusd").join(LOCK_NAME))
+}
+
+/// An exclusive lock held by the running daemon, released when dropped or
+/// when the process exits.
+#[derive(Debug)]
+pub struct InstanceLock {
+    _file: File,
+}
+
+impl InstanceLock {
+    /// Take the lock at `path`, or return `None` if another process holds
+    /// it.
+    pub fn try_acquire(path: &Path) -> Result<Option<Self>> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create directory {}", parent.display()))?;
+        }
+        let file = File::options()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(path)
+            .with_context(|| format!("Failed to open lock file {}", path.display()))?;
+        match file.try_lock() {
+            Ok(()) => Ok(Some(Self { _file: file })),
+            Err(TryLockError::WouldBlock) => Ok(None),
+            Err(TryLockError::Error(e)) => {
+                Err(e).with_context(|| format!("Failed to lock {}", path.display()))
+            }
+        }
+    }
+
+    /// Take the lock at `path`, waiting up to the takeover timeout for its
+    /// holder to exit.
+    pub async fn acquire_after_takeover(path: &Path) -> Result<Self> {
+        let acquired = async {
+            loop {
+                if let Some(lock) = Self::try_acquire(path)? {
+                    return Ok(lock);
+                }
+                tokio::time::sleep(TAKEOVER_POLL_INTERVAL).await;
+            }
+        };
+        tokio::time::timeout(TAKEOVER_TIMEOUT, acquired)
+            .await
+            .context("Timed out waiting for the running daemon to exit")?
+    }
+}
+
+/// A control request.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum Request {
+    /// Report daemon status and counters.
+    Status,
+    /// Report today's total and the current entity, for status bars.
+    ///
+    /// Answered from memory, so it is cheap enough to poll every second.
+    Bar,
+    /// Report the active browser tab (from the browser companion extension).
+    BrowserTab {
+        /// Active tab URL, or `null` to clear (e.g. in a private window).
+        url: Option<String>,
+    },
+    /// Pin a project for all heartbeats until cleared or expired.
+    ProjectSet {
+        /// Project name.
+        name: String,
+        /// Seconds until the pin expires, or `null` to keep it until cleared.
+        #[serde(default)]
+        duration_seconds: Option<u64>,
+    },
+    /// Clear the pinned project.
+    ProjectClear,
+    /// Bind a workspace to a project. The binding is saved and survives
+    /// restarts.
+    WorkspaceBind {
+        /// Workspace name, as reported by the compositor.
+        workspace: String,
+        /// Project name.
+        project: String,
+    },
+    /// Remove a workspace binding made with [`Request::WorkspaceBind`].
+    WorkspaceUnbind {
+        /// Workspace name.
+        workspace: String,
+    },
+    /// Add a temporary rule for an app class.
+    RuleAdd {
+        /// App class the rule applies to.
+        app_class: String,
+        /// What the rule does.
+        effect: RuleEffect,
+        /// Seconds until the rule expires.
+        duration_seconds: u64,
+    },
+    /// Remove temporary rules.
+    RuleClear {
+        /// App class whose rules to remove, or `null` for all.
+        #[serde(default)]
+        app_class: Option<String>,
+    },
+    /// Send a heartbeat for every focus change, without throttling.
+    ThrottleBypass {
+        /// Seconds until throttling applies again, or `null` to bypass it
+        /// until restored.
+        #[serde(default)]
+        duration_seconds: Option<u64>,
+    },
+    /// Apply throttling again.
+    ThrottleRestore,
+    /// Turn idle gating on or off, overriding `idle_gating`.
+    IdleGating {
+        /// Whether idle and lock state gate heartbeats.
+        enabled: bool,
+    },
+    /// Annotate the heartbeats sent for a while, e.g. with a ticket ID.
+    AnnotationSet {
+        /// What the annotation is, e.g. `ticket`.
+        key: String,
+        /// Its value, e.g. `PROJ-123`.
+        value: String,
+        /// Seconds until the annotation expires.
+        duration_seconds: u64,
+    },
+    /// Remove annotations.
+    AnnotationClear {
+        /// Annotation to remove, or `null` for all.
+        #[serde(default)]
+        key: Option<String>,
+    },
+    /// Stop sending heartbeats until resumed or until a date.
+    Pause {
+        /// Date (`YYYY-MM-DD`) on which heartbeats resume, or `null` to pause
+        /// until resumed.
+        #[serde(default)]
+        until: Option<Date>,
+    },
+    /// End a pause.
+    Resume,
+    /// Stream focus events and heartbeat decisions, starting with the most
+    /// recent ones. The connection answers nothing else afterwards.
+    Tail {
+        /// Number of recent entries to replay first.
+        #[serde(default = "default_replay")]
+        replay: usize,
+    },
+    /// Flush buffered heartbeats and exit (used by `--replace`).
+    Shutdown,
+}
+
+fn default_replay() -> usize {
+    50
+}
+
+fn default_idle_gating() -> bool {
+    true
+}
+
+/// A control response.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "result", rename_all = "snake_case")]
+pub enum Response {
+    /// The request was applied.
+    Ok,
+    /// Answer to [`Request::Status`].
+    Status(Box<DaemonStatus>),
+    /// Answer to [`Request::Bar`].
+    Bar(BarStatus),
+    /// An entry streamed after [`Request::Tail`].
+    Activity(ActivityEntry),
+    /// The request could not be handled.
+    Error { message: String },
+}
+
+/// Daemon status as reported over the control socket.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DaemonStatus {
+    /// Daemon version.
+    pub version: String,
+    /// Process-wide counters.
+    pub metrics: MetricsSnapshot,
+    /// Project pinned with `project set`, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pinned_project: Option<PinnedProject>,
+    /// Projects bound to workspaces, by workspace name.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub workspace_projects: BTreeMap<String, String>,
+    /// Active time today per workspace, in seconds.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub workspace_seconds_today: BTreeMap<String, u64>,
+    /// Active time today per app, longest first, with a breakdown by
+    /// entity.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub app_seconds_today: Vec<AppTotal>,
+    /// Temporary rules in effect.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub temporary_rules: Vec<TemporaryRule>,
+    /// Throttle bypass in effect, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub throttle_bypass: Option<BypassStatus>,
+    /// Whether idle and lock state gate heartbeats.
+    #[serde(default = "default_idle_gating")]
+    pub idle_gating: bool,
+    /// Annotations recorded with heartbeats.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub annotations: Vec<Annotation>,
+    /// Whether a screen share was detected.
+    #[serde(default)]
+    pub screen_sharing: bool,
+    /// Machines forwarding heartbeats to this daemon in fleet mode.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub remote_machines: Vec<RemoteMachine>,
+    /// Machine in use, with `[fleet] handoff`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub active_machine: Option<String>,
+    /// Servers heartbeats are sent to, main one first.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub destinations: Vec<DestinationStatus>,
+    /// Recent watchdog restarts, oldest first.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub incidents: Vec<Incident>,
+    /// Subsystem restarts since startup, by watchdog checks or after a
+    /// panic.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub restarts: BTreeMap<Subsystem, u32>,
+    /// Recent failed sends, oldest first.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub failed_sends: Vec<ActivityEntry>,
+    /// Whether an inhibit file or "do not disturb" pauses heartbeats.
+    #[serde(default)]
+    pub inhibited: bool,
+    /// Pause set with `pause`, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub paused: Option<Pause>,
+}
+
+/// Daemon state reachable from control requests.
+#[derive(Debug, Default)]
+pub struct ControlContext {
+    /// Active tab reports from the browser companion.
+    pub browser_tabs: Arc<BrowserTabs>,
+    /// Project pinned at runtime.
+    pub project_pin: Arc<ProjectPin>,
+    /// Workspace-to-project bindings.
+    pub workspaces: Arc<WorkspaceProjects>,
+    /// Screen sharing state.
+    pub screen_share: Arc<ScreenShareMonitor>,
+    /// Fleet server counters.
+    pub fleet: Arc<FleetStats>,
+    /// Fleet handoff between machines.
+    pub handoff: Arc<Handoff>,
+    /// Delivery counters of the current sender's destinations.
+    pub destinations: Arc<Destinations>,
+    /// Subsystem health checks.
+    pub watchdog: Arc<Watchdog>,
+    /// Vacation pause.
+    pub pause: Arc<PauseState>,
+    /// Inhibit file and "do not disturb" state.
+    pub inhibitor: Arc<Inhibitor>,
+    /// Running totals for status bars.
+    pub bar: Arc<BarState>,
+    /// Cancelled to shut the daemon down.
+    pub shutdown: CancellationToken,
+}
+
+/// Control socket server.
+pub struct ControlServer {
+    listener: UnixListener,
+    path: PathBuf,
+}
+
+impl ControlServer {
+    /// Bind the control socket at `path`.
+    ///
+    /// A stale socket left behind by a crashed daemon is replaced. Fails if
+    /// another daemon is already answering on the socket.
+    pub async fn bind(path: &Path) -> Result<Self> {
+        if path.exists() {
+            if is_running(path).await {
+                anyhow::bail!(
+                    "Another wakatime-focusd instance is listening on {}",
+                    path.display()
+                );
+            }
+            debug!("Removing stale control socket at {}", path.display());
+            std::fs::remove_file(path)
+                .with_context(|| format!("Failed to remove stale socket {}", path.display()))?;
+        }
+
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create directory {}", parent.display()))?;
+        }
+
+        let listener = UnixListener::bind(path)
+            .with_context(|| format!("Failed to bind control socket {}", path.display()))?;
+
+        Ok(Self {
+            listener,
+            path: path.to_path_buf(),
+        })
+    }
+
+    /// Accept and serve connections until `shutdown` is cancelled.
+    ///
+    /// The accept loop is supervised by the watchdog, which starts it over
+    /// if it panics. The socket file is removed on shutdown.
+    pub fn serve(self, context: Arc<ControlContext>, shutdown: CancellationToken) {
+        info!("Control socket listening on {}", self.path.display());
+        let server = Arc::new(self);
+        let watchdog = Arc::clone(&context.watchdog);
+        watchdog.supervise(Subsystem::ControlSocket, shutdown, move || {
+            let server = Arc::clone(&server);
+            let context = Arc::clone(&context);
+            async move { server.accept_loop(context).await }
+        });
+    }
+
+    async fn accept_loop(&self, context: Arc<ControlContext>) {
+        loop {
+            match self.listener.accept().await {
+                Ok((stream, _)) => {
+                    let context = Arc::clone(&context);
+                    tokio::spawn(async move {
+                        if let Err(e) = handle_connection(stream, &context).await {
+                            debug!("Control connection error: {e}");
+                        }
+                    });
+                }
+                Err(e) => warn!("Failed to accept control connection: {e}"),
+            }
+        }
+    }
+}
+
+impl Drop for ControlServer {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// Serve requests on a single connection until the client hangs up.
+async fn handle_connection(stream: UnixStream, context: &ControlContext) -> io::Result<()> {
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+
+    while let Some(line) = lines.next_line().await? {
+        let response = match serde_json::from_str::<Request>(&line) {
+            Ok(Request::Tail { replay }) => {
+                return stream_activity(lines, writer, replay).await;
+            }
+            Ok(request) => handle_request(request, context),
+            Err(e) => Response::Error {
+                message: format!("Invalid request: {e}"),
+            },
+        };
+        write_response(&mut writer, &response).await?;
+    }
+
+    Ok(())
+}
+
+/// Replay recent activity, then stream new entries until the client hangs
+/// up.
+async fn stream_activity(
+    mut lines: Lines<BufReader<OwnedReadHalf>>,
+    mut writer: OwnedWriteHalf,
+    replay: usize,
+) -> io::Result<()> {
+    let (recent, mut live) = activity::global().subscribe(replay);
+    for entry in recent {
+        write_response(&mut writer, &Response::Activity(entry)).await?;
+    }
+    loop {
+        tokio::select! {
+            // Anything but more input means the client is gone
+            input = lines.next_line() => if !matches!(input, Ok(Some(_))) {
+                return Ok(());
+            },
+            entry = live.recv() => match entry {
+                Ok(entry) => write_response(&mut writer, &Response::Activity(entry)).await?,
+                Err(RecvError::Lagged(missed)) => {
+                    debug!("Tail client fell behind, skipped {missed} entries");
+                }
+                Err(RecvError::Closed) => return Ok(()),
+            },
+        }
+    }
+}
+
+async fn write_response(writer: &mut OwnedWriteHalf, response: &Response) -> io::Result<()> {
+    let mut encoded = serde_json::to_string(response).map_err(io::Error::other)?;
+    encoded.push('\n');
+    writer.write_all(encoded.as_bytes()).await
+}
+
+/// Snapshot of the daemon for [`Request::Status`].
+fn daemon_status(context: &ControlContext) -> DaemonStatus {
+    DaemonStatus {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        metrics: metrics::global().snapshot(),
+        pinned_project: context.project_pin.status(),
+        workspace_projects: context.workspaces.bindings(),
+        workspace_seconds_today: context.bar.workspace_seconds(),
+        app_seconds_today: context.bar.app_totals(),
+        temporary_rules: rules::global().list(),
+        throttle_bypass: throttle::bypass().status(),
+        idle_gating: idle::gating(),
+        annotations: annotation::global().list(),
+        screen_sharing: context.screen_share.is_sharing(),
+        remote_machines: context.fleet.snapshot(),
+        active_machine: context.handoff.active_machine(),
+        destinations: context.destinations.snapshot(),
+        incidents: context.watchdog.incidents(),
+        restarts: context.watchdog.restarts(),
+        failed_sends: activity::global().recent(ActivityKind::Failed),
+        inhibited: context.inhibitor.is_inhibited(),
+        paused: context.pause.current(),
+    }
+}
+
+/// Bind a workspace to a project.
+fn bind_workspace(workspaces: &WorkspaceProjects, workspace: String, project: String) -> Response {
+    if workspace.trim().is_empty() || project.trim().is_empty() {
+        return Response::Error {
+            message: "Workspace and project names must not be empty".to_string(),
+        };
+    }
+    match workspaces.bind(workspace, project) {
+        Ok(()) => Response::Ok,
+        Err(e) => Response::Error {
+            message: format!("{e:#}"),
+        },
+    }
+}
+
+/// Remove a runtime workspace binding.
+fn unbind_workspace(workspaces: &WorkspaceProjects, workspace: &str) -> Response {
+    match workspaces.unbind(workspace) {
+        Ok(_) if workspaces.project(workspace).is_some() => Response::Error {
+            message: format!(
+                "Workspace '{workspace}' is bound in the config file; remove it from [workspace_projects]"
+            ),
+        },
+        Ok(_) => Response::Ok,
+        Err(e) => Response::Error {
+            message: format!("{e:#}"),
+        },
+    }
+}
+
+/// Set an annotation, checking what the CLI can't.
+fn set_annotation(key: String, value: String, duration: Duration) -> Response {
+    if key.trim().is_empty() || value.trim().is_empty() {
+        return Response::Error {
+            message: "An annotation needs a key and a value".to_string(),
+        };
+    }
+    if duration.is_zero() || duration > annotation::MAX_DURATION {
+        return Response::Error {
+            message: "An annotation lasts between a second and a day".to_string(),
+        };
+    }
+    if annotation::global().set(key, value, duration) {
+        Response::Ok
+    } else {
+        Response::Error {
+            message: format!("An annotation can't last {duration:?}"),
+        }
+    }
+}
+
+/// Pause heartbeats until `until`, which must be in the future.
+fn start_pause(pause: &PauseState, until: Option<Date>) -> Response {
+    if let Some(until) = until
+        && Date::today().is_some_and(|today| until <= today)
+    {
+        return Response::Error {
+            message: format!("Pause end date {until} is not in the future"),
+        };
+    }
+    match pause.pause(Pause { until }) {
+        Ok(()) => Response::Ok,
+        Err(e) => Response::Error {
+            message: format!("{e:#}"),
+        },
+    }
+}
+
+/// Produce the response for a request.
+fn handle_request(request: Request, context: &ControlContext) -> Response {
+    match request {
+        Request::Status => Response::Status(Box::new(daemon_status(context))),
+        Request::Bar => Response::Bar(
+            context
+                .bar
+                .status(context.pause.is_paused_today() || context.inhibitor.is_inhibited()),
+        ),
+        Request::BrowserTab { url } => {
+            context.browser_tabs.report(url);
+            Response::Ok
+        }
+        Request::ProjectSet {
+            name,
+            duration_seconds,
+        } => {
+            if name.trim().is_empty() {
+                return Response::Error {
+                    message: "Project name must not be empty".to_string(),
+                };
+            }
+            context
+                .project_pin
+                .set(name, duration_seconds.map(Duration::from_secs));
+            Response::Ok
+        }
+        Request::ProjectClear => {
+            context.project_pin.clear();
+            Response::Ok
+        }
+        Request::WorkspaceBind { workspace, project } => {
+            bind_workspace(&context.workspaces, workspace, project)
+        }
+        Request::WorkspaceUnbind { workspace } => unbind_workspace(&context.workspaces, &workspace),
+        Request::RuleAdd {
+            app_class,
+            effect,
+            duration_seconds,
+        } => {
+            if app_class.trim().is_empty() || duration_seconds == 0 {
+                return Response::Error {
+                    message: "A rule needs an app class and a positive duration".to_string(),
+                };
+            }
+            if rules::global().add(app_class, effect, Duration::from_secs(duration_seconds)) {
+                Response::Ok
+            } else {
+                Response::Error {
+                    message: format!("A rule can't last {duration_seconds} seconds"),
+                }
+            }
+        }
+        Request::RuleClear { app_class } => {
+            rules::global().clear(app_class.as_deref());
+            Response::Ok
+        }
+        Request::ThrottleBypass { duration_seconds } => {
+            throttle::bypass().set(duration_seconds.map(Duration::from_secs));
+            Response::Ok
+        }
+        Request::ThrottleRestore => {
+            throttle::bypass().restore();
+            Response::Ok
+        }
+        Request::IdleGating { enabled } => {
+            idle::set_gating(enabled);
+            Response::Ok
+        }
+        Request::AnnotationSet {
+            key,
+            value,
+            duration_seconds,
+        } => set_annotation(key, value, Duration::from_secs(duration_seconds)),
+        Request::AnnotationClear { key } => {
+            annotation::global().clear(key.as_deref());
+            Response::Ok
+        }
+        Request::Pause { until } => start_pause(&context.pause, until),
+        Request::Resume => match context.pause.resume() {
+            Ok(_) => Response::Ok,
+            Err(e) => Response::Error {
+                message: format!("{e:#}"),
+            },
+        },
+        Request::Tail { .. } => Response::Error {
+            message: "Tail is handled by the connection".to_string(),
+        },
+        Request::Shutdown => {
+            info!("Shutdown requested over the control socket");
+            context.shutdown.cancel();
+            Response::Ok
+        }
+    }
+}
+
+/// Whether a daemon is answering on the socket at `path`.
+pub async fn is_running(path: &Path) -> bool {
+    UnixStream::connect(path).await.is_ok()
+}
+
+/// Ask the daemon listening on `path` to shut down, and wait until it has
+/// released the socket.
+pub async fn request_shutdown(path: &Path) -> Result<()> {
+    match query(path, &Request::Shutdown).await? {
+        Response::Ok => {}
+        Response::Error { message } => anyhow::bail!("Daemon refused to shut down: {message}"),
+        Response::Status(_) | Response::Bar(_) | Response::Activity(_) => {
+            anyhow::bail!("Unexpected response from daemon")
+        }
+    }
+
+    let released = async {
+        while is_running(path).await {
+            tokio::time::sleep(TAKEOVER_POLL_INTERVAL).await;
+        }
+    };
+    tokio::time::timeout(TAKEOVER_TIMEOUT, released)
+        .await
+        .context("Timed out waiting for the running daemon to exit")
+}
+
+/// Send a request to the daemon listening on `path` and wait for the reply.
+pub async fn query(path: &Path, request: &Request) -> Result<Response> {
+    let exchange = async {
+        let stream = UnixStream::connect(path)
+            .await
+            .with_context(|| format!("Failed to connect to {}", path.display()))?;
+        let (reader, mut writer) = stream.into_split();
+
+        let mut encoded = serde_json::to_string(request)?;
+        encoded.push('\n');
+        writer.write_all(encoded.as_bytes()).await?;
+
+        let line = BufReader::new(reader)
+            .lines()
+            .next_line()
+            .await?
+            .context("Daemon closed the connection without answering")?;
+        serde_json::from_str(&line).context("Invalid response from daemon")
+    };
+
+    tokio::time::timeout(QUERY_TIMEOUT, exchange)
+        .await
+        .context("Timed out waiting for daemon")?
+}
+
+/// Follow the activity stream of the daemon listening on `path`, replaying
+/// up to `replay` recent entries first. Returns when the daemon goes away.
+pub async fn tail(
+    path: &Path,
+    replay: usize,
+    mut on_entry: impl FnMut(ActivityEntry),
+) -> Result<()> {
+    let stream = UnixStream::connect(path)
+        .await
+        .with_context(|| format!("Failed to connect to {}", path.display()))?;
+    let (reader, mut writer) = stream.into_split();
+
+    let mut encoded = serde_json::to_string(&Request::Tail { replay })?;
+    encoded.push('\n');
+    writer.write_all(encoded.as_bytes()).await?;
+
+    let mut lines = BufReader::new(reader).lines();
+    while let Some(line) = lines.next_line().await? {
+        match serde_json::from_str(&line).context("Invalid response from daemon")? {
+            Response::Activity(entry) => on_entry(entry),
+            Response::Error { message } => anyhow::bail!("Daemon returned an error: {message}"),
+            other => anyhow::bail!("Unexpected response from daemon: {other:?}"),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::activity::ActivityKind;
+
+    #[test]
+    fn test_request_wire_format() {
+        let encoded = serde_json::to_string(&Request::Status).unwrap();
+        assert_eq!(encoded, r#"{"command":"status"}"#);
+        let encoded = serde_json::to_string(&Request::IdleGating { enabled: false }).unwrap();
+        assert_eq!(encoded, r#"{"command":"idle_gating","enabled":false}"#);
+    }
+
+    #[tokio::test]
+    async fn test_status_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("control.sock");
+        let shutdown = CancellationToken::new();
+
+        ControlServer::bind(&path)
+            .await
+            .unwrap()
+            .serve(Arc::default(), shutdown.clone());
+
+        let response = query(&path, &Request::Status).await.unwrap();
+        let Response::Status(status) = response else {
+            panic!("expected status response, got {response:?}");
+        };
+        assert_eq!(status.version, env!("CARGO_PKG_VERSION"));
+
+        shutdown.cancel();
+    }
+
+    #[tokio::test]
+    async fn test_browser_tab_updates_context() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("control.sock");
+        let shutdown = CancellationToken::new();
+        let context = Arc::new(ControlContext::default());
+        ControlServer::bind(&path)
+            .await
+            .unwrap()
+            .serve(Arc::clone(&context), shutdown.clone());
+
+        let request = Request::BrowserTab {
+            url: Some("https://github.com/".to_string()),
+        };
+        assert_eq!(query(&path, &request).await.unwrap(), Response::Ok);
+        assert_eq!(
+            context.browser_tabs.current().as_deref(),
+            Some("https://github.com/")
+        );
+
+        shutdown.cancel();
+    }
+
+    #[tokio::test]
+    async fn test_project_pin_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("control.sock");
+        let shutdown = CancellationToken::new();
+        let context = Arc::new(ControlContext::default());
+        ControlServer::bind(&path)
+            .await
+            .unwrap()
+            .serve(Arc::clone(&context), shutdown.clone());
+
+        let request = Request::ProjectSet {
+            name: "client-a".to_string(),
+            duration_seconds: Some(3600),
+        };
+        assert_eq!(query(&path, &request).await.unwrap(), Response::Ok);
+        assert_eq!(context.project_pin.current().as_deref(), Some("client-a"));
+
+        let Response::Status(status) = query(&path, &Request::Status).await.unwrap() else {
+            panic!("expected status response");
+        };
+        assert_eq!(status.pinned_project.unwrap().name, "client-a");
+
+        assert_eq!(
+            query(&path, &Request::ProjectClear).await.unwrap(),
+            Response::Ok
+        );
+        assert_eq!(context.project_pin.current(), None);
+
+        let request = Request::ProjectSet {
+            name: " ".to_string(),
+            duration_seconds: None,
+        };
+        assert!(matches!(
+            query(&path, &request).await.unwrap(),
+            Response::Error { .. }
+        ));
+
+        shutdown.cancel();
+    }
+
+    #[tokio::test]
+    async fn test_workspace_bind_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("control.sock");
+        let shutdown = CancellationToken::new();
+        let context = Arc::new(ControlContext::default());
+        context.workspaces.set_configured(&BTreeMap::from([(
+            "web".to_string(),
+            "browsing".to_string(),
+        )]));
+        ControlServer::bind(&path)
+            .await
+            .unwrap()
+            .serve(Arc::clone(&context), shutdown.clone());
+
+        let request = Request::WorkspaceBind {
+            workspace: "3".to_string(),
+            project: "client-x".to_string(),
+        };
+        assert_eq!(query(&path, &request).await.unwrap(), Response::Ok);
+
+        let Response::Status(status) = query(&path, &Request::Status).await.unwrap() else {
+            panic!("expected status response");
+        };
+        assert_eq!(
+            status.workspace_projects,
+            BTreeMap::from([
+                ("3".to_string(), "client-x".to_string()),
+                ("web".to_string(), "browsing".to_string()),
+            ])
+        );
+
+        let request = Request::WorkspaceUnbind {
+            workspace: "3".to_string(),
+        };
+        assert_eq!(query(&path, &request).await.unwrap(), Response::Ok);
+        assert_eq!(context.workspaces.project("3"), None);
+
+        // Configured bindings can't be removed at runtime
+        let request = Request::WorkspaceUnbind {
+            workspace: "web".to_string(),
+        };
+        assert!(matches!(
+            query(&path, &request).await.unwrap(),
+            Response::Error { .. }
+        ));
+
+        shutdown.cancel();
+    }
+
+    #[tokio::test]
+    async fn test_tail_replays_then_streams() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("control.sock");
+        let shutdown = CancellationToken::new();
+        ControlServer::bind(&path)
+            .await
+            .unwrap()
+            .serve(Arc::default(), shutdown.clone());
+
+        activity::record(ActivityKind::Focus, "tail test: before".to_string());
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let client = tokio::spawn(async move {
+            tail(&path, activity::REPLAY_CAPACITY, move |entry| {
+                let _ = tx.send(entry.message);
+            })
+            .await
+        });
+
+        // Other tests record into the same log, so skip their entries
+        while rx.recv().await.unwrap() != "tail test: before" {}
+        activity::record(ActivityKind::Sent, "tail test: after".to_string());
+        while rx.recv().await.unwrap() != "tail test: after" {}
+
+        client.abort();
+        shutdown.cancel();
+    }
+
+    #[tokio::test]
+    async fn test_invalid_request_returns_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("control.sock");
+        let shutdown = CancellationToken::new();
+        ControlServer::bind(&path)
+            .await
+            .unwrap()
+            .serve(Arc::default(), shutdown.clone());
+
+        let mut stream = UnixStream::connect(&path).await.unwrap();
+        stream
+            .write_all(b"{\"command\":\"bogus\"}\n")
+            .await
+            .unwrap();
+        let mut lines = BufReader::new(stream).lines();
+        let line = lines.next_line().await.unwrap().unwrap();
+        let response: Response = serde_json::from_str(&line).unwrap();
+        assert!(matches!(response, Response::Error { .. }));
+
+        shutdown.cancel();
+    }
+
+    #[tokio::test]
+    async fn test_rule_add_rejects_overlong_duration() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("control.sock");
+        let shutdown = CancellationToken::new();
+        ControlServer::bind(&path)
+            .await
+            .unwrap()
+            .serve(Arc::default(), shutdown.clone());
+
+        let request = Request::RuleAdd {
+            app_class: "overlong-rule-app".to_string(),
+            effect: RuleEffect::Deny,
+            duration_seconds: u64::MAX,
+        };
+        assert!(matches!(
+            query(&path, &request).await.unwrap(),
+            Response::Error { .. }
+        ));
+
+        shutdown.cancel();
+    }
+
+    #[tokio::test]
+    async fn test_instance_lock_is_exclusive() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("wakatime-focusd").join(LOCK_NAME);
+
+        let lock = InstanceLock::try_acquire(&path).unwrap().unwrap();
+        assert!(InstanceLock::try_acquire(&path).unwrap().is_none());
+
+        drop(lock);
+        assert!(InstanceLock::try_acquire(&path).unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_bind_replaces_stale_socket() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("control.sock");
+        // A socket file nobody is listening on
+        drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+        assert!(path.exists());
+
+        assert!(ControlServer::bind(&path).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_bind_refuses_live_socket() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("control.sock");
+        let shutdown = CancellationToken::new();
+        ControlServer::bind(&path)
+            .await
+            .unwrap()
+            .serve(Arc::default(), shutdown.clone());
+
+        assert!(ControlServer::bind(&path).await.is_err());
+        shutdown.cancel();
+    }
+
+    #[tokio::test]
+    async fn test_request_shutdown_releases_socket() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("control.sock");
+        let context = Arc::new(ControlContext::default());
+        ControlServer::bind(&path)
+            .await
+            .unwrap()
+            .serve(Arc::clone(&context), context.shutdown.clone());
+
+        request_shutdown(&path).await.unwrap();
+        assert!(context.shutdown.is_cancelled());
+        assert!(!is_running(&path).await);
+        assert!(ControlServer::bind(&path).await.is_ok());
+    }
+}
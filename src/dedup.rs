@@ -0,0 +1,108 @@
+//! Dropping duplicate focus events.
+//!
+//! Some compositors report the same focus more than once in quick
+//! succession; Hyprland, for one, can emit repeated `activewindow` lines for
+//! a window that never lost focus. [`DedupSource`] drops an event identical
+//! to the previous one (same app class, title and window) that arrives
+//! within `focus_dedup_window_ms` of it, so the event loop, logs and
+//! `tail` see each focus change once.
+
+use std::time::Duration;
+
+use futures_util::future::BoxFuture;
+use tokio::time::Instant;
+use tracing::trace;
+
+use crate::backend::FocusError;
+use crate::backend::FocusEvent;
+use crate::backend::FocusSource;
+use crate::metrics;
+use crate::redact::sensitive;
+
+/// A [`FocusSource`] wrapper that drops repeated events.
+pub struct DedupSource {
+    inner: Box<dyn FocusSource>,
+    window: Duration,
+    /// Previous event passed on, and when.
+    last: Option<(FocusEvent, Instant)>,
+}
+
+impl DedupSource {
+    /// Wrap `inner`, dropping repeats within `window`. A zero window passes
+    /// every event on.
+    #[must_use]
+    pub fn new(inner: Box<dyn FocusSource>, window: Duration) -> Self {
+        Self {
+            inner,
+            window,
+            last: None,
+        }
+    }
+
+    /// Whether `event` repeats the previous event within the window.
+    fn is_duplicate(&self, event: &FocusEvent, now: Instant) -> bool {
+        self.last.as_ref().is_some_and(|(last, at)| {
+            now.duration_since(*at) < self.window
+                && last.app_class == event.app_class
+                && last.title == event.title
+                && last.window_id == event.window_id
+        })
+    }
+}
+
+impl FocusSource for DedupSource {
+    fn next_event(&mut self) -> BoxFuture<'_, Result<FocusEvent, FocusError>> {
+        Box::pin(async move {
+            loop {
+                let event = self.inner.next_event().await?;
+                let now = Instant::now();
+                if self.is_duplicate(&event, now) {
+                    trace!(
+                        "Dropping duplicate focus event: class={} title={:?}",
+                        event.app_class,
+                        event.title.as_deref().map(sensitive)
+                    );
+                    metrics::incr(&metrics::global().focus_events_deduplicated);
+                    continue;
+                }
+                self.last = Some((event.clone(), now));
+                return Ok(event);
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::sync::mpsc;
+
+    use super::*;
+    use crate::backend::ChannelSource;
+
+    fn event(window_id: &str) -> FocusEvent {
+        FocusEvent::new(
+            "kitty".to_string(),
+            Some("~/src".to_string()),
+            Some(window_id.to_string()),
+        )
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_repeats_within_window_are_dropped() {
+        let (tx, rx) = mpsc::channel(8);
+        let mut source = DedupSource::new(Box::new(ChannelSource(rx)), Duration::from_millis(200));
+        let mut next_window = async || source.next_event().await.unwrap().window_id.unwrap();
+
+        tx.send(event("0x1")).await.unwrap();
+        tx.send(event("0x1")).await.unwrap();
+        // Another window of the same app is a change
+        tx.send(event("0x2")).await.unwrap();
+        assert_eq!(next_window().await, "0x1");
+        assert_eq!(next_window().await, "0x2");
+
+        // After the window, the same event counts again
+        tokio::time::advance(Duration::from_millis(200)).await;
+        tx.send(event("0x2")).await.unwrap();
+        assert_eq!(next_window().await, "0x2");
+    }
+}
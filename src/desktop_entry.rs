@@ -0,0 +1,225 @@
+//! Categories from freedesktop desktop entries.
+//!
+//! Installed apps describe themselves in `.desktop` files, whose
+//! `Categories` key lists menu categories like `Development` or
+//! `WebBrowser`. [`DesktopEntries`] finds the entry for an app class and
+//! maps those to a `WakaTime` category, for the `"desktop_entry"` step of the
+//! `default_category` chain.
+
+use std::collections::HashMap;
+use std::env;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::sync::PoisonError;
+
+use tracing::debug;
+
+use crate::domain::Category;
+use crate::redact::sensitive;
+
+/// Desktop entry categories and the `WakaTime` category they map to, most
+/// specific first.
+const CATEGORY_MAP: &[(&str, Category)] = &[
+    ("Debugger", Category::Debugging),
+    ("IDE", Category::Coding),
+    ("Development", Category::Coding),
+    ("WebBrowser", Category::Browsing),
+    ("Chat", Category::Communicating),
+    ("InstantMessaging", Category::Communicating),
+    ("Email", Category::Communicating),
+    ("IRCClient", Category::Communicating),
+    ("VideoConference", Category::Communicating),
+    ("Telephony", Category::Communicating),
+    ("Graphics", Category::Designing),
+    ("WordProcessor", Category::WritingDocs),
+    ("Office", Category::WritingDocs),
+    ("Dictionary", Category::Researching),
+    ("Education", Category::Learning),
+    ("Science", Category::Learning),
+];
+
+/// Looks up app classes in the installed desktop entries.
+///
+/// Results are cached per class, so each class is looked up once.
+#[derive(Debug)]
+pub struct DesktopEntries {
+    /// `applications` directories, most important first.
+    dirs: Vec<PathBuf>,
+    cache: Mutex<HashMap<String, Option<Category>>>,
+}
+
+impl DesktopEntries {
+    /// Search the XDG data directories and Flatpak exports.
+    #[must_use]
+    pub fn new() -> Self {
+        let mut data_dirs: Vec<PathBuf> = dirs::data_dir().into_iter().collect();
+        data_dirs.extend(dirs::data_dir().map(|d| d.join("flatpak").join("exports").join("share")));
+        let system = env::var("XDG_DATA_DIRS")
+            .ok()
+            .filter(|v| !v.is_empty())
+            .unwrap_or_else(|| "/usr/local/share:/usr/share".to_string());
+        data_dirs.extend(env::split_paths(&system));
+        data_dirs.push(PathBuf::from("/var/lib/flatpak/exports/share"));
+
+        Self::with_dirs(
+            data_dirs
+                .into_iter()
+                .map(|d| d.join("applications"))
+                .collect(),
+        )
+    }
+
+    /// Search only `dirs` (each an `applications` directory).
+    #[must_use]
+    pub fn with_dirs(dirs: Vec<PathBuf>) -> Self {
+        Self {
+            dirs,
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The category for an app class, if its desktop entry has a mapped
+    /// category.
+    pub fn category(&self, app_class: &str) -> Option<Category> {
+        let key = app_class.to_ascii_lowercase();
+        if let Some(category) = self.lock().get(&key) {
+            return *category;
+        }
+
+        let category = self.find_entry(&key).and_then(|(path, content)| {
+            let category = map_categories(&content);
+            debug!(
+                "Desktop entry for {}: {} ({category:?})",
+                sensitive(app_class),
+                path.display()
+            );
+            category
+        });
+        self.lock().insert(key, category);
+        category
+    }
+
+    /// Find the entry for a lowercased app class: by file name first
+    /// (`firefox.desktop`, `org.gnome.Nautilus.desktop`), then by
+    /// `StartupWMClass`.
+    fn find_entry(&self, class: &str) -> Option<(PathBuf, String)> {
+        let entries: Vec<PathBuf> = self
+            .dirs
+            .iter()
+            .filter_map(|dir| std::fs::read_dir(dir).ok())
+            .flat_map(|dir| dir.filter_map(|e| e.ok().map(|e| e.path())))
+            .filter(|path| path.extension().is_some_and(|ext| ext == "desktop"))
+            .collect();
+
+        let by_name = entries.iter().find(|path| {
+            let stem = stem(path);
+            stem == class || stem.rsplit('.').next() == Some(class)
+        });
+        if let Some(path) = by_name
+            && let Ok(content) = std::fs::read_to_string(path)
+        {
+            return Some((path.clone(), content));
+        }
+
+        entries.into_iter().find_map(|path| {
+            let content = std::fs::read_to_string(&path).ok()?;
+            let matches = entry_values(&content, "StartupWMClass")
+                .any(|wm_class| wm_class.eq_ignore_ascii_case(class));
+            matches.then_some((path, content))
+        })
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, Option<Category>>> {
+        self.cache.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Default for DesktopEntries {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Lowercased file name without the `.desktop` extension.
+fn stem(path: &Path) -> String {
+    path.file_stem()
+        .map(|s| s.to_string_lossy().to_ascii_lowercase())
+        .unwrap_or_default()
+}
+
+/// Values of a key in the `[Desktop Entry]` group, split on `;`.
+fn entry_values<'a>(content: &'a str, key: &'a str) -> impl Iterator<Item = &'a str> {
+    let mut in_group = false;
+    content
+        .lines()
+        .filter(move |line| {
+            let line = line.trim();
+            if line.starts_with('[') {
+                in_group = line == "[Desktop Entry]";
+                return false;
+            }
+            in_group
+        })
+        .filter_map(move |line| {
+            let (k, v) = line.split_once('=')?;
+            (k.trim() == key).then_some(v.trim())
+        })
+        .flat_map(|v| v.split(';'))
+        .filter(|v| !v.is_empty())
+}
+
+/// Map an entry's `Categories` to a `WakaTime` category.
+fn map_categories(content: &str) -> Option<Category> {
+    let categories: Vec<&str> = entry_values(content, "Categories").collect();
+    CATEGORY_MAP
+        .iter()
+        .find(|(name, _)| categories.contains(name))
+        .map(|(_, category)| *category)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_entry(dir: &Path, name: &str, body: &str) {
+        std::fs::write(
+            dir.join(name),
+            format!("[Desktop Entry]\nType=Application\n{body}\n[Desktop Action new]\nCategories=Office;\n"),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_category_from_entry() {
+        let dir = tempfile::tempdir().unwrap();
+        write_entry(
+            dir.path(),
+            "org.gnome.Builder.desktop",
+            "Categories=GNOME;GTK;Development;IDE;",
+        );
+        write_entry(
+            dir.path(),
+            "google-chrome.desktop",
+            "Categories=Network;WebBrowser;\nStartupWMClass=Google-chrome",
+        );
+        write_entry(dir.path(), "vlc.desktop", "Categories=AudioVideo;Player;");
+        let entries = DesktopEntries::with_dirs(vec![dir.path().to_path_buf()]);
+
+        assert_eq!(
+            entries.category("org.gnome.Builder"),
+            Some(Category::Coding)
+        );
+        assert_eq!(entries.category("builder"), Some(Category::Coding));
+        assert_eq!(entries.category("Google-chrome"), Some(Category::Browsing));
+        // Unmapped categories and actions don't count
+        assert_eq!(entries.category("vlc"), None);
+        assert_eq!(entries.category("missing"), None);
+    }
+
+    #[test]
+    fn test_most_specific_category_wins() {
+        let content = "[Desktop Entry]\nCategories=Development;Debugger;\n";
+        assert_eq!(map_categories(content), Some(Category::Debugging));
+    }
+}
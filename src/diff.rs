@@ -0,0 +1,152 @@
+//! Comparing a candidate configuration against the current one.
+//!
+//! `wakatime-focusd --dry-run --diff-config candidate.toml` runs live focus
+//! events through both configurations and reports the events they decide
+//! differently: one sends a heartbeat and the other filters the app, or both
+//! send with a different entity, category or project. Nothing is sent, so
+//! rule changes can be tried out on real use before adopting them. Idle
+//! gating and throttling don't depend on the rules and are not compared.
+
+use std::fmt;
+
+use crate::backend::FocusEvent;
+use crate::config::Config;
+use crate::domain::Category;
+use crate::heartbeat::HeartbeatBuilder;
+
+/// What a configuration does with a focus event.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Decision {
+    /// The app is excluded by the allowlist or denylist.
+    Filtered,
+    /// The app's own `WakaTime` plugin reports its time.
+    DefersToPlugin,
+    /// A heartbeat is sent.
+    Send {
+        /// Entity of the heartbeat.
+        entity: String,
+        /// Category of the heartbeat.
+        category: Category,
+        /// Project of the heartbeat, if any.
+        project: Option<String>,
+    },
+}
+
+impl fmt::Display for Decision {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Filtered => f.write_str("filtered"),
+            Self::DefersToPlugin => f.write_str("defers to plugin"),
+            Self::Send {
+                entity,
+                category,
+                project,
+            } => {
+                write!(f, "send {entity} ({category}")?;
+                if let Some(project) = project {
+                    write!(f, ", project {project}")?;
+                }
+                f.write_str(")")
+            }
+        }
+    }
+}
+
+/// One configuration's rules.
+struct Side {
+    builder: HeartbeatBuilder,
+    track_hidden_apps: bool,
+}
+
+impl Side {
+    fn new(config: &Config) -> Self {
+        Self {
+            builder: HeartbeatBuilder::from_config(config),
+            track_hidden_apps: config.track_hidden_apps,
+        }
+    }
+
+    /// Decide the same way the event loop does.
+    fn decide(&self, event: &FocusEvent) -> Decision {
+        let allowed = self.builder.is_event_allowed(event);
+        if !allowed && !self.track_hidden_apps {
+            return Decision::Filtered;
+        }
+        if allowed && self.builder.defers_to_plugin(&event.app_class) {
+            return Decision::DefersToPlugin;
+        }
+        let heartbeat = if allowed {
+            self.builder.build(event.clone())
+        } else {
+            self.builder.build_hidden()
+        };
+        Decision::Send {
+            entity: heartbeat.entity.to_string(),
+            category: heartbeat.category,
+            project: heartbeat.project,
+        }
+    }
+}
+
+/// The current and the candidate configuration.
+pub struct ConfigDiff {
+    current: Side,
+    candidate: Side,
+}
+
+impl ConfigDiff {
+    /// Prepare both configurations' rules.
+    #[must_use]
+    pub fn new(current: &Config, candidate: &Config) -> Self {
+        Self {
+            current: Side::new(current),
+            candidate: Side::new(candidate),
+        }
+    }
+
+    /// The current and the candidate decision for `event`, if they differ.
+    #[must_use]
+    pub fn compare(&self, event: &FocusEvent) -> Option<(Decision, Decision)> {
+        if event.is_empty() {
+            return None;
+        }
+        let current = self.current.decide(event);
+        let candidate = self.candidate.decide(event);
+        (current != candidate).then_some((current, candidate))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(class: &str) -> FocusEvent {
+        FocusEvent::new(class.to_string(), Some("notes".to_string()), None)
+    }
+
+    #[test]
+    fn test_compare_reports_differences() {
+        let current = Config::default();
+        let candidate: Config = toml::from_str(
+            r#"
+            app_denylist = ["slack"]
+
+            [categories]
+            firefox = "researching"
+            "#,
+        )
+        .unwrap();
+        let diff = ConfigDiff::new(&current, &candidate);
+
+        assert_eq!(diff.compare(&event("kitty")), None);
+        assert_eq!(
+            diff.compare(&event("slack"))
+                .map(|(_, candidate)| candidate),
+            Some(Decision::Filtered)
+        );
+
+        let (current, candidate) = diff.compare(&event("firefox")).unwrap();
+        assert!(matches!(current, Decision::Send { .. }));
+        assert_eq!(candidate.to_string(), "send firefox (researching)");
+    }
+}
@@ -65,6 +65,51 @@ impl fmt::Display for Category {
     }
 }
 
+/// Which step of categorization decided a heartbeat's category.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CategorySource {
+    /// A temporary rule added at runtime.
+    Temporary,
+    /// An entry in the `[categories]` map.
+    Map,
+    /// A `category_rules` entry.
+    Rule,
+    /// An AI coding tool in a terminal title.
+    AiTerminal,
+    /// A video call (meeting mode).
+    Meeting,
+    /// The `Categories` of the app's desktop entry.
+    DesktopEntry,
+    /// A preset pack or built-in AI tool rule.
+    Preset,
+    /// The fixed category ending the `default_category` chain.
+    #[default]
+    Default,
+}
+
+impl CategorySource {
+    /// Get the source as a string for logs.
+    #[must_use]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Temporary => "temporary rule",
+            Self::Map => "categories map",
+            Self::Rule => "category rule",
+            Self::AiTerminal => "AI terminal",
+            Self::Meeting => "meeting",
+            Self::DesktopEntry => "desktop entry",
+            Self::Preset => "preset",
+            Self::Default => "default",
+        }
+    }
+}
+
+impl fmt::Display for CategorySource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
 /// Entity sent to `WakaTime` (newtype for type safety).
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct Entity(String);
@@ -88,20 +133,94 @@ impl fmt::Display for Entity {
     }
 }
 
+/// Kind of entity a heartbeat refers to (the `type` field in the API).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EntityType {
+    /// A desktop application.
+    #[default]
+    App,
+    /// A website domain.
+    Domain,
+    /// A web page URL.
+    Url,
+    /// A file open in an editor.
+    File,
+}
+
+impl EntityType {
+    /// Get the entity type as a string for `WakaTime` API.
+    #[must_use]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::App => "app",
+            Self::Domain => "domain",
+            Self::Url => "url",
+            Self::File => "file",
+        }
+    }
+}
+
+/// Why a heartbeat was produced.
+///
+/// Used to prioritize delivery: when the sender is backlogged or rate
+/// limited, focus changes carry more information than periodic refreshes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum HeartbeatKind {
+    /// Focus moved to this entity (or its first heartbeat).
+    #[default]
+    FocusChange,
+    /// Periodic refresh while the same entity stays focused.
+    Periodic,
+    /// Closes out the previous entity when focus moves away from it.
+    Leave,
+}
+
+impl HeartbeatKind {
+    /// Delivery priority (lower is sent first).
+    #[must_use]
+    pub fn priority(self) -> u8 {
+        match self {
+            Self::FocusChange | Self::Leave => 0,
+            Self::Periodic => 1,
+        }
+    }
+}
+
 /// Complete heartbeat ready to send to `WakaTime`.
 #[derive(Debug, Clone)]
 pub struct Heartbeat {
-    /// The entity (app name or app + title).
+    /// The entity (app name, app + title, or website domain).
     pub entity: Entity,
 
+    /// What kind of thing the entity is.
+    pub entity_type: EntityType,
+
     /// The activity category.
     pub category: Category,
 
+    /// Which step of categorization decided `category`.
+    pub category_source: CategorySource,
+
+    /// Project name, if one could be determined.
+    pub project: Option<String>,
+
+    /// Programming language, for file heartbeats.
+    pub language: Option<String>,
+
+    /// Git branch checked out in the project's repository, if known.
+    pub branch: Option<String>,
+
     /// The source focus event (for provenance).
     pub source: FocusEvent,
 
     /// Unix timestamp (seconds) when the event occurred.
     pub time: f64,
+
+    /// Why this heartbeat was produced.
+    pub kind: HeartbeatKind,
+
+    /// Whether this heartbeat is for a video call (sent even while idle).
+    pub meeting: bool,
 }
 
 impl Heartbeat {
@@ -112,16 +231,74 @@ impl Heartbeat {
     /// Panics if the system clock is before the UNIX epoch.
     #[must_use]
     pub fn new(entity: Entity, category: Category, source: FocusEvent) -> Self {
-        let time = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .expect("system time before UNIX epoch")
-            .as_secs_f64();
-
         Self {
             entity,
+            entity_type: EntityType::App,
             category,
+            category_source: CategorySource::Default,
+            project: None,
+            language: None,
+            branch: None,
             source,
+            time: unix_time_now(),
+            kind: HeartbeatKind::FocusChange,
+            meeting: false,
+        }
+    }
+
+    /// Return a periodic copy of this heartbeat stamped with the current time.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the system clock is before the UNIX epoch.
+    #[must_use]
+    pub fn refreshed(&self) -> Self {
+        self.at(unix_time_now()).with_kind(HeartbeatKind::Periodic)
+    }
+
+    /// Return a copy of this heartbeat stamped with a different time.
+    #[must_use]
+    pub fn at(&self, time: f64) -> Self {
+        Self {
             time,
+            ..self.clone()
+        }
+    }
+
+    /// Return a copy of this heartbeat with a different kind.
+    #[must_use]
+    pub fn with_kind(&self, kind: HeartbeatKind) -> Self {
+        Self {
+            kind,
+            ..self.clone()
+        }
+    }
+
+    /// Return an app-level copy of this heartbeat with the title, URL, and
+    /// everything derived from them (entity, project, language, branch)
+    /// removed.
+    #[must_use]
+    pub fn without_title(&self) -> Self {
+        Self {
+            entity: Entity::new(self.source.app_class.clone()),
+            entity_type: EntityType::App,
+            project: None,
+            language: None,
+            branch: None,
+            source: FocusEvent {
+                title: None,
+                url: None,
+                ..self.source.clone()
+            },
+            ..self.clone()
         }
     }
 }
+
+/// Current Unix timestamp in (fractional) seconds.
+pub(crate) fn unix_time_now() -> f64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("system time before UNIX epoch")
+        .as_secs_f64()
+}
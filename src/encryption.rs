@@ -0,0 +1,187 @@
+//! Encryption of the offline queues.
+//!
+//! Queued heartbeats carry entities, projects and a timeline of the day,
+//! which shouldn't sit around in plain text on shared or backed-up machines.
+//! With `encrypt_queue`, every line written to an offline queue is sealed
+//! with ChaCha20-Poly1305 under a key kept in the desktop keyring, created
+//! there on first use. Lines written before the option was turned on are
+//! still read, and sent like the rest.
+
+use std::sync::Arc;
+use std::sync::PoisonError;
+use std::sync::RwLock;
+
+use anyhow::Result;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use ring::aead::Aad;
+use ring::aead::CHACHA20_POLY1305;
+use ring::aead::LessSafeKey;
+use ring::aead::NONCE_LEN;
+use ring::aead::Nonce;
+use ring::aead::UnboundKey;
+use ring::rand::SecureRandom;
+use ring::rand::SystemRandom;
+
+use crate::config::Config;
+
+/// Marks a sealed queue line.
+const SEALED_PREFIX: &str = "sealed:";
+
+/// Key length in bytes.
+const KEY_LEN: usize = 32;
+
+/// Keyring label of the queue key.
+#[cfg(feature = "secret-service")]
+const KEY_LABEL: &str = "wakatime-focusd offline queue key";
+
+static CIPHER: RwLock<Option<Arc<QueueCipher>>> = RwLock::new(None);
+
+/// Seals and opens offline queue lines.
+pub struct QueueCipher {
+    key: LessSafeKey,
+    random: SystemRandom,
+}
+
+impl std::fmt::Debug for QueueCipher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("QueueCipher")
+    }
+}
+
+impl QueueCipher {
+    /// Create a cipher from a 32-byte key.
+    pub fn new(key: &[u8]) -> Result<Self> {
+        let key = UnboundKey::new(&CHACHA20_POLY1305, key)
+            .map_err(|_| anyhow::anyhow!("The queue key must be {KEY_LEN} bytes"))?;
+        Ok(Self {
+            key: LessSafeKey::new(key),
+            random: SystemRandom::new(),
+        })
+    }
+
+    /// A new random key.
+    ///
+    /// # Panics
+    ///
+    /// If the system random number generator fails.
+    #[must_use]
+    pub fn generate_key() -> Vec<u8> {
+        let mut key = vec![0; KEY_LEN];
+        SystemRandom::new()
+            .fill(&mut key)
+            .expect("the system random number generator failed");
+        key
+    }
+
+    /// Seal a queue line with a random nonce.
+    ///
+    /// # Panics
+    ///
+    /// If the system random number generator fails.
+    #[must_use]
+    pub fn seal(&self, line: &str) -> String {
+        let mut nonce = [0; NONCE_LEN];
+        self.random
+            .fill(&mut nonce)
+            .expect("the system random number generator failed");
+        let mut sealed = line.as_bytes().to_vec();
+        self.key
+            .seal_in_place_append_tag(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::empty(),
+                &mut sealed,
+            )
+            .expect("sealing only fails for oversized input");
+        let mut data = nonce.to_vec();
+        data.append(&mut sealed);
+        format!("{SEALED_PREFIX}{}", STANDARD.encode(data))
+    }
+
+    /// Open a sealed queue line.
+    pub fn open(&self, line: &str) -> Result<String, String> {
+        let data = line
+            .strip_prefix(SEALED_PREFIX)
+            .ok_or("not a sealed line")?;
+        let mut data = STANDARD
+            .decode(data)
+            .map_err(|e| format!("invalid sealed line: {e}"))?;
+        if data.len() < NONCE_LEN {
+            return Err("sealed line is too short".to_string());
+        }
+        let mut sealed = data.split_off(NONCE_LEN);
+        let nonce = Nonce::try_assume_unique_for_key(&data).map_err(|_| "invalid nonce")?;
+        let plain = self
+            .key
+            .open_in_place(nonce, Aad::empty(), &mut sealed)
+            .map_err(|_| "the line doesn't open with the queue key")?;
+        String::from_utf8(plain.to_vec()).map_err(|e| e.to_string())
+    }
+}
+
+/// Whether a queue line is sealed.
+#[must_use]
+pub fn is_sealed(line: &str) -> bool {
+    line.starts_with(SEALED_PREFIX)
+}
+
+/// Load the queue key from the keyring if `encrypt_queue` is set and it
+/// isn't loaded yet.
+pub async fn load(config: &Config) -> Result<()> {
+    if !config.encrypt_queue || cipher().is_some() {
+        return Ok(());
+    }
+    let key = fetch_key().await?;
+    *CIPHER.write().unwrap_or_else(PoisonError::into_inner) =
+        Some(Arc::new(QueueCipher::new(&key)?));
+    Ok(())
+}
+
+/// The queue cipher, once [`load`] has succeeded.
+#[must_use]
+pub fn cipher() -> Option<Arc<QueueCipher>> {
+    CIPHER
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .clone()
+}
+
+#[cfg(feature = "secret-service")]
+async fn fetch_key() -> Result<Vec<u8>> {
+    use anyhow::Context;
+
+    let attributes = [("application", "wakatime-focusd"), ("key", "queue")];
+    crate::secret_service::get_or_create(KEY_LABEL, &attributes, QueueCipher::generate_key)
+        .await
+        .context("Failed to get the offline queue key from the keyring")
+}
+
+#[cfg(not(feature = "secret-service"))]
+#[allow(clippy::unused_async)]
+async fn fetch_key() -> Result<Vec<u8>> {
+    anyhow::bail!("encrypt_queue needs the secret-service feature, which this build doesn't have")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seal_and_open() {
+        let cipher = QueueCipher::new(&QueueCipher::generate_key()).unwrap();
+        let line = r#"[{"entity":"kitty — ~/src/secret-project"}]"#;
+
+        let sealed = cipher.seal(line);
+        assert!(is_sealed(&sealed));
+        assert!(!sealed.contains("secret-project"));
+        assert_ne!(sealed, cipher.seal(line));
+        assert_eq!(cipher.open(&sealed).unwrap(), line);
+
+        // Another key, or a modified line, doesn't open
+        let other = QueueCipher::new(&QueueCipher::generate_key()).unwrap();
+        assert!(other.open(&sealed).is_err());
+        let mut tampered = sealed.clone();
+        tampered.insert(SEALED_PREFIX.len() + 20, 'A');
+        assert!(cipher.open(&tampered).is_err());
+    }
+}
@@ -0,0 +1,181 @@
+//! Compositor, distribution and kernel of the machine.
+//!
+//! Detected once at startup. With `report_environment`, they are added to
+//! the User-Agent of heartbeat requests, where `WakaTime` shows the
+//! compositor as the editor and the rest as the operating system, so users
+//! with several machines can tell their stats apart. Logged at debug level
+//! either way.
+
+use std::process::Command;
+use std::sync::PoisonError;
+use std::sync::RwLock;
+
+use crate::backend::Backend;
+
+static ENVIRONMENT: RwLock<Option<Environment>> = RwLock::new(None);
+
+/// What the daemon runs on.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Environment {
+    /// Compositor or desktop, e.g. `Hyprland`.
+    pub compositor: Option<String>,
+    /// Compositor version, e.g. `0.45.2`.
+    pub compositor_version: Option<String>,
+    /// Distribution name and version, e.g. `Fedora Linux 41`.
+    pub distro: Option<String>,
+    /// Kernel release, e.g. `6.11.4-301.fc41.x86_64`.
+    pub kernel: Option<String>,
+}
+
+impl Environment {
+    /// Detect the environment for `backend`.
+    ///
+    /// The compositor version comes from running its binary with
+    /// `--version`; anything that can't be found is left out.
+    #[must_use]
+    pub fn detect(backend: Backend) -> Self {
+        let (compositor, binary) = match backend {
+            Backend::Hyprland => (Some("Hyprland".to_string()), Some("Hyprland")),
+            Backend::Sway => (Some("Sway".to_string()), Some("sway")),
+            Backend::Gnome => (Some("GNOME".to_string()), Some("gnome-shell")),
+            Backend::Kde => (Some("KDE".to_string()), Some("plasmashell")),
+            Backend::Niri => (Some("niri".to_string()), Some("niri")),
+            Backend::Cosmic => (Some("COSMIC".to_string()), None),
+            Backend::Auto
+            | Backend::WlrForeignToplevel
+            | Backend::X11
+            | Backend::Mock
+            | Backend::Stdin => (current_desktop(), None),
+        };
+        Self {
+            compositor,
+            compositor_version: binary.and_then(binary_version),
+            distro: ["/etc/os-release", "/usr/lib/os-release"]
+                .iter()
+                .find_map(|path| std::fs::read_to_string(path).ok())
+                .and_then(|content| distro_name(&content)),
+            kernel: std::fs::read_to_string("/proc/sys/kernel/osrelease")
+                .ok()
+                .map(|release| release.trim().to_string())
+                .filter(|release| !release.is_empty()),
+        }
+    }
+
+    /// User-Agent for `os` and `plugin` (`name/version`), e.g.
+    /// `(Linux-6.11.4; Fedora Linux 41) Hyprland/0.45.2 wakatime-focusd/1.0.0`.
+    ///
+    /// A compositor without a known version goes with the operating system.
+    #[must_use]
+    pub fn user_agent(&self, os: &str, plugin: &str) -> String {
+        let mut platform = match self.kernel {
+            Some(ref kernel) => format!("{os}-{kernel}"),
+            None => os.to_string(),
+        };
+        if let Some(ref distro) = self.distro {
+            platform.push_str("; ");
+            platform.push_str(distro);
+        }
+        match (&self.compositor, &self.compositor_version) {
+            (Some(compositor), Some(version)) => {
+                format!("({platform}) {compositor}/{version} {plugin}")
+            }
+            (Some(compositor), None) => format!("({platform}; {compositor}) {plugin}"),
+            (None, _) => format!("({platform}) {plugin}"),
+        }
+    }
+}
+
+/// Remember the detected environment for the process.
+pub fn set(environment: Environment) {
+    *ENVIRONMENT.write().unwrap_or_else(PoisonError::into_inner) = Some(environment);
+}
+
+/// The environment detected at startup, if any.
+#[must_use]
+pub fn current() -> Option<Environment> {
+    ENVIRONMENT
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .clone()
+}
+
+/// First desktop named in `XDG_CURRENT_DESKTOP`.
+fn current_desktop() -> Option<String> {
+    std::env::var("XDG_CURRENT_DESKTOP")
+        .ok()
+        .and_then(|desktops| desktops.split(':').next().map(str::to_string))
+        .filter(|desktop| !desktop.is_empty())
+}
+
+/// Version reported by `binary --version`.
+fn binary_version(binary: &str) -> Option<String> {
+    let output = Command::new(binary).arg("--version").output().ok()?;
+    parse_version(&String::from_utf8_lossy(&output.stdout))
+}
+
+/// The first version-like word of a `--version` output, e.g. `1.9` from
+/// `sway version 1.9`.
+fn parse_version(output: &str) -> Option<String> {
+    output
+        .lines()
+        .next()?
+        .split_whitespace()
+        .map(|word| word.trim_start_matches('v').trim_end_matches(','))
+        .find(|word| word.starts_with(|c: char| c.is_ascii_digit()))
+        .map(str::to_string)
+}
+
+/// `NAME` and `VERSION_ID` from an os-release file.
+fn distro_name(os_release: &str) -> Option<String> {
+    let field = |key: &str| {
+        os_release.lines().find_map(|line| {
+            line.strip_prefix(key)?
+                .strip_prefix('=')
+                .map(|value| value.trim().trim_matches('"').to_string())
+        })
+    };
+    let name = field("NAME").filter(|name| !name.is_empty())?;
+    Some(match field("VERSION_ID") {
+        Some(version) if !version.is_empty() => format!("{name} {version}"),
+        _ => name,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_user_agent() {
+        let environment = Environment {
+            compositor: Some("Hyprland".to_string()),
+            compositor_version: Some("0.45.2".to_string()),
+            distro: distro_name("NAME=\"Fedora Linux\"\nVERSION_ID=41\nID=fedora\n"),
+            kernel: Some("6.11.4".to_string()),
+        };
+        assert_eq!(
+            environment.user_agent("Linux", "wakatime-focusd/1.0.0"),
+            "(Linux-6.11.4; Fedora Linux 41) Hyprland/0.45.2 wakatime-focusd/1.0.0"
+        );
+
+        let environment = Environment {
+            compositor: Some("COSMIC".to_string()),
+            ..Environment::default()
+        };
+        assert_eq!(
+            environment.user_agent("Linux", "wakatime-focusd/1.0.0"),
+            "(Linux; COSMIC) wakatime-focusd/1.0.0"
+        );
+    }
+
+    #[test]
+    fn test_parse_version() {
+        assert_eq!(parse_version("sway version 1.9\n").as_deref(), Some("1.9"));
+        assert_eq!(
+            parse_version("Hyprland v0.45.2 built from branch main").as_deref(),
+            Some("0.45.2")
+        );
+        assert_eq!(parse_version("GNOME Shell 46.0").as_deref(), Some("46.0"));
+        assert_eq!(parse_version("unknown"), None);
+    }
+}
@@ -0,0 +1,207 @@
+//! Thinning out repeated error logs.
+//!
+//! When the API, the backend connection or idle polling keeps failing, the
+//! same error would otherwise fill the journal every few seconds. Each kind
+//! of error is counted on its own: the first `error_log_burst` in a row are
+//! logged, then only every `error_log_every`th. How many were left out is
+//! logged every `error_log_summary_seconds`, and once the failing operation
+//! works again.
+
+use std::fmt;
+use std::sync::Mutex;
+use std::sync::PoisonError;
+use std::time::Duration;
+
+use tokio_util::sync::CancellationToken;
+use tracing::info;
+use tracing::warn;
+
+use crate::config::Config;
+
+static ERROR_LOG: ErrorLog = ErrorLog::new();
+
+/// A kind of error that is counted on its own.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorClass {
+    /// The API answered with an unexpected status.
+    Api,
+    /// Connecting to the backend failed, or its event stream broke.
+    Backend,
+    /// Polling the idle state failed or timed out.
+    IdlePoll,
+}
+
+impl ErrorClass {
+    const ALL: [Self; 3] = [Self::Api, Self::Backend, Self::IdlePoll];
+}
+
+impl fmt::Display for ErrorClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Api => write!(f, "API"),
+            Self::Backend => write!(f, "backend"),
+            Self::IdlePoll => write!(f, "idle poll"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Counts {
+    /// Errors since the operation last worked.
+    in_a_row: u64,
+    /// Errors not logged since the last summary.
+    suppressed: u64,
+}
+
+#[derive(Debug)]
+struct State {
+    burst: u64,
+    every: u64,
+    summary_interval: Duration,
+    counts: [Counts; ErrorClass::ALL.len()],
+}
+
+/// Decides which errors are logged.
+#[derive(Debug)]
+pub struct ErrorLog {
+    state: Mutex<State>,
+}
+
+impl Default for ErrorLog {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ErrorLog {
+    /// Create an error log with the default thresholds.
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            state: Mutex::new(State {
+                burst: 5,
+                every: 10,
+                summary_interval: Duration::from_mins(5),
+                counts: [Counts {
+                    in_a_row: 0,
+                    suppressed: 0,
+                }; ErrorClass::ALL.len()],
+            }),
+        }
+    }
+
+    /// Apply the thresholds from `config`.
+    pub fn configure(&self, config: &Config) {
+        let mut state = self.lock();
+        state.burst = u64::from(config.error_log_burst);
+        state.every = u64::from(config.error_log_every);
+        state.summary_interval = Duration::from_secs(config.error_log_summary_seconds);
+    }
+
+    /// Count an error of `class` and return whether to log it.
+    pub fn should_log(&self, class: ErrorClass) -> bool {
+        let mut state = self.lock();
+        let (burst, every) = (state.burst, state.every);
+        let counts = &mut state.counts[class as usize];
+        counts.in_a_row += 1;
+        let past_burst = counts.in_a_row.saturating_sub(burst);
+        let log = counts.in_a_row <= burst || (every > 0 && past_burst.is_multiple_of(every));
+        if !log {
+            if counts.in_a_row == burst + 1 {
+                if every > 0 {
+                    warn!("Repeated {class} errors, logging every {every}th from now on");
+                } else {
+                    warn!("Repeated {class} errors, logging only a summary from now on");
+                }
+            }
+            counts.suppressed += 1;
+        }
+        log
+    }
+
+    /// Note that the operation behind `class` worked, starting the count
+    /// over.
+    pub fn recovered(&self, class: ErrorClass) {
+        let mut state = self.lock();
+        let counts = &mut state.counts[class as usize];
+        if counts.suppressed > 0 {
+            info!(
+                "Recovered from {class} errors ({} not logged)",
+                counts.suppressed
+            );
+        }
+        counts.in_a_row = 0;
+        counts.suppressed = 0;
+    }
+
+    /// Log how many errors of each kind were left out since the last
+    /// summary.
+    pub fn summarize(&self) {
+        let mut state = self.lock();
+        let interval = state.summary_interval;
+        for class in ErrorClass::ALL {
+            let counts = &mut state.counts[class as usize];
+            if counts.suppressed > 0 {
+                warn!(
+                    "{} {class} error(s) not logged in the last {interval:?}, {} in a row so far",
+                    counts.suppressed, counts.in_a_row
+                );
+                counts.suppressed = 0;
+            }
+        }
+    }
+
+    /// Summarize every `error_log_summary_seconds` until `shutdown` is
+    /// cancelled.
+    pub fn start(&'static self, shutdown: CancellationToken) {
+        tokio::spawn(async move {
+            loop {
+                let interval = self.lock().summary_interval.max(Duration::from_secs(1));
+                tokio::select! {
+                    () = shutdown.cancelled() => return,
+                    () = tokio::time::sleep(interval) => self.summarize(),
+                }
+            }
+        });
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Process-wide error log.
+#[must_use]
+pub fn global() -> &'static ErrorLog {
+    &ERROR_LOG
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_errors_are_thinned_out_per_class() {
+        let log = ErrorLog::new();
+        log.configure(&Config {
+            error_log_burst: 2,
+            error_log_every: 3,
+            ..Config::default()
+        });
+
+        let logged: Vec<_> = (0..8).map(|_| log.should_log(ErrorClass::Api)).collect();
+        assert_eq!(logged, [true, true, false, false, true, false, false, true]);
+        // Other classes are counted separately
+        assert!(log.should_log(ErrorClass::Backend));
+
+        log.summarize();
+        assert_eq!(log.lock().counts[ErrorClass::Api as usize].suppressed, 0);
+        assert_eq!(log.lock().counts[ErrorClass::Api as usize].in_a_row, 8);
+
+        // Once it works again, the burst starts over
+        log.recovered(ErrorClass::Api);
+        assert!(log.should_log(ErrorClass::Api));
+        assert!(log.should_log(ErrorClass::Api));
+        assert!(!log.should_log(ErrorClass::Api));
+    }
+}
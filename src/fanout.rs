@@ -0,0 +1,381 @@
+//! Sending every heartbeat to several destinations.
+//!
+//! [`FanoutSender`] hands each heartbeat to the main `WakaTime` API sender and
+//! to one [`ApiSender`] per `[[destinations]]` entry. The payload is built
+//! once per entity granularity and shared between their buffers. Every
+//! destination has its own offline queue and retry schedule, and they flush
+//! concurrently, so one that is down doesn't hold up the others.
+
+use std::borrow::Cow;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::PoisonError;
+
+use futures_util::future::BoxFuture;
+use futures_util::future::join_all;
+use tracing::info;
+use tracing::warn;
+
+use crate::api::ApiSender;
+use crate::api::DestinationStats;
+use crate::api::DestinationStatus;
+use crate::api::HeartbeatPayload;
+use crate::api::HeartbeatSender;
+use crate::api::SendError;
+use crate::browser;
+use crate::config::Config;
+use crate::config::EntityGranularity;
+use crate::domain::Entity;
+use crate::domain::EntityType;
+use crate::domain::Heartbeat;
+
+/// Destinations of the current sender, for status reports.
+#[derive(Debug, Default)]
+pub struct Destinations {
+    stats: Mutex<Vec<Arc<DestinationStats>>>,
+}
+
+impl Destinations {
+    /// Report on `stats` from now on, replacing the previous sender's.
+    pub fn set(&self, stats: Vec<Arc<DestinationStats>>) {
+        *self.stats.lock().unwrap_or_else(PoisonError::into_inner) = stats;
+    }
+
+    /// Counters of every destination, main one first.
+    #[must_use]
+    pub fn snapshot(&self) -> Vec<DestinationStatus> {
+        self.stats
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .iter()
+            .map(|stats| stats.snapshot())
+            .collect()
+    }
+}
+
+/// Sends heartbeats to the `WakaTime` API and every extra destination.
+pub struct FanoutSender {
+    primary: Target,
+    destinations: Vec<Target>,
+}
+
+/// A sender and the entity detail it receives.
+struct Target {
+    sender: ApiSender,
+    granularity: EntityGranularity,
+}
+
+impl FanoutSender {
+    /// Create senders for the `WakaTime` API and each `[[destinations]]`
+    /// entry.
+    ///
+    /// Extra destinations are skipped in dry run mode; the main sender
+    /// already logs (and captures) every heartbeat.
+    pub fn from_config(config: &Config) -> Result<Self, SendError> {
+        let primary = Target {
+            sender: ApiSender::from_config(config)?,
+            granularity: config.entity_granularity,
+        };
+
+        let mut destinations = Vec::new();
+        if config.dry_run && !config.destinations.is_empty() {
+            info!("[DRY RUN] Not sending to extra destinations");
+        } else {
+            config.validate_destinations()?;
+            for destination in &config.destinations {
+                destinations.push(Target {
+                    sender: ApiSender::for_destination(destination, config)?,
+                    granularity: destination.entity_granularity,
+                });
+            }
+        }
+
+        Ok(Self {
+            primary,
+            destinations,
+        })
+    }
+
+    /// Counters of every destination, main one first.
+    #[must_use]
+    pub fn stats(&self) -> Vec<Arc<DestinationStats>> {
+        std::iter::once(&self.primary)
+            .chain(&self.destinations)
+            .map(|target| target.sender.stats())
+            .collect()
+    }
+}
+
+impl HeartbeatSender for FanoutSender {
+    fn send_heartbeat<'a>(
+        &'a self,
+        heartbeat: &'a Heartbeat,
+    ) -> BoxFuture<'a, Result<(), SendError>> {
+        Box::pin(async move {
+            let mut payloads: Vec<(EntityGranularity, Arc<HeartbeatPayload>)> = Vec::new();
+            let mut payload_for = |granularity| {
+                if let Some((_, payload)) = payloads.iter().find(|(g, _)| *g == granularity) {
+                    return Arc::clone(payload);
+                }
+                let payload = Arc::new(HeartbeatPayload::from_heartbeat(&coarsen(
+                    heartbeat,
+                    granularity,
+                )));
+                payloads.push((granularity, Arc::clone(&payload)));
+                payload
+            };
+            let primary = payload_for(self.primary.granularity);
+            let copies: Vec<_> = self
+                .destinations
+                .iter()
+                .map(|destination| (destination, payload_for(destination.granularity)))
+                .collect();
+
+            let (result, _) = tokio::join!(
+                self.primary.sender.buffer_payload(primary),
+                join_all(copies.into_iter().map(|(destination, payload)| async move {
+                    if let Err(e) = destination.sender.buffer_payload(payload).await {
+                        warn!(
+                            "Failed to buffer heartbeat for {}: {e}",
+                            destination.sender.stats().name()
+                        );
+                    }
+                })),
+            );
+            result
+        })
+    }
+
+    /// Flush every destination concurrently. Only the main destination's
+    /// outcome is returned; the others are logged.
+    fn flush(&self) -> BoxFuture<'_, Result<(), SendError>> {
+        Box::pin(async move {
+            let (result, _) = tokio::join!(
+                self.primary.sender.flush(),
+                join_all(self.destinations.iter().map(|destination| async {
+                    if let Err(e) = destination.sender.flush().await {
+                        warn!(
+                            "Failed to flush heartbeats to {}: {e}",
+                            destination.sender.stats().name()
+                        );
+                    }
+                })),
+            );
+            result
+        })
+    }
+}
+
+/// Reduce a heartbeat to `granularity`. Heartbeats with more detail become
+/// `app` heartbeats for the app class. A reduced heartbeat loses its project
+/// and branch too, which often name a repository or client.
+fn coarsen(heartbeat: &Heartbeat, granularity: EntityGranularity) -> Cow<'_, Heartbeat> {
+    let needed = match heartbeat.entity_type {
+        EntityType::App if heartbeat.entity.as_str() == heartbeat.source.app_class => {
+            EntityGranularity::Class
+        }
+        EntityType::App => EntityGranularity::Title,
+        EntityType::Domain => EntityGranularity::Domain,
+        EntityType::Url | EntityType::File => EntityGranularity::File,
+    };
+    if granularity >= needed {
+        return Cow::Borrowed(heartbeat);
+    }
+    // A page URL comes down to its domain
+    if heartbeat.entity_type == EntityType::Url
+        && granularity == EntityGranularity::Domain
+        && let Some(domain) = browser::url_domain(heartbeat.entity.as_str())
+    {
+        return Cow::Owned(Heartbeat {
+            entity: Entity::new(domain),
+            entity_type: EntityType::Domain,
+            project: None,
+            branch: None,
+            ..heartbeat.clone()
+        });
+    }
+    Cow::Owned(Heartbeat {
+        entity: Entity::new(heartbeat.source.app_class.clone()),
+        entity_type: EntityType::App,
+        project: None,
+        language: None,
+        branch: None,
+        ..heartbeat.clone()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::FocusEvent;
+    use crate::config::ConfigError;
+    use crate::config::Destination;
+    use crate::domain::Category;
+    use crate::domain::Entity;
+
+    fn destination(name: &str) -> Destination {
+        Destination {
+            name: name.to_string(),
+            api_url: "https://wakapi.example.com/api".to_string(),
+            api_key: "key".to_string(),
+            ..Destination::default()
+        }
+    }
+
+    fn target(granularity: EntityGranularity) -> Target {
+        Target {
+            sender: ApiSender::offline(),
+            granularity,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_destinations_share_payloads() {
+        let sender = FanoutSender {
+            primary: target(EntityGranularity::Class),
+            destinations: vec![
+                target(EntityGranularity::File),
+                target(EntityGranularity::Class),
+                target(EntityGranularity::File),
+            ],
+        };
+        let heartbeat = Heartbeat::new(
+            Entity::new("code — main.rs"),
+            Category::Coding,
+            FocusEvent::new("code".to_string(), Some("main.rs".to_string()), None),
+        );
+        sender.send_heartbeat(&heartbeat).await.unwrap();
+
+        let buffered: Vec<_> = sender
+            .destinations
+            .iter()
+            .map(|destination| destination.sender.buffered()[0].clone())
+            .collect();
+        let primary = sender.primary.sender.buffered();
+        assert_eq!(primary.len(), 1);
+        assert_eq!(primary[0].entity(), "code");
+        assert!(Arc::ptr_eq(&primary[0], &buffered[1]));
+        assert!(Arc::ptr_eq(&buffered[0], &buffered[2]));
+        assert_eq!(buffered[0].entity(), "code — main.rs");
+    }
+
+    #[tokio::test]
+    async fn test_reduced_payloads_have_no_project_or_branch() {
+        let sender = FanoutSender {
+            primary: target(EntityGranularity::Class),
+            destinations: vec![target(EntityGranularity::File)],
+        };
+        let heartbeat = Heartbeat {
+            entity_type: EntityType::File,
+            project: Some("client-x".to_string()),
+            branch: Some("feature/acme-rebrand".to_string()),
+            ..Heartbeat::new(
+                Entity::new("main.rs"),
+                Category::Coding,
+                FocusEvent::new("code".to_string(), None, None),
+            )
+        };
+        sender.send_heartbeat(&heartbeat).await.unwrap();
+
+        let payload = |sender: &ApiSender| serde_json::to_value(&*sender.buffered()[0]).unwrap();
+        let primary = payload(&sender.primary.sender);
+        assert_eq!(primary["entity"], "code");
+        assert!(primary["project"].is_null());
+        assert!(primary["branch"].is_null());
+        let full = payload(&sender.destinations[0].sender);
+        assert_eq!(full["project"], "client-x");
+        assert_eq!(full["branch"], "feature/acme-rebrand");
+    }
+
+    #[test]
+    fn test_coarsen_by_granularity() {
+        let event = FocusEvent::new(
+            "firefox".to_string(),
+            Some("Issues · GitHub".to_string()),
+            None,
+        );
+        let domain = Heartbeat {
+            entity_type: EntityType::Domain,
+            ..Heartbeat::new(Entity::new("github.com"), Category::Browsing, event.clone())
+        };
+        assert!(matches!(
+            coarsen(&domain, EntityGranularity::Domain),
+            Cow::Borrowed(_)
+        ));
+        let app = coarsen(&domain, EntityGranularity::Title);
+        assert_eq!(app.entity.as_str(), "firefox");
+        assert_eq!(app.entity_type, EntityType::App);
+
+        let titled = Heartbeat::new(
+            Entity::new("firefox — Issues · GitHub"),
+            Category::Browsing,
+            event,
+        );
+        assert!(matches!(
+            coarsen(&titled, EntityGranularity::Title),
+            Cow::Borrowed(_)
+        ));
+        assert_eq!(
+            coarsen(&titled, EntityGranularity::Class).entity.as_str(),
+            "firefox"
+        );
+
+        let file = Heartbeat {
+            entity_type: EntityType::File,
+            language: Some("Rust".to_string()),
+            ..Heartbeat::new(
+                Entity::new("main.rs"),
+                Category::Coding,
+                FocusEvent::new("code".to_string(), None, None),
+            )
+        };
+        let app = coarsen(&file, EntityGranularity::Domain);
+        assert_eq!(app.entity.as_str(), "code");
+        assert_eq!(app.language, None);
+
+        let page = Heartbeat {
+            entity_type: EntityType::Url,
+            ..Heartbeat::new(
+                Entity::new("https://github.com/issues"),
+                Category::Browsing,
+                FocusEvent::new("firefox".to_string(), None, None),
+            )
+        };
+        let domain = coarsen(&page, EntityGranularity::Domain);
+        assert_eq!(domain.entity.as_str(), "github.com");
+        assert_eq!(domain.entity_type, EntityType::Domain);
+        assert_eq!(
+            coarsen(&page, EntityGranularity::Title).entity.as_str(),
+            "firefox"
+        );
+    }
+
+    #[test]
+    fn test_destination_names_are_checked() {
+        let config = |destinations| Config {
+            destinations,
+            ..Config::default()
+        };
+        assert!(
+            config(vec![destination("wakapi"), destination("self_2")])
+                .validate_destinations()
+                .is_ok()
+        );
+        assert!(matches!(
+            config(vec![destination("../x")]).validate_destinations(),
+            Err(ConfigError::InvalidDestinationName(_))
+        ));
+        assert!(matches!(
+            config(vec![destination("")]).validate_destinations(),
+            Err(ConfigError::InvalidDestinationName(_))
+        ));
+        assert!(matches!(
+            config(vec![destination("a"), destination("a")]).validate_destinations(),
+            Err(ConfigError::DuplicateDestination(_))
+        ));
+
+        let sender =
+            ApiSender::for_destination(&destination("wakapi"), &Config::default()).unwrap();
+        assert_eq!(sender.stats().name(), "wakapi");
+    }
+}
@@ -0,0 +1,728 @@
+//! Fleet mode: one daemon sends heartbeats for several machines.
+//!
+//! Client machines forward their heartbeats to a central daemon instead of
+//! the `WakaTime` API, so a desktop, a laptop, and a remote dev box are sent,
+//! queued offline, and reported as one timeline. [`FleetSender`] is the
+//! client side; [`FleetServer`] accepts the connections.
+//!
+//! The protocol is newline-delimited JSON over TCP, like the control socket:
+//! the client opens with a hello line naming its machine and token, then
+//! sends one heartbeat per line. Every line is answered with a control
+//! [`Response`]. There is no transport encryption; run it over a trusted
+//! network, VPN, or SSH tunnel. The server only listens on loopback
+//! addresses unless `[fleet] insecure_listen` is set, and reads no line
+//! longer than [`MAX_LINE`].
+//!
+//! With `[fleet] handoff`, the server also decides which machine is in use
+//! (see [`Handoff`]) and drops heartbeats from the others, so a laptop left
+//! awake next to the desktop doesn't double the hours.
+
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::io;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::PoisonError;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+use anyhow::Context;
+use anyhow::Result;
+use futures_util::future::BoxFuture;
+use serde::Deserialize;
+use serde::Serialize;
+use tokio::io::AsyncBufReadExt;
+use tokio::io::AsyncReadExt;
+use tokio::io::AsyncWriteExt;
+use tokio::io::BufReader;
+use tokio::net::TcpListener;
+use tokio::net::TcpStream;
+use tokio::net::tcp::OwnedReadHalf;
+use tokio::net::tcp::OwnedWriteHalf;
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+use tokio_util::sync::CancellationToken;
+use tracing::debug;
+use tracing::info;
+use tracing::warn;
+
+use crate::api;
+use crate::api::ApiSender;
+use crate::api::HeartbeatPayload;
+use crate::api::HeartbeatSender;
+use crate::api::SendError;
+use crate::config::Config;
+use crate::config::FleetConfig;
+use crate::control::Response;
+use crate::domain::Heartbeat;
+use crate::metrics;
+use crate::redact::sensitive;
+
+/// How long either side waits for the other to answer.
+const EXCHANGE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Longest line either side reads; hellos and heartbeats are a few hundred
+/// bytes.
+const MAX_LINE: u64 = 16 * 1024;
+
+/// How often the server flushes forwarded heartbeats to the API.
+const FLUSH_INTERVAL: Duration = Duration::from_mins(1);
+
+/// Offline queue file for forwarded heartbeats, kept apart from the local one.
+const QUEUE_FILE: &str = "fleet-queue.jsonl";
+
+/// How long the machine in use keeps the handoff without a focus change.
+const HANDOFF_TIMEOUT: Duration = Duration::from_mins(5);
+
+/// First line sent by a client.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Hello {
+    machine: String,
+    token: String,
+}
+
+/// A machine forwarding heartbeats to this daemon.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RemoteMachine {
+    /// Machine name from the `[fleet] clients` table.
+    pub name: String,
+    /// Open connections from the machine.
+    pub connections: u32,
+    /// Heartbeats received since the daemon started.
+    pub heartbeats_received: u64,
+    /// Received heartbeats dropped because another machine was in use.
+    #[serde(default)]
+    pub heartbeats_handed_off: u64,
+}
+
+/// Per-machine counters for the fleet server, reported in status.
+#[derive(Debug, Default)]
+pub struct FleetStats {
+    machines: Mutex<BTreeMap<String, RemoteMachine>>,
+}
+
+impl FleetStats {
+    /// Machines that have connected since the daemon started, by name.
+    #[must_use]
+    pub fn snapshot(&self) -> Vec<RemoteMachine> {
+        self.lock().values().cloned().collect()
+    }
+
+    fn update(&self, machine: &str, f: impl FnOnce(&mut RemoteMachine)) {
+        let mut machines = self.lock();
+        let entry = machines
+            .entry(machine.to_string())
+            .or_insert_with(|| RemoteMachine {
+                name: machine.to_string(),
+                ..RemoteMachine::default()
+            });
+        f(entry);
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, BTreeMap<String, RemoteMachine>> {
+        self.machines.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Which machine is in use, with `[fleet] handoff`.
+///
+/// A heartbeat for a different entity than the machine's previous one means
+/// someone switched windows there, so that machine takes over. Until it has
+/// gone [`HANDOFF_TIMEOUT`] without a switch, heartbeats from the other
+/// machines are dropped. The server's own heartbeats take part as well.
+#[derive(Debug, Default)]
+pub struct Handoff {
+    enabled: AtomicBool,
+    state: Mutex<HandoffState>,
+}
+
+#[derive(Debug, Default)]
+struct HandoffState {
+    /// Machine in use, and when it took over or last switched windows.
+    active: Option<(String, Instant)>,
+    /// Entity of each machine's previous heartbeat.
+    entities: HashMap<String, String>,
+}
+
+impl Handoff {
+    /// Turn the handoff on or off.
+    pub fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed);
+    }
+
+    /// Whether a heartbeat for `entity` from `machine` should be sent.
+    ///
+    /// Always `true` while the handoff is off.
+    pub fn admit(&self, machine: &str, entity: &str) -> bool {
+        if !self.enabled.load(Ordering::Relaxed) {
+            return true;
+        }
+
+        let now = Instant::now();
+        let mut state = self.lock();
+        let switched = state
+            .entities
+            .insert(machine.to_string(), entity.to_string())
+            .is_none_or(|previous| previous != entity);
+        match state.active {
+            Some((ref active, _)) if active == machine => {
+                if switched {
+                    state.active = Some((machine.to_string(), now));
+                }
+                true
+            }
+            Some((ref active, at)) if !switched && now - at < HANDOFF_TIMEOUT => {
+                debug!("Dropping heartbeat from {machine}: {active} is in use");
+                false
+            }
+            _ => {
+                info!("Handing off to {machine}");
+                state.active = Some((machine.to_string(), now));
+                true
+            }
+        }
+    }
+
+    /// The machine in use, if the handoff is on and one has taken over.
+    #[must_use]
+    pub fn active_machine(&self) -> Option<String> {
+        if !self.enabled.load(Ordering::Relaxed) {
+            return None;
+        }
+        self.lock()
+            .active
+            .as_ref()
+            .map(|(machine, _)| machine.clone())
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HandoffState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// A [`HeartbeatSender`] wrapper that drops the server's own heartbeats while
+/// another machine is in use.
+pub struct HandoffSender<S> {
+    inner: S,
+    handoff: Arc<Handoff>,
+    machine: String,
+}
+
+impl<S> HandoffSender<S> {
+    /// Wrap `inner`, taking part in `handoff` as this machine.
+    pub fn new(inner: S, handoff: Arc<Handoff>) -> Self {
+        let machine = api::hostname().unwrap_or_else(|_| "localhost".to_string());
+        Self {
+            inner,
+            handoff,
+            machine,
+        }
+    }
+}
+
+impl<S: HeartbeatSender + Sync> HeartbeatSender for HandoffSender<S> {
+    fn send_heartbeat<'a>(
+        &'a self,
+        heartbeat: &'a Heartbeat,
+    ) -> BoxFuture<'a, Result<(), SendError>> {
+        if !self.handoff.admit(&self.machine, heartbeat.entity.as_str()) {
+            return Box::pin(async { Ok(()) });
+        }
+        self.inner.send_heartbeat(heartbeat)
+    }
+
+    fn flush(&self) -> BoxFuture<'_, Result<(), SendError>> {
+        self.inner.flush()
+    }
+}
+
+/// Accepts heartbeats forwarded by other machines and sends them to the API.
+pub struct FleetServer {
+    listener: TcpListener,
+    clients: Arc<BTreeMap<String, String>>,
+    handoff: Arc<Handoff>,
+}
+
+impl FleetServer {
+    /// Bind the address in `[fleet] listen`, handing off between machines
+    /// through `handoff` if `[fleet] handoff` is set.
+    pub async fn bind(config: &FleetConfig, handoff: Arc<Handoff>) -> Result<Self> {
+        let addr = config
+            .listen
+            .as_deref()
+            .context("Fleet server requires `listen` in the [fleet] config section")?;
+        if config.clients.is_empty() {
+            warn!(
+                "Fleet server has no [fleet] clients configured, all connections will be refused"
+            );
+        }
+        let listener = TcpListener::bind(addr)
+            .await
+            .with_context(|| format!("Failed to bind fleet server to {addr}"))?;
+        let local = listener.local_addr()?;
+        if !local.ip().is_loopback() {
+            if !config.insecure_listen {
+                anyhow::bail!(
+                    "Fleet server would listen on {local} without transport encryption; \
+                     listen on a loopback address behind an SSH tunnel or VPN, or set \
+                     `insecure_listen = true` in the [fleet] config section"
+                );
+            }
+            warn!("Fleet server on {local} receives tokens and heartbeats unencrypted");
+        }
+
+        handoff.set_enabled(config.handoff);
+        Ok(Self {
+            listener,
+            clients: Arc::new(config.clients.clone()),
+            handoff,
+        })
+    }
+
+    /// Accept connections until `shutdown` is cancelled, buffering forwarded
+    /// heartbeats in `sender`.
+    ///
+    /// The returned task finishes after a final flush on shutdown.
+    pub fn serve(
+        self,
+        sender: Arc<ApiSender>,
+        stats: Arc<FleetStats>,
+        shutdown: CancellationToken,
+    ) -> JoinHandle<()> {
+        if let Ok(addr) = self.listener.local_addr() {
+            info!("Fleet server listening on {addr}");
+        }
+        tokio::spawn(async move {
+            let mut flush_timer = tokio::time::interval(FLUSH_INTERVAL);
+            loop {
+                tokio::select! {
+                    () = shutdown.cancelled() => break,
+                    _ = flush_timer.tick() => {
+                        if let Err(e) = sender.flush().await {
+                            warn!("Failed to flush forwarded heartbeats: {e}");
+                        }
+                    }
+                    accepted = self.listener.accept() => match accepted {
+                        Ok((stream, peer)) => {
+                            let server = Server {
+                                clients: Arc::clone(&self.clients),
+                                sender: Arc::clone(&sender),
+                                stats: Arc::clone(&stats),
+                                handoff: Arc::clone(&self.handoff),
+                            };
+                            tokio::spawn(async move {
+                                if let Err(e) = handle_connection(stream, &server).await {
+                                    debug!("Fleet connection from {peer} failed: {e}");
+                                }
+                            });
+                        }
+                        Err(e) => warn!("Failed to accept fleet connection: {e}"),
+                    },
+                }
+            }
+            if let Err(e) = sender.flush().await {
+                warn!("Failed to flush forwarded heartbeats on shutdown: {e}");
+            }
+        })
+    }
+}
+
+/// Create the sender that delivers forwarded heartbeats to the API.
+pub fn server_sender(config: &Config) -> Result<ApiSender> {
+    Ok(ApiSender::from_config(config)?.with_queue_file(QUEUE_FILE))
+}
+
+/// Server state shared with each connection.
+struct Server {
+    clients: Arc<BTreeMap<String, String>>,
+    sender: Arc<ApiSender>,
+    stats: Arc<FleetStats>,
+    handoff: Arc<Handoff>,
+}
+
+/// Authenticate a client, then buffer its heartbeats until it hangs up.
+async fn handle_connection(stream: TcpStream, server: &Server) -> io::Result<()> {
+    let (reader, mut writer) = stream.into_split();
+    let mut reader = BufReader::new(reader);
+
+    let hello = tokio::time::timeout(EXCHANGE_TIMEOUT, next_line(&mut reader))
+        .await
+        .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "no hello"))??
+        .and_then(|line| serde_json::from_str::<Hello>(&line).ok());
+    let Some(machine) = hello.and_then(|hello| authenticate(&server.clients, hello)) else {
+        write_response(&mut writer, &error("Authentication failed")).await?;
+        return Ok(());
+    };
+    write_response(&mut writer, &Response::Ok).await?;
+
+    info!("Fleet client {machine} connected");
+    server.stats.update(&machine, |m| m.connections += 1);
+    let result = forward_heartbeats(&mut reader, &mut writer, &machine, server).await;
+    server.stats.update(&machine, |m| m.connections -= 1);
+    info!("Fleet client {machine} disconnected");
+    result
+}
+
+/// Buffer each heartbeat line from an authenticated client.
+async fn forward_heartbeats(
+    reader: &mut BufReader<OwnedReadHalf>,
+    writer: &mut OwnedWriteHalf,
+    machine: &str,
+    server: &Server,
+) -> io::Result<()> {
+    while let Some(line) = next_line(reader).await? {
+        let response = match serde_json::from_str::<HeartbeatPayload>(&line) {
+            Ok(payload) if !server.handoff.admit(machine, payload.entity()) => {
+                // Acknowledged, so the client doesn't retry it
+                server.stats.update(machine, |m| {
+                    m.heartbeats_received += 1;
+                    m.heartbeats_handed_off += 1;
+                });
+                Response::Ok
+            }
+            Ok(payload) => {
+                // Attribute to the authenticated machine, whatever the client claims
+                match server
+                    .sender
+                    .buffer_payload(Arc::new(payload.with_machine(machine.to_string())))
+                    .await
+                {
+                    Ok(()) => {
+                        server.stats.update(machine, |m| m.heartbeats_received += 1);
+                        Response::Ok
+                    }
+                    Err(e) => error(&format!("Failed to buffer heartbeat: {e}")),
+                }
+            }
+            Err(e) => error(&format!("Invalid heartbeat: {e}")),
+        };
+        write_response(writer, &response).await?;
+    }
+    Ok(())
+}
+
+/// Read a line of at most [`MAX_LINE`] bytes without its line ending, or
+/// `None` at the end of the stream. Longer lines are an error.
+async fn next_line(reader: &mut BufReader<OwnedReadHalf>) -> io::Result<Option<String>> {
+    let mut line = String::new();
+    let read = reader.take(MAX_LINE).read_line(&mut line).await?;
+    if read == 0 {
+        return Ok(None);
+    }
+    if !line.ends_with('\n') && read as u64 == MAX_LINE {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("line longer than {MAX_LINE} bytes"),
+        ));
+    }
+    let len = line.trim_end_matches(['\n', '\r']).len();
+    line.truncate(len);
+    Ok(Some(line))
+}
+
+/// Return the machine name if the hello carries its configured token.
+fn authenticate(clients: &BTreeMap<String, String>, hello: Hello) -> Option<String> {
+    let expected = clients.get(&hello.machine)?;
+    if tokens_match(expected.as_bytes(), hello.token.as_bytes()) {
+        Some(hello.machine)
+    } else {
+        warn!("Fleet client {} sent a wrong token", hello.machine);
+        None
+    }
+}
+
+/// Compare tokens in time independent of where they differ.
+fn tokens_match(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+fn error(message: &str) -> Response {
+    Response::Error {
+        message: message.to_string(),
+    }
+}
+
+async fn write_response(writer: &mut OwnedWriteHalf, response: &Response) -> io::Result<()> {
+    let mut encoded = serde_json::to_string(response).map_err(io::Error::other)?;
+    encoded.push('\n');
+    writer.write_all(encoded.as_bytes()).await
+}
+
+/// An authenticated connection to the fleet server.
+struct Connection {
+    reader: BufReader<OwnedReadHalf>,
+    writer: OwnedWriteHalf,
+}
+
+impl Connection {
+    /// Send one line and wait for the server's answer.
+    async fn request(&mut self, line: &str) -> Result<(), SendError> {
+        let reply = async {
+            self.writer.write_all(line.as_bytes()).await?;
+            self.writer.write_all(b"\n").await?;
+            next_line(&mut self.reader).await?.ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "fleet server closed the connection",
+                )
+            })
+        }
+        .await
+        .map_err(SendError::FleetIo)?;
+
+        match serde_json::from_str(&reply).map_err(|_| SendError::FleetProtocol)? {
+            Response::Ok => Ok(()),
+            Response::Error { message } => Err(SendError::FleetRefused(message)),
+            Response::Status(_) | Response::Bar(_) | Response::Activity(_) => {
+                Err(SendError::FleetProtocol)
+            }
+        }
+    }
+}
+
+/// Forwards heartbeats to a fleet server instead of the `WakaTime` API.
+///
+/// Heartbeats are sent as they arrive over one long-lived connection, which
+/// is re-established on the next send after a failure. A failed send is
+/// reported to the event loop, which retries it like a failed API send.
+pub struct FleetSender {
+    server: String,
+    hello: String,
+    machine: String,
+    dry_run: bool,
+    capture_path: Option<PathBuf>,
+    connection: tokio::sync::Mutex<Option<Connection>>,
+}
+
+impl FleetSender {
+    /// Create a sender for the `[fleet] server` in `config`.
+    pub fn from_config(config: &Config) -> Result<Self> {
+        let fleet = &config.fleet;
+        let server = fleet
+            .server
+            .clone()
+            .context("Fleet forwarding requires `server` in the [fleet] config section")?;
+        let token = fleet
+            .token
+            .clone()
+            .context("Fleet forwarding requires `token` in the [fleet] config section")?;
+        let machine = match &fleet.machine {
+            Some(machine) => machine.clone(),
+            None => api::hostname().context("Failed to determine machine name")?,
+        };
+        let hello = serde_json::to_string(&Hello {
+            machine: machine.clone(),
+            token,
+        })?;
+
+        info!("Forwarding heartbeats to fleet server {server} as {machine}");
+        Ok(Self {
+            server,
+            hello,
+            machine,
+            dry_run: config.dry_run,
+            capture_path: config.dry_run_capture.clone(),
+            connection: tokio::sync::Mutex::new(None),
+        })
+    }
+
+    async fn connect(&self) -> Result<Connection, SendError> {
+        let stream =
+            TcpStream::connect(&self.server)
+                .await
+                .map_err(|source| SendError::FleetConnect {
+                    server: self.server.clone(),
+                    source,
+                })?;
+        let (reader, writer) = stream.into_split();
+        let mut connection = Connection {
+            reader: BufReader::new(reader),
+            writer,
+        };
+        connection.request(&self.hello).await?;
+        debug!("Connected to fleet server {}", self.server);
+        Ok(connection)
+    }
+
+    async fn forward(&self, heartbeat: &Heartbeat) -> Result<(), SendError> {
+        let payload =
+            HeartbeatPayload::from_heartbeat(heartbeat).with_machine(self.machine.clone());
+        let line = serde_json::to_string(&payload).expect("payloads serialize to JSON");
+        if self.dry_run {
+            info!(
+                "[DRY RUN] Would forward heartbeat to {}: {}",
+                self.server,
+                sensitive(&line)
+            );
+            if let Some(ref path) = self.capture_path {
+                api::capture_payload(path, &payload)?;
+            }
+            return Ok(());
+        }
+
+        let mut connection = self.connection.lock().await;
+        let exchange = async {
+            if connection.is_none() {
+                *connection = Some(self.connect().await?);
+            }
+            connection
+                .as_mut()
+                .expect("connected above")
+                .request(&line)
+                .await
+        };
+        let result = metrics::timed_send(tokio::time::timeout(EXCHANGE_TIMEOUT, exchange))
+            .await
+            .unwrap_or(Err(SendError::FleetTimeout));
+        if result.is_err() {
+            *connection = None;
+        }
+        result
+    }
+}
+
+impl HeartbeatSender for FleetSender {
+    fn send_heartbeat<'a>(
+        &'a self,
+        heartbeat: &'a Heartbeat,
+    ) -> BoxFuture<'a, Result<(), SendError>> {
+        Box::pin(self.forward(heartbeat))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::FocusEvent;
+    use crate::heartbeat::HeartbeatBuilder;
+
+    async fn start_server(sender: Arc<ApiSender>) -> (String, Arc<FleetStats>, CancellationToken) {
+        let fleet = FleetConfig {
+            listen: Some("127.0.0.1:0".to_string()),
+            clients: BTreeMap::from([("laptop".to_string(), "secret".to_string())]),
+            ..FleetConfig::default()
+        };
+        let server = FleetServer::bind(&fleet, Arc::default()).await.unwrap();
+        let addr = server.listener.local_addr().unwrap().to_string();
+        let stats = Arc::new(FleetStats::default());
+        let shutdown = CancellationToken::new();
+        server.serve(sender, Arc::clone(&stats), shutdown.clone());
+        (addr, stats, shutdown)
+    }
+
+    fn client_config(addr: &str, token: &str) -> Config {
+        let mut config = Config::default();
+        config.fleet.server = Some(addr.to_string());
+        config.fleet.token = Some(token.to_string());
+        config.fleet.machine = Some("laptop".to_string());
+        config
+    }
+
+    fn heartbeat(app_class: &str) -> Heartbeat {
+        let event = FocusEvent::new(app_class.to_string(), None, None);
+        HeartbeatBuilder::from_config(&Config::default()).build(event)
+    }
+
+    #[tokio::test]
+    async fn test_forwarded_heartbeats_are_buffered_for_the_machine() {
+        let sender = Arc::new(ApiSender::offline());
+        let (addr, stats, shutdown) = start_server(Arc::clone(&sender)).await;
+
+        let client = FleetSender::from_config(&client_config(&addr, "secret")).unwrap();
+        client.send_heartbeat(&heartbeat("firefox")).await.unwrap();
+        client.send_heartbeat(&heartbeat("kitty")).await.unwrap();
+
+        let buffered = serde_json::to_value(sender.buffered()).unwrap();
+        assert_eq!(buffered.as_array().unwrap().len(), 2);
+        assert_eq!(buffered[0]["entity"], "firefox");
+        assert_eq!(buffered[1]["machine_name_id"], "laptop");
+
+        let machines = stats.snapshot();
+        assert_eq!(machines.len(), 1);
+        assert_eq!(machines[0].name, "laptop");
+        assert_eq!(machines[0].connections, 1);
+        assert_eq!(machines[0].heartbeats_received, 2);
+        shutdown.cancel();
+    }
+
+    #[tokio::test]
+    async fn test_wrong_token_is_refused() {
+        let sender = Arc::new(ApiSender::offline());
+        let (addr, stats, shutdown) = start_server(Arc::clone(&sender)).await;
+
+        let client = FleetSender::from_config(&client_config(&addr, "guess")).unwrap();
+        let err = client
+            .send_heartbeat(&heartbeat("firefox"))
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("Authentication failed"), "{err}");
+        assert!(sender.buffered().is_empty());
+        assert!(stats.snapshot().is_empty());
+        shutdown.cancel();
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_handoff_follows_window_switches() {
+        let handoff = Handoff::default();
+        assert!(handoff.admit("desktop", "code"));
+        handoff.set_enabled(true);
+
+        assert!(handoff.admit("desktop", "code"));
+        assert!(handoff.admit("desktop", "code"));
+        // The laptop's first heartbeat is a switch there
+        assert!(handoff.admit("laptop", "firefox"));
+        assert!(!handoff.admit("desktop", "code"));
+        assert!(handoff.admit("desktop", "kitty"));
+        assert!(!handoff.admit("laptop", "firefox"));
+        assert_eq!(handoff.active_machine().as_deref(), Some("desktop"));
+
+        // Without switches, the handoff lapses
+        tokio::time::advance(HANDOFF_TIMEOUT).await;
+        assert!(handoff.admit("laptop", "firefox"));
+        assert!(!handoff.admit("desktop", "kitty"));
+        assert_eq!(handoff.active_machine().as_deref(), Some("laptop"));
+    }
+
+    #[tokio::test]
+    async fn test_non_loopback_listen_needs_opt_in() {
+        let mut fleet = FleetConfig {
+            listen: Some("0.0.0.0:0".to_string()),
+            ..FleetConfig::default()
+        };
+        let err = FleetServer::bind(&fleet, Arc::default())
+            .await
+            .err()
+            .expect("refused without insecure_listen");
+        assert!(err.to_string().contains("insecure_listen"), "{err}");
+
+        fleet.insecure_listen = true;
+        assert!(FleetServer::bind(&fleet, Arc::default()).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_overlong_line_closes_the_connection() {
+        let sender = Arc::new(ApiSender::offline());
+        let (addr, stats, shutdown) = start_server(Arc::clone(&sender)).await;
+
+        let mut stream = TcpStream::connect(&addr).await.unwrap();
+        let garbage = vec![b'a'; usize::try_from(MAX_LINE).unwrap() + 1];
+        // The server may hang up before it has all of it
+        let _ = stream.write_all(&garbage).await;
+        let mut reply = Vec::new();
+        let read = tokio::time::timeout(EXCHANGE_TIMEOUT, stream.read_to_end(&mut reply))
+            .await
+            .expect("the server kept waiting for a newline");
+        assert!(read.is_err() || reply.is_empty());
+        assert!(stats.snapshot().is_empty());
+        shutdown.cancel();
+    }
+
+    #[test]
+    fn test_tokens_match() {
+        assert!(tokens_match(b"secret", b"secret"));
+        assert!(!tokens_match(b"secret", b"secreT"));
+        assert!(!tokens_match(b"secret", b"secret2"));
+    }
+}
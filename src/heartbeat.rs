@@ -1,16 +1,38 @@
 //! Heartbeat construction from focus events.
 
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+
 use regex::Regex;
 use regex::RegexBuilder;
+use tracing::debug;
 use tracing::warn;
 
 use crate::backend::FocusEvent;
+use crate::browser;
+use crate::browser::BrowserMode;
+use crate::config::AppConfig;
+use crate::config::CategoryChain;
 use crate::config::CategoryRule;
+use crate::config::CategoryStep;
 use crate::config::Config;
-use crate::config::TitleStrategy;
+use crate::config::ConfigError;
+use crate::config::ProjectRule;
+use crate::config::TitlePolicy;
+use crate::desktop_entry::DesktopEntries;
 use crate::domain::Category;
+use crate::domain::CategorySource;
 use crate::domain::Entity;
+use crate::domain::EntityType;
 use crate::domain::Heartbeat;
+use crate::presets;
+use crate::redact::sensitive;
+use crate::repo::Repos;
+use crate::rules;
+use crate::title;
+
+/// Entity for time in apps excluded from tracking (`track_hidden_apps`).
+pub const HIDDEN_ENTITY: &str = "Hidden";
 
 /// Compiled category matching rule.
 struct CompiledRule {
@@ -18,43 +40,145 @@ struct CompiledRule {
     category: Category,
 }
 
+/// Compiled project rule; `None` patterns match anything.
+struct CompiledProjectRule {
+    app_class: Option<Regex>,
+    title: Option<Regex>,
+    workspace: Option<Regex>,
+    project: String,
+}
+
+impl CompiledProjectRule {
+    /// Whether every pattern of the rule matches the event.
+    fn matches(&self, event: &FocusEvent) -> bool {
+        let field_matches = |pattern: &Option<Regex>, field: Option<&str>| {
+            pattern
+                .as_ref()
+                .is_none_or(|pattern| field.is_some_and(|f| pattern.is_match(f)))
+        };
+        field_matches(&self.app_class, Some(&event.app_class))
+            && field_matches(&self.title, event.title.as_deref())
+            && field_matches(&self.workspace, event.workspace.as_deref())
+    }
+}
+
 /// Constructs Heartbeats from `FocusEvents` using configured rules.
 pub struct HeartbeatBuilder {
+    /// Categories by lowercased app class, from `[categories]`.
+    category_map: HashMap<String, Category>,
+    /// User category rules.
     rules: Vec<CompiledRule>,
-    default_category: Category,
-    track_titles: bool,
-    title_strategy: TitleStrategy,
+    /// AI tool detection and preset rules, evaluated after user rules.
+    preset_rules: Vec<CompiledRule>,
+    /// Terminal classes whose titles are checked for AI tools (empty = off).
+    ai_terminal_classes: Vec<String>,
+    /// AI tool commands looked for in terminal titles.
+    ai_terminal_commands: Vec<String>,
+    /// Where the category comes from when no user rule matches.
+    category_chain: CategoryChain,
+    /// Installed desktop entries, for the `desktop_entry` step.
+    desktop_entries: DesktopEntries,
+    /// Title policy for apps without their own.
+    title_policy: TitlePolicy,
     app_allowlist: Option<Vec<String>>,
     app_denylist: Option<Vec<String>>,
+    /// Per-app settings from config.
+    apps: BTreeMap<String, AppConfig>,
+    /// What browser time is attributed to.
+    browser_mode: BrowserMode,
+    /// Title patterns capturing the site, by lowercased browser class.
+    browser_title_patterns: HashMap<String, Regex>,
+    /// Terminal classes whose titles are parsed for a project (empty = off).
+    terminal_classes: Vec<String>,
+    /// Repository projects of terminal working directories.
+    repos: Repos,
+    /// Whether heartbeats carry the branch of repository projects.
+    git_branch: bool,
+    /// Project rules, evaluated before title-derived projects.
+    project_rules: Vec<CompiledProjectRule>,
+    /// Editor classes whose titles are parsed for a file (empty = off).
+    editor_classes: Vec<String>,
+    /// Patterns identifying video calls (empty = meeting mode off).
+    meeting_patterns: Vec<Regex>,
+    meeting_hide_titles: bool,
 }
 
 impl HeartbeatBuilder {
     /// Build from config, compiling regexes and validating.
+    #[must_use]
     pub fn from_config(config: &Config) -> Self {
-        let mut rules = Vec::new();
+        let ai_terminal_commands = if config.detect_ai_tools {
+            config.ai_terminal_commands.clone()
+        } else {
+            Vec::new()
+        };
 
-        for rule in &config.category_rules {
-            match compile_rule(rule) {
-                Ok(compiled) => rules.push(compiled),
-                Err(e) => {
-                    warn!("Skipping invalid category rule '{}': {}", rule.pattern, e);
-                }
-            }
-        }
+        let meeting_patterns = if config.meeting_mode {
+            config
+                .meeting_patterns
+                .iter()
+                .filter_map(|pattern| {
+                    RegexBuilder::new(pattern)
+                        .case_insensitive(true)
+                        .build()
+                        .inspect_err(|e| {
+                            warn!("Skipping invalid meeting pattern '{}': {}", pattern, e);
+                        })
+                        .ok()
+                })
+                .collect()
+        } else {
+            Vec::new()
+        };
 
         Self {
-            rules,
-            default_category: config.default_category,
-            track_titles: config.track_titles,
-            title_strategy: config.title_strategy.clone(),
+            category_map: config
+                .categories
+                .iter()
+                .map(|(class, category)| (class.to_lowercase(), *category))
+                .collect(),
+            rules: compile_rules(&config.category_rules),
+            preset_rules: compile_rules(&preset_rules(config)),
+            ai_terminal_classes: if ai_terminal_commands.is_empty() {
+                Vec::new()
+            } else {
+                config.terminal_classes.clone()
+            },
+            ai_terminal_commands,
+            category_chain: config.default_category.clone(),
+            desktop_entries: DesktopEntries::new(),
+            title_policy: config.title_policy,
             app_allowlist: config.app_allowlist.clone(),
             app_denylist: config.app_denylist.clone(),
+            apps: config.apps.clone(),
+            browser_mode: config.browser_mode,
+            browser_title_patterns: compile_browser_title_patterns(&config.browser_title_patterns),
+            terminal_classes: if config.parse_terminal_titles {
+                config.terminal_classes.clone()
+            } else {
+                Vec::new()
+            },
+            repos: Repos::new(config.git_project_name),
+            git_branch: config.git_branch,
+            project_rules: compile_project_rules(&config.project_rules),
+            editor_classes: if config.parse_editor_titles {
+                config.editor_classes.clone()
+            } else {
+                Vec::new()
+            },
+            meeting_patterns,
+            meeting_hide_titles: config.meeting_hide_titles,
         }
     }
 
-    /// Check if an app class is allowed based on allowlist/denylist.
+    /// Check if an app class is allowed based on temporary rules, then
+    /// allowlist/denylist.
     #[must_use]
     pub fn is_app_allowed(&self, app_class: &str) -> bool {
+        if let Some(allowed) = rules::global().allowed(app_class) {
+            return allowed;
+        }
+
         // Denylist takes precedence
         if let Some(ref denylist) = self.app_denylist
             && denylist.iter().any(|d| d.eq_ignore_ascii_case(app_class))
@@ -71,46 +195,498 @@ impl HeartbeatBuilder {
         true
     }
 
+    /// Like [`Self::is_app_allowed`], but also applying `app_denylist`
+    /// entries for the backend that reported the event, like
+    /// `"hyprland:sway"`.
+    #[must_use]
+    pub fn is_event_allowed(&self, event: &FocusEvent) -> bool {
+        let denied_for_backend = event.backend.is_some_and(|backend| {
+            let qualified = format!("{backend}:{}", event.app_class);
+            self.app_denylist
+                .iter()
+                .flatten()
+                .any(|d| d.eq_ignore_ascii_case(&qualified))
+        });
+        if denied_for_backend && rules::global().allowed(&event.app_class).is_none() {
+            return false;
+        }
+        self.is_app_allowed(&event.app_class)
+    }
+
+    /// Check if an app's own `WakaTime` plugin reports its activity.
+    ///
+    /// Configured `defer_to_plugin` settings win; otherwise editors with
+    /// well-known plugins defer by default.
+    #[must_use]
+    pub fn defers_to_plugin(&self, app_class: &str) -> bool {
+        self.apps
+            .iter()
+            .find(|(class, _)| class.eq_ignore_ascii_case(app_class))
+            .and_then(|(_, app)| app.defer_to_plugin)
+            .unwrap_or_else(|| has_known_plugin(app_class))
+    }
+
+    /// The title policy for an app: its own from `[apps]`, else the global
+    /// one.
+    #[must_use]
+    pub fn title_policy(&self, app_class: &str) -> TitlePolicy {
+        self.apps
+            .iter()
+            .find(|(class, _)| class.eq_ignore_ascii_case(app_class))
+            .and_then(|(_, app)| app.title_policy)
+            .unwrap_or(self.title_policy)
+    }
+
+    /// Construct the anonymous heartbeat counted for an app excluded by the
+    /// allowlist or denylist.
+    ///
+    /// Neither the app class nor the title is kept, and no category rule is
+    /// applied, so the heartbeat reveals only that some app was in use.
+    #[must_use]
+    pub fn build_hidden(&self) -> Heartbeat {
+        Heartbeat::new(
+            Entity::new(HIDDEN_ENTITY),
+            self.category_chain.fallback(),
+            FocusEvent::new(HIDDEN_ENTITY.to_string(), None, None),
+        )
+    }
+
     /// Construct a Heartbeat from a `FocusEvent`.
+    ///
+    /// Events carrying a browser tab URL, or a browser title matching its title
+    /// pattern, produce a `domain` heartbeat for the tab's website (or a `url`
+    /// one, per `browser_mode`) instead of an `app` heartbeat for the browser.
+    /// Titles are stripped of notification noise (see [`title::strip_noise`]).
+    /// Parsed editor titles produce a `file` heartbeat with project and
+    /// language. Video calls produce a `meeting` heartbeat categorized as
+    /// communicating.
     #[must_use]
-    pub fn build(&self, event: FocusEvent) -> Heartbeat {
-        let category = self.match_category(&event.app_class);
+    pub fn build(&self, mut event: FocusEvent) -> Heartbeat {
+        event.title = event.title.as_deref().map(title::strip_noise);
+        let rule_project = self.rule_project(&event);
+        if self.is_meeting(&event) {
+            return Heartbeat {
+                project: rule_project,
+                ..self.build_meeting(event)
+            };
+        }
+
+        let (category, category_source) = self.categorize(&event);
+
+        if let Some((site, entity_type)) = self.browser_site(&event) {
+            return Heartbeat {
+                entity_type,
+                category_source,
+                project: rule_project,
+                ..Heartbeat::new(Entity::new(site), category, event)
+            };
+        }
+
+        if let Some(editor) = self.editor_title(&event) {
+            let branch = match (&rule_project, &editor.project) {
+                (None, Some(project)) => self.editor_branch(&event, project),
+                _ => None,
+            };
+            return Heartbeat {
+                entity_type: EntityType::File,
+                category_source,
+                project: rule_project.or(editor.project),
+                language: editor
+                    .language
+                    .map(String::from)
+                    .or_else(|| self.app_language(&event.app_class)),
+                branch,
+                ..Heartbeat::new(Entity::new(editor.file), category, event)
+            };
+        }
+
         let entity = self.build_entity(&event);
+        let terminal = self.terminal_title(&event);
+        let (project, branch) = match (rule_project, &terminal) {
+            (Some(project), _) => (Some(project), None),
+            (None, Some(terminal)) => (
+                terminal.project(&self.repos),
+                self.git_branch
+                    .then(|| terminal.branch(&self.repos))
+                    .flatten(),
+            ),
+            (None, None) => (None, None),
+        };
+        let language = terminal
+            .and_then(|terminal| terminal.language)
+            .map(String::from)
+            .or_else(|| self.app_language(&event.app_class));
+        Heartbeat {
+            category_source,
+            project,
+            language,
+            branch,
+            ..Heartbeat::new(entity, category, event)
+        }
+    }
+
+    /// The branch of an editor's working directory, if its repository is
+    /// the project named in the editor's title.
+    fn editor_branch(&self, event: &FocusEvent, project: &str) -> Option<String> {
+        if !self.git_branch {
+            return None;
+        }
+        let cwd = event.process.as_ref()?.cwd.as_ref()?;
+        let repo_project = self.repos.project(cwd)?;
+        // With git_project_name = "remote", the repository is "owner/name"
+        let same = repo_project == project
+            || repo_project
+                .rsplit_once('/')
+                .is_some_and(|(_, name)| name == project);
+        if same { self.repos.branch(cwd) } else { None }
+    }
+
+    /// The language configured for an app in `[apps]`.
+    fn app_language(&self, app_class: &str) -> Option<String> {
+        self.apps
+            .iter()
+            .find(|(class, _)| class.eq_ignore_ascii_case(app_class))
+            .and_then(|(_, app)| app.language.clone())
+    }
+
+    /// The project of the first project rule matching the event.
+    fn rule_project(&self, event: &FocusEvent) -> Option<String> {
+        let rule = self.project_rules.iter().find(|rule| rule.matches(event))?;
+        debug!(
+            "Project rule matched {}: {}",
+            event.app_class,
+            sensitive(&rule.project)
+        );
+        Some(rule.project.clone())
+    }
+
+    /// The website of a browser event, from its tab URL or title pattern,
+    /// as a domain or page URL per `browser_mode`.
+    fn browser_site(&self, event: &FocusEvent) -> Option<(String, EntityType)> {
+        if self.browser_mode == BrowserMode::App {
+            return None;
+        }
+        let site = match event.url.clone() {
+            Some(url) => url,
+            None => self.title_site(event)?,
+        };
+        if self.browser_mode == BrowserMode::Url
+            && let Some(page) = browser::url_page(&site)
+        {
+            return Some((page, EntityType::Url));
+        }
+        browser::url_domain(&site)
+            .or_else(|| browser::bare_domain(&site))
+            .map(|domain| (domain, EntityType::Domain))
+    }
 
-        Heartbeat::new(entity, category, event)
+    /// The URL or domain captured by the browser's title pattern.
+    fn title_site(&self, event: &FocusEvent) -> Option<String> {
+        let pattern = self
+            .browser_title_patterns
+            .get(&event.app_class.to_lowercase())?;
+        let site = pattern.captures(event.title.as_deref()?)?.get(1)?.as_str();
+        debug!(
+            "Browser title pattern matched {}: {}",
+            event.app_class,
+            sensitive(site)
+        );
+        Some(site.to_string())
+    }
+
+    /// Check whether an event is a video call.
+    fn is_meeting(&self, event: &FocusEvent) -> bool {
+        let fields = [
+            Some(event.app_class.as_str()),
+            event.title.as_deref(),
+            event.url.as_deref(),
+        ];
+        self.meeting_patterns
+            .iter()
+            .any(|pattern| fields.iter().flatten().any(|f| pattern.is_match(f)))
+    }
+
+    /// Construct a meeting heartbeat, optionally without the title.
+    fn build_meeting(&self, mut event: FocusEvent) -> Heartbeat {
+        debug!("Meeting detected in {}", event.app_class);
+        if self.meeting_hide_titles {
+            event.title = None;
+        }
+
+        let (entity, entity_type) = match event.url.as_deref().and_then(browser::url_domain) {
+            Some(domain) => (Entity::new(domain), EntityType::Domain),
+            None => (self.build_entity(&event), EntityType::App),
+        };
+        Heartbeat {
+            entity_type,
+            category_source: CategorySource::Meeting,
+            meeting: true,
+            ..Heartbeat::new(entity, Category::Communicating, event)
+        }
+    }
+
+    /// Parse an editor's title, if it is a parsed editor.
+    fn editor_title(&self, event: &FocusEvent) -> Option<title::EditorTitle> {
+        if !self
+            .editor_classes
+            .iter()
+            .any(|e| e.eq_ignore_ascii_case(&event.app_class))
+        {
+            return None;
+        }
+
+        let parsed = title::parse_editor_title(event.title.as_deref()?, &event.app_class)?;
+        debug!(
+            "Parsed editor title: file={} project={:?} language={:?}",
+            sensitive(&parsed.file),
+            sensitive(&parsed.project),
+            parsed.language
+        );
+        Some(parsed)
+    }
+
+    /// Parse a terminal's title, if it is a parsed terminal.
+    fn terminal_title(&self, event: &FocusEvent) -> Option<title::TerminalTitle> {
+        if !self
+            .terminal_classes
+            .iter()
+            .any(|t| t.eq_ignore_ascii_case(&event.app_class))
+        {
+            return None;
+        }
+
+        let mut parsed = event
+            .title
+            .as_deref()
+            .map(title::parse_terminal_title)
+            .unwrap_or_default();
+        debug!(
+            "Parsed terminal title: cwd={:?} command={:?} language={:?}",
+            sensitive(&parsed.cwd),
+            sensitive(&parsed.command),
+            parsed.language
+        );
+        // Fall back to the terminal's own working directory, which is where
+        // it was started from: right for one window per project, the home
+        // directory (no project) for a terminal started by the compositor
+        if parsed.cwd.is_none() {
+            parsed.cwd = event
+                .process
+                .as_ref()
+                .and_then(|process| process.cwd.as_ref())
+                .map(|cwd| cwd.display().to_string());
+        }
+        Some(parsed)
     }
 
     /// Match the category for an app class using rules.
     ///
     /// Patterns are substring matches (not anchored). A pattern like `"code"`
     /// will match `"unicode-input"`. Use `^...$` anchors in config for exact matching.
+    #[cfg(test)]
     fn match_category(&self, app_class: &str) -> Category {
-        for rule in &self.rules {
-            if rule.pattern.is_match(app_class) {
-                return rule.category;
-            }
+        first_match(&self.rules, app_class).unwrap_or_else(|| self.fallback_category(app_class).0)
+    }
+
+    /// Match the category for an event, including AI tools run in terminals,
+    /// and report which step decided it.
+    ///
+    /// User rules still take precedence over the title check.
+    fn categorize(&self, event: &FocusEvent) -> (Category, CategorySource) {
+        if let Some(category) = rules::global().category(&event.app_class) {
+            return (category, CategorySource::Temporary);
+        }
+        if let Some(category) = self.category_map.get(&event.app_class.to_lowercase()) {
+            return (*category, CategorySource::Map);
         }
-        self.default_category
+        if let Some(category) = first_match(&self.rules, &event.app_class) {
+            return (category, CategorySource::Rule);
+        }
+        if self.is_ai_terminal(event) {
+            return (Category::AiCoding, CategorySource::AiTerminal);
+        }
+        self.fallback_category(&event.app_class)
     }
 
-    /// Build the entity string from a focus event.
-    fn build_entity(&self, event: &FocusEvent) -> Entity {
-        if self.track_titles {
-            match self.title_strategy {
-                TitleStrategy::Ignore => Entity::new(event.app_class.clone()),
-                TitleStrategy::Append => {
-                    if let Some(ref title) = event.title
-                        && !title.is_empty()
-                    {
-                        return Entity::new(format!("{} — {}", event.app_class, title));
+    /// Walk the `default_category` chain for an app no user rule matches.
+    fn fallback_category(&self, app_class: &str) -> (Category, CategorySource) {
+        for step in self.category_chain.steps() {
+            match *step {
+                CategoryStep::DesktopEntry => {
+                    if let Some(category) = self.desktop_entries.category(app_class) {
+                        return (category, CategorySource::DesktopEntry);
+                    }
+                }
+                CategoryStep::Presets => {
+                    if let Some(category) = first_match(&self.preset_rules, app_class) {
+                        return (category, CategorySource::Preset);
                     }
-                    Entity::new(event.app_class.clone())
                 }
+                CategoryStep::Category(category) => return (category, CategorySource::Default),
             }
+        }
+        (Category::default(), CategorySource::Default)
+    }
+
+    /// Check whether a terminal's title shows an AI coding tool running:
+    /// the command parsed from the title, not just any word in it.
+    fn is_ai_terminal(&self, event: &FocusEvent) -> bool {
+        let Some(title) = &event.title else {
+            return false;
+        };
+        if !self
+            .ai_terminal_classes
+            .iter()
+            .any(|t| t.eq_ignore_ascii_case(&event.app_class))
+        {
+            return false;
+        }
+        let Some(command) = title::parse_terminal_title(title).command else {
+            return false;
+        };
+        // "/usr/bin/aider" runs aider
+        let command = command.rsplit('/').next().unwrap_or(&command);
+        self.ai_terminal_commands
+            .iter()
+            .any(|c| c.eq_ignore_ascii_case(command))
+    }
+
+    /// Build the entity string from a focus event, applying the app's title
+    /// policy.
+    fn build_entity(&self, event: &FocusEvent) -> Entity {
+        let title = match event.title.as_deref() {
+            Some(title) if !title.is_empty() => title,
+            _ => return Entity::new(event.app_class.clone()),
+        };
+        let title = match self.title_policy(&event.app_class) {
+            TitlePolicy::Off => return Entity::new(event.app_class.clone()),
+            TitlePolicy::Normalized => title::normalize(title),
+            TitlePolicy::Full => title.to_string(),
+            TitlePolicy::Hashed => title::hash(title),
+        };
+        Entity::new(format!("{} — {}", event.app_class, title))
+    }
+}
+
+/// Whether an app class belongs to an editor with a widely used `WakaTime`
+/// plugin.
+fn has_known_plugin(app_class: &str) -> bool {
+    let class = app_class.to_ascii_lowercase();
+    matches!(
+        class.as_str(),
+        "code" | "code-oss" | "codium" | "vscodium" | "android-studio" | "neovide"
+    ) || class.starts_with("jetbrains-")
+}
+
+/// Category rules applied after the user's: AI tool detection, then presets.
+fn preset_rules(config: &Config) -> Vec<CategoryRule> {
+    let mut rules = Vec::new();
+    if config.detect_ai_tools {
+        rules.extend(presets::resolve("ai").unwrap_or_default());
+    }
+
+    for preset in &config.use_presets {
+        if let Some(preset_rules) = presets::resolve(preset) {
+            rules.extend(preset_rules);
         } else {
-            Entity::new(event.app_class.clone())
+            warn!(
+                "Skipping unknown category preset '{}' (available: {})",
+                preset,
+                presets::names().join(", ")
+            );
         }
     }
+    rules
+}
+
+/// Compile category rules, skipping (and logging) invalid ones.
+fn compile_rules(rules: &[CategoryRule]) -> Vec<CompiledRule> {
+    rules
+        .iter()
+        .filter_map(|rule| {
+            compile_rule(rule)
+                .inspect_err(|e| {
+                    warn!("Skipping invalid category rule '{}': {}", rule.pattern, e);
+                })
+                .ok()
+        })
+        .collect()
+}
+
+/// Compile browser title patterns, skipping (and logging) invalid ones.
+fn compile_browser_title_patterns(patterns: &BTreeMap<String, String>) -> HashMap<String, Regex> {
+    patterns
+        .iter()
+        .filter_map(|(class, pattern)| {
+            let regex = RegexBuilder::new(pattern)
+                .case_insensitive(true)
+                .build()
+                .inspect_err(|e| {
+                    warn!(
+                        "Skipping invalid browser title pattern for {}: {}",
+                        class, e
+                    );
+                })
+                .ok()?;
+            Some((class.to_lowercase(), regex))
+        })
+        .collect()
+}
+
+/// Compile project rules, skipping (and logging) invalid ones.
+fn compile_project_rules(rules: &[ProjectRule]) -> Vec<CompiledProjectRule> {
+    rules
+        .iter()
+        .filter_map(|rule| {
+            compile_project_rule(rule)
+                .inspect_err(|e| {
+                    if let Some(source) = std::error::Error::source(e) {
+                        warn!(
+                            "Skipping project rule for '{}': {e}: {source}",
+                            rule.project
+                        );
+                    } else {
+                        warn!("Skipping project rule for '{}': {e}", rule.project);
+                    }
+                })
+                .ok()
+        })
+        .collect()
+}
+
+/// Compile a project rule's patterns into case-insensitive regexes.
+fn compile_project_rule(rule: &ProjectRule) -> Result<CompiledProjectRule, ConfigError> {
+    if rule.app_class.is_none() && rule.title.is_none() && rule.workspace.is_none() {
+        return Err(ConfigError::EmptyProjectRule);
+    }
+    let compile = |pattern: &Option<String>| {
+        pattern
+            .as_deref()
+            .map(|pattern| {
+                RegexBuilder::new(pattern)
+                    .case_insensitive(true)
+                    .build()
+                    .map_err(|source| ConfigError::InvalidRegex {
+                        pattern: pattern.to_string(),
+                        source,
+                    })
+            })
+            .transpose()
+    };
+    Ok(CompiledProjectRule {
+        app_class: compile(&rule.app_class)?,
+        title: compile(&rule.title)?,
+        workspace: compile(&rule.workspace)?,
+        project: rule.project.clone(),
+    })
+}
+
+/// Return the category of the first rule matching `app_class`.
+fn first_match(rules: &[CompiledRule], app_class: &str) -> Option<Category> {
+    rules
+        .iter()
+        .find(|rule| rule.pattern.is_match(app_class))
+        .map(|rule| rule.category)
 }
 
 /// Compile a category rule into a case-insensitive regex.
@@ -127,7 +703,11 @@ fn compile_rule(rule: &CategoryRule) -> Result<CompiledRule, regex::Error> {
 
 #[cfg(test)]
 mod tests {
+    use std::path::PathBuf;
+
     use super::*;
+    use crate::backend::Backend;
+    use crate::backend::ProcessInfo;
     use crate::config::Config;
 
     #[test]
@@ -139,6 +719,107 @@ mod tests {
         assert_eq!(builder.match_category("firefox"), Category::Coding);
     }
 
+    #[test]
+    fn test_category_chain_reports_deciding_step() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("org.gnome.Evolution.desktop"),
+            "[Desktop Entry]\nCategories=GNOME;Office;Email;\n",
+        )
+        .unwrap();
+        let config = Config {
+            use_presets: vec!["chat".to_string()],
+            category_rules: vec![CategoryRule {
+                pattern: "^evolution$".to_string(),
+                category: Category::Notes,
+            }],
+            ..toml::from_str(r#"default_category = ["desktop_entry", "presets", "learning"]"#)
+                .unwrap()
+        };
+        let mut builder = HeartbeatBuilder::from_config(&config);
+        builder.desktop_entries = DesktopEntries::with_dirs(vec![dir.path().to_path_buf()]);
+
+        let categorize =
+            |class: &str| builder.categorize(&FocusEvent::new(class.into(), None, None));
+        assert_eq!(
+            categorize("evolution"),
+            (Category::Notes, CategorySource::Rule)
+        );
+        assert_eq!(
+            categorize("org.gnome.Evolution"),
+            (Category::Communicating, CategorySource::DesktopEntry)
+        );
+        assert_eq!(
+            categorize("Slack"),
+            (Category::Communicating, CategorySource::Preset)
+        );
+        assert_eq!(
+            categorize("kitty"),
+            (Category::Learning, CategorySource::Default)
+        );
+    }
+
+    #[test]
+    fn test_temporary_rules_override_config() {
+        // The rules are process-wide; this class is used by no other test
+        let class = "temporary-rule-app";
+        let config = Config {
+            app_denylist: Some(vec![class.to_string()]),
+            categories: BTreeMap::from([(class.to_string(), Category::Coding)]),
+            ..Default::default()
+        };
+        let builder = HeartbeatBuilder::from_config(&config);
+        assert!(!builder.is_app_allowed(class));
+
+        let hour = std::time::Duration::from_hours(1);
+        assert!(rules::global().add(class.to_string(), rules::RuleEffect::Allow, hour));
+        assert!(rules::global().add(
+            class.to_string(),
+            rules::RuleEffect::Category(Category::Designing),
+            hour,
+        ));
+        assert!(builder.is_app_allowed(class));
+        assert_eq!(
+            builder.categorize(&FocusEvent::new(class.into(), None, None)),
+            (Category::Designing, CategorySource::Temporary)
+        );
+
+        rules::global().clear(Some(class));
+        assert!(!builder.is_app_allowed(class));
+    }
+
+    #[test]
+    fn test_category_map_before_rules() {
+        let config = Config {
+            categories: BTreeMap::from([
+                ("Firefox".to_string(), Category::Researching),
+                ("code".to_string(), Category::Debugging),
+            ]),
+            category_rules: vec![CategoryRule {
+                pattern: "firefox".to_string(),
+                category: Category::Browsing,
+            }],
+            ..Default::default()
+        };
+        let builder = HeartbeatBuilder::from_config(&config);
+
+        let categorize =
+            |class: &str| builder.categorize(&FocusEvent::new(class.into(), None, None));
+        assert_eq!(
+            categorize("firefox"),
+            (Category::Researching, CategorySource::Map)
+        );
+        // Exact class only
+        assert_eq!(
+            categorize("firefox-developer-edition"),
+            (Category::Browsing, CategorySource::Rule)
+        );
+        assert_eq!(
+            categorize("Code"),
+            (Category::Debugging, CategorySource::Map)
+        );
+    }
+
     #[test]
     fn test_match_category_with_rules() {
         let config = Config {
@@ -180,6 +861,86 @@ mod tests {
         assert_eq!(builder.match_category("firefox"), Category::Browsing);
     }
 
+    #[test]
+    fn test_match_category_with_presets() {
+        let config = Config {
+            category_rules: vec![CategoryRule {
+                pattern: "^firefox$".to_string(),
+                category: Category::Researching,
+            }],
+            use_presets: vec!["default".to_string(), "bogus".to_string()],
+            ..Default::default()
+        };
+        let builder = HeartbeatBuilder::from_config(&config);
+
+        // User rules take precedence over presets
+        assert_eq!(builder.match_category("firefox"), Category::Researching);
+        assert_eq!(builder.match_category("chromium"), Category::Browsing);
+        assert_eq!(builder.match_category("Slack"), Category::Communicating);
+        assert_eq!(
+            builder.match_category("org.inkscape.Inkscape"),
+            Category::Designing
+        );
+        assert_eq!(builder.match_category("Cursor"), Category::AiCoding);
+        assert_eq!(builder.match_category("kitty"), Category::Coding);
+    }
+
+    #[test]
+    fn test_ai_tools_detected_by_default() {
+        let builder = HeartbeatBuilder::from_config(&Config::default());
+
+        assert_eq!(builder.match_category("Cursor"), Category::AiCoding);
+        assert_eq!(builder.match_category("Claude"), Category::AiCoding);
+        assert_eq!(
+            builder.match_category("chrome-chatgpt.com__-Default"),
+            Category::AiCoding
+        );
+
+        let terminal = |title: &str| {
+            builder
+                .build(FocusEvent::new(
+                    "kitty".to_string(),
+                    Some(title.to_string()),
+                    None,
+                ))
+                .category
+        };
+        assert_eq!(terminal("claude - ~/src/project"), Category::AiCoding);
+        assert_eq!(terminal("aider --model sonnet"), Category::AiCoding);
+        assert_eq!(terminal("cargo test - ~/src/claudette"), Category::Coding);
+        // Only the running command counts, not the rest of the title
+        assert_eq!(terminal("vim claude-notes.md"), Category::Coding);
+        assert_eq!(terminal("git log - ~/src/codex"), Category::Coding);
+        assert_eq!(
+            terminal("/usr/bin/aider - ~/src/project"),
+            Category::AiCoding
+        );
+    }
+
+    #[test]
+    fn test_ai_tools_user_rules_and_opt_out() {
+        let config = Config {
+            category_rules: vec![CategoryRule {
+                pattern: "^kitty$|^cursor$".to_string(),
+                category: Category::Debugging,
+            }],
+            ..Default::default()
+        };
+        let builder = HeartbeatBuilder::from_config(&config);
+        let event = FocusEvent::new("kitty".to_string(), Some("claude".to_string()), None);
+        assert_eq!(builder.build(event).category, Category::Debugging);
+        assert_eq!(builder.match_category("Cursor"), Category::Debugging);
+
+        let config = Config {
+            detect_ai_tools: false,
+            ..Default::default()
+        };
+        let builder = HeartbeatBuilder::from_config(&config);
+        assert_eq!(builder.match_category("Cursor"), Category::Coding);
+        let event = FocusEvent::new("kitty".to_string(), Some("claude".to_string()), None);
+        assert_eq!(builder.build(event).category, Category::Coding);
+    }
+
     #[test]
     fn test_build_entity_no_title() {
         let config = Config::default();
@@ -192,10 +953,9 @@ mod tests {
     }
 
     #[test]
-    fn test_build_entity_with_title_ignore() {
+    fn test_build_entity_with_title_policy_off() {
         let config = Config {
-            track_titles: true,
-            title_strategy: TitleStrategy::Ignore,
+            title_policy: TitlePolicy::Off,
             ..Default::default()
         };
 
@@ -208,10 +968,9 @@ mod tests {
     }
 
     #[test]
-    fn test_build_entity_with_title_append() {
+    fn test_build_entity_with_title_policy_full() {
         let config = Config {
-            track_titles: true,
-            title_strategy: TitleStrategy::Append,
+            title_policy: TitlePolicy::Full,
             ..Default::default()
         };
 
@@ -223,6 +982,441 @@ mod tests {
         assert_eq!(entity.as_str(), "code — main.rs");
     }
 
+    #[test]
+    fn test_build_entity_with_per_app_title_policy() {
+        let app = |title_policy| AppConfig {
+            title_policy: Some(title_policy),
+            ..AppConfig::default()
+        };
+        let config = Config {
+            title_policy: TitlePolicy::Full,
+            apps: BTreeMap::from([
+                ("Firefox".to_string(), app(TitlePolicy::Hashed)),
+                ("slack".to_string(), app(TitlePolicy::Off)),
+                ("thunderbird".to_string(), app(TitlePolicy::Normalized)),
+            ]),
+            ..Default::default()
+        };
+        let builder = HeartbeatBuilder::from_config(&config);
+        let entity = |class: &str, title: &str| {
+            let event = FocusEvent::new(class.to_string(), Some(title.to_string()), None);
+            builder.build_entity(&event).as_str().to_string()
+        };
+
+        assert_eq!(entity("code", "main.rs"), "code — main.rs");
+        assert_eq!(
+            entity("firefox", "GitHub"),
+            format!("firefox — {}", title::hash("GitHub"))
+        );
+        assert_eq!(entity("slack", "general"), "slack");
+        assert_eq!(
+            entity("thunderbird", "Inbox 2024 - Mail"),
+            "thunderbird — inbox # - mail"
+        );
+    }
+
+    #[test]
+    fn test_build_strips_title_noise() {
+        let config = Config {
+            title_policy: TitlePolicy::Full,
+            ..Default::default()
+        };
+        let builder = HeartbeatBuilder::from_config(&config);
+
+        let quiet = builder.build(FocusEvent::new(
+            "slack".to_string(),
+            Some("general - Acme - Slack".to_string()),
+            None,
+        ));
+        let noisy = builder.build(FocusEvent::new(
+            "slack".to_string(),
+            Some("(2) general - Acme - Slack".to_string()),
+            None,
+        ));
+        assert_eq!(noisy.entity, quiet.entity);
+    }
+
+    #[test]
+    fn test_build_with_browser_url_uses_domain() {
+        let config = Config {
+            title_policy: TitlePolicy::Full,
+            ..Config::default()
+        };
+        let builder = HeartbeatBuilder::from_config(&config);
+
+        let event = FocusEvent::new("firefox".to_string(), Some("Issues".to_string()), None)
+            .with_url(Some("https://github.com/issues".to_string()));
+        let heartbeat = builder.build(event);
+        assert_eq!(heartbeat.entity.as_str(), "github.com");
+        assert_eq!(heartbeat.entity_type, EntityType::Domain);
+
+        // URLs without a host fall back to the app entity
+        let event = FocusEvent::new("firefox".to_string(), None, None)
+            .with_url(Some("about:blank".to_string()));
+        let heartbeat = builder.build(event);
+        assert_eq!(heartbeat.entity.as_str(), "firefox");
+        assert_eq!(heartbeat.entity_type, EntityType::App);
+    }
+
+    #[test]
+    fn test_build_browser_mode_and_title_patterns() {
+        let config = Config {
+            browser_title_patterns: BTreeMap::from([
+                (
+                    "Firefox".to_string(),
+                    r"^.* - (\S+) — Mozilla Firefox$".to_string(),
+                ),
+                ("chromium".to_string(), "(unclosed".to_string()),
+            ]),
+            ..Config::default()
+        };
+        let builder = HeartbeatBuilder::from_config(&config);
+        assert_eq!(builder.browser_title_patterns.len(), 1);
+        let titled =
+            |title: &str| FocusEvent::new("firefox".to_string(), Some(title.to_string()), None);
+        let tab =
+            || titled("Issues").with_url(Some("https://user@github.com/a/b?q=1#top".to_string()));
+
+        let heartbeat = builder.build(titled("Docs - docs.rs — Mozilla Firefox"));
+        assert_eq!(heartbeat.entity.as_str(), "docs.rs");
+        assert_eq!(heartbeat.entity_type, EntityType::Domain);
+        let heartbeat = builder.build(titled("Docs - Mozilla Firefox"));
+        assert_eq!(heartbeat.entity_type, EntityType::App);
+
+        let builder = HeartbeatBuilder::from_config(&Config {
+            browser_mode: BrowserMode::Url,
+            ..config.clone()
+        });
+        let heartbeat = builder.build(tab());
+        assert_eq!(heartbeat.entity.as_str(), "https://github.com/a/b");
+        assert_eq!(heartbeat.entity_type, EntityType::Url);
+        // Only the domain is known
+        let heartbeat = builder.build(titled("Docs - docs.rs — Mozilla Firefox"));
+        assert_eq!(heartbeat.entity.as_str(), "docs.rs");
+        assert_eq!(heartbeat.entity_type, EntityType::Domain);
+
+        let builder = HeartbeatBuilder::from_config(&Config {
+            browser_mode: BrowserMode::App,
+            ..config
+        });
+        assert_eq!(builder.build(tab()).entity_type, EntityType::App);
+    }
+
+    #[test]
+    fn test_build_terminal_project() {
+        let config = Config {
+            parse_terminal_titles: true,
+            ..Config::default()
+        };
+        let builder = HeartbeatBuilder::from_config(&config);
+
+        let event = FocusEvent::new(
+            "kitty".to_string(),
+            Some("josh@laptop: /nonexistent/src/client-x".to_string()),
+            None,
+        );
+        let heartbeat = builder.build(event);
+        assert_eq!(heartbeat.entity.as_str(), "kitty");
+        assert_eq!(heartbeat.project.as_deref(), Some("client-x"));
+
+        // Not a terminal class
+        let event = FocusEvent::new(
+            "code".to_string(),
+            Some("/nonexistent/src/client-x".to_string()),
+            None,
+        );
+        assert_eq!(builder.build(event).project, None);
+
+        // A title without a directory falls back to the process's
+        let event = FocusEvent::new("kitty".to_string(), Some("nvim".to_string()), None)
+            .with_process(Some(ProcessInfo {
+                pid: 4242,
+                name: Some("kitty".to_string()),
+                cwd: Some(PathBuf::from("/nonexistent/src/client-y")),
+            }));
+        assert_eq!(builder.build(event).project.as_deref(), Some("client-y"));
+    }
+
+    #[test]
+    fn test_project_rules() {
+        let rule = |title: Option<&str>, workspace: Option<&str>, project: &str| ProjectRule {
+            app_class: None,
+            title: title.map(String::from),
+            workspace: workspace.map(String::from),
+            project: project.to_string(),
+        };
+        let config = Config {
+            parse_terminal_titles: true,
+            project_rules: vec![
+                rule(None, None, "ignored"),
+                rule(Some("["), None, "invalid"),
+                rule(Some("acme"), Some("^3$"), "acme-on-3"),
+                rule(Some("acme"), None, "acme"),
+            ],
+            ..Config::default()
+        };
+        let builder = HeartbeatBuilder::from_config(&config);
+        assert_eq!(builder.project_rules.len(), 2);
+
+        let event = FocusEvent::new("firefox".to_string(), Some("ACME wiki".to_string()), None);
+        assert_eq!(
+            builder.build(event.clone()).project.as_deref(),
+            Some("acme")
+        );
+        let event = event.with_workspace(Some("3".to_string()));
+        assert_eq!(builder.build(event).project.as_deref(), Some("acme-on-3"));
+
+        // Rules win over projects derived from titles
+        let event = FocusEvent::new(
+            "kitty".to_string(),
+            Some("josh@laptop: /nonexistent/src/acme-api".to_string()),
+            None,
+        );
+        assert_eq!(builder.build(event).project.as_deref(), Some("acme"));
+
+        let event = FocusEvent::new("firefox".to_string(), Some("GitHub".to_string()), None);
+        assert_eq!(builder.build(event).project, None);
+    }
+
+    #[test]
+    fn test_invalid_project_rules_have_typed_errors() {
+        let empty = ProjectRule {
+            app_class: None,
+            title: None,
+            workspace: None,
+            project: "acme".to_string(),
+        };
+        assert!(matches!(
+            compile_project_rule(&empty),
+            Err(ConfigError::EmptyProjectRule)
+        ));
+
+        let invalid = ProjectRule {
+            title: Some("[".to_string()),
+            ..empty
+        };
+        assert!(matches!(
+            compile_project_rule(&invalid),
+            Err(ConfigError::InvalidRegex { pattern, .. }) if pattern == "["
+        ));
+    }
+
+    #[test]
+    fn test_build_branch() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = dir.path().join("widgets");
+        std::fs::create_dir_all(repo.join(".git")).unwrap();
+        std::fs::write(repo.join(".git/HEAD"), "ref: refs/heads/main\n").unwrap();
+        let config = Config {
+            parse_terminal_titles: true,
+            parse_editor_titles: true,
+            ..Config::default()
+        };
+        let builder = HeartbeatBuilder::from_config(&config);
+
+        let terminal = FocusEvent::new(
+            "kitty".to_string(),
+            Some(format!("cargo test - {}", repo.display())),
+            None,
+        );
+        let heartbeat = builder.build(terminal.clone());
+        assert_eq!(heartbeat.project.as_deref(), Some("widgets"));
+        assert_eq!(heartbeat.branch.as_deref(), Some("main"));
+
+        // An editor only gets the branch of the project in its title
+        let editor = |title: &str| {
+            FocusEvent::new("dev.zed.Zed".to_string(), Some(title.to_string()), None).with_process(
+                Some(ProcessInfo {
+                    pid: 4242,
+                    name: Some("zed".to_string()),
+                    cwd: Some(repo.clone()),
+                }),
+            )
+        };
+        let heartbeat = builder.build(editor("main.rs - widgets - Zed"));
+        assert_eq!(heartbeat.branch.as_deref(), Some("main"));
+        let heartbeat = builder.build(editor("main.rs - gadgets - Zed"));
+        assert_eq!(heartbeat.branch, None);
+
+        let builder = HeartbeatBuilder::from_config(&Config {
+            git_branch: false,
+            ..config
+        });
+        assert_eq!(builder.build(terminal).branch, None);
+    }
+
+    #[test]
+    fn test_build_language() {
+        let config = Config {
+            parse_terminal_titles: true,
+            apps: BTreeMap::from([
+                (
+                    "RStudio".to_string(),
+                    AppConfig {
+                        language: Some("R".to_string()),
+                        ..AppConfig::default()
+                    },
+                ),
+                (
+                    "kitty".to_string(),
+                    AppConfig {
+                        language: Some("Bash".to_string()),
+                        ..AppConfig::default()
+                    },
+                ),
+            ]),
+            ..Config::default()
+        };
+        let builder = HeartbeatBuilder::from_config(&config);
+        let language = |class: &str, title: &str| {
+            let event = FocusEvent::new(class.to_string(), Some(title.to_string()), None);
+            builder.build(event).language
+        };
+
+        assert_eq!(
+            language("kitty", "nvim main.rs - ~/src/project").as_deref(),
+            Some("Rust")
+        );
+        // The app's language when the title has none
+        assert_eq!(language("kitty", "htop").as_deref(), Some("Bash"));
+        assert_eq!(language("rstudio", "analysis").as_deref(), Some("R"));
+        assert_eq!(language("firefox", "main.rs"), None);
+    }
+
+    #[test]
+    fn test_build_terminal_project_disabled_by_default() {
+        let builder = HeartbeatBuilder::from_config(&Config::default());
+        let event = FocusEvent::new(
+            "kitty".to_string(),
+            Some("/nonexistent/src/client-x".to_string()),
+            None,
+        );
+        assert_eq!(builder.build(event).project, None);
+    }
+
+    #[test]
+    fn test_build_editor_file() {
+        let config = Config {
+            parse_editor_titles: true,
+            ..Config::default()
+        };
+        let builder = HeartbeatBuilder::from_config(&config);
+
+        let event = FocusEvent::new(
+            "dev.zed.Zed".to_string(),
+            Some("main.rs - myproject - Zed".to_string()),
+            None,
+        );
+        let heartbeat = builder.build(event);
+        assert_eq!(heartbeat.entity.as_str(), "main.rs");
+        assert_eq!(heartbeat.entity_type, EntityType::File);
+        assert_eq!(heartbeat.project.as_deref(), Some("myproject"));
+        assert_eq!(heartbeat.language.as_deref(), Some("Rust"));
+
+        // No file in the title falls back to the app entity
+        let event = FocusEvent::new(
+            "dev.zed.Zed".to_string(),
+            Some("Welcome - Zed".to_string()),
+            None,
+        );
+        let heartbeat = builder.build(event);
+        assert_eq!(heartbeat.entity.as_str(), "dev.zed.Zed");
+        assert_eq!(heartbeat.entity_type, EntityType::App);
+    }
+
+    #[test]
+    fn test_build_editor_file_disabled_by_default() {
+        let builder = HeartbeatBuilder::from_config(&Config::default());
+        let event = FocusEvent::new(
+            "dev.zed.Zed".to_string(),
+            Some("main.rs - myproject - Zed".to_string()),
+            None,
+        );
+        let heartbeat = builder.build(event);
+        assert_eq!(heartbeat.entity_type, EntityType::App);
+        assert_eq!(heartbeat.language, None);
+    }
+
+    #[test]
+    fn test_build_meeting() {
+        let config = Config {
+            meeting_mode: true,
+            title_policy: TitlePolicy::Full,
+            ..Config::default()
+        };
+        let builder = HeartbeatBuilder::from_config(&config);
+
+        let event = FocusEvent::new(
+            "firefox".to_string(),
+            Some("Meet - abc-defg-hij — Mozilla Firefox".to_string()),
+            None,
+        );
+        let heartbeat = builder.build(event);
+        assert!(heartbeat.meeting);
+        assert_eq!(heartbeat.category, Category::Communicating);
+        assert_eq!(
+            heartbeat.entity.as_str(),
+            "firefox — Meet - abc-defg-hij — Mozilla Firefox"
+        );
+
+        let event = FocusEvent::new("zoom".to_string(), Some("Zoom Workplace".to_string()), None);
+        assert!(!builder.build(event).meeting);
+    }
+
+    #[test]
+    fn test_build_meeting_hides_titles() {
+        let config = Config {
+            meeting_mode: true,
+            meeting_hide_titles: true,
+            title_policy: TitlePolicy::Full,
+            ..Config::default()
+        };
+        let builder = HeartbeatBuilder::from_config(&config);
+
+        let event = FocusEvent::new("zoom".to_string(), Some("Zoom Meeting".to_string()), None);
+        let heartbeat = builder.build(event);
+        assert!(heartbeat.meeting);
+        assert_eq!(heartbeat.entity.as_str(), "zoom");
+        assert_eq!(heartbeat.source.title, None);
+    }
+
+    #[test]
+    fn test_build_meeting_disabled_by_default() {
+        let builder = HeartbeatBuilder::from_config(&Config::default());
+        let event = FocusEvent::new("zoom".to_string(), Some("Zoom Meeting".to_string()), None);
+        let heartbeat = builder.build(event);
+        assert!(!heartbeat.meeting);
+        assert_eq!(heartbeat.category, Category::Coding);
+    }
+
+    #[test]
+    fn test_defers_to_plugin_defaults() {
+        let builder = HeartbeatBuilder::from_config(&Config::default());
+        assert!(builder.defers_to_plugin("code"));
+        assert!(builder.defers_to_plugin("Code"));
+        assert!(builder.defers_to_plugin("jetbrains-rustrover"));
+        assert!(!builder.defers_to_plugin("firefox"));
+        assert!(!builder.defers_to_plugin("dev.zed.Zed"));
+    }
+
+    #[test]
+    fn test_defers_to_plugin_overrides() {
+        let config: Config = toml::from_str(
+            r#"
+            [apps.code]
+            defer_to_plugin = false
+
+            [apps."org.gnome.gitlab.somebody.Editor"]
+            defer_to_plugin = true
+            "#,
+        )
+        .unwrap();
+        let builder = HeartbeatBuilder::from_config(&config);
+        assert!(!builder.defers_to_plugin("code"));
+        assert!(builder.defers_to_plugin("org.gnome.gitlab.somebody.Editor"));
+        assert!(builder.defers_to_plugin("codium"));
+    }
+
     #[test]
     fn test_is_app_allowed_no_filters() {
         let config = Config::default();
@@ -245,6 +1439,20 @@ mod tests {
         assert!(!builder.is_app_allowed("slack"));
     }
 
+    #[test]
+    fn test_is_event_allowed_with_backend_denylist() {
+        let config = Config {
+            app_denylist: Some(vec!["hyprland:sway".to_string()]),
+            ..Default::default()
+        };
+        let builder = HeartbeatBuilder::from_config(&config);
+
+        let event = FocusEvent::new("sway".to_string(), None, None);
+        assert!(!builder.is_event_allowed(&event.clone().with_backend(Backend::Hyprland)));
+        assert!(builder.is_event_allowed(&event.clone().with_backend(Backend::Sway)));
+        assert!(builder.is_event_allowed(&event));
+    }
+
     #[test]
     fn test_is_app_allowed_with_allowlist() {
         let config = Config {
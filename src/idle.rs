@@ -1,45 +1,205 @@
-//! Idle detection via systemd-logind `DBus` interface.
+//! Idle detection from a chain of idle sources.
 //!
-//! Polls `IdleHint` property from the current session.
+//! Like focus backends, each idle source implements a common trait,
+//! [`IdleSource`], and reports what it sees to the shared [`IdleMonitor`].
+//! The monitor tries the sources in `idle_sources` order and falls back to
+//! the next one when a source is unavailable or stops working:
+//!
+//! - `wayland`: the compositor's `ext-idle-notify-v1` protocol.
+//! - `logind`: the session's `IdleHint` and `LockedHint` from
+//!   systemd-logind.
+//! - `screensaver`: the `org.freedesktop.ScreenSaver` service on the session
+//!   bus.
+//!
+//! Alongside the idle source, the monitor listens for `PrepareForSleep` so
+//! the event loop can reset its state after a resume. When battery-aware, it
+//! also watches `UPower`'s `OnBattery` property and polls less often while
+//! unplugged.
+//!
+//! With an idle timeout, the monitor also counts the session as idle once it
+//! has seen no activity for that long, whatever the idle source says:
+//! activity being focus and title changes, and the idle source reporting a
+//! return from idle. With input tracking on, keyboard and pointer input seen
+//! through `ext-idle-notify-v1` counts as well.
+//!
+//! When no source in the chain works and no idle timeout is set, idle gating
+//! is unavailable and the session is always treated as active. Idle gating
+//! can also be turned off on purpose, with `idle_gating = false` or at runtime
+//! with `idle disable`; the sources keep running, but heartbeats no longer
+//! stop while the session is idle or locked.
 
-use std::env;
+use std::fmt;
 use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::PoisonError;
 use std::sync::atomic::AtomicBool;
 use std::sync::atomic::Ordering;
 use std::time::Duration;
 
-use anyhow::Context;
 use anyhow::Result;
-use tokio::sync::RwLock;
+use futures_util::future::BoxFuture;
+use serde::Deserialize;
+use serde::Serialize;
+use tokio::sync::Notify;
+use tokio::time::Instant;
 use tokio_util::sync::CancellationToken;
 use tracing::debug;
 use tracing::error;
 use tracing::info;
 use tracing::trace;
 use tracing::warn;
-use zbus::Connection;
 
-/// `DBus` service and path for login1.
-const LOGIND_SERVICE: &str = "org.freedesktop.login1";
-const LOGIND_PATH: &str = "/org/freedesktop/login1";
-const MANAGER_INTERFACE: &str = "org.freedesktop.login1.Manager";
+#[cfg(feature = "logind")]
+pub mod logind;
+#[cfg(feature = "screensaver")]
+pub mod screensaver;
+#[cfg(feature = "idle-notify")]
+pub mod wayland;
+
+/// Whether idle and lock state gate heartbeats, process-wide.
+static GATING: AtomicBool = AtomicBool::new(true);
+
+/// Missed polls after which the monitor counts as stalled.
+const STALL_POLLS: u32 = 3;
+
+/// Inactivity after which the `wayland` source reports idle, unless
+/// `idle_timeout_seconds` is set.
+#[cfg_attr(not(feature = "idle-notify"), allow(dead_code))]
+const DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_mins(5);
+
+/// Inactivity after which the input watcher stops counting the seat as in
+/// use.
+#[cfg_attr(not(feature = "idle-notify"), allow(dead_code))]
+const INPUT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Trait for idle state sources.
+pub trait IdleSource: Send {
+    /// Follow the idle state, reporting it to `monitor`, until `shutdown` is
+    /// cancelled.
+    ///
+    /// Returns an error when the source stops working; the monitor then
+    /// falls back to the next source in the chain.
+    fn run<'a>(
+        &'a mut self,
+        monitor: &'a IdleMonitor,
+        shutdown: &'a CancellationToken,
+    ) -> BoxFuture<'a, Result<()>>;
+}
+
+/// Available idle sources.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IdleBackend {
+    /// systemd-logind's `IdleHint` and `LockedHint`.
+    Logind,
+    /// The Wayland `ext-idle-notify-v1` protocol.
+    #[serde(alias = "idle-notify")]
+    Wayland,
+    /// The `org.freedesktop.ScreenSaver` `DBus` service.
+    Screensaver,
+}
+
+impl fmt::Display for IdleBackend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Logind => write!(f, "logind"),
+            Self::Wayland => write!(f, "wayland"),
+            Self::Screensaver => write!(f, "screensaver"),
+        }
+    }
+}
+
+impl IdleBackend {
+    /// The default fallback chain: the compositor first, since many never
+    /// set logind's `IdleHint`, then logind, which also reports the lock
+    /// state, for desktops without `ext-idle-notify-v1`.
+    pub const DEFAULT_CHAIN: [Self; 3] = [Self::Wayland, Self::Logind, Self::Screensaver];
 
-/// Idle monitor that polls systemd-logind for idle state.
+    /// Whether this build includes the source.
+    #[must_use]
+    pub fn is_compiled(self) -> bool {
+        match self {
+            Self::Logind => cfg!(feature = "logind"),
+            Self::Wayland => cfg!(feature = "idle-notify"),
+            Self::Screensaver => cfg!(feature = "screensaver"),
+        }
+    }
+}
+
+/// Turn idle gating on or off process-wide.
+pub fn set_gating(enabled: bool) {
+    if GATING.swap(enabled, Ordering::Relaxed) != enabled {
+        if enabled {
+            info!("Idle gating enabled");
+        } else {
+            info!("Idle gating disabled, heartbeats continue while idle or locked");
+        }
+    }
+}
+
+/// Whether idle gating is on.
+#[must_use]
+pub fn gating() -> bool {
+    GATING.load(Ordering::Relaxed)
+}
+
+/// Idle monitor that follows the idle state from a chain of idle sources.
 ///
 /// Always used behind `Arc<IdleMonitor>` for sharing between tasks.
-/// Interior mutability is provided by `AtomicBool` and `RwLock` directly.
+/// Interior mutability is provided by `AtomicBool` and `Mutex` directly.
+#[derive(Debug)]
 pub struct IdleMonitor {
     /// Cached idle state.
     idle_hint: AtomicBool,
 
-    /// Session object path in `DBus`.
-    session_path: RwLock<Option<String>>,
+    /// Cached lock state.
+    locked: AtomicBool,
+
+    /// Whether a locked session counts as away.
+    pause_when_locked: bool,
+
+    /// Idle sources to try, in order.
+    sources: Vec<IdleBackend>,
+
+    /// logind session ID to watch instead of the detected one.
+    #[cfg_attr(not(feature = "logind"), allow(dead_code))]
+    session_id: Option<String>,
 
-    /// Cached `DBus` system connection, reused across polls.
-    connection: RwLock<Option<Connection>>,
+    /// Inactivity after which the session counts as idle, whatever the idle
+    /// source says.
+    idle_timeout: Option<Duration>,
+
+    /// When the monitor was created, the start of the first inactivity.
+    created: Instant,
 
     /// Whether idle monitoring is available/enabled.
     enabled: AtomicBool,
+
+    /// Signalled when the system resumes from suspend.
+    resumed: Notify,
+
+    /// Signalled when the session stops being idle or locked.
+    returned: Notify,
+
+    /// When user activity was last observed.
+    last_activity: Mutex<Option<Instant>>,
+
+    /// Whether to watch keyboard and pointer input for activity.
+    #[cfg_attr(not(feature = "idle-notify"), allow(dead_code))]
+    track_input: bool,
+
+    /// Whether the input watcher saw input within the last
+    /// [`INPUT_TIMEOUT`].
+    input_active: AtomicBool,
+
+    /// Whether the system is running on battery, as reported by `UPower`.
+    on_battery: AtomicBool,
+
+    /// When the last successful poll happened, and the interval in effect.
+    last_poll: Mutex<Option<(Instant, Duration)>>,
+
+    /// Signalled to re-initialize the polling task.
+    restart: Notify,
 }
 
 impl IdleMonitor {
@@ -48,75 +208,218 @@ impl IdleMonitor {
     pub fn new() -> Self {
         Self {
             idle_hint: AtomicBool::new(false),
-            session_path: RwLock::new(None),
-            connection: RwLock::new(None),
+            locked: AtomicBool::new(false),
+            pause_when_locked: true,
+            sources: IdleBackend::DEFAULT_CHAIN.to_vec(),
+            session_id: None,
+            idle_timeout: None,
+            created: Instant::now(),
             enabled: AtomicBool::new(true),
+            resumed: Notify::new(),
+            returned: Notify::new(),
+            last_activity: Mutex::new(None),
+            track_input: false,
+            input_active: AtomicBool::new(false),
+            on_battery: AtomicBool::new(false),
+            last_poll: Mutex::new(None),
+            restart: Notify::new(),
         }
     }
 
+    /// Watch the logind session `session_id` instead of detecting it.
+    #[must_use]
+    pub fn with_session(mut self, session_id: Option<String>) -> Self {
+        self.session_id = session_id;
+        self
+    }
+
+    /// Whether [`is_locked`](Self::is_locked) reports a locked session.
+    #[must_use]
+    pub fn with_lock_gating(mut self, pause_when_locked: bool) -> Self {
+        self.pause_when_locked = pause_when_locked;
+        self
+    }
+
+    /// Try the idle sources in `sources` order instead of the default chain.
+    #[must_use]
+    pub fn with_sources(mut self, sources: Vec<IdleBackend>) -> Self {
+        self.sources = sources;
+        self
+    }
+
+    /// Count the session as idle after `timeout` without activity, and have
+    /// the `wayland` source report idle after that long without input.
+    #[must_use]
+    pub fn with_idle_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.idle_timeout = timeout;
+        self
+    }
+
+    /// Watch keyboard and pointer input, so typing in the focused window
+    /// counts as activity and not only focus and title changes.
+    #[must_use]
+    pub fn with_input_tracking(mut self, track_input: bool) -> Self {
+        self.track_input = track_input;
+        self
+    }
+
     /// Check if the session is currently idle.
     ///
-    /// Returns the cached idle state. Use `poll_idle_state` to refresh.
-    /// Returns false if idle monitoring is disabled.
+    /// Returns the cached idle state, as last reported by the idle source,
+    /// or true once the idle timeout passed without activity. The idle
+    /// source's state is ignored if idle monitoring is disabled.
     pub fn is_idle(&self) -> bool {
-        if !self.enabled.load(Ordering::Relaxed) {
-            return false; // If disabled, assume not idle
-        }
-        self.idle_hint.load(Ordering::Relaxed)
+        (self.enabled.load(Ordering::Relaxed) && self.idle_hint.load(Ordering::Relaxed))
+            || self.timed_out()
     }
 
-    /// Initialize the monitor by connecting to D-Bus and resolving the session path.
-    pub async fn init(&self) -> Result<()> {
-        let conn = Connection::system()
-            .await
-            .context("Failed to connect to system DBus")?;
-
-        let session_path = resolve_session_path(&conn).await?;
-        info!("Resolved session path: {}", session_path);
+    /// Whether the idle timeout passed since the last activity.
+    fn timed_out(&self) -> bool {
+        self.idle_timeout.is_some_and(|timeout| {
+            let last_activity = *self
+                .last_activity
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner);
+            last_activity.unwrap_or(self.created).elapsed() >= timeout
+        })
+    }
 
-        *self.session_path.write().await = Some(session_path);
-        *self.connection.write().await = Some(conn);
-        Ok(())
+    /// Check if the session is currently locked.
+    ///
+    /// Returns false if idle monitoring is disabled or lock gating is off.
+    pub fn is_locked(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+            && self.pause_when_locked
+            && self.locked.load(Ordering::Relaxed)
     }
 
-    /// Poll the current idle state from `DBus`.
+    /// Why heartbeats should be skipped: the session is idle or locked.
     ///
-    /// This updates the cached `idle_hint` value. Reuses the cached D-Bus
-    /// connection established during `init()`, reconnecting if necessary.
-    pub async fn poll_idle_state(&self) -> Result<bool> {
-        let session_path = self.session_path.read().await;
-        let Some(ref path) = *session_path else {
-            return Ok(false); // Not initialized
-        };
+    /// Always `None` while idle gating is turned off.
+    #[must_use]
+    pub fn away_reason(&self) -> Option<&'static str> {
+        if !gating() {
+            None
+        } else if self.is_idle() {
+            Some("idle")
+        } else if self.is_locked() {
+            Some("locked")
+        } else {
+            None
+        }
+    }
 
-        let conn = self.get_or_reconnect().await?;
-        let idle = get_idle_hint(&conn, path).await?;
+    /// Store a new idle hint, treating a return from idle as activity.
+    fn update_idle_hint(&self, idle: bool) {
         let prev = self.idle_hint.swap(idle, Ordering::Relaxed);
 
         if idle == prev {
             trace!("Idle state: {}", idle);
         } else {
             debug!("Idle state changed: {} -> {}", prev, idle);
+            if !idle {
+                self.record_activity();
+                if !(self.pause_when_locked && self.locked.load(Ordering::Relaxed)) {
+                    self.returned.notify_one();
+                }
+            }
         }
+    }
 
-        Ok(idle)
+    /// Store a new lock state, treating an unlock as activity.
+    fn update_locked(&self, locked: bool) {
+        if self.locked.swap(locked, Ordering::Relaxed) != locked {
+            if locked {
+                info!("Session locked");
+            } else {
+                info!("Session unlocked");
+                self.record_activity();
+                if self.pause_when_locked && !self.idle_hint.load(Ordering::Relaxed) {
+                    self.returned.notify_one();
+                }
+            }
+        }
     }
 
-    /// Return the cached D-Bus connection or establish a new one.
-    async fn get_or_reconnect(&self) -> Result<Connection> {
-        {
-            let guard = self.connection.read().await;
-            if let Some(ref conn) = *guard {
-                return Ok(conn.clone());
+    /// Record that user activity was just observed.
+    ///
+    /// Idle sources only report idle/active transitions, so callers that see
+    /// other evidence of input (such as focus or title changes) report it here.
+    pub fn record_activity(&self) {
+        *self
+            .last_activity
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = Some(Instant::now());
+    }
+
+    /// Record what the input watcher saw: input starting again, or no
+    /// input for [`INPUT_TIMEOUT`].
+    #[cfg_attr(not(feature = "idle-notify"), allow(dead_code))]
+    fn record_input(&self, active: bool) {
+        // Either way there was input, just now or a moment ago
+        self.record_activity();
+        self.input_active.store(active, Ordering::Relaxed);
+    }
+
+    /// Whether any activity was recorded after `since`, or input is ongoing.
+    #[must_use]
+    pub fn active_since(&self, since: Instant) -> bool {
+        self.input_active.load(Ordering::Relaxed)
+            || self
+                .last_activity
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .is_some_and(|at| at > since)
+    }
+
+    /// Whether the system is running on battery.
+    ///
+    /// Always false unless battery-aware polling was started.
+    #[must_use]
+    pub fn on_battery(&self) -> bool {
+        self.on_battery.load(Ordering::Relaxed)
+    }
+
+    /// Set the power source state.
+    ///
+    /// Called by the `UPower` watcher; also useful for testing without D-Bus.
+    pub fn set_on_battery(&self, on_battery: bool) {
+        if self.on_battery.swap(on_battery, Ordering::Relaxed) != on_battery {
+            if on_battery {
+                info!("Running on battery, reducing wakeups");
+            } else {
+                info!("Running on AC power");
             }
         }
+    }
 
-        debug!("D-Bus connection not cached, reconnecting");
-        let conn = Connection::system()
-            .await
-            .context("Failed to connect to system DBus")?;
-        *self.connection.write().await = Some(conn.clone());
-        Ok(conn)
+    /// Record a successful poll made with the given poll interval.
+    pub fn record_poll(&self, interval: Duration) {
+        *self
+            .last_poll
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = Some((Instant::now(), interval));
+    }
+
+    /// Whether polling has stopped updating the idle state.
+    ///
+    /// True once several poll intervals have passed without a successful
+    /// poll. A monitor that never started polling, e.g. because its idle
+    /// source reports changes as they happen, is not stalled.
+    #[must_use]
+    pub fn is_stalled(&self) -> bool {
+        if !self.enabled.load(Ordering::Relaxed) {
+            return false;
+        }
+        self.last_poll
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .is_some_and(|(at, interval)| at.elapsed() > interval * STALL_POLLS)
+    }
+
+    /// Ask the polling task to reconnect and resolve the session again.
+    pub fn restart(&self) {
+        self.restart.notify_one();
     }
 
     /// Disable idle monitoring (fallback mode).
@@ -127,44 +430,160 @@ impl IdleMonitor {
 
     /// Directly set the idle hint value.
     ///
-    /// Useful for testing without a real D-Bus connection.
+    /// Useful for testing without a real idle source.
     pub fn set_idle(&self, idle: bool) {
-        self.idle_hint.store(idle, Ordering::Relaxed);
+        self.update_idle_hint(idle);
     }
 
-    /// Start background polling task.
+    /// Directly set the lock state.
     ///
-    /// Polls idle state at the specified interval and updates the cache.
-    /// The task exits cleanly when the provided `shutdown` token is cancelled.
-    pub fn start_polling(self: Arc<Self>, interval: Duration, shutdown: CancellationToken) {
-        tokio::spawn(async move {
-            // Try to initialize
-            if let Err(e) = self.init().await {
-                error!(
-                    "Failed to initialize idle monitor: {}. Disabling idle gating.",
-                    e
-                );
-                self.disable();
-                return;
-            }
+    /// Useful for testing without a real idle source.
+    pub fn set_locked(&self, locked: bool) {
+        self.update_locked(locked);
+    }
 
-            info!("Idle monitor started, polling every {:?}", interval);
+    /// Wait until the system resumes from suspend.
+    pub async fn resumed(&self) {
+        self.resumed.notified().await;
+    }
 
-            loop {
-                if let Err(e) = self.poll_idle_state().await {
-                    warn!("Failed to poll idle state: {}", e);
-                    // Don't disable on transient errors, just log
-                }
+    /// Wait until the session returns from idle or is unlocked.
+    pub async fn returned(&self) {
+        self.returned.notified().await;
+    }
+
+    /// Signal that the system resumed from suspend.
+    ///
+    /// Called by the sleep watcher; also useful for testing without D-Bus.
+    pub fn notify_resumed(&self) {
+        self.resumed.notify_one();
+    }
+
+    /// Forget what the previous idle source reported, before the next one
+    /// in the chain takes over.
+    fn reset_source_state(&self) {
+        self.idle_hint.store(false, Ordering::Relaxed);
+        self.locked.store(false, Ordering::Relaxed);
+        *self
+            .last_poll
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = None;
+    }
+
+    /// Follow the idle state until `shutdown` is cancelled.
+    ///
+    /// Tries each idle source in turn, falling back to the next one when a
+    /// source can't connect or stops working, and disables idle gating once
+    /// none is left. Polling sources poll at the specified interval, or at
+    /// `battery_interval` while on battery; with a `battery_interval`, the
+    /// power source is watched as well. Suspend/resume is watched alongside.
+    /// The daemon runs this under
+    /// [`Watchdog::supervise`](crate::watchdog::Watchdog::supervise), which
+    /// starts it over if it panics.
+    pub async fn run(
+        self: Arc<Self>,
+        interval: Duration,
+        battery_interval: Option<Duration>,
+        shutdown: CancellationToken,
+    ) {
+        let sleep_watcher = async {
+            #[cfg(feature = "logind")]
+            if let Err(e) = logind::watch_sleep(&self, &shutdown).await {
+                warn!("Suspend/resume detection unavailable: {}", e);
+            }
+        };
+        let power_watcher = async {
+            #[cfg(feature = "logind")]
+            if battery_interval.is_some()
+                && let Err(e) = logind::watch_power(&self, &shutdown).await
+            {
+                warn!("Battery detection unavailable: {}", e);
+            }
+        };
+        let input_watcher = async {
+            #[cfg(feature = "idle-notify")]
+            if self.track_input
+                && let Err(e) = wayland::watch_input(&self, INPUT_TIMEOUT, &shutdown).await
+            {
+                self.input_active.store(false, Ordering::Relaxed);
+                warn!("Input tracking unavailable, only focus changes count as activity: {e:#}");
+            }
+        };
+        tokio::join!(
+            sleep_watcher,
+            power_watcher,
+            input_watcher,
+            self.run_chain(interval, battery_interval, &shutdown)
+        );
+    }
 
-                tokio::select! {
-                    () = shutdown.cancelled() => {
-                        info!("Idle monitor shutting down");
-                        return;
-                    }
-                    () = tokio::time::sleep(interval) => {}
+    /// Run the idle sources in turn until one of them runs until `shutdown`.
+    async fn run_chain(
+        &self,
+        interval: Duration,
+        battery_interval: Option<Duration>,
+        shutdown: &CancellationToken,
+    ) {
+        for &backend in &self.sources {
+            if shutdown.is_cancelled() {
+                return;
+            }
+            let mut source = match self.connect(backend, interval, battery_interval).await {
+                Ok(source) => source,
+                Err(e) => {
+                    info!("The {backend} idle source is unavailable: {e:#}");
+                    continue;
                 }
+            };
+            info!("Following idle state from {backend}");
+            self.reset_source_state();
+            match source.run(self, shutdown).await {
+                Ok(()) => return,
+                Err(e) => warn!("The {backend} idle source failed: {e:#}"),
             }
-        });
+            self.reset_source_state();
+        }
+        error!("No idle source is available. Disabling idle gating.");
+        self.disable();
+    }
+
+    /// Connect the idle source `backend`.
+    // Nothing is awaited when every idle source feature is disabled
+    #[allow(clippy::unused_async)]
+    async fn connect(
+        &self,
+        backend: IdleBackend,
+        interval: Duration,
+        battery_interval: Option<Duration>,
+    ) -> Result<Box<dyn IdleSource>> {
+        match backend {
+            #[cfg(feature = "logind")]
+            IdleBackend::Logind => {
+                let source = logind::LogindSource::connect(
+                    self.session_id.clone(),
+                    interval,
+                    battery_interval,
+                )
+                .await?;
+                Ok(Box::new(source))
+            }
+            #[cfg(feature = "idle-notify")]
+            IdleBackend::Wayland => {
+                let timeout = self.idle_timeout.unwrap_or(DEFAULT_IDLE_TIMEOUT);
+                let source = wayland::WaylandSource::connect(timeout)?;
+                Ok(Box::new(source))
+            }
+            #[cfg(feature = "screensaver")]
+            IdleBackend::Screensaver => {
+                let source = screensaver::ScreensaverSource::connect().await?;
+                Ok(Box::new(source))
+            }
+            #[allow(unreachable_patterns)]
+            other => {
+                let _ = (interval, battery_interval);
+                anyhow::bail!("built without the {other} idle source")
+            }
+        }
     }
 }
 
@@ -174,87 +593,133 @@ impl Default for IdleMonitor {
     }
 }
 
-/// Resolve the session object path for the current session.
-async fn resolve_session_path(conn: &Connection) -> Result<String> {
-    // First try XDG_SESSION_ID if available
-    if let Ok(session_id) = env::var("XDG_SESSION_ID") {
-        debug!("Using XDG_SESSION_ID: {}", session_id);
-
-        let proxy = zbus::Proxy::new(conn, LOGIND_SERVICE, LOGIND_PATH, MANAGER_INTERFACE)
-            .await
-            .context("Failed to create Manager proxy")?;
-
-        let path: zbus::zvariant::OwnedObjectPath = proxy
-            .call("GetSession", &(&session_id,))
-            .await
-            .context("GetSession call failed")?;
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        return Ok(path.to_string());
+    #[test]
+    fn test_idle_monitor_default_state() {
+        let monitor = IdleMonitor::new();
+        assert!(!monitor.is_idle());
     }
 
-    // Fall back to getting sessions for current user
-    debug!("XDG_SESSION_ID not set, trying to find current session");
+    #[test]
+    fn test_idle_monitor_disable() {
+        let monitor = IdleMonitor::new();
+        // Set idle hint to true
+        monitor.idle_hint.store(true, Ordering::Relaxed);
+        assert!(monitor.is_idle());
 
-    // Try "self" session - probe by reading IdleHint
-    let self_path = format!("{LOGIND_PATH}/session/self");
-    if get_idle_hint(conn, &self_path).await.is_ok() {
-        return Ok(self_path);
+        // After disable, is_idle returns false regardless of hint
+        monitor.disable();
+        assert!(!monitor.is_idle());
     }
 
-    // Try "auto" session
-    let auto_path = format!("{LOGIND_PATH}/session/auto");
-    if get_idle_hint(conn, &auto_path).await.is_ok() {
-        return Ok(auto_path);
-    }
+    #[tokio::test]
+    async fn test_activity_tracking() {
+        tokio::time::pause();
+        let monitor = IdleMonitor::new();
+        let start = Instant::now();
+        assert!(!monitor.active_since(start));
 
-    anyhow::bail!(
-        "Could not resolve session path. Set XDG_SESSION_ID or ensure logind session is available."
-    )
-}
+        tokio::time::advance(Duration::from_secs(1)).await;
+        monitor.record_activity();
+        assert!(monitor.active_since(start));
+        assert!(!monitor.active_since(Instant::now()));
+    }
 
-/// Get the `IdleHint` property from a session.
-async fn get_idle_hint(conn: &Connection, session_path: &str) -> Result<bool> {
-    const SESSION_INTERFACE: &str = "org.freedesktop.login1.Session";
-    const PROPERTIES_INTERFACE: &str = "org.freedesktop.DBus.Properties";
+    #[tokio::test]
+    async fn test_return_from_idle_counts_as_activity() {
+        tokio::time::pause();
+        let monitor = IdleMonitor::new();
+        let start = Instant::now();
+        tokio::time::advance(Duration::from_secs(1)).await;
 
-    let proxy = zbus::Proxy::new(conn, LOGIND_SERVICE, session_path, PROPERTIES_INTERFACE)
-        .await
-        .context("Failed to create Properties proxy")?;
+        monitor.set_idle(true);
+        assert!(!monitor.active_since(start));
+        monitor.set_idle(false);
+        assert!(monitor.active_since(start));
+    }
 
-    let value: zbus::zvariant::OwnedValue = proxy
-        .call("Get", &(SESSION_INTERFACE, "IdleHint"))
-        .await
-        .context("Failed to get IdleHint property")?;
+    #[tokio::test(start_paused = true)]
+    async fn test_ongoing_input_counts_as_activity() {
+        let monitor = IdleMonitor::new();
+        monitor.record_input(true);
+        tokio::time::advance(Duration::from_mins(5)).await;
+        // Typing without pause since, with no further input events
+        assert!(monitor.active_since(Instant::now()));
+
+        monitor.record_input(false);
+        tokio::time::advance(Duration::from_secs(1)).await;
+        assert!(!monitor.active_since(Instant::now()));
+    }
 
-    let idle: bool = value
-        .downcast_ref::<bool>()
-        .map_err(|_| anyhow::anyhow!("IdleHint is not a boolean"))?;
+    #[tokio::test(start_paused = true)]
+    async fn test_stalled_after_missed_polls() {
+        let monitor = IdleMonitor::new();
+        assert!(!monitor.is_stalled());
 
-    Ok(idle)
-}
+        monitor.record_poll(Duration::from_secs(10));
+        tokio::time::advance(Duration::from_secs(25)).await;
+        assert!(!monitor.is_stalled());
+        tokio::time::advance(Duration::from_secs(10)).await;
+        assert!(monitor.is_stalled());
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+        monitor.disable();
+        assert!(!monitor.is_stalled());
+    }
 
     #[test]
-    fn test_idle_monitor_default_state() {
+    fn test_lock_gating() {
         let monitor = IdleMonitor::new();
+        monitor.set_locked(true);
+        assert!(monitor.is_locked());
         assert!(!monitor.is_idle());
+        assert_eq!(monitor.away_reason(), Some("locked"));
+        monitor.set_idle(true);
+        assert_eq!(monitor.away_reason(), Some("idle"));
+
+        let monitor = IdleMonitor::new().with_lock_gating(false);
+        monitor.set_locked(true);
+        assert!(!monitor.is_locked());
+        assert_eq!(monitor.away_reason(), None);
     }
 
-    #[test]
-    fn test_idle_monitor_disable() {
-        let monitor = IdleMonitor::new();
-        // Set idle hint to true
-        monitor.idle_hint.store(true, Ordering::Relaxed);
+    #[tokio::test(start_paused = true)]
+    async fn test_idle_timeout() {
+        let monitor = IdleMonitor::new().with_idle_timeout(Some(Duration::from_mins(5)));
+        tokio::time::advance(Duration::from_mins(4)).await;
+        assert!(!monitor.is_idle());
+        tokio::time::advance(Duration::from_mins(1)).await;
         assert!(monitor.is_idle());
 
-        // After disable, is_idle returns false regardless of hint
+        monitor.record_activity();
+        assert!(!monitor.is_idle());
+
+        // Applies without a working idle source too
         monitor.disable();
+        tokio::time::advance(Duration::from_mins(5)).await;
+        assert!(monitor.is_idle());
+    }
+
+    #[tokio::test]
+    async fn test_empty_chain_disables_gating() {
+        let monitor = IdleMonitor::new().with_sources(Vec::new());
+        monitor.set_idle(true);
+        monitor
+            .run_chain(Duration::from_secs(10), None, &CancellationToken::new())
+            .await;
         assert!(!monitor.is_idle());
     }
 
+    #[test]
+    fn test_idle_backend_names() {
+        let sources: Vec<IdleBackend> =
+            serde_json::from_str(r#"["idle-notify", "logind", "screensaver"]"#).unwrap();
+        assert_eq!(sources, IdleBackend::DEFAULT_CHAIN);
+        assert_eq!(IdleBackend::Wayland.to_string(), "wayland");
+    }
+
     #[test]
     fn test_idle_hint_atomic() {
         let monitor = IdleMonitor::new();
@@ -0,0 +1,565 @@
+//! Idle and lock state from the systemd-logind `DBus` interface.
+//!
+//! Follows the current session's `IdleHint` property through
+//! `PropertiesChanged` signals, polling it as a fallback. Lock state is
+//! tracked the same way from `LockedHint`, along with the session's `Lock`
+//! and `Unlock` signals. Also provides the `PrepareForSleep` and `UPower`
+//! watchers the monitor runs alongside whichever idle source is in use.
+
+use std::env;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+use anyhow::Context;
+use anyhow::Result;
+use futures_util::StreamExt;
+use futures_util::future::BoxFuture;
+use tokio::sync::Notify;
+use tokio::sync::RwLock;
+use tokio_util::sync::CancellationToken;
+use tracing::debug;
+use tracing::info;
+use tracing::trace;
+use tracing::warn;
+use zbus::Connection;
+
+use super::IdleMonitor;
+use super::IdleSource;
+use crate::error_log;
+use crate::error_log::ErrorClass;
+
+/// `DBus` service and path for login1.
+const LOGIND_SERVICE: &str = "org.freedesktop.login1";
+const LOGIND_PATH: &str = "/org/freedesktop/login1";
+const MANAGER_INTERFACE: &str = "org.freedesktop.login1.Manager";
+const SESSION_INTERFACE: &str = "org.freedesktop.login1.Session";
+
+/// `DBus` service and path for `UPower`.
+const UPOWER_SERVICE: &str = "org.freedesktop.UPower";
+const UPOWER_PATH: &str = "/org/freedesktop/UPower";
+const UPOWER_INTERFACE: &str = "org.freedesktop.UPower";
+
+/// How long a single idle poll may take before it is abandoned.
+const POLL_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Poll interval while `IdleHint` changes arrive as signals. Polling then
+/// only catches changes whose signal was lost.
+const SIGNAL_FALLBACK_INTERVAL: Duration = Duration::from_mins(5);
+
+/// Idle source polling and following systemd-logind's session properties.
+#[derive(Debug)]
+pub struct LogindSource {
+    /// logind session ID to watch instead of the detected one.
+    session_id: Option<String>,
+
+    /// Session object path in `DBus`.
+    session_path: RwLock<Option<String>>,
+
+    /// Cached `DBus` system connection, reused across polls.
+    connection: RwLock<Option<Connection>>,
+
+    /// Cached proxy for the session, reused across polls.
+    session_proxy: RwLock<Option<zbus::Proxy<'static>>>,
+
+    /// Poll interval on AC power.
+    interval: Duration,
+
+    /// Poll interval on battery, when battery-aware.
+    battery_interval: Option<Duration>,
+
+    /// Whether `IdleHint` changes are being received as signals.
+    idle_signals: AtomicBool,
+
+    /// Signalled when `idle_signals` changes, so polling adjusts.
+    idle_signals_changed: Notify,
+}
+
+impl LogindSource {
+    /// Create a source for the session `session_id`, or the detected one,
+    /// polling every `interval` (`battery_interval` while on battery).
+    #[must_use]
+    pub fn new(
+        session_id: Option<String>,
+        interval: Duration,
+        battery_interval: Option<Duration>,
+    ) -> Self {
+        Self {
+            session_id,
+            session_path: RwLock::new(None),
+            connection: RwLock::new(None),
+            session_proxy: RwLock::new(None),
+            interval,
+            battery_interval,
+            idle_signals: AtomicBool::new(false),
+            idle_signals_changed: Notify::new(),
+        }
+    }
+
+    /// Create the source and resolve its session.
+    pub async fn connect(
+        session_id: Option<String>,
+        interval: Duration,
+        battery_interval: Option<Duration>,
+    ) -> Result<Self> {
+        let source = Self::new(session_id, interval, battery_interval);
+        source.init().await?;
+        Ok(source)
+    }
+
+    /// Connect to D-Bus and resolve the session path.
+    pub async fn init(&self) -> Result<()> {
+        let conn = Connection::system()
+            .await
+            .context("Failed to connect to system DBus")?;
+
+        let session_path = resolve_session_path(&conn, self.session_id.as_deref()).await?;
+        info!("Resolved session path: {}", session_path);
+
+        *self.session_path.write().await = Some(session_path);
+        *self.connection.write().await = Some(conn);
+        *self.session_proxy.write().await = None;
+        Ok(())
+    }
+
+    /// Poll the current idle and lock state from `DBus` into `monitor`.
+    ///
+    /// Reuses the cached D-Bus connection established during `init()` and
+    /// the session proxy, reconnecting if necessary. A failed poll drops
+    /// both, so a restarted bus or a dead socket is re-established on the
+    /// next poll instead of failing forever.
+    pub async fn poll_idle_state(&self, monitor: &IdleMonitor) -> Result<bool> {
+        let Some(proxy) = self.get_or_create_proxy().await? else {
+            return Ok(false); // Not initialized
+        };
+
+        let idle = match proxy.get_property::<bool>("IdleHint").await {
+            Ok(idle) => idle,
+            Err(e) => {
+                debug!("Dropping cached D-Bus connection after failed poll");
+                self.drop_connection().await;
+                return Err(e).context("Failed to get IdleHint property");
+            }
+        };
+        monitor.update_idle_hint(idle);
+        // Older logind versions have no LockedHint; the signals still work
+        match proxy.get_property::<bool>("LockedHint").await {
+            Ok(locked) => monitor.update_locked(locked),
+            Err(e) => trace!("Failed to get LockedHint property: {}", e),
+        }
+
+        Ok(idle)
+    }
+
+    /// Return the cached session proxy or create one, or `None` before the
+    /// session is resolved.
+    ///
+    /// The proxy doesn't cache properties, so every read asks logind: polls
+    /// are the fallback for changes whose signal was lost.
+    async fn get_or_create_proxy(&self) -> Result<Option<zbus::Proxy<'static>>> {
+        if let Some(ref proxy) = *self.session_proxy.read().await {
+            return Ok(Some(proxy.clone()));
+        }
+        let Some(path) = self.session_path.read().await.clone() else {
+            return Ok(None);
+        };
+
+        let conn = self.get_or_reconnect().await?;
+        let proxy: zbus::Proxy<'static> = zbus::proxy::Builder::new(&conn)
+            .destination(LOGIND_SERVICE)?
+            .path(path)?
+            .interface(SESSION_INTERFACE)?
+            .cache_properties(zbus::proxy::CacheProperties::No)
+            .build()
+            .await
+            .context("Failed to create Session proxy")?;
+        *self.session_proxy.write().await = Some(proxy.clone());
+        Ok(Some(proxy))
+    }
+
+    /// Forget the cached D-Bus connection and session proxy.
+    async fn drop_connection(&self) {
+        *self.connection.write().await = None;
+        *self.session_proxy.write().await = None;
+    }
+
+    /// Return the cached D-Bus connection or establish a new one.
+    async fn get_or_reconnect(&self) -> Result<Connection> {
+        {
+            let guard = self.connection.read().await;
+            if let Some(ref conn) = *guard {
+                return Ok(conn.clone());
+            }
+        }
+
+        debug!("D-Bus connection not cached, reconnecting");
+        let conn = Connection::system()
+            .await
+            .context("Failed to connect to system DBus")?;
+        *self.connection.write().await = Some(conn.clone());
+        Ok(conn)
+    }
+
+    /// Follow the session's `IdleHint` and `LockedHint` changes, and its
+    /// `Lock` and `Unlock` signals, as logind sends them.
+    async fn watch_session(
+        &self,
+        monitor: &IdleMonitor,
+        shutdown: &CancellationToken,
+    ) -> Result<()> {
+        let Some(path) = self.session_path.read().await.clone() else {
+            anyhow::bail!("No session to watch");
+        };
+        let conn = self.get_or_reconnect().await?;
+        let proxy = zbus::Proxy::new(&conn, LOGIND_SERVICE, path.as_str(), SESSION_INTERFACE)
+            .await
+            .context("Failed to create Session proxy")?;
+        // Yields the current value first, then every change
+        let mut idle_changes = proxy.receive_property_changed::<bool>("IdleHint").await;
+        let mut lock_changes = proxy.receive_property_changed::<bool>("LockedHint").await;
+        let mut locks = proxy
+            .receive_signal("Lock")
+            .await
+            .context("Failed to subscribe to Lock")?;
+        let mut unlocks = proxy
+            .receive_signal("Unlock")
+            .await
+            .context("Failed to subscribe to Unlock")?;
+        self.set_idle_signals(true);
+
+        let result = loop {
+            tokio::select! {
+                () = shutdown.cancelled() => break Ok(()),
+                change = idle_changes.next() => {
+                    let Some(change) = change else {
+                        break Err(anyhow::anyhow!("IdleHint property stream ended"));
+                    };
+                    match change.get().await {
+                        Ok(idle) => monitor.update_idle_hint(idle),
+                        Err(e) => warn!("Malformed IdleHint property: {}", e),
+                    }
+                }
+                Some(change) = lock_changes.next() => match change.get().await {
+                    Ok(locked) => monitor.update_locked(locked),
+                    Err(e) => warn!("Malformed LockedHint property: {}", e),
+                },
+                Some(_) = locks.next() => monitor.update_locked(true),
+                Some(_) = unlocks.next() => monitor.update_locked(false),
+            }
+        };
+        self.set_idle_signals(false);
+        result
+    }
+
+    /// Note whether `IdleHint` changes arrive as signals.
+    fn set_idle_signals(&self, active: bool) {
+        if self.idle_signals.swap(active, Ordering::Relaxed) != active {
+            if active {
+                debug!("Following IdleHint changes, polling every {SIGNAL_FALLBACK_INTERVAL:?}");
+            }
+            self.idle_signals_changed.notify_one();
+        }
+    }
+
+    /// Poll idle state every interval until `shutdown` is cancelled.
+    async fn poll(&self, monitor: &IdleMonitor, shutdown: &CancellationToken) {
+        info!(
+            "logind idle source started, polling every {:?}",
+            self.interval
+        );
+        monitor.record_poll(self.interval);
+        loop {
+            let current_interval = self.poll_interval(monitor);
+            match tokio::time::timeout(POLL_TIMEOUT, self.poll_idle_state(monitor)).await {
+                Ok(Ok(_)) => {
+                    monitor.record_poll(current_interval);
+                    error_log::global().recovered(ErrorClass::IdlePoll);
+                }
+                // Don't disable on transient errors, just log
+                Ok(Err(e)) => {
+                    if error_log::global().should_log(ErrorClass::IdlePoll) {
+                        warn!("Failed to poll idle state: {}", e);
+                    }
+                }
+                Err(_) => {
+                    if error_log::global().should_log(ErrorClass::IdlePoll) {
+                        warn!("Idle state poll timed out");
+                    }
+                    self.drop_connection().await;
+                }
+            }
+
+            tokio::select! {
+                () = shutdown.cancelled() => {
+                    info!("Idle monitor shutting down");
+                    return;
+                }
+                () = monitor.restart.notified() => {
+                    info!("Restarting idle monitor");
+                    self.drop_connection().await;
+                    if let Err(e) = self.init().await {
+                        warn!("Failed to re-initialize idle monitor: {}", e);
+                    }
+                }
+                () = self.idle_signals_changed.notified() => {}
+                () = tokio::time::sleep(current_interval) => {}
+            }
+        }
+    }
+
+    /// The idle poll interval for the current power source, and longer
+    /// while idle changes arrive as signals.
+    fn poll_interval(&self, monitor: &IdleMonitor) -> Duration {
+        let interval = match self.battery_interval {
+            Some(battery) if monitor.on_battery() => battery,
+            _ => self.interval,
+        };
+        if self.idle_signals.load(Ordering::Relaxed) {
+            interval.max(SIGNAL_FALLBACK_INTERVAL)
+        } else {
+            interval
+        }
+    }
+}
+
+impl IdleSource for LogindSource {
+    fn run<'a>(
+        &'a mut self,
+        monitor: &'a IdleMonitor,
+        shutdown: &'a CancellationToken,
+    ) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            let session_watcher = async {
+                if let Err(e) = self.watch_session(monitor, shutdown).await {
+                    warn!("Idle and lock signals unavailable, polling instead: {}", e);
+                }
+            };
+            // Polling keeps going through transient errors, so logind only
+            // stops being the idle source when the monitor shuts down
+            tokio::join!(session_watcher, self.poll(monitor, shutdown));
+            Ok(())
+        })
+    }
+}
+
+/// Forward logind `PrepareForSleep(false)` signals to
+/// [`IdleMonitor::resumed`].
+pub(super) async fn watch_sleep(monitor: &IdleMonitor, shutdown: &CancellationToken) -> Result<()> {
+    let conn = Connection::system()
+        .await
+        .context("Failed to connect to system DBus")?;
+    let proxy = zbus::Proxy::new(&conn, LOGIND_SERVICE, LOGIND_PATH, MANAGER_INTERFACE)
+        .await
+        .context("Failed to create Manager proxy")?;
+    let mut signals = proxy
+        .receive_signal("PrepareForSleep")
+        .await
+        .context("Failed to subscribe to PrepareForSleep")?;
+
+    loop {
+        let message = tokio::select! {
+            () = shutdown.cancelled() => return Ok(()),
+            message = signals.next() => message,
+        };
+        let Some(message) = message else {
+            anyhow::bail!("PrepareForSleep signal stream ended");
+        };
+        match message.body().deserialize::<bool>() {
+            Ok(true) => debug!("System is suspending"),
+            Ok(false) => {
+                info!("System resumed from suspend");
+                monitor.notify_resumed();
+            }
+            Err(e) => warn!("Malformed PrepareForSleep signal: {}", e),
+        }
+    }
+}
+
+/// Forward `UPower` `OnBattery` changes to [`IdleMonitor::on_battery`].
+pub(super) async fn watch_power(monitor: &IdleMonitor, shutdown: &CancellationToken) -> Result<()> {
+    let conn = Connection::system()
+        .await
+        .context("Failed to connect to system DBus")?;
+    let proxy = zbus::Proxy::new(&conn, UPOWER_SERVICE, UPOWER_PATH, UPOWER_INTERFACE)
+        .await
+        .context("Failed to create UPower proxy")?;
+    // Yields the current value first, then every change
+    let mut changes = proxy.receive_property_changed::<bool>("OnBattery").await;
+
+    loop {
+        let change = tokio::select! {
+            () = shutdown.cancelled() => return Ok(()),
+            change = changes.next() => change,
+        };
+        let Some(change) = change else {
+            anyhow::bail!("OnBattery property stream ended");
+        };
+        match change.get().await {
+            Ok(on_battery) => monitor.set_on_battery(on_battery),
+            Err(e) => warn!("Malformed OnBattery property: {}", e),
+        }
+    }
+}
+
+/// Resolve the session object path for the current session.
+///
+/// A configured `session_id` wins, then `XDG_SESSION_ID`. A user service
+/// has neither, and on a machine with several seats or sessions logind's
+/// `self` and `auto` may pick the wrong one, so the session owning the
+/// compositor's socket is tried before them.
+async fn resolve_session_path(conn: &Connection, session_id: Option<&str>) -> Result<String> {
+    let proxy = zbus::Proxy::new(conn, LOGIND_SERVICE, LOGIND_PATH, MANAGER_INTERFACE)
+        .await
+        .context("Failed to create Manager proxy")?;
+
+    if let Some(session_id) = session_id {
+        debug!("Using logind_session: {}", session_id);
+        let path: zbus::zvariant::OwnedObjectPath = proxy
+            .call("GetSession", &(session_id,))
+            .await
+            .with_context(|| format!("No logind session {session_id}"))?;
+        return Ok(path.to_string());
+    }
+
+    // First try XDG_SESSION_ID if available
+    if let Ok(session_id) = env::var("XDG_SESSION_ID") {
+        debug!("Using XDG_SESSION_ID: {}", session_id);
+
+        let path: zbus::zvariant::OwnedObjectPath = proxy
+            .call("GetSession", &(&session_id,))
+            .await
+            .context("GetSession call failed")?;
+
+        return Ok(path.to_string());
+    }
+
+    if let Some(pid) = compositor_pid().await {
+        match proxy
+            .call::<_, _, zbus::zvariant::OwnedObjectPath>("GetSessionByPID", &(pid,))
+            .await
+        {
+            Ok(path) => {
+                debug!("Using the session of the compositor (PID {pid})");
+                return Ok(path.to_string());
+            }
+            // A compositor started as a user service isn't in a session
+            Err(e) => debug!("Compositor (PID {pid}) has no session: {e}"),
+        }
+    }
+
+    // Fall back to getting sessions for current user
+    debug!("XDG_SESSION_ID not set, trying to find current session");
+
+    // Try "self" session - probe by reading IdleHint
+    let self_path = format!("{LOGIND_PATH}/session/self");
+    if get_idle_hint(conn, &self_path).await.is_ok() {
+        return Ok(self_path);
+    }
+
+    // Try "auto" session
+    let auto_path = format!("{LOGIND_PATH}/session/auto");
+    if get_idle_hint(conn, &auto_path).await.is_ok() {
+        return Ok(auto_path);
+    }
+
+    anyhow::bail!(
+        "Could not resolve session path. Set XDG_SESSION_ID or ensure logind session is available."
+    )
+}
+
+/// PID of the process serving the display socket, i.e. the compositor or X
+/// server, from the socket's peer credentials.
+async fn compositor_pid() -> Option<u32> {
+    for socket in display_sockets() {
+        let Ok(stream) = tokio::net::UnixStream::connect(&socket).await else {
+            continue;
+        };
+        if let Some(pid) = stream.peer_cred().ok().and_then(|cred| cred.pid()) {
+            debug!("{} is served by PID {pid}", socket.display());
+            return u32::try_from(pid).ok();
+        }
+    }
+    None
+}
+
+/// The Wayland socket from `WAYLAND_DISPLAY`, then the X11 socket from
+/// `DISPLAY`.
+fn display_sockets() -> Vec<std::path::PathBuf> {
+    let mut sockets = Vec::new();
+    if let Ok(display) = env::var("WAYLAND_DISPLAY") {
+        let runtime_dir = env::var_os("XDG_RUNTIME_DIR").unwrap_or_default();
+        sockets.push(std::path::Path::new(&runtime_dir).join(display));
+    }
+    if let Some(socket) = env::var("DISPLAY").ok().as_deref().and_then(x11_socket) {
+        sockets.push(socket);
+    }
+    sockets
+}
+
+/// The local socket for an X11 `DISPLAY` like `:1` or `:1.0`.
+fn x11_socket(display: &str) -> Option<std::path::PathBuf> {
+    let number = display.strip_prefix(':')?;
+    let number = number
+        .split_once('.')
+        .map_or(number, |(number, _screen)| number);
+    number
+        .parse::<u32>()
+        .ok()
+        .map(|number| format!("/tmp/.X11-unix/X{number}").into())
+}
+
+/// Get the `IdleHint` property from a session.
+async fn get_idle_hint(conn: &Connection, session_path: &str) -> Result<bool> {
+    const PROPERTIES_INTERFACE: &str = "org.freedesktop.DBus.Properties";
+
+    let proxy = zbus::Proxy::new(conn, LOGIND_SERVICE, session_path, PROPERTIES_INTERFACE)
+        .await
+        .context("Failed to create Properties proxy")?;
+
+    let value: zbus::zvariant::OwnedValue = proxy
+        .call("Get", &(SESSION_INTERFACE, "IdleHint"))
+        .await
+        .context("Failed to get IdleHint property")?;
+
+    let idle: bool = value
+        .downcast_ref::<bool>()
+        .map_err(|_| anyhow::anyhow!("IdleHint is not a boolean"))?;
+
+    Ok(idle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_poll_interval_on_battery() {
+        let monitor = IdleMonitor::new();
+        let ac = Duration::from_secs(10);
+        let source = LogindSource::new(None, ac, Some(Duration::from_mins(1)));
+
+        assert_eq!(source.poll_interval(&monitor), ac);
+        monitor.set_on_battery(true);
+        assert_eq!(source.poll_interval(&monitor), Duration::from_mins(1));
+        let source = LogindSource::new(None, ac, None);
+        assert_eq!(source.poll_interval(&monitor), ac);
+    }
+
+    #[test]
+    fn test_poll_interval_with_idle_signals() {
+        let monitor = IdleMonitor::new();
+        let source = LogindSource::new(None, Duration::from_secs(10), None);
+        source.idle_signals.store(true, Ordering::Relaxed);
+        assert_eq!(source.poll_interval(&monitor), SIGNAL_FALLBACK_INTERVAL);
+        let source = LogindSource::new(None, Duration::from_mins(10), None);
+        source.idle_signals.store(true, Ordering::Relaxed);
+        assert_eq!(source.poll_interval(&monitor), Duration::from_mins(10));
+    }
+
+    #[test]
+    fn test_x11_socket() {
+        assert_eq!(x11_socket(":1"), Some("/tmp/.X11-unix/X1".into()));
+        assert_eq!(x11_socket(":0.1"), Some("/tmp/.X11-unix/X0".into()));
+        assert_eq!(x11_socket("remote:0"), None);
+    }
+}
@@ -0,0 +1,89 @@
+//! Idle state from the `org.freedesktop.ScreenSaver` `DBus` service.
+//!
+//! The last resort for desktops that neither set logind's `IdleHint` nor
+//! offer `ext-idle-notify-v1`, such as KDE Plasma on X11 or XFCE with
+//! `xfce4-screensaver`. The session counts as idle while the screensaver is
+//! active, following its `ActiveChanged` signal; the desktop's screensaver
+//! settings decide when that is.
+
+use anyhow::Context;
+use anyhow::Result;
+use futures_util::StreamExt;
+use futures_util::future::BoxFuture;
+use tokio_util::sync::CancellationToken;
+use tracing::info;
+use tracing::warn;
+use zbus::Connection;
+
+use super::IdleMonitor;
+use super::IdleSource;
+
+/// `DBus` service, path and interface of the screensaver.
+const SCREENSAVER_SERVICE: &str = "org.freedesktop.ScreenSaver";
+const SCREENSAVER_PATH: &str = "/org/freedesktop/ScreenSaver";
+const SCREENSAVER_INTERFACE: &str = "org.freedesktop.ScreenSaver";
+
+/// Idle source following the screensaver's active state.
+pub struct ScreensaverSource {
+    proxy: zbus::Proxy<'static>,
+}
+
+impl ScreensaverSource {
+    /// Connect to the session bus and check the screensaver answers.
+    pub async fn connect() -> Result<Self> {
+        let conn = Connection::session()
+            .await
+            .context("Failed to connect to session DBus")?;
+        let proxy = zbus::Proxy::new(
+            &conn,
+            SCREENSAVER_SERVICE,
+            SCREENSAVER_PATH,
+            SCREENSAVER_INTERFACE,
+        )
+        .await
+        .context("Failed to create ScreenSaver proxy")?;
+        let _active: bool = proxy
+            .call("GetActive", &())
+            .await
+            .context("No screensaver service on the session bus")?;
+        info!("Connected to the screensaver service");
+        Ok(Self { proxy })
+    }
+}
+
+impl IdleSource for ScreensaverSource {
+    fn run<'a>(
+        &'a mut self,
+        monitor: &'a IdleMonitor,
+        shutdown: &'a CancellationToken,
+    ) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            let mut changes = self
+                .proxy
+                .receive_signal("ActiveChanged")
+                .await
+                .context("Failed to subscribe to ActiveChanged")?;
+            // Subscribe first, so a change between the two isn't missed
+            let active: bool = self
+                .proxy
+                .call("GetActive", &())
+                .await
+                .context("Failed to get the screensaver state")?;
+            monitor.update_idle_hint(active);
+
+            loop {
+                let message = tokio::select! {
+                    () = shutdown.cancelled() => return Ok(()),
+                    message = changes.next() => message,
+                };
+                let Some(message) = message else {
+                    anyhow::bail!("ActiveChanged signal stream ended");
+                };
+                match message.body().deserialize::<bool>() {
+                    Ok(active) => monitor.update_idle_hint(active),
+                    Err(e) => warn!("Malformed ActiveChanged signal: {}", e),
+                }
+            }
+        })
+    }
+}
@@ -0,0 +1,253 @@
+//! Idle state from the Wayland `ext-idle-notify-v1` protocol.
+//!
+//! First in the default chain, since many compositors never report idle to
+//! logind, such as most wlroots compositors without a `swayidle`/`hypridle`
+//! rule setting `IdleHint`. The compositor says when the seat has had no input
+//! for `idle_timeout_seconds`, five minutes by default, and when input resumes.
+//! Idle inhibitors, e.g. a playing video, hold off the idle notification like
+//! they hold off screen blanking.
+//!
+//! The same protocol with a short timeout backs input tracking: every
+//! return from that short idle is keyboard or pointer input, which the
+//! monitor counts as activity.
+//!
+//! The Wayland event loop is synchronous, so it runs in a dedicated thread
+//! via `spawn_blocking` and sends idle changes through a channel. Dropping
+//! the source wakes the thread with a display roundtrip so it exits.
+
+use std::env;
+use std::time::Duration;
+
+use anyhow::Context;
+use anyhow::Result;
+use futures_util::future::BoxFuture;
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+use tracing::debug;
+use tracing::info;
+use tracing::warn;
+use wayland_client::Connection;
+use wayland_client::Dispatch;
+use wayland_client::EventQueue;
+use wayland_client::Proxy;
+use wayland_client::QueueHandle;
+use wayland_client::globals::GlobalListContents;
+use wayland_client::globals::registry_queue_init;
+use wayland_client::protocol::wl_callback;
+use wayland_client::protocol::wl_callback::WlCallback;
+use wayland_client::protocol::wl_registry;
+use wayland_client::protocol::wl_seat;
+use wayland_client::protocol::wl_seat::WlSeat;
+use wayland_protocols::ext::idle_notify::v1::client::ext_idle_notification_v1;
+use wayland_protocols::ext::idle_notify::v1::client::ext_idle_notification_v1::ExtIdleNotificationV1;
+use wayland_protocols::ext::idle_notify::v1::client::ext_idle_notifier_v1::ExtIdleNotifierV1;
+
+use super::IdleMonitor;
+use super::IdleSource;
+
+/// Idle source using `ext-idle-notify-v1`.
+///
+/// Runs a blocking Wayland event loop in a dedicated thread and receives
+/// idle changes via a channel. Dropping the source stops the thread.
+pub struct WaylandSource {
+    rx: mpsc::UnboundedReceiver<bool>,
+    conn: Connection,
+    qh: QueueHandle<IdleState>,
+}
+
+impl WaylandSource {
+    /// Connect to the Wayland display and ask to be notified after
+    /// `timeout` without input.
+    pub fn connect(timeout: Duration) -> Result<Self> {
+        let source = Self::spawn(timeout)?;
+        info!("Connected to Wayland display via ext-idle-notify, idle after {timeout:?}");
+        Ok(source)
+    }
+
+    /// Set up the notification for `timeout` and start the event loop
+    /// thread.
+    fn spawn(timeout: Duration) -> Result<Self> {
+        if env::var("WAYLAND_DISPLAY").is_err() {
+            anyhow::bail!("WAYLAND_DISPLAY is not set");
+        }
+
+        let conn = Connection::connect_to_env().context("Wayland connect")?;
+        let (globals, event_queue) =
+            registry_queue_init::<IdleState>(&conn).context("Registry init")?;
+        let qh = event_queue.handle();
+
+        let interfaces: Vec<String> = globals
+            .contents()
+            .clone_list()
+            .into_iter()
+            .map(|g| g.interface)
+            .collect();
+        let has = |name: &str| interfaces.iter().any(|interface| interface == name);
+        if !has(ExtIdleNotifierV1::interface().name) {
+            anyhow::bail!("Compositor does not support ext-idle-notify-v1");
+        }
+        if !has(WlSeat::interface().name) {
+            anyhow::bail!("Compositor has no seat");
+        }
+
+        let seat: WlSeat = globals.bind(&qh, 1..=1, ()).context("Bind wl_seat")?;
+        let notifier: ExtIdleNotifierV1 = globals
+            .bind(&qh, 1..=1, ())
+            .context("Bind ext_idle_notifier_v1")?;
+        let timeout_ms = u32::try_from(timeout.as_millis()).unwrap_or(u32::MAX);
+        let notification = notifier.get_idle_notification(timeout_ms, &seat, &qh, ());
+        conn.flush().context("Wayland flush")?;
+
+        let (tx, rx) = mpsc::unbounded_channel();
+        tokio::task::spawn_blocking(move || {
+            if let Err(e) = run_event_loop(event_queue, tx) {
+                warn!("ext-idle-notify event loop exited: {e:#}");
+            }
+            notification.destroy();
+            notifier.destroy();
+        });
+        Ok(Self { rx, conn, qh })
+    }
+}
+
+impl Drop for WaylandSource {
+    fn drop(&mut self) {
+        // The event loop thread blocks until the compositor sends something,
+        // which may not be for a long time. Close the channel so it stops at
+        // its next wakeup, and ask for a `done` event to wake it right away.
+        self.rx.close();
+        self.conn.display().sync(&self.qh, ());
+        let _ = self.conn.flush();
+    }
+}
+
+impl IdleSource for WaylandSource {
+    fn run<'a>(
+        &'a mut self,
+        monitor: &'a IdleMonitor,
+        shutdown: &'a CancellationToken,
+    ) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            loop {
+                let idle = tokio::select! {
+                    () = shutdown.cancelled() => return Ok(()),
+                    idle = self.rx.recv() => idle,
+                };
+                let Some(idle) = idle else {
+                    anyhow::bail!("ext-idle-notify event loop closed");
+                };
+                monitor.update_idle_hint(idle);
+            }
+        })
+    }
+}
+
+/// Follow keyboard and pointer input until `shutdown` is cancelled.
+///
+/// Input counts as ongoing until there has been none for `timeout`.
+pub(super) async fn watch_input(
+    monitor: &IdleMonitor,
+    timeout: Duration,
+    shutdown: &CancellationToken,
+) -> Result<()> {
+    let mut source = WaylandSource::spawn(timeout)?;
+    info!("Tracking input activity via ext-idle-notify");
+    // The notification starts out not idle, the seat in use
+    monitor.record_input(true);
+    loop {
+        let idle = tokio::select! {
+            () = shutdown.cancelled() => return Ok(()),
+            idle = source.rx.recv() => idle,
+        };
+        let Some(idle) = idle else {
+            anyhow::bail!("ext-idle-notify event loop closed");
+        };
+        monitor.record_input(!idle);
+    }
+}
+
+/// State for Wayland dispatch.
+struct IdleState {
+    tx: mpsc::UnboundedSender<bool>,
+}
+
+/// Run the blocking Wayland event loop until the receiver is closed.
+fn run_event_loop(
+    mut event_queue: EventQueue<IdleState>,
+    tx: mpsc::UnboundedSender<bool>,
+) -> Result<()> {
+    let mut state = IdleState { tx };
+    while !state.tx.is_closed() {
+        event_queue
+            .blocking_dispatch(&mut state)
+            .context("Dispatch error")?;
+    }
+    debug!("Idle source closed, leaving the ext-idle-notify event loop");
+    Ok(())
+}
+
+impl Dispatch<wl_registry::WlRegistry, GlobalListContents> for IdleState {
+    fn event(
+        _state: &mut Self,
+        _proxy: &wl_registry::WlRegistry,
+        _event: wl_registry::Event,
+        _data: &GlobalListContents,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+impl Dispatch<WlCallback, ()> for IdleState {
+    fn event(
+        _state: &mut Self,
+        _proxy: &WlCallback,
+        _event: wl_callback::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+impl Dispatch<WlSeat, ()> for IdleState {
+    fn event(
+        _state: &mut Self,
+        _proxy: &WlSeat,
+        _event: wl_seat::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+impl Dispatch<ExtIdleNotifierV1, ()> for IdleState {
+    fn event(
+        _state: &mut Self,
+        _proxy: &ExtIdleNotifierV1,
+        _event: <ExtIdleNotifierV1 as Proxy>::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+impl Dispatch<ExtIdleNotificationV1, ()> for IdleState {
+    fn event(
+        state: &mut Self,
+        _proxy: &ExtIdleNotificationV1,
+        event: ext_idle_notification_v1::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        // A closed channel is noticed by the event loop
+        let _ = match event {
+            ext_idle_notification_v1::Event::Idled => state.tx.send(true),
+            ext_idle_notification_v1::Event::Resumed => state.tx.send(false),
+            _ => Ok(()),
+        };
+    }
+}
@@ -0,0 +1,318 @@
+//! Inhibitors: outside conditions that pause heartbeats.
+//!
+//! Other tools can stop tracking without talking to the control socket.
+//! [`Inhibitor`] watches for a file (`inhibit_file`), which any script can
+//! create and remove, and for the desktop's "do not disturb" state
+//! (`inhibit_on_dnd`), read from the `Inhibited` property of the
+//! notification server. The first `startup_grace_seconds` after the daemon
+//! starts count as inhibited too, so session restore and autostarted apps
+//! grabbing focus at login don't produce a burst of heartbeats; focus events
+//! are still followed. [`InhibitSender`] drops heartbeats while any of them
+//! holds.
+
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::OnceLock;
+use std::sync::PoisonError;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+#[cfg(feature = "logind")]
+use anyhow::Context;
+use anyhow::Result;
+#[cfg(feature = "logind")]
+use futures_util::StreamExt;
+use futures_util::future::BoxFuture;
+use tokio::time::Instant;
+use tokio_util::sync::CancellationToken;
+use tracing::debug;
+use tracing::info;
+use tracing::warn;
+
+use crate::api::HeartbeatSender;
+use crate::api::SendError;
+use crate::config::Config;
+use crate::config::MAX_STARTUP_GRACE_SECONDS;
+use crate::domain::Heartbeat;
+use crate::title;
+
+/// `DBus` service, path, and interface of the notification server.
+#[cfg(feature = "logind")]
+const NOTIFICATIONS_SERVICE: &str = "org.freedesktop.Notifications";
+#[cfg(feature = "logind")]
+const NOTIFICATIONS_PATH: &str = "/org/freedesktop/Notifications";
+#[cfg(feature = "logind")]
+const NOTIFICATIONS_INTERFACE: &str = "org.freedesktop.Notifications";
+
+/// Tracks whether an inhibitor currently pauses heartbeats.
+#[derive(Debug, Default)]
+pub struct Inhibitor {
+    /// File whose existence pauses heartbeats (`inhibit_file`).
+    file: Mutex<Option<PathBuf>>,
+    file_present: AtomicBool,
+    /// Whether "do not disturb" pauses heartbeats (`inhibit_on_dnd`).
+    dnd_enabled: AtomicBool,
+    dnd_active: AtomicBool,
+    polling: AtomicBool,
+    #[cfg(feature = "logind")]
+    watching_dnd: AtomicBool,
+    /// End of the startup grace period, set on the first [`start`](Self::start).
+    grace_until: OnceLock<Instant>,
+}
+
+impl Inhibitor {
+    /// Create an inhibitor that never pauses until configured.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether heartbeats are paused by an inhibitor.
+    #[must_use]
+    pub fn is_inhibited(&self) -> bool {
+        self.file_present.load(Ordering::Relaxed)
+            || (self.dnd_enabled.load(Ordering::Relaxed) && self.dnd_active.load(Ordering::Relaxed))
+            || self.in_startup_grace()
+    }
+
+    /// Whether the startup grace period is still running.
+    #[must_use]
+    pub fn in_startup_grace(&self) -> bool {
+        self.grace_until
+            .get()
+            .is_some_and(|until| Instant::now() < *until)
+    }
+
+    /// Apply config and start watching what it asks for, until `shutdown`.
+    ///
+    /// Called on startup and on every reload; watchers that are already
+    /// running pick up the new settings. The startup grace period begins
+    /// with the first call and isn't restarted by reloads.
+    pub fn start(self: &Arc<Self>, config: &Config, shutdown: &CancellationToken) {
+        let seconds = config.startup_grace_seconds.min(MAX_STARTUP_GRACE_SECONDS);
+        let grace = Duration::from_secs(seconds);
+        if let Some(until) = Instant::now().checked_add(grace)
+            && self.grace_until.set(until).is_ok()
+            && !grace.is_zero()
+        {
+            if seconds < config.startup_grace_seconds {
+                warn!(
+                    "startup_grace_seconds = {} is too long, using {seconds}",
+                    config.startup_grace_seconds
+                );
+            }
+            info!("Startup grace: heartbeats start in {seconds}s");
+        }
+
+        let file = config
+            .inhibit_file
+            .as_deref()
+            .map(|path| title::expand_home(&path.to_string_lossy()));
+        let watch_file = file.is_some();
+        *self.file.lock().unwrap_or_else(PoisonError::into_inner) = file;
+        self.dnd_enabled
+            .store(config.inhibit_on_dnd, Ordering::Relaxed);
+
+        if watch_file {
+            self.check_file();
+            if !self.polling.swap(true, Ordering::Relaxed) {
+                let inhibitor = Arc::clone(self);
+                let interval = Duration::from_secs(config.idle_check_interval_seconds.max(1));
+                let shutdown = shutdown.clone();
+                tokio::spawn(async move {
+                    loop {
+                        tokio::select! {
+                            () = shutdown.cancelled() => break,
+                            () = tokio::time::sleep(interval) => inhibitor.check_file(),
+                        }
+                    }
+                    inhibitor.polling.store(false, Ordering::Relaxed);
+                });
+            }
+        } else {
+            self.set_file_present(false);
+        }
+
+        #[cfg(feature = "logind")]
+        if config.inhibit_on_dnd && !self.watching_dnd.swap(true, Ordering::Relaxed) {
+            let inhibitor = Arc::clone(self);
+            let shutdown = shutdown.clone();
+            tokio::spawn(async move {
+                if let Err(e) = inhibitor.watch_dnd(&shutdown).await {
+                    warn!("Do not disturb detection unavailable: {e:#}");
+                }
+                inhibitor.dnd_active.store(false, Ordering::Relaxed);
+                inhibitor.watching_dnd.store(false, Ordering::Relaxed);
+            });
+        }
+    }
+
+    /// Check whether the inhibit file exists.
+    fn check_file(&self) {
+        let present = self
+            .file
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .as_deref()
+            .is_some_and(std::path::Path::exists);
+        self.set_file_present(present);
+    }
+
+    fn set_file_present(&self, present: bool) {
+        if self.file_present.swap(present, Ordering::Relaxed) != present {
+            if present {
+                info!("Inhibit file present, heartbeats paused");
+            } else {
+                info!("Inhibit file removed, heartbeats resumed");
+            }
+        }
+    }
+
+    /// Follow the notification server's `Inhibited` property.
+    #[cfg(feature = "logind")]
+    async fn watch_dnd(&self, shutdown: &CancellationToken) -> Result<()> {
+        let conn = zbus::Connection::session()
+            .await
+            .context("Failed to connect to session DBus")?;
+        let proxy = zbus::Proxy::new(
+            &conn,
+            NOTIFICATIONS_SERVICE,
+            NOTIFICATIONS_PATH,
+            NOTIFICATIONS_INTERFACE,
+        )
+        .await
+        .context("Failed to create notifications proxy")?;
+        // Yields the current value first, then every change
+        let mut changes = proxy.receive_property_changed::<bool>("Inhibited").await;
+
+        loop {
+            let change = tokio::select! {
+                () = shutdown.cancelled() => return Ok(()),
+                change = changes.next() => change,
+            };
+            let Some(change) = change else {
+                anyhow::bail!("Inhibited property stream ended");
+            };
+            let active = change
+                .get()
+                .await
+                .context("Notification server has no Inhibited property")?;
+            if self.dnd_active.swap(active, Ordering::Relaxed) != active {
+                if active {
+                    info!("Do not disturb enabled");
+                } else {
+                    info!("Do not disturb disabled");
+                }
+            }
+        }
+    }
+}
+
+/// A [`HeartbeatSender`] wrapper that drops heartbeats while an
+/// [`Inhibitor`] holds.
+pub struct InhibitSender<S> {
+    inner: S,
+    inhibitor: Arc<Inhibitor>,
+}
+
+impl<S> InhibitSender<S> {
+    /// Wrap `inner`, dropping heartbeats while `inhibitor` holds.
+    pub fn new(inner: S, inhibitor: Arc<Inhibitor>) -> Self {
+        Self { inner, inhibitor }
+    }
+}
+
+impl<S: HeartbeatSender + Sync> HeartbeatSender for InhibitSender<S> {
+    fn send_heartbeat<'a>(
+        &'a self,
+        heartbeat: &'a Heartbeat,
+    ) -> BoxFuture<'a, Result<(), SendError>> {
+        if self.inhibitor.is_inhibited() {
+            debug!("Skipping heartbeat: inhibited");
+            return Box::pin(async { Ok(()) });
+        }
+        self.inner.send_heartbeat(heartbeat)
+    }
+
+    fn flush(&self) -> BoxFuture<'_, Result<(), SendError>> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn test_startup_grace() {
+        let config = Config {
+            startup_grace_seconds: 30,
+            ..Config::default()
+        };
+        let inhibitor = Arc::new(Inhibitor::new());
+        let shutdown = CancellationToken::new();
+        inhibitor.start(&config, &shutdown);
+        assert!(inhibitor.is_inhibited());
+
+        // A reload doesn't start the grace period again
+        tokio::time::advance(Duration::from_secs(30)).await;
+        inhibitor.start(&config, &shutdown);
+        assert!(!inhibitor.is_inhibited());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_overlong_startup_grace_is_capped() {
+        let config = Config {
+            startup_grace_seconds: u64::MAX,
+            ..Config::default()
+        };
+        let inhibitor = Arc::new(Inhibitor::new());
+        inhibitor.start(&config, &CancellationToken::new());
+        assert!(inhibitor.in_startup_grace());
+
+        tokio::time::advance(Duration::from_secs(MAX_STARTUP_GRACE_SECONDS)).await;
+        assert!(!inhibitor.in_startup_grace());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_inhibit_file_pauses_while_present() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("paused");
+        let config = Config {
+            inhibit_file: Some(path.clone()),
+            idle_check_interval_seconds: 10,
+            ..Config::default()
+        };
+        let inhibitor = Arc::new(Inhibitor::new());
+        let shutdown = CancellationToken::new();
+        inhibitor.start(&config, &shutdown);
+        assert!(!inhibitor.is_inhibited());
+
+        std::fs::write(&path, "").unwrap();
+        tokio::time::sleep(Duration::from_secs(11)).await;
+        assert!(inhibitor.is_inhibited());
+
+        std::fs::remove_file(&path).unwrap();
+        tokio::time::sleep(Duration::from_secs(10)).await;
+        assert!(!inhibitor.is_inhibited());
+
+        // Dropping the option on reload lifts the inhibitor right away
+        std::fs::write(&path, "").unwrap();
+        inhibitor.check_file();
+        assert!(inhibitor.is_inhibited());
+        inhibitor.start(&Config::default(), &shutdown);
+        assert!(!inhibitor.is_inhibited());
+        shutdown.cancel();
+    }
+
+    #[test]
+    fn test_dnd_counts_only_when_enabled() {
+        let inhibitor = Inhibitor::new();
+        inhibitor.dnd_active.store(true, Ordering::Relaxed);
+        assert!(!inhibitor.is_inhibited());
+        inhibitor.dnd_enabled.store(true, Ordering::Relaxed);
+        assert!(inhibitor.is_inhibited());
+    }
+}
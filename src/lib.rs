@@ -1,33 +1,78 @@
 //! wakatime-focusd - Systemd user daemon for `WakaTime` app heartbeats.
 //!
 //! Tracks currently focused desktop application and sends heartbeats to
-//! `WakaTime` via the API directly (default) or using wakatime-cli (legacy,
-//! requires the `cli-sender` feature).
+//! `WakaTime` over HTTP, reusing one API client (and its keep-alive
+//! connections) for the lifetime of the daemon. No process is spawned per
+//! heartbeat.
 
+pub mod activity;
+pub mod annotation;
 pub mod api;
 pub mod api_key;
 pub mod backend;
+pub mod bar;
+pub mod browser;
 pub mod config;
+pub mod control;
+pub mod dedup;
+pub mod desktop_entry;
+pub mod diff;
 pub mod domain;
+pub mod encryption;
+pub mod environment;
+pub mod error_log;
+pub mod fanout;
+pub mod fleet;
 pub mod heartbeat;
 pub mod idle;
+pub mod inhibit;
+pub mod media;
+pub mod metrics;
+pub mod onboarding;
+pub mod presets;
+pub mod project;
+pub mod redact;
+pub mod repo;
+pub mod rules;
+pub mod screencast;
+#[cfg(feature = "secret-service")]
+pub mod secret_service;
+pub mod session_env;
+pub mod snapshot;
 pub mod throttle;
+pub mod timezone;
+pub mod title;
+pub mod vacation;
+pub mod watchdog;
+pub mod workspace;
 
+use std::hash::BuildHasher;
+use std::hash::Hasher;
 use std::time::Duration;
 
 use tokio::sync::Notify;
+use tokio::time::Instant;
 use tokio_util::sync::CancellationToken;
 use tracing::debug;
 use tracing::info;
 use tracing::warn;
 
+use crate::activity::ActivityKind;
+use crate::backend::FocusError;
 use crate::backend::FocusSource;
 use crate::config::Config;
 use crate::heartbeat::HeartbeatBuilder;
 use crate::idle::IdleMonitor;
+use crate::redact::sensitive;
+use crate::throttle::AdaptiveInterval;
 use crate::throttle::HeartbeatThrottle;
 use crate::throttle::ThrottleDecision;
 
+/// Minimum time since the previous entity's last heartbeat before a leave
+/// heartbeat is sent for it. Matches the sender's coalescing window so the
+/// leave heartbeat is never folded into the one before it.
+const LEAVE_HEARTBEAT_MIN_ELAPSED: Duration = Duration::from_mins(1);
+
 /// Outcome of an event loop iteration (used for reconnection logic).
 pub enum EventLoopOutcome {
     /// The source returned an error; the caller should reconnect.
@@ -43,8 +88,8 @@ pub enum EventLoopOutcome {
 /// Core event loop, decoupled from backend connection for testability.
 ///
 /// Accepts injected dependencies so integration tests can provide mock
-/// implementations without needing a real desktop environment, wakatime-cli
-/// binary, or D-Bus session.
+/// implementations without needing a real desktop environment, `WakaTime`
+/// API, or D-Bus session.
 pub async fn run_event_loop(
     mut source: Box<dyn FocusSource>,
     config: &Config,
@@ -56,9 +101,9 @@ pub async fn run_event_loop(
 ) -> EventLoopOutcome {
     let mut throttle = HeartbeatThrottle::new(config.min_entity_resend_seconds);
     let heartbeat_builder = HeartbeatBuilder::from_config(config);
-    let mut periodic_timer =
-        tokio::time::interval(Duration::from_secs(config.heartbeat_interval_seconds));
-    periodic_timer.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    let mut schedule = PeriodicSchedule::from_config(config, &mut throttle);
+    let mut last_flush = Instant::now();
+    let mut current_focus = None;
 
     loop {
         tokio::select! {
@@ -81,14 +126,34 @@ pub async fn run_event_loop(
             event = source.next_event() => {
                 match event {
                     Ok(focus_event) => {
-                        handle_focus_event(
+                        metrics::incr(&metrics::global().focus_events_received);
+                        let focus_event =
+                            focus_event.with_sanitized_title(config.max_title_length);
+                        let previous_entity =
+                            throttle.last_heartbeat().map(|h| h.entity.clone());
+                        let sent = handle_focus_event(
                             &focus_event,
+                            config,
                             &heartbeat_builder,
                             idle_monitor,
                             &mut throttle,
                             sender,
                             print_events,
                         ).await;
+
+                        if sent {
+                            let entity_changed = previous_entity.is_some()
+                                && throttle.last_heartbeat().map(|h| &h.entity)
+                                    != previous_entity.as_ref();
+                            schedule.on_focus_heartbeat(entity_changed, &mut throttle);
+                        }
+                        current_focus = Some(focus_event);
+                    }
+                    Err(FocusError::Exhausted) => {
+                        if let Err(e) = sender.flush().await {
+                            warn!("Failed to flush heartbeat buffer: {e}");
+                        }
+                        return EventLoopOutcome::Finished;
                     }
                     Err(e) => {
                         if let Err(flush_err) = sender.flush().await {
@@ -99,45 +164,284 @@ pub async fn run_event_loop(
                 }
             }
 
-            _ = periodic_timer.tick() => {
-                if let Some(last_heartbeat) = throttle.last_heartbeat()
-                    && throttle.should_send(&last_heartbeat.entity) == ThrottleDecision::Send
-                {
-                    if idle_monitor.is_idle() {
-                        debug!("Skipping periodic heartbeat: session is idle");
-                    } else {
-                        // Re-send the same heartbeat rather than rebuilding from
-                        // the source event — entity and category haven't changed.
-                        let periodic_heartbeat = last_heartbeat.clone();
-                        debug!(
-                            "Sending periodic heartbeat for: {}",
-                            periodic_heartbeat.entity
-                        );
-                        match sender.send_heartbeat(&periodic_heartbeat).await {
-                            Ok(()) => throttle.record_sent(periodic_heartbeat),
-                            Err(e) => warn!("Failed to send periodic heartbeat: {}", e),
-                        }
-                    }
+            () = idle_monitor.resumed() => {
+                // Heartbeat timing from before the suspend no longer applies
+                throttle.reset();
+                schedule.reset();
+            }
+
+            () = idle_monitor.returned() => {
+                if let Some(focus_event) = &current_focus {
+                    send_catch_up_heartbeat(
+                        focus_event,
+                        config,
+                        &heartbeat_builder,
+                        idle_monitor,
+                        &mut throttle,
+                        sender,
+                    )
+                    .await;
                 }
+            }
 
-                // Flush any buffered heartbeats (no-op for non-batching senders)
-                if let Err(e) = sender.flush().await {
-                    warn!("Failed to flush heartbeat buffer: {e}");
+            () = schedule.tick() => {
+                let sent = send_periodic_heartbeat(
+                    &mut throttle,
+                    config.periodic_requires_activity,
+                    &config.media_apps,
+                    idle_monitor,
+                    sender,
+                )
+                .await;
+                if sent {
+                    schedule.on_periodic_heartbeat(&mut throttle);
                 }
+                flush_on_tick(config, idle_monitor, sender, &mut last_flush).await;
+            }
+        }
+    }
+}
+
+/// Flush any buffered heartbeats after a periodic tick (no-op for
+/// non-batching senders). On battery, uploads are batched into fewer wakeups.
+async fn flush_on_tick(
+    config: &Config,
+    idle_monitor: &IdleMonitor,
+    sender: &(dyn api::HeartbeatSender + Sync),
+    last_flush: &mut Instant,
+) {
+    let battery_flush_interval = Duration::from_secs(config.battery_flush_interval_seconds);
+    if config.battery_aware
+        && idle_monitor.on_battery()
+        && last_flush.elapsed() < battery_flush_interval
+    {
+        debug!("On battery, deferring heartbeat upload");
+    } else {
+        if let Err(e) = sender.flush().await {
+            warn!("Failed to flush heartbeat buffer: {e}");
+        }
+        *last_flush = Instant::now();
+    }
+}
+
+/// Periodic heartbeat schedule, either fixed or adaptive.
+struct PeriodicSchedule {
+    timer: tokio::time::Interval,
+    adaptive: Option<AdaptiveInterval>,
+    jitter: Duration,
+}
+
+impl PeriodicSchedule {
+    /// Build the schedule from config.
+    ///
+    /// In adaptive mode the throttle's resend threshold follows the current
+    /// interval, so it is updated here as well.
+    fn from_config(config: &Config, throttle: &mut HeartbeatThrottle) -> Self {
+        let adaptive = config.adaptive_interval.then(|| {
+            AdaptiveInterval::new(
+                config.heartbeat_interval_seconds,
+                config.adaptive_min_interval_seconds,
+                config.adaptive_max_interval_seconds,
+            )
+        });
+
+        let interval = adaptive
+            .as_ref()
+            .map_or(config.heartbeat_interval_seconds, AdaptiveInterval::current);
+        if adaptive.is_some() {
+            throttle.set_min_resend_seconds(interval);
+        }
+
+        let mut timer = tokio::time::interval(Duration::from_secs(interval));
+        timer.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+        Self {
+            timer,
+            adaptive,
+            jitter: Duration::from_secs(config.periodic_jitter_seconds),
+        }
+    }
+
+    /// Wait for the next periodic tick.
+    async fn tick(&mut self) {
+        self.timer.tick().await;
+    }
+
+    /// Restart the timer so the next tick fires soon.
+    ///
+    /// The tick is delayed by a random amount up to the configured jitter, so
+    /// the heartbeat and the uploads that were due during a suspend don't all
+    /// go out the instant the system wakes up.
+    fn reset(&mut self) {
+        let delay = random_delay(self.jitter);
+        debug!("Next periodic heartbeat in {delay:?}");
+        self.timer.reset_after(delay);
+    }
+
+    /// A focus-change heartbeat was sent.
+    ///
+    /// Lengthens an adaptive interval on entity switches and re-arms the timer
+    /// so the next tick lands exactly one interval after this heartbeat.
+    fn on_focus_heartbeat(&mut self, entity_changed: bool, throttle: &mut HeartbeatThrottle) {
+        let Some(ref mut adaptive) = self.adaptive else {
+            return;
+        };
+        if entity_changed {
+            adaptive.on_switch();
+        }
+        let interval = adaptive.current();
+        self.rearm(interval, throttle);
+    }
+
+    /// A periodic heartbeat was sent: shorten an adaptive interval.
+    fn on_periodic_heartbeat(&mut self, throttle: &mut HeartbeatThrottle) {
+        if let Some(ref mut adaptive) = self.adaptive
+            && let Some(interval) = adaptive.on_stable()
+        {
+            self.rearm(interval, throttle);
+        }
+    }
+
+    /// Restart the timer with a new interval and update the throttle to match.
+    fn rearm(&mut self, interval_seconds: u64, throttle: &mut HeartbeatThrottle) {
+        let period = Duration::from_secs(interval_seconds);
+        throttle.set_min_resend_seconds(interval_seconds);
+        self.timer = tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+        self.timer
+            .set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    }
+}
+
+/// A random duration between zero and `max`, with millisecond resolution.
+fn random_delay(max: Duration) -> Duration {
+    let max_ms = u64::try_from(max.as_millis()).unwrap_or(u64::MAX);
+    if max_ms == 0 {
+        return Duration::ZERO;
+    }
+    let random = std::hash::RandomState::new().build_hasher().finish();
+    Duration::from_millis(random % max_ms.saturating_add(1))
+}
+
+/// Re-send the last heartbeat if its entity is due for a periodic refresh.
+///
+/// A heartbeat whose earlier send failed is retried first (with its original
+/// timestamp) and takes the place of the periodic refresh for this tick.
+///
+/// With `requires_activity`, the refresh is skipped unless the idle monitor
+/// saw activity since the last heartbeat. Media playing in one of
+/// `media_apps` counts as activity.
+///
+/// Returns `true` if a periodic heartbeat was sent.
+async fn send_periodic_heartbeat(
+    throttle: &mut HeartbeatThrottle,
+    requires_activity: bool,
+    media_apps: &[String],
+    idle_monitor: &IdleMonitor,
+    sender: &(dyn api::HeartbeatSender + Sync),
+) -> bool {
+    if let Some(pending) = throttle.take_pending() {
+        debug!(
+            "Retrying failed heartbeat for: {}",
+            sensitive(&pending.entity)
+        );
+        return match sender.send_heartbeat(&pending).await {
+            Ok(()) => {
+                activity::record(
+                    ActivityKind::Sent,
+                    annotation::global()
+                        .annotate(format!("{} (retry)", sensitive(&pending.entity))),
+                );
+                throttle.record_sent(pending);
+                true
+            }
+            Err(e) => {
+                warn!("Failed to retry heartbeat: {}", e);
+                activity::record(
+                    ActivityKind::Failed,
+                    format!("{} (retry): {e}", sensitive(&pending.entity)),
+                );
+                throttle.record_failed(pending);
+                false
             }
+        };
+    }
+
+    let Some(last_heartbeat) = throttle.last_heartbeat() else {
+        return false;
+    };
+    if throttle.should_send(&last_heartbeat.entity) != ThrottleDecision::Send {
+        return false;
+    }
+    if rules::global().allowed(&last_heartbeat.source.app_class) == Some(false) {
+        debug!("Skipping periodic heartbeat: app denied by a temporary rule");
+        return false;
+    }
+    let watching = watching(
+        last_heartbeat.meeting,
+        &last_heartbeat.source,
+        idle_monitor,
+        media_apps,
+    )
+    .await;
+    if let Some(reason) = idle_monitor.away_reason()
+        && !watching
+    {
+        debug!("Skipping periodic heartbeat: session is {reason}");
+        return false;
+    }
+    if requires_activity
+        && !last_heartbeat.meeting
+        && !watching
+        && let Some(sent_at) = throttle.last_sent_at()
+        && !idle_monitor.active_since(sent_at)
+    {
+        debug!("Skipping periodic heartbeat: no activity since last heartbeat");
+        return false;
+    }
+
+    // Re-send the same heartbeat rather than rebuilding from the source
+    // event — entity and category haven't changed.
+    let periodic_heartbeat = last_heartbeat.refreshed();
+    debug!(
+        "Sending periodic heartbeat for: {}",
+        sensitive(&periodic_heartbeat.entity)
+    );
+    match sender.send_heartbeat(&periodic_heartbeat).await {
+        Ok(()) => {
+            activity::record(
+                ActivityKind::Sent,
+                annotation::global().annotate(format!(
+                    "{} (periodic)",
+                    sensitive(&periodic_heartbeat.entity)
+                )),
+            );
+            throttle.record_sent(periodic_heartbeat);
+            true
+        }
+        Err(e) => {
+            warn!("Failed to send periodic heartbeat: {}", e);
+            activity::record(
+                ActivityKind::Failed,
+                format!("{} (periodic): {e}", sensitive(&periodic_heartbeat.entity)),
+            );
+            throttle.record_failed(periodic_heartbeat);
+            false
         }
     }
 }
 
 /// Handle a focus event through the full pipeline.
+///
+/// Returns `true` if a heartbeat was sent for the event.
 async fn handle_focus_event(
     event: &backend::FocusEvent,
+    config: &Config,
     heartbeat_builder: &HeartbeatBuilder,
     idle_monitor: &IdleMonitor,
     throttle: &mut HeartbeatThrottle,
     sender: &(dyn api::HeartbeatSender + Sync),
     print_events: bool,
-) {
+) -> bool {
     if print_events {
         println!(
             "[FOCUS] | class={} title={:?} window_id={:?}",
@@ -148,36 +452,205 @@ async fn handle_focus_event(
     // Skip empty focus (no focused window)
     if event.is_empty() {
         debug!("Ignoring empty focus event");
-        return;
+        return false;
     }
 
+    // A focus or title change means someone is at the keyboard
+    idle_monitor.record_activity();
+    activity::record(
+        ActivityKind::Focus,
+        match event.title.as_deref() {
+            Some(title) => format!("{} — {}", event.app_class, sensitive(title)),
+            None => event.app_class.clone(),
+        },
+    );
+
     // Check allowlist/denylist
-    if !heartbeat_builder.is_app_allowed(&event.app_class) {
+    let allowed = heartbeat_builder.is_event_allowed(event);
+    if !allowed && !config.track_hidden_apps {
         debug!("App '{}' not allowed by filter", event.app_class);
-        return;
+        activity::record(
+            ActivityKind::Skipped,
+            format!("{}: not allowed by filter", event.app_class),
+        );
+        return false;
+    }
+
+    // The app's own WakaTime plugin reports this time; also stop periodic
+    // heartbeats for the previous app so it isn't counted alongside
+    if allowed && heartbeat_builder.defers_to_plugin(&event.app_class) {
+        debug!("App '{}' defers to its WakaTime plugin", event.app_class);
+        activity::record(
+            ActivityKind::Skipped,
+            format!("{}: defers to its WakaTime plugin", event.app_class),
+        );
+        throttle.reset();
+        return false;
     }
 
     // Build heartbeat
-    let heartbeat = heartbeat_builder.build(event.clone());
+    let heartbeat = if allowed {
+        heartbeat_builder.build(event.clone())
+    } else {
+        debug!(
+            "App '{}' not allowed by filter, counting it as hidden",
+            event.app_class
+        );
+        heartbeat_builder.build_hidden()
+    };
 
-    // Check idle state
-    if idle_monitor.is_idle() {
-        debug!("Skipping heartbeat: session is idle");
-        return;
+    // Check idle and lock state (nobody types during a call or a video)
+    if let Some(reason) = idle_monitor.away_reason()
+        && !watching(heartbeat.meeting, event, idle_monitor, &config.media_apps).await
+    {
+        debug!("Skipping heartbeat: session is {reason}");
+        activity::record(
+            ActivityKind::Skipped,
+            format!("{}: session is {reason}", sensitive(&heartbeat.entity)),
+        );
+        return false;
     }
 
-    // Check throttle
-    match throttle.should_send(&heartbeat.entity) {
+    // Check throttle; another window of the same entity counts as a switch
+    let decision = if throttle::bypass().is_active() {
+        debug!(
+            "Throttling bypassed, sending heartbeat for {}",
+            sensitive(&heartbeat.entity)
+        );
+        ThrottleDecision::Send
+    } else if config.distinguish_windows && throttle.is_window_switch(&heartbeat) {
+        debug!(
+            "Switched windows within {}, sending heartbeat",
+            sensitive(&heartbeat.entity)
+        );
+        ThrottleDecision::Send
+    } else {
+        throttle.should_send(&heartbeat.entity)
+    };
+    match decision {
         ThrottleDecision::Send => {
-            debug!("Sending heartbeat for: {}", heartbeat.entity);
-            if let Err(e) = sender.send_heartbeat(&heartbeat).await {
-                warn!("Failed to send heartbeat: {}", e);
-            } else {
-                throttle.record_sent(heartbeat);
+            if config.send_leave_heartbeats {
+                send_leave_heartbeat(&heartbeat, throttle, sender).await;
             }
+
+            send_focus_heartbeat(heartbeat, throttle, sender).await
         }
         ThrottleDecision::Skip => {
-            debug!("Throttled heartbeat for: {}", heartbeat.entity);
+            debug!("Throttled heartbeat for: {}", sensitive(&heartbeat.entity));
+            activity::record(
+                ActivityKind::Skipped,
+                format!(
+                    "{}: sent less than the resend interval ago",
+                    sensitive(&heartbeat.entity)
+                ),
+            );
+            false
+        }
+    }
+}
+
+/// Whether a video call, or media playing in the window of `event`, counts
+/// as activity. Either keeps an idle session active, but not a locked one.
+async fn watching(
+    meeting: bool,
+    event: &backend::FocusEvent,
+    idle_monitor: &IdleMonitor,
+    media_apps: &[String],
+) -> bool {
+    !idle_monitor.is_locked() && (meeting || media::is_playing(event, media_apps).await)
+}
+
+/// Send a heartbeat for the focused window when the session returns from
+/// idle or is unlocked, so the active period counts from now instead of
+/// from the next focus change or periodic tick.
+///
+/// Returns `true` if a heartbeat was sent.
+async fn send_catch_up_heartbeat(
+    event: &backend::FocusEvent,
+    config: &Config,
+    heartbeat_builder: &HeartbeatBuilder,
+    idle_monitor: &IdleMonitor,
+    throttle: &mut HeartbeatThrottle,
+    sender: &(dyn api::HeartbeatSender + Sync),
+) -> bool {
+    if event.is_empty() || idle_monitor.away_reason().is_some() {
+        return false;
+    }
+    let allowed = heartbeat_builder.is_event_allowed(event);
+    if (!allowed && !config.track_hidden_apps)
+        || (allowed && heartbeat_builder.defers_to_plugin(&event.app_class))
+    {
+        return false;
+    }
+    let heartbeat = if allowed {
+        heartbeat_builder.build(event.clone())
+    } else {
+        heartbeat_builder.build_hidden()
+    };
+    debug!(
+        "Session is back, sending heartbeat for {}",
+        sensitive(&heartbeat.entity)
+    );
+    // The time away is neither throttled against nor stretched by a leave
+    // heartbeat
+    throttle.reset();
+    send_focus_heartbeat(heartbeat, throttle, sender).await
+}
+
+/// Send the heartbeat for a focus event, recording the outcome in the
+/// throttle. Returns `true` if it was sent.
+async fn send_focus_heartbeat(
+    heartbeat: domain::Heartbeat,
+    throttle: &mut HeartbeatThrottle,
+    sender: &(dyn api::HeartbeatSender + Sync),
+) -> bool {
+    let description = format!(
+        "{} (category {}, decided by {})",
+        sensitive(&heartbeat.entity),
+        heartbeat.category,
+        heartbeat.category_source
+    );
+    debug!("Sending heartbeat for: {description}");
+    if let Err(e) = sender.send_heartbeat(&heartbeat).await {
+        warn!("Failed to send heartbeat: {}", e);
+        activity::record(
+            ActivityKind::Failed,
+            format!("{}: {e}", sensitive(&heartbeat.entity)),
+        );
+        throttle.record_failed(heartbeat);
+        false
+    } else {
+        activity::record(
+            ActivityKind::Sent,
+            annotation::global().annotate(description),
+        );
+        throttle.record_sent(heartbeat);
+        true
+    }
+}
+
+/// Send a final heartbeat for the entity focus moves away from, if its last
+/// heartbeat is old enough.
+async fn send_leave_heartbeat(
+    next: &domain::Heartbeat,
+    throttle: &HeartbeatThrottle,
+    sender: &(dyn api::HeartbeatSender + Sync),
+) {
+    let Some(leave) = throttle.leave_heartbeat(next, LEAVE_HEARTBEAT_MIN_ELAPSED) else {
+        return;
+    };
+    debug!("Sending leave heartbeat for: {}", sensitive(&leave.entity));
+    match sender.send_heartbeat(&leave).await {
+        Ok(()) => activity::record(
+            ActivityKind::Sent,
+            annotation::global().annotate(format!("{} (leave)", sensitive(&leave.entity))),
+        ),
+        Err(e) => {
+            warn!("Failed to send leave heartbeat: {}", e);
+            activity::record(
+                ActivityKind::Failed,
+                format!("{} (leave): {e}", sensitive(&leave.entity)),
+            );
         }
     }
 }
@@ -2,6 +2,7 @@
 
 mod service;
 
+use std::collections::BTreeMap;
 use std::fs;
 use std::path::Path;
 use std::path::PathBuf;
@@ -10,11 +11,15 @@ use std::time::Duration;
 
 use anyhow::Context;
 use anyhow::Result;
+#[cfg(feature = "self-update")]
 use axoupdater::AxoUpdater;
+#[cfg(feature = "self-update")]
 use axoupdater::UpdateRequest;
 use clap::Parser;
 use clap::Subcommand;
 use tokio::sync::Notify;
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
 use tokio_util::sync::CancellationToken;
 use tracing::error;
 use tracing::info;
@@ -22,15 +27,71 @@ use tracing::warn;
 use tracing_subscriber::EnvFilter;
 use wakatime_focusd::EventLoopOutcome;
 use wakatime_focusd::api::ApiSender;
+use wakatime_focusd::api::HeartbeatSender;
+use wakatime_focusd::api_key;
 use wakatime_focusd::backend::Backend;
+use wakatime_focusd::backend::FocusError;
 use wakatime_focusd::backend::FocusSource;
+#[cfg(feature = "hyprland")]
+use wakatime_focusd::backend::fake_hyprland;
+#[cfg(feature = "hyprland")]
+use wakatime_focusd::backend::fake_hyprland::FakeHyprland;
+use wakatime_focusd::bar::AppTotal;
+use wakatime_focusd::bar::BarSender;
+use wakatime_focusd::browser::BrowserAwareSource;
 use wakatime_focusd::config::Config;
+use wakatime_focusd::config::Migration;
+use wakatime_focusd::control;
+use wakatime_focusd::control::ControlContext;
+use wakatime_focusd::control::ControlServer;
+use wakatime_focusd::control::InstanceLock;
+use wakatime_focusd::dedup::DedupSource;
+use wakatime_focusd::diff::ConfigDiff;
+use wakatime_focusd::domain::Category;
+use wakatime_focusd::encryption;
+use wakatime_focusd::environment;
+use wakatime_focusd::environment::Environment;
+use wakatime_focusd::error_log;
+use wakatime_focusd::error_log::ErrorClass;
+use wakatime_focusd::fanout::FanoutSender;
+use wakatime_focusd::fleet;
+use wakatime_focusd::fleet::FleetSender;
+use wakatime_focusd::fleet::FleetServer;
+use wakatime_focusd::fleet::HandoffSender;
+use wakatime_focusd::fleet::RemoteMachine;
+use wakatime_focusd::idle;
 use wakatime_focusd::idle::IdleMonitor;
+use wakatime_focusd::inhibit::InhibitSender;
+use wakatime_focusd::onboarding;
+use wakatime_focusd::onboarding::CredentialProblem;
+use wakatime_focusd::project;
+use wakatime_focusd::project::PinnedProjectSender;
+use wakatime_focusd::redact;
+use wakatime_focusd::rules;
+use wakatime_focusd::rules::RuleEffect;
+use wakatime_focusd::screencast::ScreenShareMode;
+use wakatime_focusd::screencast::ScreenShareSender;
+use wakatime_focusd::session_env;
+use wakatime_focusd::snapshot::Snapshot;
+use wakatime_focusd::throttle;
+use wakatime_focusd::throttle::BypassStatus;
+use wakatime_focusd::timezone;
+use wakatime_focusd::vacation::Date;
+use wakatime_focusd::vacation::PauseState;
+use wakatime_focusd::vacation::VacationSender;
+use wakatime_focusd::watchdog::Incident;
+use wakatime_focusd::watchdog::Subsystem;
+use wakatime_focusd::watchdog::Watchdog;
+use wakatime_focusd::watchdog::WatchedSender;
+use wakatime_focusd::watchdog::WatchedSource;
+use wakatime_focusd::workspace::WorkspaceProjectSender;
+use wakatime_focusd::workspace::WorkspaceProjects;
 
 /// `WakaTime` focus daemon.
 ///
 /// Tracks currently focused desktop application and sends heartbeats to `WakaTime`.
 #[derive(Parser, Debug)]
+#[allow(clippy::struct_excessive_bools)]
 #[command(name = "wakatime-focusd")]
 #[command(author, version, about, long_about = None)]
 struct Args {
@@ -45,10 +106,29 @@ struct Args {
     #[arg(short, long, default_value = "auto", global = true)]
     backend: Backend,
 
+    /// Focus events to replay with `--backend mock` (JSON Lines).
+    #[arg(long, global = true, value_name = "FILE")]
+    script: Option<PathBuf>,
+
+    /// Replay `--script` this many times faster than recorded; 0 replays it
+    /// without delays.
+    #[arg(long, global = true, value_name = "FACTOR", default_value_t = 1.0, value_parser = parse_speed)]
+    script_speed: f64,
+
+    /// WakaTime-compatible API URL, e.g. a self-hosted Wakapi's; overrides
+    /// `api_url` in the config and `~/.wakatime.cfg`.
+    #[arg(long, global = true, value_name = "URL")]
+    api_url: Option<String>,
+
     /// Enable dry-run mode (don't actually send heartbeats).
     #[arg(long)]
     dry_run: bool,
 
+    /// With `--dry-run`, compare this config's decisions against the
+    /// current config's instead of running the daemon.
+    #[arg(long, requires = "dry_run", value_name = "FILE")]
+    diff_config: Option<PathBuf>,
+
     /// Log level (trace, debug, info, warn, error).
     #[arg(long, default_value = "info")]
     log_level: String,
@@ -56,6 +136,21 @@ struct Args {
     /// Print normalized focus events to stdout.
     #[arg(long)]
     print_events: bool,
+
+    /// Shut down an already running daemon and take over from it.
+    #[arg(long)]
+    replace: bool,
+
+    /// Send a heartbeat for every focus change, without throttling (for
+    /// demos and testing).
+    #[arg(long)]
+    no_throttle: bool,
+
+    /// At startup, wait up to this many seconds (default 120) for the
+    /// graphical session's environment instead of exiting when no backend
+    /// is found.
+    #[arg(long, value_name = "SECONDS", num_args = 0..=1, default_missing_value = "120")]
+    wait_for_env: Option<u64>,
 }
 
 #[derive(Subcommand, Debug)]
@@ -72,6 +167,129 @@ enum Command {
         action: ServiceAction,
     },
 
+    /// Pin a project for all heartbeats of the running daemon.
+    Project {
+        #[command(subcommand)]
+        action: ProjectAction,
+    },
+
+    /// Bind workspaces to projects in the running daemon.
+    Workspace {
+        #[command(subcommand)]
+        action: WorkspaceAction,
+    },
+
+    /// Add temporary rules to the running daemon.
+    ///
+    /// Rules apply right away, take precedence over the config, and expire
+    /// on their own.
+    Rule {
+        #[command(subcommand)]
+        action: RuleAction,
+    },
+
+    /// Bypass heartbeat throttling in the running daemon.
+    ///
+    /// While bypassed, every focus change sends a heartbeat right away; handy
+    /// when validating a new destination or debugging sparse dashboards.
+    Throttle {
+        #[command(subcommand)]
+        action: ThrottleAction,
+    },
+
+    /// Turn idle gating off or on in the running daemon.
+    ///
+    /// While off, heartbeats are sent even when the session is idle or
+    /// locked; handy for finding out why heartbeats stop.
+    Idle {
+        #[command(subcommand)]
+        action: IdleAction,
+    },
+
+    /// Annotate heartbeats in the running daemon's activity log.
+    ///
+    /// For external tools noting what the time is spent on, e.g. a ticket
+    /// ID or a pairing partner. Annotations stay local and expire on their
+    /// own.
+    Annotate {
+        #[command(subcommand)]
+        action: AnnotateAction,
+    },
+
+    /// Stop sending heartbeats, e.g. for time off.
+    ///
+    /// The daemon keeps running but sends nothing until the given date, or
+    /// until `resume`. The pause survives restarts.
+    Pause {
+        /// Resume on this date (YYYY-MM-DD); pause until `resume` if omitted.
+        #[arg(long, value_name = "DATE")]
+        until: Option<Date>,
+    },
+
+    /// End a pause and send heartbeats again.
+    Resume,
+
+    /// Follow the running daemon's focus events and heartbeat decisions.
+    ///
+    /// Starts with the most recent entries, so a short session still shows
+    /// what led to the current state.
+    Tail {
+        /// Number of recent entries to show first.
+        #[arg(short = 'n', long, default_value_t = 50)]
+        replay: usize,
+    },
+
+    /// Print the daemon's state, today's totals, where the configuration
+    /// comes from, and recent errors as one JSON document.
+    ///
+    /// Meant for bug reports and dashboards. Without a running daemon, only
+    /// the configuration part is filled in.
+    Snapshot {
+        /// Print compact JSON on one line instead of indented JSON.
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Check which backend is used and what it can report.
+    ///
+    /// Lists whether the backend reports window titles, window IDs,
+    /// workspaces, title changes and fullscreen state, and which config
+    /// options rely on each, so you know what will work on this desktop.
+    Doctor,
+
+    /// Send heartbeats recorded in a dry run.
+    ///
+    /// Reads a `dry_run_capture` file and sends its heartbeats with their
+    /// original timestamps.
+    Import {
+        /// Capture file to send.
+        file: PathBuf,
+    },
+
+    /// Send heartbeats for other machines without tracking focus here.
+    ///
+    /// Runs only the fleet server from the [fleet] config section, for a
+    /// machine that collects heartbeats from the others but has no desktop
+    /// session of its own.
+    Serve,
+
+    /// Serve a fake Hyprland socket replaying a script (for testing).
+    ///
+    /// Creates `$XDG_RUNTIME_DIR/hypr/SIGNATURE/.socket2.sock` and replays
+    /// the script's event lines to whoever connects. Run the daemon with
+    /// `HYPRLAND_INSTANCE_SIGNATURE=SIGNATURE` and `--backend hyprland`
+    /// against it.
+    #[cfg(feature = "hyprland")]
+    #[command(hide = true)]
+    FakeHyprland {
+        /// Script of socket2 event lines, `sleep MS`, and `disconnect`.
+        script: PathBuf,
+
+        /// Instance signature to serve as.
+        #[arg(long, default_value = "wakatime-focusd-fake")]
+        signature: String,
+    },
+
     /// Capture a few focus events and exit (for debugging).
     Oneshot {
         /// Number of events to capture.
@@ -84,6 +302,7 @@ enum Command {
     /// Checks for a newer version and installs it. Only works when installed
     /// via the shell installer (cargo-dist). Builds from source or
     /// cargo-binstall should use their original install method to update.
+    #[cfg(feature = "self-update")]
     Update,
 }
 
@@ -102,6 +321,13 @@ enum ConfigAction {
 
     /// Print the resolved configuration and exit.
     Dump,
+
+    /// Rewrite deprecated options in the config file.
+    ///
+    /// Updates the file to the current config version, keeping comments and
+    /// formatting. The previous file is saved next to it with a `.bak`
+    /// suffix.
+    Migrate,
 }
 
 #[derive(Subcommand, Debug)]
@@ -132,9 +358,170 @@ enum ServiceAction {
     Uninstall,
 
     /// Show the service status.
+    ///
+    /// Also reports live counters (such as dropped focus events) from the
+    /// running daemon via its control socket.
     Status,
 }
 
+#[derive(Subcommand, Debug)]
+enum ProjectAction {
+    /// Pin a project until cleared or expired.
+    ///
+    /// All heartbeats carry this project, overriding any project derived from
+    /// window titles. Handy when switching between clients without distinct
+    /// window signals.
+    Set {
+        /// Project name.
+        name: String,
+
+        /// Clear the pin automatically after this long (e.g. 2h, 90m, 1h30m).
+        #[arg(long = "for", value_name = "DURATION", value_parser = project::parse_duration)]
+        duration: Option<Duration>,
+    },
+
+    /// Clear the pinned project.
+    Clear,
+}
+
+#[derive(Subcommand, Debug)]
+enum RuleAction {
+    /// Track an app, even if the config excludes it.
+    Allow {
+        /// App class.
+        app_class: String,
+
+        /// How long the rule lasts (e.g. 1h, 90m).
+        #[arg(long = "for", value_name = "DURATION", value_parser = project::parse_duration)]
+        duration: Duration,
+    },
+
+    /// Stop tracking an app.
+    Deny {
+        /// App class.
+        app_class: String,
+
+        /// How long the rule lasts (e.g. 1h, 90m).
+        #[arg(long = "for", value_name = "DURATION", value_parser = project::parse_duration)]
+        duration: Duration,
+    },
+
+    /// Set an app's category.
+    Category {
+        /// App class.
+        app_class: String,
+
+        /// Category, e.g. researching or "writing docs".
+        #[arg(value_parser = rules::parse_category)]
+        category: Category,
+
+        /// How long the rule lasts (e.g. 1h, 90m).
+        #[arg(long = "for", value_name = "DURATION", value_parser = project::parse_duration)]
+        duration: Duration,
+    },
+
+    /// Remove the temporary rules for an app, or all of them.
+    Clear {
+        /// App class; all rules if omitted.
+        app_class: Option<String>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum ThrottleAction {
+    /// Send a heartbeat for every focus change for a while.
+    Bypass {
+        /// How long to bypass throttling (e.g. 10m, 1h).
+        #[arg(long = "for", value_name = "DURATION", value_parser = project::parse_duration, default_value = "15m")]
+        duration: Duration,
+    },
+
+    /// Apply throttling again.
+    Restore,
+}
+
+#[derive(Subcommand, Debug)]
+enum IdleAction {
+    /// Send heartbeats whether or not the session is idle or locked.
+    Disable,
+
+    /// Skip heartbeats while the session is idle or locked again.
+    Enable,
+}
+
+#[derive(Subcommand, Debug)]
+enum AnnotateAction {
+    /// Record an annotation with the heartbeats sent from now on.
+    ///
+    /// Setting a key again replaces its value.
+    Set {
+        /// What the annotation is, e.g. ticket or pair.
+        key: String,
+
+        /// Its value, e.g. PROJ-123.
+        value: String,
+
+        /// How long the annotation lasts, up to a day (e.g. 1h, 90m).
+        #[arg(long = "for", value_name = "DURATION", value_parser = project::parse_duration)]
+        duration: Duration,
+    },
+
+    /// Remove an annotation, or all of them.
+    Clear {
+        /// Annotation key; all annotations if omitted.
+        key: Option<String>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum WorkspaceAction {
+    /// Bind a workspace to a project.
+    ///
+    /// Heartbeats from windows on the workspace carry this project, unless
+    /// one is pinned with `project set`. The binding survives restarts.
+    /// Workspaces are named as the compositor reports them (Hyprland only).
+    Bind {
+        /// Workspace name, e.g. 3.
+        workspace: String,
+
+        /// Project name.
+        project: String,
+    },
+
+    /// Remove a workspace binding.
+    Unbind {
+        /// Workspace name.
+        workspace: String,
+    },
+}
+
+/// Parse a `--script-speed` factor.
+fn parse_speed(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(speed) if speed.is_finite() && speed >= 0.0 => Ok(speed),
+        _ => Err(format!(
+            "invalid speed '{s}': expected a number like 10 or 0.5"
+        )),
+    }
+}
+
+/// Resolve the configured backend, first waiting up to `wait_for_env`
+/// seconds for the session environment it needs.
+async fn resolve_backend(config: &Config, wait_for_env: Option<u64>) -> Result<Backend> {
+    if let Some(seconds) = wait_for_env {
+        let ready = || match config.backend.resolve(&config.backend_priority) {
+            Ok(Backend::Mock | Backend::Stdin) => true,
+            Ok(backend) => backend.is_present(),
+            Err(_) => false,
+        };
+        session_env::wait(Duration::from_secs(seconds), ready).await;
+    }
+    config
+        .backend
+        .resolve(&config.backend_priority)
+        .context("Backend detection failed")
+}
+
 /// Return the default config file path.
 fn default_config_path() -> Result<PathBuf> {
     let config_dir = dirs::config_dir().context("Could not determine config directory")?;
@@ -144,6 +531,7 @@ fn default_config_path() -> Result<PathBuf> {
 #[tokio::main]
 async fn main() -> Result<()> {
     let args = Args::parse();
+    wakatime_focusd::backend::set_script_speed(args.script_speed);
 
     // Handle subcommands that don't need the full daemon setup.
     if let Some(command) = &args.command {
@@ -153,18 +541,43 @@ async fn main() -> Result<()> {
                     return cmd_init(output.as_deref(), *force);
                 }
                 ConfigAction::Dump => return cmd_dump_config(&args),
+                ConfigAction::Migrate => return cmd_migrate_config(args.config.as_deref()),
             },
             Command::Service { action } => match action {
                 ServiceAction::Install { now, force } => {
-                    return service::install(*now, *force, args.config.as_deref(), args.backend);
+                    return service::install(
+                        *now,
+                        *force,
+                        args.config.as_deref(),
+                        args.backend,
+                        args.wait_for_env,
+                    );
                 }
                 ServiceAction::Uninstall => return service::uninstall(),
                 ServiceAction::Status => {
-                    service::status();
+                    cmd_status().await;
                     return Ok(());
                 }
             },
+            Command::Project { action } => return cmd_project(action).await,
+            Command::Workspace { action } => return cmd_workspace(action).await,
+            Command::Rule { action } => return cmd_rule(action).await,
+            Command::Throttle { action } => return cmd_throttle(action).await,
+            Command::Idle { action } => return cmd_idle(action).await,
+            Command::Annotate { action } => return cmd_annotate(action).await,
+            Command::Pause { until } => return cmd_pause(*until).await,
+            Command::Resume => return cmd_resume().await,
+            Command::Tail { replay } => return cmd_tail(*replay).await,
+            Command::Snapshot { json } => return cmd_snapshot(&args, *json).await,
+            Command::Doctor => return cmd_doctor(&args),
+            Command::Import { file } => return cmd_import(&args, file).await,
+            Command::Serve => return cmd_serve(&args).await,
+            #[cfg(feature = "hyprland")]
+            Command::FakeHyprland { script, signature } => {
+                return cmd_fake_hyprland(&args, script, signature).await;
+            }
             Command::Oneshot { count } => return cmd_oneshot(&args, *count).await,
+            #[cfg(feature = "self-update")]
             Command::Update => return cmd_update().await,
         }
     }
@@ -180,26 +593,53 @@ async fn main() -> Result<()> {
     if args.dry_run {
         config.dry_run = true;
     }
+    redact::set_enabled(config.redact_logs);
+    idle::set_gating(config.idle_gating);
+    timezone::set(config.timezone.as_ref());
+    error_log::global().configure(&config);
 
-    let backend = config
-        .backend
-        .resolve()
-        .context("Backend detection failed")?;
+    let backend = resolve_backend(&config, args.wait_for_env).await?;
     info!("Using backend: {backend}");
 
     for diag in wakatime_focusd::backend::diagnostics(backend) {
         tracing::debug!("{}", diag);
     }
 
+    let environment = Environment::detect(backend);
+    tracing::debug!("Environment: {environment:?}");
+    environment::set(environment);
+
     info!("Configuration loaded (dry_run={})", config.dry_run);
 
+    if let Some(candidate) = args.diff_config.as_deref() {
+        return cmd_diff_config(backend, args.script.as_deref(), &config, candidate).await;
+    }
+
+    load_secrets(&config).await?;
+
+    if args.no_throttle {
+        throttle::bypass().set(None);
+    }
+    if config.fleet.server.is_none() && api_key::api_key_source(&config).is_none() {
+        onboarding::notify_once(CredentialProblem::Missing).await;
+    }
+
     // Normal daemon mode
     let cli_overrides = CliOverrides {
         config_path: args.config,
         backend: args.backend,
+        api_url: args.api_url,
         dry_run: args.dry_run,
     };
-    run_daemon(backend, config, cli_overrides, args.print_events).await
+    run_daemon(
+        backend,
+        args.script.as_deref(),
+        config,
+        cli_overrides,
+        args.print_events,
+        args.replace,
+    )
+    .await
 }
 
 /// Load config and apply CLI overrides.
@@ -210,6 +650,11 @@ fn load_config(args: &Args) -> Result<Config> {
     if args.backend != Backend::Auto {
         config.backend = args.backend;
     }
+    if args.api_url.is_some() {
+        config.api_url.clone_from(&args.api_url);
+    }
+    #[cfg(feature = "hyprland")]
+    wakatime_focusd::backend::set_hyprland_instance(config.hyprland_instance.clone());
 
     Ok(config)
 }
@@ -253,7 +698,624 @@ fn cmd_dump_config(args: &Args) -> Result<()> {
     Ok(())
 }
 
+/// `config migrate` — rewrite deprecated options in the config file.
+fn cmd_migrate_config(path: Option<&Path>) -> Result<()> {
+    let path = match path {
+        Some(p) => p.to_path_buf(),
+        None => default_config_path()?,
+    };
+    let content = fs::read_to_string(&path)
+        .with_context(|| format!("Failed to read config file: {}", path.display()))?;
+    let migration = Migration::apply(&content)
+        .with_context(|| format!("Failed to parse config file: {}", path.display()))?;
+    if !migration.is_needed() {
+        println!("{} is up to date", path.display());
+        return Ok(());
+    }
+
+    let mut backup = path.clone().into_os_string();
+    backup.push(".bak");
+    let backup = PathBuf::from(backup);
+    fs::write(&backup, &content)
+        .with_context(|| format!("Failed to write backup to {}", backup.display()))?;
+    fs::write(&path, &migration.content)
+        .with_context(|| format!("Failed to write config to {}", path.display()))?;
+
+    for change in &migration.changes {
+        println!("Migrated: {change}");
+    }
+    println!(
+        "Updated {} from version {} (previous file saved to {})",
+        path.display(),
+        migration.from_version,
+        backup.display()
+    );
+    Ok(())
+}
+
+/// `service status` — show the systemd status plus live daemon counters.
+async fn cmd_status() {
+    service::status();
+
+    let Some(path) = control::default_socket_path() else {
+        return;
+    };
+    match control::query(&path, &control::Request::Status).await {
+        Ok(control::Response::Status(status)) => print_daemon_status(*status),
+        Ok(control::Response::Error { message }) => {
+            eprintln!("Daemon returned an error: {message}");
+        }
+        Ok(other) => eprintln!("Unexpected response from daemon: {other:?}"),
+        Err(e) => eprintln!("\nDaemon not reachable: {e:#}"),
+    }
+}
+
+/// Print the live daemon counters under `service status`.
+fn print_daemon_status(status: control::DaemonStatus) {
+    println!();
+    println!("Daemon v{}:", status.version);
+    println!(
+        "  Focus events received: {}",
+        status.metrics.focus_events_received
+    );
+    println!(
+        "  Focus events dropped:  {}",
+        status.metrics.focus_events_dropped
+    );
+    if status.metrics.focus_events_deduplicated > 0 {
+        println!(
+            "  Duplicate events:      {}",
+            status.metrics.focus_events_deduplicated
+        );
+    }
+    if let Some(latency) = status.metrics.send_latency {
+        println!(
+            "  Send latency:          p50 {} ms, p95 {} ms, max {} ms (last {} sends)",
+            latency.p50_ms, latency.p95_ms, latency.max_ms, latency.samples
+        );
+    }
+    if status.metrics.slow_sends > 0 {
+        println!("  Slow sends (over 2s):  {}", status.metrics.slow_sends);
+    }
+    if let Some(pause) = status.paused {
+        match pause.until {
+            Some(until) => println!("  Paused until:          {until}"),
+            None => println!("  Paused until:          resumed"),
+        }
+    }
+    if let Some(pinned) = status.pinned_project {
+        match pinned.remaining_seconds {
+            Some(secs) => println!(
+                "  Pinned project:        {} ({}m left)",
+                pinned.name,
+                secs.div_ceil(60)
+            ),
+            None => println!("  Pinned project:        {}", pinned.name),
+        }
+    }
+    for (workspace, project) in &status.workspace_projects {
+        println!("  Workspace project:     {workspace} -> {project}");
+    }
+    for (workspace, seconds) in &status.workspace_seconds_today {
+        println!(
+            "  Workspace time today:  {workspace}: {}",
+            format_duration(*seconds)
+        );
+    }
+    print_app_totals(&status.app_seconds_today);
+    print_gating(status.throttle_bypass.as_ref(), status.idle_gating);
+    for annotation in &status.annotations {
+        println!(
+            "  Annotation:            {}={} ({}m left)",
+            annotation.key,
+            annotation.value,
+            annotation.remaining_seconds.div_ceil(60)
+        );
+    }
+    for rule in &status.temporary_rules {
+        println!(
+            "  Temporary rule:        {} {} ({}m left)",
+            describe_rule(rule.effect),
+            rule.app_class,
+            rule.remaining_seconds.div_ceil(60)
+        );
+    }
+    if status.screen_sharing {
+        println!("  Screen sharing:        yes");
+    }
+    if status.inhibited {
+        println!("  Inhibited:             yes");
+    }
+    print_restarts(&status.restarts, &status.incidents);
+    for destination in status.destinations {
+        match destination.retry_in_seconds {
+            Some(secs) => println!(
+                "  Destination {}: {} sent, retrying in {}m",
+                destination.name,
+                destination.heartbeats_sent,
+                secs.div_ceil(60)
+            ),
+            None => println!(
+                "  Destination {}: {} sent",
+                destination.name, destination.heartbeats_sent
+            ),
+        }
+    }
+    print_fleet(&status.remote_machines, status.active_machine.as_deref());
+}
+
+/// Print a throttle bypass and disabled idle gating, if in effect.
+fn print_gating(throttle_bypass: Option<&BypassStatus>, idle_gating: bool) {
+    if let Some(bypass) = throttle_bypass {
+        match bypass.remaining_seconds {
+            Some(secs) => println!(
+                "  Throttling:            bypassed ({}m left)",
+                secs.div_ceil(60)
+            ),
+            None => println!("  Throttling:            bypassed"),
+        }
+    }
+    if !idle_gating {
+        println!("  Idle gating:           off");
+    }
+}
+
+/// Print the machines forwarding heartbeats in fleet mode.
+fn print_fleet(remote_machines: &[RemoteMachine], active_machine: Option<&str>) {
+    for machine in remote_machines {
+        println!(
+            "  Remote {}: {} heartbeat(s) ({} handed off), {}",
+            machine.name,
+            machine.heartbeats_received,
+            machine.heartbeats_handed_off,
+            if machine.connections > 0 {
+                "connected"
+            } else {
+                "disconnected"
+            }
+        );
+    }
+    if let Some(machine) = active_machine {
+        println!("  Machine in use:        {machine}");
+    }
+}
+
+/// Print today's time per app; the per-entity breakdown is left to
+/// `snapshot`.
+fn print_app_totals(totals: &[AppTotal]) {
+    for total in totals {
+        match total.entities.len() {
+            0 => println!(
+                "  App time today:        {}: {}",
+                total.app,
+                format_duration(total.seconds)
+            ),
+            entities => println!(
+                "  App time today:        {}: {} across {entities} entities",
+                total.app,
+                format_duration(total.seconds)
+            ),
+        }
+    }
+}
+
+/// Print subsystem restarts and the recent incidents behind them.
+fn print_restarts(restarts: &BTreeMap<Subsystem, u32>, incidents: &[Incident]) {
+    if !restarts.is_empty() {
+        let restarts: Vec<_> = restarts
+            .iter()
+            .map(|(subsystem, count)| format!("{subsystem} {count}"))
+            .collect();
+        println!("  Restarts:              {}", restarts.join(", "));
+    }
+    for incident in incidents {
+        println!(
+            "  Watchdog restarted {}: {} ({})",
+            incident.subsystem,
+            incident.message,
+            format_age(incident.unix_time)
+        );
+    }
+}
+
+/// Format a number of seconds as hours and minutes, e.g. "1h 05m".
+fn format_duration(seconds: u64) -> String {
+    let minutes = seconds / 60;
+    if minutes < 60 {
+        format!("{minutes}m")
+    } else {
+        format!("{}h {:02}m", minutes / 60, minutes % 60)
+    }
+}
+
+/// Describe how long ago a Unix timestamp was, e.g. "5m ago".
+fn format_age(unix_time: u64) -> String {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    let secs = now.saturating_sub(unix_time);
+    match secs {
+        0..60 => format!("{secs}s ago"),
+        60..3600 => format!("{}m ago", secs / 60),
+        _ => format!("{}h ago", secs / 3600),
+    }
+}
+
+/// `project` — pin or clear the running daemon's project.
+async fn cmd_project(action: &ProjectAction) -> Result<()> {
+    let request = match action {
+        ProjectAction::Set { name, duration } => control::Request::ProjectSet {
+            name: name.clone(),
+            duration_seconds: duration.map(|d| d.as_secs()),
+        },
+        ProjectAction::Clear => control::Request::ProjectClear,
+    };
+    send_control_request(&request).await?;
+    match action {
+        ProjectAction::Set { name, .. } => println!("Pinned project: {name}"),
+        ProjectAction::Clear => println!("Cleared pinned project"),
+    }
+    Ok(())
+}
+
+/// `workspace` — bind or unbind the running daemon's workspace projects.
+async fn cmd_workspace(action: &WorkspaceAction) -> Result<()> {
+    let request = match action {
+        WorkspaceAction::Bind { workspace, project } => control::Request::WorkspaceBind {
+            workspace: workspace.clone(),
+            project: project.clone(),
+        },
+        WorkspaceAction::Unbind { workspace } => control::Request::WorkspaceUnbind {
+            workspace: workspace.clone(),
+        },
+    };
+    send_control_request(&request).await?;
+    match action {
+        WorkspaceAction::Bind { workspace, project } => {
+            println!("Bound workspace {workspace} to project {project}");
+        }
+        WorkspaceAction::Unbind { workspace } => println!("Unbound workspace {workspace}"),
+    }
+    Ok(())
+}
+
+/// `throttle` — bypass or restore the running daemon's heartbeat throttling.
+async fn cmd_throttle(action: &ThrottleAction) -> Result<()> {
+    match action {
+        ThrottleAction::Bypass { duration } => {
+            send_control_request(&control::Request::ThrottleBypass {
+                duration_seconds: Some(duration.as_secs()),
+            })
+            .await?;
+            println!(
+                "Throttling bypassed for {}m",
+                duration.as_secs().div_ceil(60)
+            );
+        }
+        ThrottleAction::Restore => {
+            send_control_request(&control::Request::ThrottleRestore).await?;
+            println!("Throttling restored");
+        }
+    }
+    Ok(())
+}
+
+/// `idle` — turn the running daemon's idle gating off or on.
+async fn cmd_idle(action: &IdleAction) -> Result<()> {
+    let enabled = matches!(action, IdleAction::Enable);
+    send_control_request(&control::Request::IdleGating { enabled }).await?;
+    if enabled {
+        println!("Idle gating enabled");
+    } else {
+        println!("Idle gating disabled");
+    }
+    Ok(())
+}
+
+/// `annotate` — set or clear annotations on the running daemon's heartbeats.
+async fn cmd_annotate(action: &AnnotateAction) -> Result<()> {
+    match action {
+        AnnotateAction::Set {
+            key,
+            value,
+            duration,
+        } => {
+            send_control_request(&control::Request::AnnotationSet {
+                key: key.clone(),
+                value: value.clone(),
+                duration_seconds: duration.as_secs(),
+            })
+            .await?;
+            println!(
+                "Annotating heartbeats with {key}={value} for {}m",
+                duration.as_secs().div_ceil(60)
+            );
+        }
+        AnnotateAction::Clear { key } => {
+            send_control_request(&control::Request::AnnotationClear { key: key.clone() }).await?;
+            match key {
+                Some(key) => println!("Cleared annotation {key}"),
+                None => println!("Cleared all annotations"),
+            }
+        }
+    }
+    Ok(())
+}
+
+/// `rule` — add or clear the running daemon's temporary rules.
+async fn cmd_rule(action: &RuleAction) -> Result<()> {
+    let (app_class, effect, duration) = match action {
+        RuleAction::Allow {
+            app_class,
+            duration,
+        } => (app_class, RuleEffect::Allow, duration),
+        RuleAction::Deny {
+            app_class,
+            duration,
+        } => (app_class, RuleEffect::Deny, duration),
+        RuleAction::Category {
+            app_class,
+            category,
+            duration,
+        } => (app_class, RuleEffect::Category(*category), duration),
+        RuleAction::Clear { app_class } => {
+            send_control_request(&control::Request::RuleClear {
+                app_class: app_class.clone(),
+            })
+            .await?;
+            match app_class {
+                Some(app_class) => println!("Cleared temporary rules for {app_class}"),
+                None => println!("Cleared all temporary rules"),
+            }
+            return Ok(());
+        }
+    };
+    send_control_request(&control::Request::RuleAdd {
+        app_class: app_class.clone(),
+        effect,
+        duration_seconds: duration.as_secs(),
+    })
+    .await?;
+    println!(
+        "Added rule for {app_class}: {} for {}m",
+        describe_rule(effect),
+        duration.as_secs().div_ceil(60)
+    );
+    Ok(())
+}
+
+/// Describe a rule's effect, e.g. "deny" or "category researching".
+fn describe_rule(effect: RuleEffect) -> String {
+    match effect {
+        RuleEffect::Allow => "allow".to_string(),
+        RuleEffect::Deny => "deny".to_string(),
+        RuleEffect::Category(category) => format!("category {category}"),
+    }
+}
+
+/// `pause` — stop sending heartbeats until a date or until resumed.
+async fn cmd_pause(until: Option<Date>) -> Result<()> {
+    send_control_request(&control::Request::Pause { until }).await?;
+    match until {
+        Some(until) => println!("Paused until {until}"),
+        None => println!("Paused until `wakatime-focusd resume`"),
+    }
+    Ok(())
+}
+
+/// `resume` — end a pause.
+async fn cmd_resume() -> Result<()> {
+    send_control_request(&control::Request::Resume).await?;
+    println!("Resumed");
+    Ok(())
+}
+
+/// `tail` — print the daemon's activity as it happens.
+async fn cmd_tail(replay: usize) -> Result<()> {
+    let path = control::default_socket_path().context("Could not determine runtime directory")?;
+    let tz = jiff::tz::TimeZone::system();
+    control::tail(&path, replay, |entry| {
+        let time = i64::try_from(entry.unix_time)
+            .ok()
+            .and_then(|secs| jiff::Timestamp::from_second(secs).ok())
+            .map_or_else(String::new, |ts| {
+                ts.to_zoned(tz.clone()).strftime("%H:%M:%S").to_string()
+            });
+        println!("{time}  {:<8} {}", entry.kind.as_str(), entry.message);
+    })
+    .await
+    .context("Daemon not reachable")?;
+    println!("Daemon stopped");
+    Ok(())
+}
+
+/// Send a control request that expects a plain acknowledgement.
+async fn send_control_request(request: &control::Request) -> Result<()> {
+    let path = control::default_socket_path().context("Could not determine runtime directory")?;
+    match control::query(&path, request)
+        .await
+        .context("Daemon not reachable")?
+    {
+        control::Response::Ok => Ok(()),
+        control::Response::Error { message } => {
+            anyhow::bail!("Daemon returned an error: {message}")
+        }
+        other @ (control::Response::Status(_)
+        | control::Response::Bar(_)
+        | control::Response::Activity(_)) => {
+            anyhow::bail!("Unexpected response from daemon: {other:?}")
+        }
+    }
+}
+
+/// The daemon's heartbeat sender chain.
+type DaemonSender = InhibitSender<
+    VacationSender<
+        HandoffSender<
+            WorkspaceProjectSender<
+                PinnedProjectSender<
+                    ScreenShareSender<BarSender<WatchedSender<Box<dyn HeartbeatSender + Sync>>>>,
+                >,
+            >,
+        >,
+    >,
+>;
+
+/// Create the heartbeat sender, applying inhibitors, vacation mode, the fleet
+/// handoff, workspace projects, the runtime project pin and the screen share
+/// mode.
+///
+/// Starts screen share detection and inhibitor watches (until `shutdown`) if
+/// the config needs them.
+fn build_sender(
+    config: &Config,
+    control_context: &ControlContext,
+    shutdown: &CancellationToken,
+) -> Result<DaemonSender> {
+    let upstream: Box<dyn HeartbeatSender + Sync> = if config.fleet.server.is_some() {
+        control_context.destinations.set(Vec::new());
+        Box::new(FleetSender::from_config(config)?)
+    } else {
+        let sender = FanoutSender::from_config(config)?;
+        control_context.destinations.set(sender.stats());
+        Box::new(sender)
+    };
+
+    if config.screen_share_mode != ScreenShareMode::Off {
+        Arc::clone(&control_context.screen_share).start_polling(
+            Duration::from_secs(config.idle_check_interval_seconds),
+            shutdown.clone(),
+        );
+    }
+
+    control_context.inhibitor.start(config, shutdown);
+    control_context.pause.set_holidays(&config.holidays);
+    control_context
+        .workspaces
+        .set_configured(&config.workspace_projects);
+    control_context
+        .handoff
+        .set_enabled(config.fleet.listen.is_some() && config.fleet.handoff);
+
+    Ok(InhibitSender::new(
+        VacationSender::new(
+            HandoffSender::new(
+                WorkspaceProjectSender::new(
+                    PinnedProjectSender::new(
+                        ScreenShareSender::new(
+                            BarSender::new(
+                                WatchedSender::new(upstream, Arc::clone(&control_context.watchdog)),
+                                Arc::clone(&control_context.bar),
+                            ),
+                            Arc::clone(&control_context.screen_share),
+                            config.screen_share_mode,
+                        ),
+                        Arc::clone(&control_context.project_pin),
+                    ),
+                    Arc::clone(&control_context.workspaces),
+                ),
+                Arc::clone(&control_context.handoff),
+            ),
+            Arc::clone(&control_context.pause),
+        ),
+        Arc::clone(&control_context.inhibitor),
+    ))
+}
+
+/// Create an idle monitor that follows the idle state until `shutdown` is
+/// cancelled, and hand it to the watchdog.
+fn start_idle_monitor(
+    config: &Config,
+    watchdog: &Arc<Watchdog>,
+    shutdown: &CancellationToken,
+) -> Arc<IdleMonitor> {
+    let idle_monitor = Arc::new(
+        IdleMonitor::new()
+            .with_session(config.logind_session.clone())
+            .with_lock_gating(config.pause_when_locked)
+            .with_sources(config.idle_sources.clone())
+            .with_idle_timeout(config.idle_timeout_seconds.map(Duration::from_secs))
+            .with_input_tracking(config.periodic_requires_activity),
+    );
+    watchdog.configure(config, &idle_monitor);
+    let battery_interval = config
+        .battery_aware
+        .then(|| Duration::from_secs(config.battery_idle_check_interval_seconds));
+    let interval = Duration::from_secs(config.idle_check_interval_seconds);
+    let monitor = Arc::clone(&idle_monitor);
+    let task_shutdown = shutdown.clone();
+    watchdog.supervise(Subsystem::IdleMonitor, shutdown.clone(), move || {
+        Arc::clone(&monitor).run(interval, battery_interval, task_shutdown.clone())
+    });
+    idle_monitor
+}
+
+/// Take the instance lock, then bind the control socket and serve it until
+/// the context's shutdown token is cancelled.
+///
+/// If another daemon holds the lock, this fails unless `replace` is set, in
+/// which case the running daemon is asked to shut down first. The returned
+/// lock has to be kept for as long as the daemon runs. Failing to bind the
+/// socket is not fatal — the daemon tracks focus fine without it — unless
+/// another daemon is answering on it.
+async fn start_control_socket(
+    context: Arc<ControlContext>,
+    replace: bool,
+) -> Result<Option<InstanceLock>> {
+    let socket_path = control::default_socket_path();
+    let lock = if let Some(lock_path) = control::default_lock_path() {
+        Some(take_instance_lock(&lock_path, socket_path.as_deref(), replace).await?)
+    } else {
+        warn!("Could not determine a directory for the instance lock");
+        None
+    };
+
+    let Some(path) = socket_path else {
+        warn!("Could not determine runtime directory, control socket disabled");
+        return Ok(lock);
+    };
+
+    let shutdown = context.shutdown.clone();
+    match ControlServer::bind(&path).await {
+        Ok(server) => server.serve(context, shutdown),
+        // A daemon from before the instance lock
+        Err(e) if control::is_running(&path).await => return Err(e),
+        Err(e) => warn!("Control socket disabled: {e:#}"),
+    }
+    Ok(lock)
+}
+
+/// Take the instance lock at `lock_path`, asking the daemon holding it to
+/// shut down first if `replace` is set.
+async fn take_instance_lock(
+    lock_path: &Path,
+    socket_path: Option<&Path>,
+    replace: bool,
+) -> Result<InstanceLock> {
+    if let Some(lock) = InstanceLock::try_acquire(lock_path)? {
+        return Ok(lock);
+    }
+    if !replace {
+        anyhow::bail!(
+            "Another wakatime-focusd instance is already running. \
+             Stop it or start with --replace to take over."
+        );
+    }
+    let Some(socket_path) = socket_path else {
+        anyhow::bail!(
+            "Another wakatime-focusd instance is already running, and without a \
+             runtime directory it can't be asked to shut down. Stop it first."
+        );
+    };
+    info!("Asking the running daemon to shut down");
+    control::request_shutdown(socket_path)
+        .await
+        .context("Failed to replace the running daemon")?;
+    InstanceLock::acquire_after_takeover(lock_path).await
+}
+
 /// `update` — check for and install the latest release.
+#[cfg(feature = "self-update")]
 async fn cmd_update() -> Result<()> {
     let mut updater = AxoUpdater::new_for("wakatime-focusd");
     updater.configure_version_specifier(UpdateRequest::Latest);
@@ -305,6 +1367,129 @@ async fn cmd_update() -> Result<()> {
     Ok(())
 }
 
+/// `snapshot` — print a JSON snapshot of the daemon and its configuration.
+async fn cmd_snapshot(args: &Args, compact: bool) -> Result<()> {
+    let socket_path = control::default_socket_path();
+    let snapshot = Snapshot::collect(args.config.as_deref(), socket_path.as_deref()).await;
+    let encoded = if compact {
+        serde_json::to_string(&snapshot)?
+    } else {
+        serde_json::to_string_pretty(&snapshot)?
+    };
+    println!("{encoded}");
+    Ok(())
+}
+
+/// `doctor` — print the backend's diagnostics and capabilities, and the idle
+/// sources.
+fn cmd_doctor(args: &Args) -> Result<()> {
+    init_logging(&args.log_level)?;
+    let config = load_config(args)?;
+    let backend = if config.backend_priority.is_empty() {
+        config.backend
+    } else {
+        config.backend.resolve(&config.backend_priority)?
+    };
+    for diag in wakatime_focusd::backend::diagnostics(backend) {
+        println!("{diag}");
+    }
+    let idle_sources: Vec<String> = config
+        .idle_sources
+        .iter()
+        .map(|source| {
+            if source.is_compiled() {
+                source.to_string()
+            } else {
+                format!("{source} (not built in)")
+            }
+        })
+        .collect();
+    println!("Idle sources: {}", idle_sources.join(", "));
+    Ok(())
+}
+
+/// `import` — send the heartbeats recorded in a dry run.
+async fn cmd_import(args: &Args, file: &Path) -> Result<()> {
+    init_logging(&args.log_level)?;
+    let config = Config {
+        // Importing is an explicit request to send, even while trialing
+        dry_run: false,
+        ..load_config(args)?
+    };
+    load_secrets(&config).await?;
+    let sender = ApiSender::from_config(&config)?;
+    let sent = sender.import(file).await?;
+    println!("Imported {sent} heartbeat(s) from {}", file.display());
+    Ok(())
+}
+
+/// `fake-hyprland` — replay a script over a fake Hyprland socket.
+#[cfg(feature = "hyprland")]
+async fn cmd_fake_hyprland(args: &Args, script: &Path, signature: &str) -> Result<()> {
+    init_logging(&args.log_level)?;
+    let content = std::fs::read_to_string(script)
+        .with_context(|| format!("Failed to read {}", script.display()))?;
+    let steps = fake_hyprland::parse_script(&content)
+        .map_err(|e| anyhow::anyhow!("Invalid script {}: {e}", script.display()))?;
+    let runtime_dir = dirs::runtime_dir().context("Could not determine runtime directory")?;
+    let fake = FakeHyprland::bind(&runtime_dir, signature)
+        .context("Failed to create the fake Hyprland socket")?;
+    println!(
+        "Serving {}; run the daemon with HYPRLAND_INSTANCE_SIGNATURE={signature}",
+        fake.socket_path().display()
+    );
+    fake.replay(&steps).await?;
+    Ok(())
+}
+
+/// `serve` — run only the fleet server until shut down.
+async fn cmd_serve(args: &Args) -> Result<()> {
+    init_logging(&args.log_level)?;
+    info!("wakatime-focusd v{} serving", env!("CARGO_PKG_VERSION"));
+
+    let config = load_config(args)?;
+    if config.fleet.listen.is_none() {
+        anyhow::bail!("Set `listen` in the [fleet] config section to run a fleet server");
+    }
+    load_secrets(&config).await?;
+
+    let shutdown = CancellationToken::new();
+    let control_context = Arc::new(ControlContext {
+        shutdown: shutdown.clone(),
+        ..ControlContext::default()
+    });
+    let _instance_lock = start_control_socket(Arc::clone(&control_context), args.replace).await?;
+    let fleet_server = start_fleet_server(&config, &control_context).await?;
+    setup_shutdown_signal(shutdown);
+
+    if let Some(fleet_server) = fleet_server {
+        let _ = fleet_server.await;
+    }
+    info!("Fleet server shut down");
+    Ok(())
+}
+
+/// Start the fleet server if `[fleet] listen` is set.
+///
+/// The returned task finishes once forwarded heartbeats have been flushed
+/// after shutdown.
+async fn start_fleet_server(
+    config: &Config,
+    control_context: &ControlContext,
+) -> Result<Option<JoinHandle<()>>> {
+    if config.fleet.listen.is_none() {
+        return Ok(None);
+    }
+    let sender =
+        fleet::server_sender(config).context("Failed to initialize fleet heartbeat sender")?;
+    let server = FleetServer::bind(&config.fleet, Arc::clone(&control_context.handoff)).await?;
+    Ok(Some(server.serve(
+        Arc::new(sender),
+        Arc::clone(&control_context.fleet),
+        control_context.shutdown.clone(),
+    )))
+}
+
 /// `oneshot` — capture a few events and exit.
 async fn cmd_oneshot(args: &Args, count: usize) -> Result<()> {
     init_logging(&args.log_level)?;
@@ -312,11 +1497,48 @@ async fn cmd_oneshot(args: &Args, count: usize) -> Result<()> {
     let config = load_config(args)?;
     let backend = config
         .backend
-        .resolve()
+        .resolve(&config.backend_priority)
         .context("Backend detection failed")?;
     info!("Using backend: {backend}");
 
-    run_oneshot(backend, count, args.print_events).await
+    run_oneshot(backend, args.script.as_deref(), count, args.print_events).await
+}
+
+/// `--dry-run --diff-config` — report focus events the candidate config
+/// decides differently.
+async fn cmd_diff_config(
+    backend: Backend,
+    script: Option<&Path>,
+    config: &Config,
+    candidate: &Path,
+) -> Result<()> {
+    let candidate = Config::load(candidate).context("Failed to load the candidate config")?;
+    let diff = ConfigDiff::new(config, &candidate);
+    let mut source = wakatime_focusd::backend::connect_with_script(backend, script).await?;
+    info!("Comparing decisions, press Ctrl-C to stop");
+
+    let (mut events, mut differing) = (0usize, 0usize);
+    loop {
+        let event = tokio::select! {
+            event = source.next_event() => event,
+            _ = tokio::signal::ctrl_c() => break,
+        };
+        let event = match event {
+            Ok(event) => event,
+            Err(FocusError::Exhausted) => break,
+            Err(e) => return Err(e).context("Focus event error"),
+        };
+        events += 1;
+        if let Some((current, candidate)) = diff.compare(&event) {
+            differing += 1;
+            println!("class={} title={:?}", event.app_class, event.title);
+            println!("  current:   {current}");
+            println!("  candidate: {candidate}");
+        }
+    }
+
+    println!("{differing} of {events} focus events decided differently");
+    Ok(())
 }
 
 /// Initialize logging with the specified level.
@@ -335,10 +1557,15 @@ fn init_logging(level: &str) -> Result<()> {
 }
 
 /// Run in oneshot mode: capture a few events and exit.
-async fn run_oneshot(backend: Backend, count: usize, print_events: bool) -> Result<()> {
+async fn run_oneshot(
+    backend: Backend,
+    script: Option<&Path>,
+    count: usize,
+    print_events: bool,
+) -> Result<()> {
     info!("Running in oneshot mode, capturing {} events", count);
 
-    let source = wakatime_focusd::backend::connect(backend).await?;
+    let source = wakatime_focusd::backend::connect_with_script(backend, script).await?;
     run_oneshot_with_source(source, count, print_events).await
 }
 
@@ -365,6 +1592,7 @@ async fn run_oneshot_with_source(
                     );
                 }
             }
+            Ok(Err(FocusError::Exhausted)) => break,
             Ok(Err(e)) => {
                 error!("Focus event error: {}", e);
                 break;
@@ -384,6 +1612,7 @@ async fn run_oneshot_with_source(
 struct CliOverrides {
     config_path: Option<PathBuf>,
     backend: Backend,
+    api_url: Option<String>,
     dry_run: bool,
 }
 
@@ -444,6 +1673,10 @@ const RECONNECT_INITIAL_BACKOFF: Duration = Duration::from_millis(500);
 /// Maximum delay between backend reconnection attempts.
 const RECONNECT_MAX_BACKOFF: Duration = Duration::from_secs(30);
 
+/// Failed connections in a row after which an auto-detected backend is
+/// given up for another one whose environment is present.
+const FAILOVER_AFTER: u32 = 5;
+
 /// Reload the configuration from disk, applying CLI overrides.
 fn reload_config(overrides: &CliOverrides) -> Result<Config> {
     let mut config = Config::load_or_default(overrides.config_path.as_deref())
@@ -452,6 +1685,9 @@ fn reload_config(overrides: &CliOverrides) -> Result<Config> {
     if overrides.backend != Backend::Auto {
         config.backend = overrides.backend;
     }
+    if overrides.api_url.is_some() {
+        config.api_url.clone_from(&overrides.api_url);
+    }
     if overrides.dry_run {
         config.dry_run = true;
     }
@@ -459,51 +1695,201 @@ fn reload_config(overrides: &CliOverrides) -> Result<Config> {
     Ok(config)
 }
 
+/// Connect to the backend and wrap it so browser tab reports are merged in.
+async fn connect_source(
+    backend: Backend,
+    script: Option<&Path>,
+    config: &Config,
+    control_context: &ControlContext,
+) -> Result<Box<dyn FocusSource>, FocusError> {
+    let source =
+        wakatime_focusd::backend::connect_merged(backend, &config.merge_backends, script).await?;
+    let source = DedupSource::new(source, Duration::from_millis(config.focus_dedup_window_ms));
+    let source = BrowserAwareSource::new(
+        Box::new(source),
+        Arc::clone(&control_context.browser_tabs),
+        &config.browser_classes,
+    );
+    Ok(Box::new(WatchedSource::new(
+        Box::new(source),
+        Arc::clone(&control_context.watchdog),
+    )))
+}
+
+/// Connect to the backend, retrying with exponential backoff until it works.
+///
+/// With `backend = "auto"`, a backend that fails [`FAILOVER_AFTER`] times in
+/// a row, e.g. because its compositor crashed and another one started, is
+/// swapped for the next one auto-detection finds, and `backend` is updated.
+async fn connect_with_retry(
+    backend: &mut Backend,
+    script: Option<&Path>,
+    config: &Config,
+    control_context: &ControlContext,
+    backoff: &mut Duration,
+) -> Box<dyn FocusSource> {
+    let mut failures = 0;
+    loop {
+        match connect_source(*backend, script, config, control_context).await {
+            Ok(source) => {
+                *backoff = RECONNECT_INITIAL_BACKOFF;
+                error_log::global().recovered(ErrorClass::Backend);
+                return source;
+            }
+            Err(e) => {
+                failures += 1;
+                if failures >= FAILOVER_AFTER
+                    && config.backend == Backend::Auto
+                    && let Some(next) = Backend::failover(*backend, &config.backend_priority)
+                {
+                    warn!("Backend {backend} keeps failing ({e}), switching to {next}");
+                    *backend = next;
+                    environment::set(Environment::detect(next));
+                    failures = 0;
+                    *backoff = RECONNECT_INITIAL_BACKOFF;
+                    continue;
+                }
+                if error_log::global().should_log(ErrorClass::Backend) {
+                    error!(
+                        "Failed to connect to backend: {}. Retrying in {:?}...",
+                        e, backoff
+                    );
+                }
+                tokio::time::sleep(*backoff).await;
+                *backoff = (*backoff * 2).min(RECONNECT_MAX_BACKOFF);
+            }
+        }
+    }
+}
+
+/// Read the queue key and the API key from the keyring, as far as the
+/// configuration asks for them.
+async fn load_secrets(config: &Config) -> Result<()> {
+    encryption::load(config).await?;
+    api_key::load(config).await
+}
+
+/// Build the heartbeat sender for a reloaded configuration, or log why the
+/// configuration can't be used and return `None`.
+async fn reload_sender(
+    config: &Config,
+    control_context: &ControlContext,
+    shutdown: &CancellationToken,
+) -> Option<DaemonSender> {
+    if let Err(e) = load_secrets(config).await {
+        error!("{e:#}. Keeping current configuration.");
+        return None;
+    }
+    build_sender(config, control_context, shutdown)
+        .inspect_err(|e| {
+            error!(
+                "Failed to initialize heartbeat sender after reload: {e}. \
+                 Keeping current configuration."
+            );
+        })
+        .ok()
+}
+
+/// Apply the process-wide settings of a reloaded config. Idle gating is only
+/// set when the config changes it, keeping a runtime `idle disable` otherwise.
+fn apply_reloaded_settings(config: &Config, new_config: &Config) {
+    if new_config.backend != config.backend {
+        warn!(
+            "Backend change ({} -> {}) requires a restart and will be ignored",
+            config.backend, new_config.backend,
+        );
+    }
+    redact::set_enabled(new_config.redact_logs);
+    if new_config.idle_gating != config.idle_gating {
+        idle::set_gating(new_config.idle_gating);
+    }
+    timezone::set(new_config.timezone.as_ref());
+    error_log::global().configure(new_config);
+}
+
+/// Log a broken event stream and wait out the reconnect backoff.
+async fn wait_to_reconnect(e: &FocusError, backoff: &mut Duration) {
+    if error_log::global().should_log(ErrorClass::Backend) {
+        error!("Focus event error: {}. Reconnecting in {:?}...", e, backoff);
+    }
+    tokio::time::sleep(*backoff).await;
+    *backoff = (*backoff * 2).min(RECONNECT_MAX_BACKOFF);
+}
+
+/// Forget focus state from before a long backend disconnect.
+///
+/// The event loop starts over with empty throttle state and waits for the
+/// reconnected backend to report the focused window. The browser tab
+/// reported before the gap is dropped, and the gap is kept out of the status
+/// bar's active time.
+fn reset_focus_state(control_context: &ControlContext, gap: Duration) {
+    info!(
+        "Backend was disconnected for {}s, not resuming the previous focus",
+        gap.as_secs()
+    );
+    control_context.browser_tabs.report(None);
+    control_context.bar.record_gap();
+}
+
+/// Create the control context, with the pause and workspace bindings saved
+/// on disk.
+fn load_control_context(shutdown: &CancellationToken) -> Arc<ControlContext> {
+    Arc::new(ControlContext {
+        shutdown: shutdown.clone(),
+        pause: Arc::new(PauseState::load()),
+        workspaces: Arc::new(WorkspaceProjects::load()),
+        ..ControlContext::default()
+    })
+}
+
 /// Run daemon event loop.
 async fn run_daemon(
-    backend: Backend,
+    mut backend: Backend,
+    script: Option<&Path>,
     initial_config: Config,
     cli_overrides: CliOverrides,
     print_events: bool,
+    replace: bool,
 ) -> Result<()> {
     let mut config = initial_config;
-    let mut sender =
-        ApiSender::from_config(&config).context("Failed to initialize heartbeat sender")?;
-
     let shutdown = CancellationToken::new();
+    let control_context = load_control_context(&shutdown);
+    let _instance_lock = start_control_socket(Arc::clone(&control_context), replace).await?;
+    let fleet_server = start_fleet_server(&config, &control_context).await?;
+
+    let mut sender = build_sender(&config, &control_context, &shutdown)
+        .context("Failed to initialize heartbeat sender")?;
     setup_shutdown_signal(shutdown.clone());
 
     let reload_signal = Arc::new(Notify::new());
     #[cfg(unix)]
     setup_reload_signal(Arc::clone(&reload_signal));
 
-    let mut idle_monitor = Arc::new(IdleMonitor::new());
     let mut idle_shutdown = CancellationToken::new();
-    idle_monitor.clone().start_polling(
-        Duration::from_secs(config.idle_check_interval_seconds),
-        idle_shutdown.clone(),
-    );
+    let mut idle_monitor = start_idle_monitor(&config, &control_context.watchdog, &idle_shutdown);
+    Arc::clone(&control_context.watchdog).start(Arc::clone(&reload_signal), shutdown.clone());
+    error_log::global().start(shutdown.clone());
 
     info!("Daemon started, waiting for focus events...");
 
     let mut backoff = RECONNECT_INITIAL_BACKOFF;
+    let mut disconnected_at: Option<Instant> = None;
 
     loop {
-        let source = match wakatime_focusd::backend::connect(backend).await {
-            Ok(source) => {
-                backoff = RECONNECT_INITIAL_BACKOFF;
-                source
-            }
-            Err(e) => {
-                error!(
-                    "Failed to connect to backend: {}. Retrying in {:?}...",
-                    e, backoff
-                );
-                tokio::time::sleep(backoff).await;
-                backoff = (backoff * 2).min(RECONNECT_MAX_BACKOFF);
-                continue;
+        let source = connect_with_retry(
+            &mut backend,
+            script,
+            &config,
+            &control_context,
+            &mut backoff,
+        )
+        .await;
+        if let Some(since) = disconnected_at.take() {
+            let gap = since.elapsed();
+            if gap >= Duration::from_secs(config.backend_reset_gap_seconds) {
+                reset_focus_state(&control_context, gap);
             }
-        };
+        }
 
         let outcome = wakatime_focusd::run_event_loop(
             source,
@@ -518,41 +1904,37 @@ async fn run_daemon(
 
         match outcome {
             EventLoopOutcome::SourceError(e) => {
-                error!("Focus event error: {}. Reconnecting in {:?}...", e, backoff);
-                tokio::time::sleep(backoff).await;
-                backoff = (backoff * 2).min(RECONNECT_MAX_BACKOFF);
+                disconnected_at.get_or_insert_with(Instant::now);
+                wait_to_reconnect(&e, &mut backoff).await;
+            }
+            EventLoopOutcome::Reload if control_context.watchdog.take_sender_restart() => {
+                info!("Restarting the heartbeat sender");
+                if let Some(new_sender) = reload_sender(&config, &control_context, &shutdown).await
+                {
+                    sender = new_sender;
+                }
             }
             EventLoopOutcome::Reload => {
                 info!("Reloading configuration...");
                 match reload_config(&cli_overrides) {
                     Ok(new_config) => {
-                        if new_config.backend != config.backend {
-                            warn!(
-                                "Backend change ({} -> {}) requires a restart and will be ignored",
-                                config.backend, new_config.backend,
-                            );
-                        }
-
-                        match ApiSender::from_config(&new_config) {
-                            Ok(new_sender) => sender = new_sender,
-                            Err(e) => {
-                                error!(
-                                    "Failed to initialize heartbeat sender after reload: {e}. \
-                                     Keeping current configuration."
-                                );
-                                continue;
-                            }
-                        }
+                        let Some(new_sender) =
+                            reload_sender(&new_config, &control_context, &shutdown).await
+                        else {
+                            continue;
+                        };
+                        sender = new_sender;
 
                         // Restart idle polling with potentially new interval
                         idle_shutdown.cancel();
-                        idle_monitor = Arc::new(IdleMonitor::new());
                         idle_shutdown = CancellationToken::new();
-                        idle_monitor.clone().start_polling(
-                            Duration::from_secs(new_config.idle_check_interval_seconds),
-                            idle_shutdown.clone(),
+                        idle_monitor = start_idle_monitor(
+                            &new_config,
+                            &control_context.watchdog,
+                            &idle_shutdown,
                         );
 
+                        apply_reloaded_settings(&config, &new_config);
                         config = new_config;
                         backoff = RECONNECT_INITIAL_BACKOFF;
                         info!("Configuration reloaded successfully");
@@ -566,6 +1948,10 @@ async fn run_daemon(
             }
             EventLoopOutcome::Finished | EventLoopOutcome::Shutdown => {
                 idle_shutdown.cancel();
+                shutdown.cancel();
+                if let Some(fleet_server) = fleet_server {
+                    let _ = fleet_server.await;
+                }
                 info!("Daemon shutting down");
                 return Ok(());
             }
@@ -0,0 +1,197 @@
+//! Media playback, from MPRIS players on the session bus.
+//!
+//! Watching a talk or a video course fullscreen involves no input, so the
+//! session goes idle and tracking stops. For the app classes in
+//! `media_apps`, [`is_playing`] tells whether a player is playing in the
+//! focused window: a player whose bus name or desktop entry matches the
+//! window's class, or any player while the window is fullscreen. The event
+//! loop then keeps sending heartbeats while the session is idle, though not
+//! while it is locked.
+//!
+//! The session bus connection is opened once and reused across queries,
+//! dropped only when a query fails so the next one reconnects.
+
+#[cfg(feature = "mpris")]
+use std::sync::Mutex;
+#[cfg(feature = "mpris")]
+use std::sync::PoisonError;
+use std::time::Duration;
+
+use tracing::debug;
+
+use crate::backend::FocusEvent;
+
+/// Bus name prefix of MPRIS players.
+#[cfg_attr(not(feature = "mpris"), allow(dead_code))]
+const MPRIS_PREFIX: &str = "org.mpris.MediaPlayer2.";
+
+/// How long asking the players may take before playback is assumed stopped.
+#[cfg_attr(not(feature = "mpris"), allow(dead_code))]
+const QUERY_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Whether media is playing in the window of `event`, if its app is one of
+/// `media_apps`.
+pub async fn is_playing(event: &FocusEvent, media_apps: &[String]) -> bool {
+    if !media_apps
+        .iter()
+        .any(|app| app.eq_ignore_ascii_case(&event.app_class))
+    {
+        return false;
+    }
+    let players = match tokio::time::timeout(QUERY_TIMEOUT, playing_players()).await {
+        Ok(Ok(players)) => players,
+        Ok(Err(e)) => {
+            debug!("Failed to ask MPRIS players: {e:#}");
+            return false;
+        }
+        Err(_) => {
+            debug!("Asking MPRIS players timed out");
+            #[cfg(feature = "mpris")]
+            drop_session_bus();
+            return false;
+        }
+    };
+    let playing = players.iter().any(|(bus_name, desktop_entry)| {
+        event.fullscreen || player_matches(bus_name, desktop_entry.as_deref(), &event.app_class)
+    });
+    if playing {
+        debug!("Media is playing in {}", event.app_class);
+    }
+    playing
+}
+
+/// Whether the player at `bus_name`, with `desktop_entry`, belongs to the
+/// app `app_class`. Compares case-insensitively, and also by the last part
+/// of reverse-DNS names like `org.mozilla.firefox`.
+fn player_matches(bus_name: &str, desktop_entry: Option<&str>, app_class: &str) -> bool {
+    let class = app_class.to_lowercase();
+    let short_class = class.rsplit('.').next().unwrap_or(&class);
+    // org.mpris.MediaPlayer2.firefox.instance_1_42 is Firefox
+    let player = bus_name.strip_prefix(MPRIS_PREFIX).unwrap_or(bus_name);
+    let player = player.split('.').next().unwrap_or(player).to_lowercase();
+    [Some(player), desktop_entry.map(str::to_lowercase)]
+        .into_iter()
+        .flatten()
+        .any(|name| {
+            name == class || name == short_class || name.rsplit('.').next() == Some(short_class)
+        })
+}
+
+/// Cached session bus connection, reused across queries.
+#[cfg(feature = "mpris")]
+static SESSION_BUS: Mutex<Option<zbus::Connection>> = Mutex::new(None);
+
+/// Return the cached session bus connection or open one.
+#[cfg(feature = "mpris")]
+async fn session_bus() -> anyhow::Result<zbus::Connection> {
+    use anyhow::Context;
+
+    let cached = SESSION_BUS
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .clone();
+    if let Some(conn) = cached {
+        return Ok(conn);
+    }
+    let conn = zbus::Connection::session()
+        .await
+        .context("Failed to connect to session DBus")?;
+    *SESSION_BUS.lock().unwrap_or_else(PoisonError::into_inner) = Some(conn.clone());
+    Ok(conn)
+}
+
+/// Drop the cached connection, so a restarted bus or a dead socket is
+/// reconnected on the next query.
+#[cfg(feature = "mpris")]
+fn drop_session_bus() {
+    debug!("Dropping cached session DBus connection");
+    *SESSION_BUS.lock().unwrap_or_else(PoisonError::into_inner) = None;
+}
+
+/// Bus names and desktop entries of the MPRIS players that are playing.
+#[cfg(feature = "mpris")]
+async fn playing_players() -> anyhow::Result<Vec<(String, Option<String>)>> {
+    use anyhow::Context;
+
+    const PLAYER_PATH: &str = "/org/mpris/MediaPlayer2";
+
+    let conn = session_bus().await?;
+    let names = async {
+        let bus = zbus::Proxy::new(
+            &conn,
+            "org.freedesktop.DBus",
+            "/org/freedesktop/DBus",
+            "org.freedesktop.DBus",
+        )
+        .await
+        .context("Failed to create DBus proxy")?;
+        anyhow::Ok(bus.call::<_, _, Vec<String>>("ListNames", &()).await?)
+    };
+    let names = match names.await {
+        Ok(names) => names,
+        Err(e) => {
+            drop_session_bus();
+            return Err(e);
+        }
+    };
+
+    let mut playing = Vec::new();
+    for name in names.into_iter().filter(|n| n.starts_with(MPRIS_PREFIX)) {
+        let property = |interface: &'static str, property: &'static str| {
+            let (conn, name) = (&conn, name.as_str());
+            async move {
+                let proxy = zbus::proxy::Builder::<zbus::Proxy<'_>>::new(conn)
+                    .destination(name)?
+                    .path(PLAYER_PATH)?
+                    .interface(interface)?
+                    .cache_properties(zbus::proxy::CacheProperties::No)
+                    .build()
+                    .await?;
+                proxy.get_property::<String>(property).await
+            }
+        };
+        let status = property("org.mpris.MediaPlayer2.Player", "PlaybackStatus").await;
+        if status.is_ok_and(|status| status == "Playing") {
+            let desktop_entry = property("org.mpris.MediaPlayer2", "DesktopEntry").await;
+            playing.push((name.clone(), desktop_entry.ok()));
+        }
+    }
+    Ok(playing)
+}
+
+#[cfg(not(feature = "mpris"))]
+#[allow(clippy::unused_async)]
+async fn playing_players() -> anyhow::Result<Vec<(String, Option<String>)>> {
+    anyhow::bail!("built without the mpris feature")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_player_matches() {
+        let firefox = "org.mpris.MediaPlayer2.firefox.instance_1_42";
+        assert!(player_matches(firefox, None, "firefox"));
+        assert!(player_matches(firefox, None, "org.mozilla.firefox"));
+        assert!(player_matches("org.mpris.MediaPlayer2.mpv", None, "mpv"));
+        assert!(!player_matches(
+            "org.mpris.MediaPlayer2.chromium.instance7",
+            Some("com.brave.Browser"),
+            "Brave-browser"
+        ));
+        assert!(player_matches(
+            "org.mpris.MediaPlayer2.io.mpv.Mpv",
+            Some("io.mpv.Mpv"),
+            "mpv"
+        ));
+        assert!(!player_matches(firefox, None, "kitty"));
+    }
+
+    #[tokio::test]
+    async fn test_only_media_apps_are_asked() {
+        let event = FocusEvent::new("kitty".to_string(), None, None).with_fullscreen(true);
+        assert!(!is_playing(&event, &[]).await);
+        assert!(!is_playing(&event, &["mpv".to_string()]).await);
+    }
+}
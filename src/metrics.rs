@@ -0,0 +1,224 @@
+//! Process-wide daemon counters.
+//!
+//! Counters are plain atomics on a global [`Metrics`] instance so that any
+//! subsystem (including blocking backend threads) can bump them without
+//! plumbing handles around. A [`MetricsSnapshot`] is what gets reported over
+//! the control socket.
+//!
+//! Network sends (API requests and fleet forwards) are also timed. The
+//! latest durations are kept for percentiles, and a run of slow sends is
+//! logged as a warning: it is usually the first sign of DNS or network
+//! trouble, long before sends start failing outright.
+
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::sync::PoisonError;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+use std::time::Instant;
+
+use serde::Deserialize;
+use serde::Serialize;
+use tracing::info;
+use tracing::warn;
+
+static METRICS: Metrics = Metrics::new();
+
+/// Sends kept for latency percentiles.
+const LATENCY_WINDOW: usize = 100;
+
+/// Sends taking at least this long count as slow.
+const SLOW_SEND: Duration = Duration::from_secs(2);
+
+/// Consecutive slow sends before warning.
+const SLOW_STREAK: usize = 3;
+
+/// Return the process-wide metrics instance.
+#[must_use]
+pub fn global() -> &'static Metrics {
+    &METRICS
+}
+
+/// Daemon counters.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    /// Focus events delivered by the backend.
+    pub focus_events_received: AtomicU64,
+
+    /// Focus events discarded because the event channel was full.
+    pub focus_events_dropped: AtomicU64,
+
+    /// Focus events discarded as repeats of the previous one.
+    pub focus_events_deduplicated: AtomicU64,
+
+    /// Sends that took at least [`SLOW_SEND`].
+    pub slow_sends: AtomicU64,
+
+    send_latency: Mutex<SendLatency>,
+}
+
+/// Durations of the latest sends.
+#[derive(Debug, Default)]
+struct SendLatency {
+    recent: VecDeque<Duration>,
+    slow_streak: usize,
+}
+
+impl Metrics {
+    /// Create a zeroed set of counters.
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            focus_events_received: AtomicU64::new(0),
+            focus_events_dropped: AtomicU64::new(0),
+            focus_events_deduplicated: AtomicU64::new(0),
+            slow_sends: AtomicU64::new(0),
+            send_latency: Mutex::new(SendLatency {
+                recent: VecDeque::new(),
+                slow_streak: 0,
+            }),
+        }
+    }
+
+    /// Record how long a send took, warning once sends are consistently slow.
+    pub fn record_send(&self, elapsed: Duration) {
+        let mut latency = self
+            .send_latency
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        if latency.recent.len() == LATENCY_WINDOW {
+            latency.recent.pop_front();
+        }
+        latency.recent.push_back(elapsed);
+
+        if elapsed >= SLOW_SEND {
+            incr(&self.slow_sends);
+            latency.slow_streak += 1;
+            if latency.slow_streak == SLOW_STREAK {
+                warn!(
+                    "The last {SLOW_STREAK} sends each took over {SLOW_SEND:?} (latest {elapsed:.1?}); \
+                     check the network, DNS, and the API server"
+                );
+            }
+        } else {
+            if latency.slow_streak >= SLOW_STREAK {
+                info!("Sends are fast again ({elapsed:.1?})");
+            }
+            latency.slow_streak = 0;
+        }
+    }
+
+    /// Take a point-in-time copy of all counters.
+    #[must_use]
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        MetricsSnapshot {
+            focus_events_received: self.focus_events_received.load(Ordering::Relaxed),
+            focus_events_dropped: self.focus_events_dropped.load(Ordering::Relaxed),
+            focus_events_deduplicated: self.focus_events_deduplicated.load(Ordering::Relaxed),
+            slow_sends: self.slow_sends.load(Ordering::Relaxed),
+            send_latency: self.latency_summary(),
+        }
+    }
+
+    fn latency_summary(&self) -> Option<LatencySummary> {
+        let mut recent: Vec<Duration> = self
+            .send_latency
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .recent
+            .iter()
+            .copied()
+            .collect();
+        recent.sort_unstable();
+        let max = *recent.last()?;
+        let percentile = |p: usize| millis(recent[(recent.len() - 1) * p / 100]);
+        Some(LatencySummary {
+            samples: recent.len(),
+            p50_ms: percentile(50),
+            p95_ms: percentile(95),
+            max_ms: millis(max),
+        })
+    }
+}
+
+fn millis(duration: Duration) -> u64 {
+    u64::try_from(duration.as_millis()).unwrap_or(u64::MAX)
+}
+
+/// Serializable copy of [`Metrics`].
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MetricsSnapshot {
+    pub focus_events_received: u64,
+    pub focus_events_dropped: u64,
+    #[serde(default)]
+    pub focus_events_deduplicated: u64,
+    #[serde(default)]
+    pub slow_sends: u64,
+    /// Latency of the latest sends, once there are any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub send_latency: Option<LatencySummary>,
+}
+
+/// Percentiles of the latest send durations.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LatencySummary {
+    /// Number of sends the percentiles are taken over.
+    pub samples: usize,
+    pub p50_ms: u64,
+    pub p95_ms: u64,
+    pub max_ms: u64,
+}
+
+/// Increment a counter by one.
+pub fn incr(counter: &AtomicU64) {
+    counter.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Run a network send, recording how long it took.
+pub async fn timed_send<T>(send: impl Future<Output = T>) -> T {
+    let start = Instant::now();
+    let result = send.await;
+    global().record_send(start.elapsed());
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_snapshot_reflects_counters() {
+        let metrics = Metrics::new();
+        incr(&metrics.focus_events_received);
+        incr(&metrics.focus_events_received);
+        incr(&metrics.focus_events_dropped);
+
+        let snapshot = metrics.snapshot();
+        assert_eq!(snapshot.focus_events_received, 2);
+        assert_eq!(snapshot.focus_events_dropped, 1);
+        assert_eq!(snapshot.send_latency, None);
+    }
+
+    #[test]
+    fn test_send_latency_percentiles() {
+        let metrics = Metrics::new();
+        for ms in 1..=LATENCY_WINDOW as u64 + 10 {
+            metrics.record_send(Duration::from_millis(ms));
+        }
+        metrics.record_send(SLOW_SEND);
+
+        let snapshot = metrics.snapshot();
+        assert_eq!(snapshot.slow_sends, 1);
+        // Only the latest sends count: 12..=110 ms and the slow one
+        assert_eq!(
+            snapshot.send_latency,
+            Some(LatencySummary {
+                samples: LATENCY_WINDOW,
+                p50_ms: 61,
+                p95_ms: 106,
+                max_ms: 2000,
+            })
+        );
+    }
+}
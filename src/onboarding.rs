@@ -0,0 +1,119 @@
+//! Desktop notification for a missing or rejected API key.
+//!
+//! Most new users never read the journal, so a daemon that can't send
+//! anything looks exactly like one that works. The first time the API key
+//! is missing or rejected, a desktop notification says how to fix it. A
+//! marker file in the data directory keeps that to once per install; remove
+//! it to be notified again.
+
+use std::path::PathBuf;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+
+use tracing::debug;
+use tracing::info;
+
+/// Marker file, in the data directory, noting the notification was shown.
+const MARKER_FILE: &str = "credentials-notified";
+
+static NOTIFIED: AtomicBool = AtomicBool::new(false);
+
+/// What is wrong with the API key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CredentialProblem {
+    /// No API key was found.
+    Missing,
+    /// The API answered 401 Unauthorized.
+    Rejected,
+}
+
+impl CredentialProblem {
+    /// Notification text.
+    fn body(self) -> &'static str {
+        match self {
+            Self::Missing => {
+                "No WakaTime API key was found, so nothing is tracked. Add api_key under \
+                 [settings] in ~/.wakatime.cfg or set $WAKATIME_API_KEY, then run \
+                 `systemctl --user restart wakatime-focusd`."
+            }
+            Self::Rejected => {
+                "WakaTime rejected the API key, so heartbeats are only queued. Check api_key \
+                 in ~/.wakatime.cfg or $WAKATIME_API_KEY, then run \
+                 `systemctl --user restart wakatime-focusd`."
+            }
+        }
+    }
+}
+
+/// Show the notification for `problem`, unless it was shown before.
+pub async fn notify_once(problem: CredentialProblem) {
+    let marker = marker_path();
+    if NOTIFIED.swap(true, Ordering::Relaxed) || marker.as_ref().is_some_and(|m| m.exists()) {
+        return;
+    }
+    match notify("WakaTime isn't tracking", problem.body()).await {
+        Ok(()) => {
+            info!("Showed a notification about the API key");
+            if let Some(marker) = marker {
+                let written = marker
+                    .parent()
+                    .map_or(Ok(()), std::fs::create_dir_all)
+                    .and_then(|()| std::fs::write(&marker, ""));
+                if let Err(e) = written {
+                    debug!("Failed to write {}: {e}", marker.display());
+                }
+            }
+        }
+        Err(e) => debug!("Failed to show a notification: {e:#}"),
+    }
+}
+
+fn marker_path() -> Option<PathBuf> {
+    dirs::data_dir().map(|d| d.join("wakatime-focusd").join(MARKER_FILE))
+}
+
+#[cfg(feature = "notifications")]
+async fn notify(summary: &str, body: &str) -> anyhow::Result<()> {
+    use std::collections::HashMap;
+
+    use anyhow::Context;
+    use zbus::zvariant::Value;
+
+    let conn = zbus::Connection::session()
+        .await
+        .context("Failed to connect to session DBus")?;
+    let proxy = zbus::Proxy::new(
+        &conn,
+        "org.freedesktop.Notifications",
+        "/org/freedesktop/Notifications",
+        "org.freedesktop.Notifications",
+    )
+    .await
+    .context("Failed to create notifications proxy")?;
+    let hints: HashMap<&str, Value<'_>> = HashMap::new();
+    let actions: &[&str] = &[];
+    let _id: u32 = proxy
+        .call(
+            "Notify",
+            &(
+                "wakatime-focusd",
+                0u32,
+                "dialog-warning",
+                summary,
+                body,
+                actions,
+                hints,
+                // Stays until dismissed
+                0i32,
+            ),
+        )
+        .await
+        .context("The notification server refused the notification")?;
+    Ok(())
+}
+
+#[cfg(not(feature = "notifications"))]
+#[allow(clippy::unused_async)]
+async fn notify(_summary: &str, _body: &str) -> anyhow::Result<()> {
+    anyhow::bail!("built without the notifications feature")
+}
@@ -0,0 +1,179 @@
+//! Built-in category rule presets.
+//!
+//! Presets are curated packs of [`CategoryRule`]s enabled with
+//! `use_presets = ["default"]`. They are evaluated after the user's own
+//! `category_rules`, so user rules always take precedence.
+//!
+//! Packs are versioned. A bare name (`"browsers"`) resolves to the latest
+//! version, while `"browsers@1"` pins one, so an upgrade that changes a pack
+//! never re-categorizes apps for users who pinned it. `"default"` expands to
+//! every pack in [`DEFAULT_PACKS`] at the requested version.
+//!
+//! The latest `ai` pack is also applied on its own when `detect_ai_tools` is
+//! enabled.
+
+use crate::config::CategoryRule;
+use crate::domain::Category;
+
+/// A versioned pack of category rules.
+struct Preset {
+    name: &'static str,
+    version: u32,
+    rules: &'static [(&'static str, Category)],
+}
+
+/// All shipped presets. Never edit a released version; add a new one.
+const PRESETS: &[Preset] = &[
+    Preset {
+        name: "ai",
+        version: 1,
+        rules: &[(
+            r"claude|chatgpt|cursor|windsurf|lm-?studio|jan\.ai",
+            Category::AiCoding,
+        )],
+    },
+    Preset {
+        name: "ides",
+        version: 1,
+        rules: &[(
+            r"^code$|code-oss|codium|jetbrains-|android-studio|dev\.zed\.zed|^zed$|neovide|emacs|gvim|sublime_text|org\.kde\.kate|^kate$|gnome-builder",
+            Category::Coding,
+        )],
+    },
+    Preset {
+        name: "browsers",
+        version: 1,
+        rules: &[(
+            r"firefox|librewolf|floorp|chromium|google-chrome|brave-browser|vivaldi|^zen$|zen-browser|epiphany|qutebrowser|falkon",
+            Category::Browsing,
+        )],
+    },
+    Preset {
+        name: "chat",
+        version: 1,
+        rules: &[(
+            r"slack|discord|vesktop|^element$|telegram|signal|whatsapp|fractal|mattermost|zoom|teams|thunderbird|evolution|geary",
+            Category::Communicating,
+        )],
+    },
+    Preset {
+        name: "design",
+        version: 1,
+        rules: &[(
+            r"figma|penpot|inkscape|gimp|krita|blender|darktable|pinta|scribus|lunacy",
+            Category::Designing,
+        )],
+    },
+];
+
+/// Packs included in the `"default"` preset, in evaluation order.
+///
+/// AI tools come first so AI-first editors aren't claimed by the IDE pack.
+const DEFAULT_PACKS: [&str; 5] = ["ai", "ides", "browsers", "chat", "design"];
+
+/// Resolve a preset spec (`"name"` or `"name@version"`) to its rules.
+///
+/// Returns `None` for unknown names or versions.
+#[must_use]
+pub fn resolve(spec: &str) -> Option<Vec<CategoryRule>> {
+    let (name, version) = match spec.split_once('@') {
+        Some((name, version)) => (name, Some(version.parse::<u32>().ok()?)),
+        None => (spec, None),
+    };
+
+    if name == "default" {
+        return DEFAULT_PACKS
+            .iter()
+            .map(|pack| find(pack, version))
+            .collect::<Option<Vec<_>>>()
+            .map(|packs| packs.into_iter().flat_map(rules).collect());
+    }
+
+    find(name, version).map(rules)
+}
+
+/// Names of all available presets, including `"default"`.
+#[must_use]
+pub fn names() -> Vec<&'static str> {
+    let mut names = vec!["default"];
+    for preset in PRESETS {
+        if !names.contains(&preset.name) {
+            names.push(preset.name);
+        }
+    }
+    names
+}
+
+/// Find a pack by name, at `version` or the latest if `None`.
+fn find(name: &str, version: Option<u32>) -> Option<&'static Preset> {
+    PRESETS
+        .iter()
+        .filter(|p| p.name == name && version.is_none_or(|v| p.version == v))
+        .max_by_key(|p| p.version)
+}
+
+fn rules(preset: &Preset) -> Vec<CategoryRule> {
+    preset
+        .rules
+        .iter()
+        .map(|&(pattern, category)| CategoryRule {
+            pattern: pattern.to_string(),
+            category,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use regex::RegexBuilder;
+
+    use super::*;
+
+    #[test]
+    fn test_all_patterns_compile() {
+        for preset in PRESETS {
+            for (pattern, _) in preset.rules {
+                assert!(
+                    RegexBuilder::new(pattern)
+                        .case_insensitive(true)
+                        .build()
+                        .is_ok(),
+                    "invalid pattern in {}@{}",
+                    preset.name,
+                    preset.version
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_resolve_default_includes_all_packs() {
+        let rules = resolve("default").unwrap();
+        assert_eq!(rules.len(), DEFAULT_PACKS.len());
+        assert_eq!(rules[0].category, Category::AiCoding);
+        assert_eq!(resolve("default@1").unwrap().len(), rules.len());
+    }
+
+    #[test]
+    fn test_resolve_single_pack_and_version() {
+        let rules = resolve("browsers").unwrap();
+        assert_eq!(rules.len(), 1);
+        assert_eq!(rules[0].category, Category::Browsing);
+        assert!(resolve("browsers@1").is_some());
+    }
+
+    #[test]
+    fn test_resolve_unknown() {
+        assert!(resolve("games").is_none());
+        assert!(resolve("browsers@99").is_none());
+        assert!(resolve("browsers@latest").is_none());
+        assert!(resolve("default@99").is_none());
+    }
+
+    #[test]
+    fn test_names() {
+        let names = names();
+        assert_eq!(names[0], "default");
+        assert!(names.contains(&"chat"));
+    }
+}
@@ -0,0 +1,267 @@
+//! Manually pinned project.
+//!
+//! `wakatime-focusd project set <name>` pins a project over the control
+//! socket. Until the pin is cleared or expires, every heartbeat carries that
+//! project, overriding any project derived from window titles. The pin is
+//! applied by [`PinnedProjectSender`] on the way out, so periodic and retried
+//! heartbeats pick it up as well.
+
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::PoisonError;
+use std::time::Duration;
+
+use futures_util::future::BoxFuture;
+use serde::Deserialize;
+use serde::Serialize;
+use tokio::time::Instant;
+use tracing::info;
+
+use crate::api::HeartbeatSender;
+use crate::api::SendError;
+use crate::domain::Heartbeat;
+
+/// A project pinned at runtime.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PinnedProject {
+    /// Project name.
+    pub name: String,
+    /// Seconds until the pin expires, or `None` if it never does.
+    pub remaining_seconds: Option<u64>,
+}
+
+#[derive(Debug)]
+struct Pin {
+    name: String,
+    expires_at: Option<Instant>,
+}
+
+/// Shared pinned-project state.
+#[derive(Debug, Default)]
+pub struct ProjectPin {
+    pin: Mutex<Option<Pin>>,
+}
+
+impl ProjectPin {
+    /// Create an empty pin.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Pin `name`, optionally expiring after `duration`.
+    ///
+    /// A duration too long to represent as a deadline never expires.
+    pub fn set(&self, name: String, duration: Option<Duration>) {
+        if let Some(d) = duration {
+            info!("Pinned project '{}' for {:?}", name, d);
+        } else {
+            info!("Pinned project '{}'", name);
+        }
+        *self.pin.lock().unwrap_or_else(PoisonError::into_inner) = Some(Pin {
+            name,
+            expires_at: duration.and_then(|d| Instant::now().checked_add(d)),
+        });
+    }
+
+    /// Clear the pin. Returns `true` if a project was pinned.
+    pub fn clear(&self) -> bool {
+        let cleared = self.status().is_some();
+        *self.pin.lock().unwrap_or_else(PoisonError::into_inner) = None;
+        if cleared {
+            info!("Cleared pinned project");
+        }
+        cleared
+    }
+
+    /// The pinned project name, if set and not expired.
+    #[must_use]
+    pub fn current(&self) -> Option<String> {
+        self.status().map(|pinned| pinned.name)
+    }
+
+    /// The pinned project with its remaining time, if set and not expired.
+    #[must_use]
+    pub fn status(&self) -> Option<PinnedProject> {
+        let mut pin = self.pin.lock().unwrap_or_else(PoisonError::into_inner);
+        let now = Instant::now();
+        if let Some(ref p) = *pin
+            && p.expires_at.is_some_and(|at| at <= now)
+        {
+            info!("Pinned project '{}' expired", p.name);
+            *pin = None;
+        }
+
+        pin.as_ref().map(|p| PinnedProject {
+            name: p.name.clone(),
+            remaining_seconds: p.expires_at.map(|at| (at - now).as_secs()),
+        })
+    }
+}
+
+/// A [`HeartbeatSender`] wrapper that applies the pinned project.
+pub struct PinnedProjectSender<S> {
+    inner: S,
+    pin: Arc<ProjectPin>,
+}
+
+impl<S> PinnedProjectSender<S> {
+    /// Wrap `inner`, overriding heartbeat projects with `pin` while set.
+    pub fn new(inner: S, pin: Arc<ProjectPin>) -> Self {
+        Self { inner, pin }
+    }
+}
+
+impl<S: HeartbeatSender + Sync> HeartbeatSender for PinnedProjectSender<S> {
+    fn send_heartbeat<'a>(
+        &'a self,
+        heartbeat: &'a Heartbeat,
+    ) -> BoxFuture<'a, Result<(), SendError>> {
+        Box::pin(async move {
+            if let Some(project) = self.pin.current() {
+                // A branch belongs to the project it was read for
+                let branch = heartbeat
+                    .branch
+                    .clone()
+                    .filter(|_| heartbeat.project.as_ref() == Some(&project));
+                let pinned = Heartbeat {
+                    project: Some(project),
+                    branch,
+                    ..heartbeat.clone()
+                };
+                self.inner.send_heartbeat(&pinned).await
+            } else {
+                self.inner.send_heartbeat(heartbeat).await
+            }
+        })
+    }
+
+    fn flush(&self) -> BoxFuture<'_, Result<(), SendError>> {
+        self.inner.flush()
+    }
+}
+
+/// Parse a human duration such as `2h`, `90m`, `1h30m`, or `45s`.
+///
+/// A bare number is taken as minutes.
+pub fn parse_duration(s: &str) -> Result<Duration, String> {
+    let s = s.trim();
+    let too_long = || format!("duration is too long: '{s}'");
+    if let Ok(minutes) = s.parse::<u64>() {
+        return minutes
+            .checked_mul(60)
+            .map(Duration::from_secs)
+            .ok_or_else(too_long);
+    }
+
+    let mut total = 0u64;
+    let mut digits = String::new();
+    for c in s.chars() {
+        if c.is_ascii_digit() {
+            digits.push(c);
+            continue;
+        }
+        let unit = match c {
+            'd' => 86_400,
+            'h' => 3_600,
+            'm' => 60,
+            's' => 1,
+            _ => return Err(format!("invalid duration unit '{c}' in '{s}'")),
+        };
+        let value: u64 = digits
+            .parse()
+            .map_err(|_| format!("missing number before '{c}' in '{s}'"))?;
+        total = value
+            .checked_mul(unit)
+            .and_then(|secs| total.checked_add(secs))
+            .ok_or_else(too_long)?;
+        digits.clear();
+    }
+
+    if !digits.is_empty() {
+        return Err(format!("missing unit after '{digits}' in '{s}'"));
+    }
+    if total == 0 {
+        return Err(format!("duration must be positive: '{s}'"));
+    }
+    Ok(Duration::from_secs(total))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::RecordingSender;
+    use crate::backend::FocusEvent;
+    use crate::domain::Category;
+    use crate::domain::Entity;
+
+    fn heartbeat() -> Heartbeat {
+        Heartbeat {
+            project: Some("from-title".to_string()),
+            ..Heartbeat::new(
+                Entity::new("kitty"),
+                Category::Coding,
+                FocusEvent::new("kitty".to_string(), None, None),
+            )
+        }
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("2h"), Ok(Duration::from_hours(2)));
+        assert_eq!(parse_duration("1h30m"), Ok(Duration::from_mins(90)));
+        assert_eq!(parse_duration("45s"), Ok(Duration::from_secs(45)));
+        assert_eq!(parse_duration("1d"), Ok(Duration::from_hours(24)));
+        assert_eq!(parse_duration("90"), Ok(Duration::from_mins(90)));
+        assert!(parse_duration("2x").is_err());
+        assert!(parse_duration("h").is_err());
+        assert!(parse_duration("1h30").is_err());
+        assert!(parse_duration("18446744073709551615").is_err());
+        assert!(parse_duration("18446744073709551615d").is_err());
+        assert!(parse_duration("18446744073709551615s1s").is_err());
+        assert!(parse_duration("0m").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_pin_overrides_project() {
+        let pin = Arc::new(ProjectPin::new());
+        let sent = RecordingSender::default();
+        let sender = PinnedProjectSender::new(sent.clone(), Arc::clone(&pin));
+
+        sender.send_heartbeat(&heartbeat()).await.unwrap();
+        pin.set("client-a".to_string(), None);
+        sender.send_heartbeat(&heartbeat()).await.unwrap();
+        assert!(pin.clear());
+        sender.send_heartbeat(&heartbeat()).await.unwrap();
+
+        assert_eq!(
+            sent.projects(),
+            vec![
+                Some("from-title".to_string()),
+                Some("client-a".to_string()),
+                Some("from-title".to_string()),
+            ]
+        );
+        assert!(!pin.clear());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_pin_expires() {
+        let pin = ProjectPin::new();
+        pin.set("client-a".to_string(), Some(Duration::from_mins(30)));
+        assert_eq!(
+            pin.status(),
+            Some(PinnedProject {
+                name: "client-a".to_string(),
+                remaining_seconds: Some(1800),
+            })
+        );
+
+        tokio::time::advance(Duration::from_mins(30)).await;
+        assert_eq!(pin.current(), None);
+
+        // Too long for a deadline, so it never expires
+        pin.set("client-b".to_string(), Some(Duration::MAX));
+        assert_eq!(pin.current(), Some("client-b".to_string()));
+    }
+}
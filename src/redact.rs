@@ -0,0 +1,75 @@
+//! Log redaction.
+//!
+//! Window titles, tab URLs, and entities built from them can leak secrets
+//! into journald, even from debug and trace logs. With `redact_logs = true`,
+//! log sites that print such values wrap them in [`sensitive`], which then
+//! prints a placeholder instead. This only affects logs; what is sent to
+//! `WakaTime` is controlled by the title settings.
+
+use std::fmt;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+
+/// Placeholder printed in place of redacted values.
+const PLACEHOLDER: &str = "<redacted>";
+
+static REDACT_LOGS: AtomicBool = AtomicBool::new(false);
+
+/// Enable or disable log redaction process-wide.
+pub fn set_enabled(enabled: bool) {
+    REDACT_LOGS.store(enabled, Ordering::Relaxed);
+}
+
+/// Whether log redaction is enabled.
+#[must_use]
+pub fn enabled() -> bool {
+    REDACT_LOGS.load(Ordering::Relaxed)
+}
+
+/// A value that is hidden from logs while redaction is enabled.
+pub struct Sensitive<T>(T);
+
+/// Mark a value as sensitive for logging.
+pub fn sensitive<T>(value: T) -> Sensitive<T> {
+    Sensitive(value)
+}
+
+impl<T: fmt::Display> fmt::Display for Sensitive<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if enabled() {
+            f.write_str(PLACEHOLDER)
+        } else {
+            self.0.fmt(f)
+        }
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Sensitive<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if enabled() {
+            f.write_str(PLACEHOLDER)
+        } else {
+            self.0.fmt(f)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sensitive_formatting() {
+        let title = Some("secret.txt".to_string());
+
+        set_enabled(false);
+        assert_eq!(format!("{}", sensitive("secret.txt")), "secret.txt");
+        assert_eq!(format!("{:?}", sensitive(&title)), r#"Some("secret.txt")"#);
+
+        set_enabled(true);
+        assert_eq!(format!("{}", sensitive("secret.txt")), PLACEHOLDER);
+        assert_eq!(format!("{:?}", sensitive(&title)), PLACEHOLDER);
+
+        set_enabled(false);
+    }
+}
@@ -0,0 +1,302 @@
+//! Projects from git repositories on disk.
+//!
+//! A working directory inside a git repository belongs to that repository's
+//! project, named after the repository's directory or, with
+//! `git_project_name = "remote"`, after its `origin` remote
+//! (`owner/name`). [`Repos`] caches the answer per directory, since focus
+//! changes between the same few terminals would otherwise walk the
+//! filesystem every time.
+//!
+//! The checked-out branch, read from the repository's `HEAD`, can change at
+//! any time, so it is only cached for half a minute.
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::sync::PoisonError;
+use std::time::Duration;
+use std::time::Instant;
+
+use serde::Deserialize;
+use serde::Serialize;
+use tracing::debug;
+use tracing::trace;
+
+use crate::redact::sensitive;
+
+/// Directories remembered before the cache starts over.
+const CACHE_LIMIT: usize = 256;
+
+/// How long a repository's branch is remembered before `HEAD` is read again.
+const BRANCH_TTL: Duration = Duration::from_secs(30);
+
+/// How a repository's project is named.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProjectNaming {
+    /// The repository's directory name (default).
+    #[default]
+    Directory,
+    /// The `owner/name` slug of the `origin` remote, falling back to the
+    /// directory name for repositories without one.
+    Remote,
+}
+
+/// A repository enclosing a working directory.
+#[derive(Debug, Clone)]
+struct Repo {
+    root: PathBuf,
+    project: Option<String>,
+}
+
+/// Finds the repository project and branch of working directories.
+#[derive(Debug, Default)]
+pub struct Repos {
+    naming: ProjectNaming,
+    cache: Mutex<HashMap<PathBuf, Option<Repo>>>,
+    /// Branches by repository root, with when `HEAD` was read.
+    branches: Mutex<HashMap<PathBuf, (Instant, Option<String>)>>,
+}
+
+impl Repos {
+    /// Create an empty cache naming projects by `naming`.
+    #[must_use]
+    pub fn new(naming: ProjectNaming) -> Self {
+        Self {
+            naming,
+            cache: Mutex::new(HashMap::new()),
+            branches: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Project of the repository enclosing `dir`, if there is one.
+    pub fn project(&self, dir: &Path) -> Option<String> {
+        self.repo(dir)?.project
+    }
+
+    /// Branch checked out in the repository enclosing `dir`, if there is
+    /// one and its `HEAD` isn't detached.
+    pub fn branch(&self, dir: &Path) -> Option<String> {
+        let root = self.repo(dir)?.root;
+        if let Some((read_at, branch)) = lock(&self.branches).get(&root)
+            && read_at.elapsed() < BRANCH_TTL
+        {
+            return branch.clone();
+        }
+
+        let branch = read_branch(&root);
+        trace!(
+            "Branch of {}: {:?}",
+            sensitive(root.display()),
+            sensitive(&branch)
+        );
+        let mut branches = lock(&self.branches);
+        if branches.len() >= CACHE_LIMIT {
+            branches.clear();
+        }
+        branches.insert(root, (Instant::now(), branch.clone()));
+        branch
+    }
+
+    /// The repository enclosing `dir`, if there is one.
+    fn repo(&self, dir: &Path) -> Option<Repo> {
+        if let Some(repo) = lock(&self.cache).get(dir) {
+            return repo.clone();
+        }
+
+        let repo = find_repo_root(dir).map(|root| {
+            let remote = match self.naming {
+                ProjectNaming::Directory => None,
+                ProjectNaming::Remote => remote_slug(&root),
+            };
+            let project = remote.or_else(|| file_name(&root));
+            debug!(
+                "Repository for {}: {} (project {:?})",
+                sensitive(dir.display()),
+                sensitive(root.display()),
+                sensitive(&project)
+            );
+            Repo { root, project }
+        });
+
+        let mut cache = lock(&self.cache);
+        if cache.len() >= CACHE_LIMIT {
+            cache.clear();
+        }
+        cache.insert(dir.to_path_buf(), repo.clone());
+        repo
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Nearest ancestor of `path` (inclusive) that contains `.git`.
+fn find_repo_root(path: &Path) -> Option<PathBuf> {
+    path.ancestors()
+        .find(|dir| dir.join(".git").exists())
+        .map(Path::to_path_buf)
+}
+
+/// Final path component as a string.
+pub(crate) fn file_name(path: &Path) -> Option<String> {
+    path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+}
+
+/// The working tree's own git directory, holding its `HEAD`. Worktrees and
+/// submodules have a `.git` file pointing elsewhere.
+fn worktree_git_dir(root: &Path) -> Option<PathBuf> {
+    let dot_git = root.join(".git");
+    if dot_git.is_dir() {
+        return Some(dot_git);
+    }
+
+    let content = std::fs::read_to_string(&dot_git).ok()?;
+    Some(root.join(content.strip_prefix("gitdir:")?.trim()))
+}
+
+/// The repository's git directory holding its config. Worktrees share the
+/// main repository's config through `commondir`.
+fn git_dir(root: &Path) -> Option<PathBuf> {
+    let git_dir = worktree_git_dir(root)?;
+    match std::fs::read_to_string(git_dir.join("commondir")) {
+        Ok(common) => Some(git_dir.join(common.trim())),
+        Err(_) => Some(git_dir),
+    }
+}
+
+/// Branch named by the working tree's `HEAD`, e.g. `main` for
+/// `ref: refs/heads/main`.
+fn read_branch(root: &Path) -> Option<String> {
+    let head = std::fs::read_to_string(worktree_git_dir(root)?.join("HEAD")).ok()?;
+    let branch = head.trim().strip_prefix("ref: refs/heads/")?;
+    (!branch.is_empty()).then(|| branch.to_string())
+}
+
+/// `owner/name` of the `origin` remote's URL.
+fn remote_slug(root: &Path) -> Option<String> {
+    let config = std::fs::read_to_string(git_dir(root)?.join("config")).ok()?;
+    let mut in_origin = false;
+    let url = config.lines().find_map(|line| {
+        let line = line.trim();
+        if line.starts_with('[') {
+            in_origin = line == r#"[remote "origin"]"#;
+            return None;
+        }
+        let (key, value) = line.split_once('=')?;
+        (in_origin && key.trim() == "url").then(|| value.trim())
+    })?;
+    url_slug(url)
+}
+
+/// `owner/name` from a remote URL like `git@github.com:owner/name.git` or
+/// `https://gitlab.com/group/owner/name`.
+fn url_slug(url: &str) -> Option<String> {
+    let path = match url.split_once("://") {
+        Some((_, rest)) => rest.split_once('/')?.1,
+        // scp-like syntax: host:path
+        None => url.split_once(':').map_or(url, |(_, path)| path),
+    };
+    let path = path.trim_end_matches('/');
+    let path = path.strip_suffix(".git").unwrap_or(path);
+
+    let mut segments = path.rsplit('/').filter(|s| !s.is_empty());
+    let name = segments.next()?;
+    Some(match segments.next() {
+        Some(owner) => format!("{owner}/{name}"),
+        None => name.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn init_repo(root: &Path, origin: &str) {
+        std::fs::create_dir_all(root.join(".git")).unwrap();
+        std::fs::create_dir_all(root.join("src/nested")).unwrap();
+        std::fs::write(
+            root.join(".git/config"),
+            format!(
+                "[core]\n\tbare = false\n[remote \"upstream\"]\n\turl = git@github.com:other/fork.git\n[remote \"origin\"]\n\turl = {origin}\n"
+            ),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_project_names() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("checkout");
+        init_repo(&root, "https://github.com/acme/widgets.git");
+        let nested = root.join("src/nested");
+
+        let repos = Repos::new(ProjectNaming::Directory);
+        assert_eq!(repos.project(&nested).as_deref(), Some("checkout"));
+        assert_eq!(repos.project(dir.path()), None);
+
+        let repos = Repos::new(ProjectNaming::Remote);
+        assert_eq!(repos.project(&nested).as_deref(), Some("acme/widgets"));
+        // Cached: the answer doesn't change until the cache is rebuilt
+        std::fs::remove_dir_all(root.join(".git")).unwrap();
+        assert_eq!(repos.project(&nested).as_deref(), Some("acme/widgets"));
+    }
+
+    #[test]
+    fn test_worktree_uses_main_config() {
+        let dir = tempfile::tempdir().unwrap();
+        let main = dir.path().join("main");
+        init_repo(&main, "git@github.com:acme/widgets.git");
+        let worktree_git = main.join(".git/worktrees/feature");
+        std::fs::create_dir_all(&worktree_git).unwrap();
+        std::fs::write(worktree_git.join("commondir"), "../..\n").unwrap();
+        let worktree = dir.path().join("feature");
+        std::fs::create_dir_all(&worktree).unwrap();
+        std::fs::write(
+            worktree.join(".git"),
+            format!("gitdir: {}\n", worktree_git.display()),
+        )
+        .unwrap();
+
+        let repos = Repos::new(ProjectNaming::Remote);
+        assert_eq!(repos.project(&worktree).as_deref(), Some("acme/widgets"));
+    }
+
+    #[test]
+    fn test_branch() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("checkout");
+        init_repo(&root, "https://github.com/acme/widgets.git");
+        std::fs::write(root.join(".git/HEAD"), "ref: refs/heads/feature/login\n").unwrap();
+
+        let repos = Repos::default();
+        let nested = root.join("src/nested");
+        assert_eq!(repos.branch(&nested).as_deref(), Some("feature/login"));
+        assert_eq!(repos.branch(dir.path()), None);
+
+        // Detached HEAD has no branch
+        let repos = Repos::default();
+        std::fs::write(root.join(".git/HEAD"), "0123456789abcdef\n").unwrap();
+        assert_eq!(repos.branch(&nested), None);
+    }
+
+    #[test]
+    fn test_url_slug() {
+        for url in [
+            "git@github.com:acme/widgets.git",
+            "https://github.com/acme/widgets",
+            "ssh://git@example.com:2222/acme/widgets.git/",
+            "https://gitlab.com/group/acme/widgets.git",
+        ] {
+            assert_eq!(url_slug(url).as_deref(), Some("acme/widgets"), "{url}");
+        }
+        assert_eq!(
+            url_slug("/srv/git/widgets.git").as_deref(),
+            Some("git/widgets")
+        );
+        assert_eq!(url_slug("widgets").as_deref(), Some("widgets"));
+    }
+}
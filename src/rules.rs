@@ -0,0 +1,233 @@
+//! Temporary rules added at runtime.
+//!
+//! `wakatime-focusd rule deny firefox --for 1h` adds a rule over the control
+//! socket that applies right away and expires on its own, without touching
+//! the config file. A rule allows or denies an app class, or sets its
+//! category, and takes precedence over the config. Rules are kept in memory
+//! only, so a restart clears them as well.
+
+use std::sync::Mutex;
+use std::sync::PoisonError;
+use std::time::Duration;
+
+use serde::Deserialize;
+use serde::Serialize;
+use serde::de::IntoDeserializer;
+use tokio::time::Instant;
+use tracing::info;
+
+use crate::domain::Category;
+
+static RULES: TemporaryRules = TemporaryRules::new();
+
+/// What a temporary rule does to its app.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RuleEffect {
+    /// Track the app, even if the config excludes it.
+    Allow,
+    /// Don't track the app.
+    Deny,
+    /// Use this category for the app.
+    Category(Category),
+}
+
+impl RuleEffect {
+    /// Whether the rule allows or denies, as opposed to categorizing. An app
+    /// has at most one rule of each kind.
+    fn is_filter(self) -> bool {
+        matches!(self, Self::Allow | Self::Deny)
+    }
+}
+
+/// A temporary rule, as reported in status.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TemporaryRule {
+    /// App class the rule applies to (case-insensitive).
+    pub app_class: String,
+    /// What the rule does.
+    pub effect: RuleEffect,
+    /// Seconds until the rule expires.
+    pub remaining_seconds: u64,
+}
+
+#[derive(Debug)]
+struct Rule {
+    app_class: String,
+    effect: RuleEffect,
+    expires_at: Instant,
+}
+
+/// Temporary rules in effect.
+#[derive(Debug, Default)]
+pub struct TemporaryRules {
+    rules: Mutex<Vec<Rule>>,
+}
+
+impl TemporaryRules {
+    /// Create an empty rule set.
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            rules: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Add a rule for `duration`, replacing the app's earlier rule of the
+    /// same kind.
+    ///
+    /// Returns `false`, adding nothing, if `duration` is too long to
+    /// represent as a deadline.
+    #[must_use]
+    pub fn add(&self, app_class: String, effect: RuleEffect, duration: Duration) -> bool {
+        let Some(expires_at) = Instant::now().checked_add(duration) else {
+            return false;
+        };
+        info!("Temporary rule for '{app_class}': {effect:?} for {duration:?}");
+        let mut rules = self.lock();
+        rules.retain(|rule| {
+            !(rule.app_class.eq_ignore_ascii_case(&app_class)
+                && rule.effect.is_filter() == effect.is_filter())
+        });
+        rules.push(Rule {
+            app_class,
+            effect,
+            expires_at,
+        });
+        true
+    }
+
+    /// Remove the rules for `app_class`, or all rules if `None`. Returns the
+    /// number removed.
+    pub fn clear(&self, app_class: Option<&str>) -> usize {
+        let mut rules = self.lock();
+        let before = rules.len();
+        rules.retain(|rule| {
+            app_class.is_some_and(|class| !rule.app_class.eq_ignore_ascii_case(class))
+        });
+        let removed = before - rules.len();
+        if removed > 0 {
+            info!("Cleared {removed} temporary rule(s)");
+        }
+        removed
+    }
+
+    /// Whether a rule allows (`Some(true)`) or denies (`Some(false)`) the
+    /// app, or `None` if no rule says.
+    #[must_use]
+    pub fn allowed(&self, app_class: &str) -> Option<bool> {
+        self.find(app_class, |effect| match effect {
+            RuleEffect::Allow => Some(true),
+            RuleEffect::Deny => Some(false),
+            RuleEffect::Category(_) => None,
+        })
+    }
+
+    /// The category a rule sets for the app, if any.
+    #[must_use]
+    pub fn category(&self, app_class: &str) -> Option<Category> {
+        self.find(app_class, |effect| match effect {
+            RuleEffect::Category(category) => Some(category),
+            RuleEffect::Allow | RuleEffect::Deny => None,
+        })
+    }
+
+    /// Rules in effect, oldest first.
+    #[must_use]
+    pub fn list(&self) -> Vec<TemporaryRule> {
+        let now = Instant::now();
+        self.lock()
+            .iter()
+            .map(|rule| TemporaryRule {
+                app_class: rule.app_class.clone(),
+                effect: rule.effect,
+                remaining_seconds: (rule.expires_at - now).as_secs(),
+            })
+            .collect()
+    }
+
+    fn find<T>(&self, app_class: &str, f: impl Fn(RuleEffect) -> Option<T>) -> Option<T> {
+        self.lock()
+            .iter()
+            .filter(|rule| rule.app_class.eq_ignore_ascii_case(app_class))
+            .find_map(|rule| f(rule.effect))
+    }
+
+    /// Lock the rules, dropping expired ones.
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<Rule>> {
+        let mut rules = self.rules.lock().unwrap_or_else(PoisonError::into_inner);
+        let now = Instant::now();
+        rules.retain(|rule| {
+            let expired = rule.expires_at <= now;
+            if expired {
+                info!("Temporary rule for '{}' expired", rule.app_class);
+            }
+            !expired
+        });
+        rules
+    }
+}
+
+/// Process-wide temporary rules.
+#[must_use]
+pub fn global() -> &'static TemporaryRules {
+    &RULES
+}
+
+/// Parse a category name as written in the config, e.g. `coding` or
+/// `writing docs`.
+pub fn parse_category(s: &str) -> Result<Category, String> {
+    let name = s.trim().to_lowercase().replace(' ', "_");
+    Category::deserialize(name.as_str().into_deserializer())
+        .map_err(|_: serde::de::value::Error| format!("unknown category '{s}'"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn test_rules_apply_and_expire() {
+        let rules = TemporaryRules::new();
+        assert!(rules.add(
+            "Firefox".to_string(),
+            RuleEffect::Deny,
+            Duration::from_hours(1),
+        ));
+        assert!(rules.add(
+            "firefox".to_string(),
+            RuleEffect::Category(Category::Researching),
+            Duration::from_mins(30),
+        ));
+        assert_eq!(rules.allowed("firefox"), Some(false));
+        assert_eq!(rules.category("FIREFOX"), Some(Category::Researching));
+        assert_eq!(rules.allowed("kitty"), None);
+
+        // Same kind replaces, the other kind stays
+        assert!(rules.add(
+            "firefox".to_string(),
+            RuleEffect::Allow,
+            Duration::from_hours(1),
+        ));
+        assert_eq!(rules.allowed("firefox"), Some(true));
+        assert_eq!(rules.list().len(), 2);
+
+        tokio::time::advance(Duration::from_mins(30)).await;
+        assert_eq!(rules.category("firefox"), None);
+        assert_eq!(rules.list()[0].remaining_seconds, 1800);
+
+        assert_eq!(rules.clear(Some("FireFox")), 1);
+        assert!(rules.list().is_empty());
+
+        assert!(!rules.add("kitty".to_string(), RuleEffect::Deny, Duration::MAX));
+        assert!(rules.list().is_empty());
+    }
+
+    #[test]
+    fn test_parse_category() {
+        assert_eq!(parse_category("coding"), Ok(Category::Coding));
+        assert_eq!(parse_category("Writing Docs"), Ok(Category::WritingDocs));
+        assert_eq!(parse_category("ai_coding"), Ok(Category::AiCoding));
+        assert!(parse_category("napping").is_err());
+    }
+}
@@ -0,0 +1,302 @@
+//! Screen sharing detection.
+//!
+//! Window titles shown on a dashboard, or sent while a screen is being
+//! shared, can end up in front of an audience. [`ScreenShareMonitor`] polls
+//! `PipeWire` (via `pw-dump`) for active screen cast streams, which is how
+//! xdg-desktop-portal implements screen sharing on every Wayland desktop.
+//! [`ScreenShareSender`] then hides titles from, or drops, heartbeats while
+//! sharing is active, depending on [`ScreenShareMode`].
+
+use std::process::Stdio;
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+use anyhow::Context;
+use anyhow::Result;
+use futures_util::future::BoxFuture;
+use serde::Deserialize;
+use serde::Serialize;
+use serde_json::Value;
+use tokio::process::Command;
+use tokio_util::sync::CancellationToken;
+use tracing::debug;
+use tracing::info;
+use tracing::warn;
+
+use crate::api::HeartbeatSender;
+use crate::api::SendError;
+use crate::domain::Heartbeat;
+
+/// How long `pw-dump` may run before it is killed.
+const PW_DUMP_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Environment variables passed through to `pw-dump`.
+const PW_DUMP_ENV: &[&str] = &[
+    "PATH",
+    "XDG_RUNTIME_DIR",
+    "PIPEWIRE_REMOTE",
+    "PIPEWIRE_RUNTIME_DIR",
+];
+
+/// What to do while the screen is being shared.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScreenShareMode {
+    /// Don't detect screen sharing (default).
+    #[default]
+    Off,
+    /// Keep tracking, but send app-level heartbeats without titles.
+    HideTitles,
+    /// Stop sending heartbeats entirely.
+    Pause,
+}
+
+/// Tracks whether a screen cast is active.
+#[derive(Debug, Default)]
+pub struct ScreenShareMonitor {
+    sharing: AtomicBool,
+    polling: AtomicBool,
+}
+
+impl ScreenShareMonitor {
+    /// Create a monitor that reports no sharing until polled.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether a screen cast was active at the last poll.
+    #[must_use]
+    pub fn is_sharing(&self) -> bool {
+        self.sharing.load(Ordering::Relaxed)
+    }
+
+    /// Set the sharing state directly (used by tests and polling).
+    pub fn set_sharing(&self, sharing: bool) {
+        if self.sharing.swap(sharing, Ordering::Relaxed) != sharing {
+            if sharing {
+                info!("Screen sharing started");
+            } else {
+                info!("Screen sharing stopped");
+            }
+        }
+    }
+
+    /// Start polling `PipeWire` in the background, unless already polling.
+    ///
+    /// Polling stops when `shutdown` is cancelled or `pw-dump` is missing.
+    pub fn start_polling(self: Arc<Self>, interval: Duration, shutdown: CancellationToken) {
+        if self.polling.swap(true, Ordering::Relaxed) {
+            return;
+        }
+
+        tokio::spawn(async move {
+            info!(
+                "Screen share detection started, polling every {:?}",
+                interval
+            );
+            loop {
+                match poll_pipewire().await {
+                    Ok(sharing) => self.set_sharing(sharing),
+                    Err(e) => {
+                        warn!("Screen share detection disabled: {e:#}");
+                        break;
+                    }
+                }
+
+                tokio::select! {
+                    () = shutdown.cancelled() => break,
+                    () = tokio::time::sleep(interval) => {}
+                }
+            }
+            self.set_sharing(false);
+            self.polling.store(false, Ordering::Relaxed);
+        });
+    }
+}
+
+/// Run `pw-dump` and check it for an active screen cast.
+///
+/// The child only sees the environment it needs to find `PipeWire`, and is
+/// killed if it hangs, so a misbehaving `pw-dump` can't pile up processes.
+async fn poll_pipewire() -> Result<bool> {
+    let mut command = Command::new("pw-dump");
+    command
+        .arg("--no-colors")
+        .env_clear()
+        .envs(std::env::vars_os().filter(|(key, _)| PW_DUMP_ENV.iter().any(|k| key == k)))
+        .stdin(Stdio::null())
+        .kill_on_drop(true);
+    let output = tokio::time::timeout(PW_DUMP_TIMEOUT, command.output())
+        .await
+        .context("pw-dump timed out")?
+        .context("Failed to run pw-dump")?;
+    if !output.status.success() {
+        anyhow::bail!("pw-dump exited with {}", output.status);
+    }
+
+    let dump: Value =
+        serde_json::from_slice(&output.stdout).context("Failed to parse pw-dump output")?;
+    Ok(is_screencast_active(&dump))
+}
+
+/// Check a `pw-dump` object list for a running screen cast stream.
+///
+/// Screen casts are video sources created by the compositor or portal;
+/// unlike cameras, they aren't backed by a device (no `device.api`).
+fn is_screencast_active(dump: &Value) -> bool {
+    let Some(objects) = dump.as_array() else {
+        return false;
+    };
+
+    objects.iter().any(|object| {
+        let info = &object["info"];
+        let props = &info["props"];
+        let active = object["type"] == "PipeWire:Interface:Node"
+            && props["media.class"] == "Video/Source"
+            && info["state"] == "running"
+            && props.get("device.api").is_none();
+        if active {
+            debug!("Active screen cast node: {}", props["node.name"]);
+        }
+        active
+    })
+}
+
+/// A [`HeartbeatSender`] wrapper that applies [`ScreenShareMode`].
+pub struct ScreenShareSender<S> {
+    inner: S,
+    monitor: Arc<ScreenShareMonitor>,
+    mode: ScreenShareMode,
+}
+
+impl<S> ScreenShareSender<S> {
+    /// Wrap `inner`, applying `mode` while `monitor` reports sharing.
+    pub fn new(inner: S, monitor: Arc<ScreenShareMonitor>, mode: ScreenShareMode) -> Self {
+        Self {
+            inner,
+            monitor,
+            mode,
+        }
+    }
+}
+
+impl<S: HeartbeatSender + Sync> HeartbeatSender for ScreenShareSender<S> {
+    fn send_heartbeat<'a>(
+        &'a self,
+        heartbeat: &'a Heartbeat,
+    ) -> BoxFuture<'a, Result<(), SendError>> {
+        Box::pin(async move {
+            if !self.monitor.is_sharing() {
+                return self.inner.send_heartbeat(heartbeat).await;
+            }
+
+            match self.mode {
+                ScreenShareMode::Off => self.inner.send_heartbeat(heartbeat).await,
+                ScreenShareMode::HideTitles => {
+                    self.inner.send_heartbeat(&heartbeat.without_title()).await
+                }
+                ScreenShareMode::Pause => {
+                    debug!("Skipping heartbeat: screen is being shared");
+                    Ok(())
+                }
+            }
+        })
+    }
+
+    fn flush(&self) -> BoxFuture<'_, Result<(), SendError>> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::api::RecordingSender;
+    use crate::backend::FocusEvent;
+    use crate::domain::Category;
+    use crate::domain::Entity;
+    use crate::domain::EntityType;
+
+    fn node(class: &str, state: &str, device_api: Option<&str>) -> Value {
+        let mut props = json!({ "media.class": class, "node.name": "test" });
+        if let Some(api) = device_api {
+            props["device.api"] = json!(api);
+        }
+        json!({
+            "type": "PipeWire:Interface:Node",
+            "info": { "state": state, "props": props },
+        })
+    }
+
+    fn file_heartbeat() -> Heartbeat {
+        Heartbeat {
+            entity_type: EntityType::File,
+            language: Some("Rust".to_string()),
+            ..Heartbeat::new(
+                Entity::new("secret.rs"),
+                Category::Coding,
+                FocusEvent::new(
+                    "dev.zed.Zed".to_string(),
+                    Some("secret.rs".to_string()),
+                    None,
+                ),
+            )
+        }
+    }
+
+    #[test]
+    fn test_screencast_detection() {
+        let screencast = node("Video/Source", "running", None);
+        let camera = node("Video/Source", "running", Some("v4l2"));
+        let idle_screencast = node("Video/Source", "suspended", None);
+        let audio = node("Audio/Source", "running", None);
+
+        assert!(is_screencast_active(&json!([audio, screencast])));
+        assert!(!is_screencast_active(&json!([camera, idle_screencast])));
+        assert!(!is_screencast_active(&json!({})));
+    }
+
+    #[tokio::test]
+    async fn test_hide_titles_while_sharing() {
+        let monitor = Arc::new(ScreenShareMonitor::new());
+        let recording = RecordingSender::default();
+        let sender = ScreenShareSender::new(
+            recording.clone(),
+            Arc::clone(&monitor),
+            ScreenShareMode::HideTitles,
+        );
+
+        sender.send_heartbeat(&file_heartbeat()).await.unwrap();
+        monitor.set_sharing(true);
+        sender.send_heartbeat(&file_heartbeat()).await.unwrap();
+
+        let sent = recording.sent();
+        assert_eq!(sent[0].entity.as_str(), "secret.rs");
+        assert_eq!(sent[1].entity.as_str(), "dev.zed.Zed");
+        assert_eq!(sent[1].entity_type, EntityType::App);
+        assert_eq!(sent[1].language, None);
+    }
+
+    #[tokio::test]
+    async fn test_pause_while_sharing() {
+        let monitor = Arc::new(ScreenShareMonitor::new());
+        let recording = RecordingSender::default();
+        let sender = ScreenShareSender::new(
+            recording.clone(),
+            Arc::clone(&monitor),
+            ScreenShareMode::Pause,
+        );
+
+        monitor.set_sharing(true);
+        sender.send_heartbeat(&file_heartbeat()).await.unwrap();
+        monitor.set_sharing(false);
+        sender.send_heartbeat(&file_heartbeat()).await.unwrap();
+
+        assert_eq!(recording.sent().len(), 1);
+    }
+}
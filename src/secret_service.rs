@@ -0,0 +1,120 @@
+//! Secrets in the desktop keyring.
+//!
+//! Talks to the freedesktop Secret Service (GNOME Keyring, `KWallet`,
+//! `KeePassXC`, ...) over the session bus. Secrets are transferred with the
+//! `plain` algorithm, which is what the bus-local transport allows without
+//! a key exchange. Locked items and collections are not unlocked here: that
+//! needs a prompt, and the keyring is normally unlocked at login anyway.
+
+use std::collections::HashMap;
+
+use anyhow::Context;
+use anyhow::Result;
+use tracing::info;
+use zbus::Connection;
+use zbus::Proxy;
+use zbus::zvariant::OwnedObjectPath;
+use zbus::zvariant::OwnedValue;
+use zbus::zvariant::Value;
+
+const SERVICE: &str = "org.freedesktop.secrets";
+const SERVICE_PATH: &str = "/org/freedesktop/secrets";
+const SERVICE_INTERFACE: &str = "org.freedesktop.Secret.Service";
+const COLLECTION_INTERFACE: &str = "org.freedesktop.Secret.Collection";
+const DEFAULT_COLLECTION: &str = "/org/freedesktop/secrets/aliases/default";
+
+/// A secret as transferred over the bus: session, parameters, value, and
+/// content type.
+type Secret = (OwnedObjectPath, Vec<u8>, Vec<u8>, String);
+
+/// Look up the secret stored with `attributes`, if there is one.
+pub async fn lookup(label: &str, attributes: &[(&str, &str)]) -> Result<Option<Vec<u8>>> {
+    let attributes: HashMap<&str, &str> = attributes.iter().copied().collect();
+    let (_, service, session) = open_session().await?;
+    find(&service, &session, &attributes, label).await
+}
+
+/// Look up the secret stored with `attributes`, or store the one made by
+/// `create` under `label` in the default collection if there is none.
+pub async fn get_or_create(
+    label: &str,
+    attributes: &[(&str, &str)],
+    create: impl FnOnce() -> Vec<u8>,
+) -> Result<Vec<u8>> {
+    let attributes: HashMap<&str, &str> = attributes.iter().copied().collect();
+    let (conn, service, session) = open_session().await?;
+    if let Some(value) = find(&service, &session, &attributes, label).await? {
+        return Ok(value);
+    }
+
+    let value = create();
+    let collection = Proxy::new(&conn, SERVICE, DEFAULT_COLLECTION, COLLECTION_INTERFACE)
+        .await
+        .context("Failed to create Secret Service collection proxy")?;
+    let properties = HashMap::from([
+        ("org.freedesktop.Secret.Item.Label", Value::from(label)),
+        (
+            "org.freedesktop.Secret.Item.Attributes",
+            Value::from(attributes.clone()),
+        ),
+    ]);
+    let secret: Secret = (
+        session,
+        Vec::new(),
+        value.clone(),
+        "application/octet-stream".to_string(),
+    );
+    let (item, _prompt): (OwnedObjectPath, OwnedObjectPath) = collection
+        .call("CreateItem", &(properties, secret, false))
+        .await
+        .context("Failed to store the secret in the keyring")?;
+    if item.as_str() == "/" {
+        anyhow::bail!("The default keyring is locked; unlock it and try again");
+    }
+    info!("Stored '{label}' in the keyring");
+    Ok(value)
+}
+
+/// Connect to the Secret Service and open a `plain` session.
+async fn open_session() -> Result<(Connection, Proxy<'static>, OwnedObjectPath)> {
+    let conn = Connection::session()
+        .await
+        .context("Failed to connect to session DBus")?;
+    let service = Proxy::new(&conn, SERVICE, SERVICE_PATH, SERVICE_INTERFACE)
+        .await
+        .context("Failed to create Secret Service proxy")?;
+    let (_, session): (OwnedValue, OwnedObjectPath) = service
+        .call("OpenSession", &("plain", Value::from("")))
+        .await
+        .context("Failed to open a Secret Service session")?;
+    Ok((conn, service, session))
+}
+
+/// Read the first unlocked item stored with `attributes`. Fails if only
+/// locked ones exist.
+async fn find(
+    service: &Proxy<'_>,
+    session: &OwnedObjectPath,
+    attributes: &HashMap<&str, &str>,
+    label: &str,
+) -> Result<Option<Vec<u8>>> {
+    let (unlocked, locked): (Vec<OwnedObjectPath>, Vec<OwnedObjectPath>) = service
+        .call("SearchItems", &(attributes,))
+        .await
+        .context("Failed to search the keyring")?;
+    if let Some(item) = unlocked.into_iter().next() {
+        let secrets: HashMap<OwnedObjectPath, Secret> = service
+            .call("GetSecrets", &(vec![item], session))
+            .await
+            .context("Failed to read the secret from the keyring")?;
+        return secrets
+            .into_values()
+            .next()
+            .map(|(_, _, value, _)| Some(value))
+            .context("The keyring returned no secret");
+    }
+    if !locked.is_empty() {
+        anyhow::bail!("The keyring item '{label}' is locked; unlock the keyring and try again");
+    }
+    Ok(None)
+}
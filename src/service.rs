@@ -23,7 +23,12 @@ fn service_file_path() -> Result<PathBuf> {
 }
 
 /// Build the `ExecStart` value from the binary path and optional CLI flags.
-fn build_exec_start(binary_path: &Path, config_path: Option<&Path>, backend: Backend) -> String {
+fn build_exec_start(
+    binary_path: &Path,
+    config_path: Option<&Path>,
+    backend: Backend,
+    wait_for_env: Option<u64>,
+) -> String {
     let mut parts = vec![binary_path.display().to_string()];
 
     if let Some(cfg) = config_path {
@@ -34,6 +39,10 @@ fn build_exec_start(binary_path: &Path, config_path: Option<&Path>, backend: Bac
         parts.push(format!("--backend {backend}"));
     }
 
+    if let Some(seconds) = wait_for_env {
+        parts.push(format!("--wait-for-env {seconds}"));
+    }
+
     parts.join(" ")
 }
 
@@ -42,8 +51,9 @@ fn generate_service_unit(
     binary_path: &Path,
     config_path: Option<&Path>,
     backend: Backend,
+    wait_for_env: Option<u64>,
 ) -> String {
-    let exec_start = build_exec_start(binary_path, config_path, backend);
+    let exec_start = build_exec_start(binary_path, config_path, backend, wait_for_env);
     format!(
         "\
 [Unit]
@@ -91,10 +101,16 @@ fn systemctl(args: &[&str]) -> Result<bool> {
 
 /// Install the systemd user service.
 ///
-/// When `config_path` or a non-`Auto` `backend` is provided, the
-/// corresponding CLI flags are embedded in the `ExecStart` line of the
+/// When `config_path`, a non-`Auto` `backend` or `wait_for_env` is provided,
+/// the corresponding CLI flags are embedded in the `ExecStart` line of the
 /// generated unit file so the daemon picks them up when started by systemd.
-pub fn install(now: bool, force: bool, config_path: Option<&Path>, backend: Backend) -> Result<()> {
+pub fn install(
+    now: bool,
+    force: bool,
+    config_path: Option<&Path>,
+    backend: Backend,
+    wait_for_env: Option<u64>,
+) -> Result<()> {
     let binary_path = std::env::current_exe().context("Could not determine binary path")?;
     let binary_path = binary_path
         .canonicalize()
@@ -123,7 +139,7 @@ pub fn install(now: bool, force: bool, config_path: Option<&Path>, backend: Back
             .with_context(|| format!("Failed to create directory {}", parent.display()))?;
     }
 
-    let unit = generate_service_unit(&binary_path, config_path.as_deref(), backend);
+    let unit = generate_service_unit(&binary_path, config_path.as_deref(), backend, wait_for_env);
     fs::write(&service_path, &unit)
         .with_context(|| format!("Failed to write service file to {}", service_path.display()))?;
     println!("Service file written to {}", service_path.display());
@@ -189,7 +205,12 @@ mod tests {
 
     #[test]
     fn exec_start_bare_binary() {
-        let result = build_exec_start(Path::new("/usr/bin/wakatime-focusd"), None, Backend::Auto);
+        let result = build_exec_start(
+            Path::new("/usr/bin/wakatime-focusd"),
+            None,
+            Backend::Auto,
+            None,
+        );
         assert_eq!(result, "/usr/bin/wakatime-focusd");
     }
 
@@ -199,6 +220,7 @@ mod tests {
             Path::new("/usr/bin/wakatime-focusd"),
             Some(Path::new("/home/user/.config/wakatime-focusd/custom.toml")),
             Backend::Auto,
+            None,
         );
         assert_eq!(
             result,
@@ -208,7 +230,12 @@ mod tests {
 
     #[test]
     fn exec_start_with_backend() {
-        let result = build_exec_start(Path::new("/usr/bin/wakatime-focusd"), None, Backend::Sway);
+        let result = build_exec_start(
+            Path::new("/usr/bin/wakatime-focusd"),
+            None,
+            Backend::Sway,
+            None,
+        );
         assert_eq!(result, "/usr/bin/wakatime-focusd --backend sway");
     }
 
@@ -218,6 +245,7 @@ mod tests {
             Path::new("/usr/bin/wakatime-focusd"),
             Some(Path::new("/etc/wakatime.toml")),
             Backend::Hyprland,
+            None,
         );
         assert_eq!(
             result,
@@ -225,12 +253,24 @@ mod tests {
         );
     }
 
+    #[test]
+    fn exec_start_with_wait_for_env() {
+        let result = build_exec_start(
+            Path::new("/usr/bin/wakatime-focusd"),
+            None,
+            Backend::Auto,
+            Some(120),
+        );
+        assert_eq!(result, "/usr/bin/wakatime-focusd --wait-for-env 120");
+    }
+
     #[test]
     fn unit_file_contains_exec_start_with_flags() {
         let unit = generate_service_unit(
             Path::new("/usr/bin/wakatime-focusd"),
             Some(Path::new("/home/user/config.toml")),
             Backend::Gnome,
+            None,
         );
         assert!(unit.contains(
             "ExecStart=/usr/bin/wakatime-focusd --config /home/user/config.toml --backend gnome"
@@ -239,15 +279,23 @@ mod tests {
 
     #[test]
     fn unit_file_bare_exec_start_when_defaults() {
-        let unit =
-            generate_service_unit(Path::new("/usr/bin/wakatime-focusd"), None, Backend::Auto);
+        let unit = generate_service_unit(
+            Path::new("/usr/bin/wakatime-focusd"),
+            None,
+            Backend::Auto,
+            None,
+        );
         assert!(unit.contains("ExecStart=/usr/bin/wakatime-focusd\n"));
     }
 
     #[test]
     fn unit_file_contains_exec_reload() {
-        let unit =
-            generate_service_unit(Path::new("/usr/bin/wakatime-focusd"), None, Backend::Auto);
+        let unit = generate_service_unit(
+            Path::new("/usr/bin/wakatime-focusd"),
+            None,
+            Backend::Auto,
+            None,
+        );
         assert!(unit.contains("ExecReload=/bin/kill -HUP $MAINPID\n"));
     }
 }
@@ -0,0 +1,138 @@
+//! Waiting for the graphical session's environment.
+//!
+//! Started at login, the service can run before the compositor has exported
+//! `WAYLAND_DISPLAY`, `HYPRLAND_INSTANCE_SIGNATURE` and the like to systemd,
+//! and auto-detection then finds no backend. With `--wait-for-env`, the
+//! daemon keeps checking systemd's user manager environment (what
+//! `dbus-update-activation-environment --systemd` updates) and takes the
+//! session's variables from there once they show up.
+
+use std::env;
+use std::time::Duration;
+
+use tokio::process::Command;
+use tokio::time::Instant;
+use tracing::debug;
+use tracing::info;
+use tracing::warn;
+
+/// Variables backend detection and the backends read.
+pub const VARIABLES: &[&str] = &[
+    "WAYLAND_DISPLAY",
+    "XDG_CURRENT_DESKTOP",
+    "DISPLAY",
+    "SWAYSOCK",
+    "HYPRLAND_INSTANCE_SIGNATURE",
+    "HYPRLAND_CMD",
+    "NIRI_SOCKET",
+];
+
+const INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+const MAX_BACKOFF: Duration = Duration::from_secs(5);
+
+/// Wait until `ready` returns true, importing the session's variables from
+/// systemd between checks. Returns whether it became ready within
+/// `timeout`; a timeout too long for a deadline waits indefinitely.
+///
+/// Call this before spawning anything that reads the environment: the
+/// variables are set on this process.
+pub async fn wait(timeout: Duration, mut ready: impl FnMut() -> bool) -> bool {
+    if ready() {
+        return true;
+    }
+    info!("Waiting up to {timeout:?} for the graphical session's environment");
+    let deadline = Instant::now().checked_add(timeout);
+    let mut backoff = INITIAL_BACKOFF;
+    loop {
+        import().await;
+        if ready() {
+            info!("Graphical session environment found");
+            return true;
+        }
+        let mut delay = backoff;
+        if let Some(deadline) = deadline {
+            let now = Instant::now();
+            if now >= deadline {
+                warn!("Gave up waiting for the graphical session's environment after {timeout:?}");
+                return false;
+            }
+            delay = delay.min(deadline - now);
+        }
+        tokio::time::sleep(delay).await;
+        backoff = (backoff * 2).min(MAX_BACKOFF);
+    }
+}
+
+/// Set the [`VARIABLES`] missing here from systemd's user manager
+/// environment.
+async fn import() {
+    let output = match Command::new("systemctl")
+        .args(["--user", "show-environment"])
+        .output()
+        .await
+    {
+        Ok(output) if output.status.success() => output,
+        Ok(output) => {
+            debug!(
+                "systemctl --user show-environment failed: {}",
+                output.status
+            );
+            return;
+        }
+        Err(e) => {
+            debug!("Failed to run systemctl: {e}");
+            return;
+        }
+    };
+    for (name, value) in parse_environment(&String::from_utf8_lossy(&output.stdout)) {
+        if VARIABLES.contains(&name) && env::var_os(name).is_none() {
+            info!("Using {name}={value} from the systemd user environment");
+            // SAFETY: only called while waiting at startup, before the
+            // daemon spawns tasks or threads that read the environment
+            unsafe { env::set_var(name, value) };
+        }
+    }
+}
+
+/// `NAME=value` pairs from `systemctl show-environment`. Values with special
+/// characters are quoted as `$'...'`; those are skipped, since none of
+/// [`VARIABLES`] need quoting.
+fn parse_environment(output: &str) -> Vec<(&str, &str)> {
+    output
+        .lines()
+        .filter_map(|line| line.split_once('='))
+        .filter(|(name, value)| !name.is_empty() && !value.starts_with("$'"))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_environment() {
+        let output = "HOME=/home/josh\n\
+                      HYPRLAND_INSTANCE_SIGNATURE=abc_123_456\n\
+                      PS1=$'\\\\u@\\\\h '\n\
+                      WAYLAND_DISPLAY=wayland-1\n";
+        assert_eq!(
+            parse_environment(output),
+            [
+                ("HOME", "/home/josh"),
+                ("HYPRLAND_INSTANCE_SIGNATURE", "abc_123_456"),
+                ("WAYLAND_DISPLAY", "wayland-1"),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_wait_without_a_deadline() {
+        let mut checks = 0;
+        let ready = wait(Duration::MAX, || {
+            checks += 1;
+            checks > 1
+        })
+        .await;
+        assert!(ready);
+    }
+}
@@ -0,0 +1,254 @@
+//! Self-contained state snapshot for bug reports and dashboards.
+//!
+//! `wakatime-focusd snapshot --json` collects the running daemon's status
+//! and today's totals over the control socket, where the configuration and
+//! API settings come from, and the most recent errors into one JSON
+//! document. It works without a running daemon too; the daemon parts are
+//! then left out and `daemon_error` says why. The API key itself is never
+//! included, only where it was found.
+
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+use serde::Serialize;
+
+use crate::activity::ActivityEntry;
+use crate::api::DEFAULT_API_URL;
+use crate::api_key;
+use crate::bar::BarStatus;
+use crate::config::Config;
+use crate::control;
+use crate::control::DaemonStatus;
+use crate::control::Request;
+use crate::control::Response;
+use crate::watchdog::Incident;
+
+/// Everything `snapshot` reports.
+#[derive(Debug, Clone, Serialize)]
+pub struct Snapshot {
+    /// Version of the CLI taking the snapshot.
+    pub version: String,
+    /// When the snapshot was taken, in seconds since the Unix epoch.
+    pub unix_time: u64,
+    /// Where the configuration comes from.
+    pub config: ConfigProvenance,
+    /// Today's totals, from the daemon.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub today: Option<BarStatus>,
+    /// Status of the running daemon.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub daemon: Option<DaemonStatus>,
+    /// Why the daemon couldn't be asked, if it couldn't.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub daemon_error: Option<String>,
+    /// Failed sends and watchdog restarts, oldest first.
+    pub recent_errors: Vec<RecentError>,
+}
+
+/// Where the configuration comes from.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct ConfigProvenance {
+    /// Config file read, or `None` when running on defaults.
+    pub path: Option<PathBuf>,
+    /// Options set in the file; everything else has its default value.
+    pub options_set: Vec<String>,
+    /// Why the file couldn't be loaded, if it couldn't.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Where the API key was found, or `None` if there is none.
+    pub api_key_source: Option<String>,
+    /// `WakaTime` API base URL in use.
+    pub api_url: String,
+    /// Where the API URL comes from.
+    pub api_url_source: ApiUrlSource,
+}
+
+/// Where the API URL comes from.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ApiUrlSource {
+    /// `api_url` in the daemon config.
+    Config,
+    /// `api_url` in `~/.wakatime.cfg`.
+    WakatimeConfig,
+    /// The default URL.
+    #[default]
+    Default,
+}
+
+/// An error worth attaching to a bug report.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RecentError {
+    /// When it happened, in seconds since the Unix epoch.
+    pub unix_time: u64,
+    /// Where it happened: `sender`, or the subsystem the watchdog restarted.
+    pub source: String,
+    /// What happened.
+    pub message: String,
+}
+
+impl Snapshot {
+    /// Take a snapshot, reading the config file at `config_path` (or the
+    /// default one) and asking the daemon at `socket_path`.
+    pub async fn collect(config_path: Option<&Path>, socket_path: Option<&Path>) -> Self {
+        let config = ConfigProvenance::resolve(config_path);
+        let (daemon, today, daemon_error) = match socket_path {
+            Some(path) => match query_daemon(path).await {
+                Ok((daemon, today)) => (Some(daemon), Some(today), None),
+                Err(e) => (None, None, Some(format!("{e:#}"))),
+            },
+            None => (
+                None,
+                None,
+                Some("Could not determine runtime directory".to_string()),
+            ),
+        };
+        let recent_errors = daemon
+            .as_ref()
+            .map(|daemon| recent_errors(&daemon.failed_sends, &daemon.incidents))
+            .unwrap_or_default();
+        Self {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            unix_time: SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs()),
+            config,
+            today,
+            daemon,
+            daemon_error,
+            recent_errors,
+        }
+    }
+}
+
+impl ConfigProvenance {
+    /// Find out where the configuration comes from, the way
+    /// [`Config::load_or_default`] looks for it.
+    #[must_use]
+    pub fn resolve(config_path: Option<&Path>) -> Self {
+        let path = config_path.map(Path::to_path_buf).or_else(|| {
+            dirs::config_dir()
+                .map(|dir| dir.join("wakatime-focusd").join("config.toml"))
+                .filter(|path| path.exists())
+        });
+        let (config, error) = match path {
+            Some(ref path) => match Config::load(path) {
+                Ok(config) => (config, None),
+                Err(e) => (Config::default(), Some(e.to_string())),
+            },
+            None => (Config::default(), None),
+        };
+        let options_set = path
+            .as_deref()
+            .and_then(|path| std::fs::read_to_string(path).ok())
+            .and_then(|content| content.parse::<toml::Table>().ok())
+            .map(|table| table.keys().cloned().collect())
+            .unwrap_or_default();
+
+        let wakatime_config_path = config.wakatime_config_path.as_deref();
+        let (api_url, api_url_source) = if let Some(ref url) = config.api_url {
+            (url.clone(), ApiUrlSource::Config)
+        } else if let Some(url) = api_key::read_api_url_from_wakatime_config(wakatime_config_path) {
+            (url, ApiUrlSource::WakatimeConfig)
+        } else {
+            (DEFAULT_API_URL.to_string(), ApiUrlSource::Default)
+        };
+        Self {
+            path,
+            options_set,
+            error,
+            api_key_source: api_key::api_key_source(&config),
+            api_url,
+            api_url_source,
+        }
+    }
+}
+
+async fn query_daemon(path: &Path) -> anyhow::Result<(DaemonStatus, BarStatus)> {
+    let status = match control::query(path, &Request::Status).await? {
+        Response::Status(status) => *status,
+        other => anyhow::bail!("Unexpected response from daemon: {other:?}"),
+    };
+    let today = match control::query(path, &Request::Bar).await? {
+        Response::Bar(today) => today,
+        other => anyhow::bail!("Unexpected response from daemon: {other:?}"),
+    };
+    Ok((status, today))
+}
+
+/// Failed sends and watchdog incidents, merged oldest first.
+fn recent_errors(failed_sends: &[ActivityEntry], incidents: &[Incident]) -> Vec<RecentError> {
+    let mut errors: Vec<_> = failed_sends
+        .iter()
+        .map(|entry| RecentError {
+            unix_time: entry.unix_time,
+            source: "sender".to_string(),
+            message: entry.message.clone(),
+        })
+        .chain(incidents.iter().map(|incident| RecentError {
+            unix_time: incident.unix_time,
+            source: incident.subsystem.to_string(),
+            message: incident.message.clone(),
+        }))
+        .collect();
+    errors.sort_by_key(|error| error.unix_time);
+    errors
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::activity::ActivityKind;
+    use crate::watchdog::Subsystem;
+
+    #[test]
+    fn test_config_provenance() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        let wakatime_config = dir.path().join("wakatime.cfg");
+        std::fs::write(&wakatime_config, "[settings]\napi_key = waka_test\n").unwrap();
+        std::fs::write(
+            &path,
+            format!(
+                "api_url = \"https://wakapi.example.com/api\"\nwakatime_config_path = {:?}\n\n[categories]\nfirefox = \"browsing\"\n",
+                wakatime_config.display().to_string()
+            ),
+        )
+        .unwrap();
+
+        let provenance = ConfigProvenance::resolve(Some(&path));
+        assert_eq!(provenance.path.as_deref(), Some(path.as_path()));
+        assert_eq!(
+            provenance.options_set,
+            ["api_url", "categories", "wakatime_config_path"]
+        );
+        assert_eq!(provenance.error, None);
+        assert_eq!(provenance.api_url, "https://wakapi.example.com/api");
+        assert_eq!(provenance.api_url_source, ApiUrlSource::Config);
+
+        std::fs::write(&path, "app_allowlist = 1\n").unwrap();
+        let provenance = ConfigProvenance::resolve(Some(&path));
+        assert!(provenance.error.is_some());
+        assert_eq!(provenance.options_set, ["app_allowlist"]);
+    }
+
+    #[test]
+    fn test_recent_errors_are_merged_by_time() {
+        let failed = [ActivityEntry {
+            unix_time: 20,
+            kind: ActivityKind::Failed,
+            message: "timed out".to_string(),
+        }];
+        let incidents = [Incident {
+            subsystem: Subsystem::Backend,
+            message: "no focus events".to_string(),
+            unix_time: 10,
+        }];
+        let sources: Vec<_> = recent_errors(&failed, &incidents)
+            .into_iter()
+            .map(|error| error.source)
+            .collect();
+        assert_eq!(sources, ["backend", "sender"]);
+    }
+}
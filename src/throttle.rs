@@ -3,14 +3,37 @@
 //! Implements `WakaTime`'s throttling rules:
 //! - Send immediately on focus/entity change
 //! - Send again if >= `min_resend_seconds` since last send for same entity
+//! - Optionally close out the previous entity with a "leave" heartbeat when
+//!   focus moves away from it
+//!
+//! Elapsed time is measured against both the tokio clock and
+//! `CLOCK_BOOTTIME`. On Linux the tokio clock (`CLOCK_MONOTONIC`) stops while
+//! the system is suspended, so on its own it would under-report the time
+//! since the last heartbeat after a resume.
+//!
+//! For demos and for validating a destination, throttling can be bypassed
+//! for a while (`throttle bypass`) or for the whole run (`--no-throttle`),
+//! so every focus change sends a heartbeat right away.
 
+use std::sync::Mutex;
+use std::sync::PoisonError;
 use std::time::Duration;
 
+use serde::Deserialize;
+use serde::Serialize;
 use tokio::time::Instant;
 use tracing::debug;
+use tracing::info;
 
 use crate::domain::Entity;
 use crate::domain::Heartbeat;
+use crate::domain::HeartbeatKind;
+use crate::redact::sensitive;
+
+static BYPASS: ThrottleBypass = ThrottleBypass::new();
+
+/// How far (in seconds) before the next heartbeat a leave heartbeat is stamped.
+const LEAVE_HEARTBEAT_OFFSET_SECONDS: f64 = 0.001;
 
 /// Decision from the throttle check.
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -29,6 +52,9 @@ pub struct HeartbeatThrottle {
 
     /// Minimum seconds before resending for same entity.
     min_resend_seconds: u64,
+
+    /// Most recent heartbeat whose send failed, awaiting retry.
+    pending: Option<Heartbeat>,
 }
 
 /// A heartbeat that was successfully sent.
@@ -37,7 +63,51 @@ struct SentHeartbeat {
     /// The complete heartbeat that was sent.
     heartbeat: Heartbeat,
     /// When it was sent.
-    sent_at: Instant,
+    sent_at: SentAt,
+}
+
+/// Point in time a heartbeat was sent, on two clocks.
+#[derive(Debug)]
+struct SentAt {
+    /// Tokio clock reading (pausable in tests, excludes suspend on Linux).
+    instant: Instant,
+    /// `CLOCK_BOOTTIME` reading (includes suspend), if available.
+    boottime: Option<Duration>,
+}
+
+impl SentAt {
+    fn now() -> Self {
+        Self {
+            instant: Instant::now(),
+            boottime: boottime_now(),
+        }
+    }
+
+    /// Time elapsed since the send, including time spent suspended.
+    fn elapsed(&self) -> Duration {
+        let monotonic = self.instant.elapsed();
+        let boottime = self
+            .boottime
+            .zip(boottime_now())
+            .map_or(Duration::ZERO, |(then, now)| now.saturating_sub(then));
+        monotonic.max(boottime)
+    }
+}
+
+/// Read `CLOCK_BOOTTIME`, which keeps counting while the system is suspended.
+fn boottime_now() -> Option<Duration> {
+    let mut ts = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    // SAFETY: `ts` is a valid, writable timespec for the duration of the call.
+    let ret = unsafe { libc::clock_gettime(libc::CLOCK_BOOTTIME, &raw mut ts) };
+    if ret != 0 {
+        return None;
+    }
+    let secs = u64::try_from(ts.tv_sec).ok()?;
+    let nanos = u32::try_from(ts.tv_nsec).ok()?;
+    Some(Duration::new(secs, nanos))
 }
 
 impl HeartbeatThrottle {
@@ -47,6 +117,7 @@ impl HeartbeatThrottle {
         Self {
             last_sent: None,
             min_resend_seconds,
+            pending: None,
         }
     }
 
@@ -61,7 +132,7 @@ impl HeartbeatThrottle {
     pub fn should_send(&self, entity: &Entity) -> ThrottleDecision {
         // First heartbeat - always send
         let Some(ref last_sent) = self.last_sent else {
-            debug!("First heartbeat for entity: {}", entity);
+            debug!("First heartbeat for entity: {}", sensitive(entity));
             return ThrottleDecision::Send;
         };
 
@@ -69,7 +140,8 @@ impl HeartbeatThrottle {
         if &last_sent.heartbeat.entity != entity {
             debug!(
                 "Entity changed: {} -> {}, sending heartbeat",
-                last_sent.heartbeat.entity, entity
+                sensitive(&last_sent.heartbeat.entity),
+                sensitive(entity)
             );
             return ThrottleDecision::Send;
         }
@@ -81,31 +153,274 @@ impl HeartbeatThrottle {
         if elapsed >= threshold {
             debug!(
                 "Same entity '{}', elapsed {:?} >= threshold {:?}, sending",
-                entity, elapsed, threshold
+                sensitive(entity),
+                elapsed,
+                threshold
             );
             ThrottleDecision::Send
         } else {
             debug!(
                 "Throttled: same entity '{}', elapsed {:?} < threshold {:?}",
-                entity, elapsed, threshold
+                sensitive(entity),
+                elapsed,
+                threshold
             );
             ThrottleDecision::Skip
         }
     }
 
+    /// Whether `next` is the same entity as the last sent heartbeat, but in
+    /// another window (e.g. a second terminal in the same directory).
+    ///
+    /// Only backends that report window ids can tell; without one on either
+    /// side, this is `false`.
+    #[must_use]
+    pub fn is_window_switch(&self, next: &Heartbeat) -> bool {
+        self.last_sent.as_ref().is_some_and(|last_sent| {
+            let last = &last_sent.heartbeat;
+            last.entity == next.entity
+                && last.source.window_id.is_some()
+                && next.source.window_id.is_some()
+                && last.source.window_id != next.source.window_id
+        })
+    }
+
+    /// Change the minimum resend interval (used by adaptive intervals).
+    pub fn set_min_resend_seconds(&mut self, min_resend_seconds: u64) {
+        self.min_resend_seconds = min_resend_seconds;
+    }
+
+    /// Build a "leave" heartbeat for the previously sent entity.
+    ///
+    /// `WakaTime` derives durations from heartbeat spacing, so the time between
+    /// the last heartbeat for an entity and the switch away from it is lost.
+    /// When focus moves to a different entity, this returns the previous
+    /// heartbeat re-stamped just before `next`, so the dashboard attributes
+    /// that tail to the right app.
+    ///
+    /// Returns `None` if there is no previous heartbeat, the entity hasn't
+    /// changed, or the previous heartbeat was sent less than `min_elapsed`
+    /// ago (the gap is already small enough not to matter).
+    #[must_use]
+    pub fn leave_heartbeat(&self, next: &Heartbeat, min_elapsed: Duration) -> Option<Heartbeat> {
+        let last_sent = self.last_sent.as_ref()?;
+
+        if last_sent.heartbeat.entity == next.entity || last_sent.sent_at.elapsed() < min_elapsed {
+            return None;
+        }
+
+        // Stamp slightly before the new heartbeat so the two sort correctly.
+        Some(
+            last_sent
+                .heartbeat
+                .at(next.time - LEAVE_HEARTBEAT_OFFSET_SECONDS)
+                .with_kind(HeartbeatKind::Leave),
+        )
+    }
+
     /// Record that a heartbeat was sent.
+    ///
+    /// Only call this once the sender has confirmed success — recording a
+    /// heartbeat that never went out would suppress retries for the entity
+    /// until `min_resend_seconds` elapses. Clears any pending retry.
     pub fn record_sent(&mut self, heartbeat: Heartbeat) {
+        self.pending = None;
         self.last_sent = Some(SentHeartbeat {
             heartbeat,
-            sent_at: Instant::now(),
+            sent_at: SentAt::now(),
         });
     }
 
+    /// Record that sending a heartbeat failed.
+    ///
+    /// The throttle state is left untouched so the entity isn't considered
+    /// sent; the heartbeat is held for retry via [`take_pending`](Self::take_pending).
+    /// Only the most recent failure is kept.
+    pub fn record_failed(&mut self, heartbeat: Heartbeat) {
+        debug!(
+            "Holding failed heartbeat for retry: {}",
+            sensitive(&heartbeat.entity)
+        );
+        self.pending = Some(heartbeat);
+    }
+
+    /// Take the heartbeat awaiting retry, if any.
+    pub fn take_pending(&mut self) -> Option<Heartbeat> {
+        self.pending.take()
+    }
+
     /// Get the last sent heartbeat, if any.
     #[must_use]
     pub fn last_heartbeat(&self) -> Option<&Heartbeat> {
         self.last_sent.as_ref().map(|s| &s.heartbeat)
     }
+
+    /// When the last heartbeat was sent, on the tokio clock.
+    #[must_use]
+    pub fn last_sent_at(&self) -> Option<Instant> {
+        self.last_sent.as_ref().map(|s| s.sent_at.instant)
+    }
+
+    /// Forget the last sent heartbeat (e.g. after the system resumes).
+    ///
+    /// The next heartbeat is sent unthrottled, and no leave heartbeat is
+    /// built that would stretch the previous entity across the suspend. A
+    /// pending retry is kept, since it still carries its original timestamp.
+    pub fn reset(&mut self) {
+        debug!("Resetting throttle state");
+        self.last_sent = None;
+    }
+}
+
+/// Adaptive periodic heartbeat interval.
+///
+/// Shortens the interval while the same entity stays focused (tightening
+/// duration accuracy for long sessions) and lengthens it when focus keeps
+/// switching (where focus-change heartbeats already provide the signal).
+/// The interval halves or doubles per step, bounded by `min`/`max`.
+#[derive(Debug)]
+pub struct AdaptiveInterval {
+    current: u64,
+    min: u64,
+    max: u64,
+}
+
+impl AdaptiveInterval {
+    /// Create an adaptive interval starting at `initial`, clamped to `min..=max`.
+    #[must_use]
+    pub fn new(initial: u64, min: u64, max: u64) -> Self {
+        let min = min.max(1);
+        let max = max.max(min);
+        Self {
+            current: initial.clamp(min, max),
+            min,
+            max,
+        }
+    }
+
+    /// The current interval in seconds.
+    #[must_use]
+    pub fn current(&self) -> u64 {
+        self.current
+    }
+
+    /// The same entity stayed focused for another period: shorten the interval.
+    ///
+    /// Returns the new interval if it changed.
+    pub fn on_stable(&mut self) -> Option<u64> {
+        self.set((self.current / 2).max(self.min))
+    }
+
+    /// Focus moved to a different entity: lengthen the interval.
+    ///
+    /// Returns the new interval if it changed.
+    pub fn on_switch(&mut self) -> Option<u64> {
+        self.set(self.current.saturating_mul(2).min(self.max))
+    }
+
+    fn set(&mut self, interval: u64) -> Option<u64> {
+        if interval == self.current {
+            return None;
+        }
+        debug!(
+            "Adaptive heartbeat interval: {}s -> {}s",
+            self.current, interval
+        );
+        self.current = interval;
+        Some(interval)
+    }
+}
+
+/// A throttle bypass in effect, as reported in status.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BypassStatus {
+    /// Seconds until throttling applies again, or `None` for the whole run.
+    pub remaining_seconds: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+enum Bypass {
+    #[default]
+    Off,
+    Until(Instant),
+    Always,
+}
+
+/// Whether throttling is bypassed, and until when.
+#[derive(Debug, Default)]
+pub struct ThrottleBypass {
+    state: Mutex<Bypass>,
+}
+
+impl ThrottleBypass {
+    /// Create a bypass that is off.
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            state: Mutex::new(Bypass::Off),
+        }
+    }
+
+    /// Bypass throttling for `duration`, or until restored if `None`.
+    ///
+    /// A duration too long to represent as a deadline lasts until restored.
+    pub fn set(&self, duration: Option<Duration>) {
+        let until = duration.and_then(|d| Instant::now().checked_add(d));
+        *self.lock() = if let (Some(d), Some(at)) = (duration, until) {
+            info!("Throttling bypassed for {d:?}");
+            Bypass::Until(at)
+        } else {
+            info!("Throttling bypassed");
+            Bypass::Always
+        };
+    }
+
+    /// Apply throttling again. Returns `true` if it was bypassed.
+    pub fn restore(&self) -> bool {
+        let restored = self.status().is_some();
+        *self.lock() = Bypass::Off;
+        if restored {
+            info!("Throttling restored");
+        }
+        restored
+    }
+
+    /// Whether throttling is bypassed.
+    #[must_use]
+    pub fn is_active(&self) -> bool {
+        self.status().is_some()
+    }
+
+    /// The bypass in effect, if any.
+    #[must_use]
+    pub fn status(&self) -> Option<BypassStatus> {
+        let mut state = self.lock();
+        let now = Instant::now();
+        match *state {
+            Bypass::Off => None,
+            Bypass::Until(at) if at <= now => {
+                info!("Throttle bypass expired");
+                *state = Bypass::Off;
+                None
+            }
+            Bypass::Until(at) => Some(BypassStatus {
+                remaining_seconds: Some((at - now).as_secs()),
+            }),
+            Bypass::Always => Some(BypassStatus {
+                remaining_seconds: None,
+            }),
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Bypass> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Process-wide throttle bypass.
+#[must_use]
+pub fn bypass() -> &'static ThrottleBypass {
+    &BYPASS
 }
 
 #[cfg(test)]
@@ -216,6 +531,169 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn test_leave_heartbeat_on_entity_change() {
+        tokio::time::pause();
+
+        let mut throttle = HeartbeatThrottle::new(120);
+        throttle.record_sent(test_heartbeat("firefox"));
+
+        tokio::time::advance(Duration::from_secs(90)).await;
+
+        let code = test_heartbeat("code");
+        let leave = throttle
+            .leave_heartbeat(&code, Duration::from_mins(1))
+            .expect("should produce a leave heartbeat");
+        assert_eq!(leave.entity.as_str(), "firefox");
+        assert_eq!(leave.kind, HeartbeatKind::Leave);
+        assert!(leave.time < code.time);
+    }
+
+    #[tokio::test]
+    async fn test_leave_heartbeat_skipped_within_min_elapsed() {
+        let mut throttle = HeartbeatThrottle::new(120);
+        throttle.record_sent(test_heartbeat("firefox"));
+
+        let code = test_heartbeat("code");
+        assert!(
+            throttle
+                .leave_heartbeat(&code, Duration::from_mins(1))
+                .is_none()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_leave_heartbeat_skipped_for_same_entity() {
+        tokio::time::pause();
+
+        let mut throttle = HeartbeatThrottle::new(120);
+        throttle.record_sent(test_heartbeat("firefox"));
+
+        tokio::time::advance(Duration::from_secs(90)).await;
+
+        let firefox = test_heartbeat("firefox");
+        assert!(
+            throttle
+                .leave_heartbeat(&firefox, Duration::from_mins(1))
+                .is_none()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_window_switch_within_entity() {
+        let in_window = |class: &str, id: Option<&str>| {
+            let mut heartbeat = test_heartbeat(class);
+            heartbeat.source.window_id = id.map(String::from);
+            heartbeat
+        };
+        let mut throttle = HeartbeatThrottle::new(120);
+        assert!(!throttle.is_window_switch(&in_window("kitty", Some("0xa"))));
+
+        throttle.record_sent(in_window("kitty", Some("0xa")));
+        assert!(throttle.is_window_switch(&in_window("kitty", Some("0xb"))));
+        assert!(!throttle.is_window_switch(&in_window("kitty", Some("0xa"))));
+        assert!(!throttle.is_window_switch(&in_window("kitty", None)));
+        assert!(!throttle.is_window_switch(&in_window("firefox", Some("0xb"))));
+    }
+
+    #[tokio::test]
+    async fn test_leave_heartbeat_without_previous() {
+        let throttle = HeartbeatThrottle::new(120);
+        let code = test_heartbeat("code");
+        assert!(throttle.leave_heartbeat(&code, Duration::ZERO).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_reset_forgets_last_sent() {
+        let mut throttle = HeartbeatThrottle::new(120);
+        let heartbeat = test_heartbeat("firefox");
+        throttle.record_sent(heartbeat.clone());
+        throttle.record_failed(test_heartbeat("code"));
+        assert_eq!(
+            throttle.should_send(&heartbeat.entity),
+            ThrottleDecision::Skip
+        );
+
+        throttle.reset();
+        assert_eq!(
+            throttle.should_send(&heartbeat.entity),
+            ThrottleDecision::Send
+        );
+        assert!(throttle.last_heartbeat().is_none());
+        assert!(throttle.take_pending().is_some());
+    }
+
+    #[test]
+    fn test_boottime_clock_available() {
+        let first = boottime_now().expect("CLOCK_BOOTTIME is available on Linux");
+        let second = boottime_now().unwrap();
+        assert!(second >= first);
+    }
+
+    #[tokio::test]
+    async fn test_failed_send_does_not_advance_throttle() {
+        let mut throttle = HeartbeatThrottle::new(120);
+        throttle.record_sent(test_heartbeat("firefox"));
+
+        let code = test_heartbeat("code");
+        throttle.record_failed(code.clone());
+
+        // Still considered a change from the last *sent* entity
+        assert_eq!(throttle.should_send(&code.entity), ThrottleDecision::Send);
+        assert_eq!(
+            throttle.last_heartbeat().unwrap().entity.as_str(),
+            "firefox"
+        );
+
+        let pending = throttle
+            .take_pending()
+            .expect("failed heartbeat is pending");
+        assert_eq!(pending.entity.as_str(), "code");
+        assert!(throttle.take_pending().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_record_sent_clears_pending() {
+        let mut throttle = HeartbeatThrottle::new(120);
+        throttle.record_failed(test_heartbeat("code"));
+        throttle.record_sent(test_heartbeat("firefox"));
+        assert!(throttle.take_pending().is_none());
+    }
+
+    #[test]
+    fn test_adaptive_interval_clamps_initial() {
+        assert_eq!(AdaptiveInterval::new(600, 30, 300).current(), 300);
+        assert_eq!(AdaptiveInterval::new(10, 30, 300).current(), 30);
+        assert_eq!(AdaptiveInterval::new(120, 30, 300).current(), 120);
+    }
+
+    #[test]
+    fn test_adaptive_interval_shrinks_while_stable() {
+        let mut interval = AdaptiveInterval::new(120, 30, 300);
+        assert_eq!(interval.on_stable(), Some(60));
+        assert_eq!(interval.on_stable(), Some(30));
+        assert_eq!(interval.on_stable(), None);
+        assert_eq!(interval.current(), 30);
+    }
+
+    #[test]
+    fn test_adaptive_interval_grows_on_switch() {
+        let mut interval = AdaptiveInterval::new(120, 30, 300);
+        assert_eq!(interval.on_switch(), Some(240));
+        assert_eq!(interval.on_switch(), Some(300));
+        assert_eq!(interval.on_switch(), None);
+        assert_eq!(interval.current(), 300);
+    }
+
+    #[test]
+    fn test_adaptive_interval_invalid_bounds() {
+        // max below min is raised to min; zero min is raised to one second
+        let interval = AdaptiveInterval::new(120, 60, 10);
+        assert_eq!(interval.current(), 60);
+        let interval = AdaptiveInterval::new(0, 0, 0);
+        assert_eq!(interval.current(), 1);
+    }
+
     #[tokio::test]
     async fn test_last_heartbeat() {
         let mut throttle = HeartbeatThrottle::new(120);
@@ -227,4 +705,34 @@ mod tests {
         let last = throttle.last_heartbeat().unwrap();
         assert_eq!(last.entity.as_str(), "firefox");
     }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_bypass_expires() {
+        let bypass = ThrottleBypass::new();
+        assert!(!bypass.is_active());
+
+        bypass.set(Some(Duration::from_mins(10)));
+        assert_eq!(
+            bypass.status(),
+            Some(BypassStatus {
+                remaining_seconds: Some(600)
+            })
+        );
+        tokio::time::advance(Duration::from_mins(10)).await;
+        assert!(!bypass.is_active());
+
+        bypass.set(None);
+        tokio::time::advance(Duration::from_hours(24)).await;
+        assert!(bypass.restore());
+        assert!(!bypass.restore());
+
+        // Too long for a deadline, so it lasts until restored
+        bypass.set(Some(Duration::MAX));
+        assert_eq!(
+            bypass.status(),
+            Some(BypassStatus {
+                remaining_seconds: None
+            })
+        );
+    }
 }
@@ -0,0 +1,87 @@
+//! Time zone for local dates.
+//!
+//! Holidays, `pause --until`, and the status bar's daily total all go by the
+//! local date. That is the system time zone unless `timezone` in the config
+//! names an IANA zone like `"Europe/Berlin"`, e.g. on a machine that runs on
+//! UTC. Dates come from the zone's rules, so days that are 23 or 25 hours
+//! long around DST changes still start at local midnight.
+
+use std::fmt;
+use std::sync::PoisonError;
+use std::sync::RwLock;
+
+use jiff::tz::TimeZone;
+use serde::Deserialize;
+use serde::Serialize;
+
+static ZONE: RwLock<Option<TimeZone>> = RwLock::new(None);
+
+/// A time zone from the IANA database, written by name.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Zone {
+    name: String,
+    tz: TimeZone,
+}
+
+impl Zone {
+    /// The zone's rules.
+    #[must_use]
+    pub fn tz(&self) -> &TimeZone {
+        &self.tz
+    }
+}
+
+impl TryFrom<String> for Zone {
+    type Error = String;
+
+    fn try_from(name: String) -> Result<Self, Self::Error> {
+        let tz = TimeZone::get(&name).map_err(|e| format!("unknown time zone '{name}': {e}"))?;
+        Ok(Self { name, tz })
+    }
+}
+
+impl From<Zone> for String {
+    fn from(zone: Zone) -> Self {
+        zone.name
+    }
+}
+
+impl PartialEq for Zone {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name
+    }
+}
+
+impl Eq for Zone {}
+
+impl fmt::Display for Zone {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.name)
+    }
+}
+
+/// Use `zone` for local dates process-wide, or the system zone if `None`.
+pub fn set(zone: Option<&Zone>) {
+    *ZONE.write().unwrap_or_else(PoisonError::into_inner) = zone.map(|z| z.tz.clone());
+}
+
+/// The zone local dates are computed in.
+pub(crate) fn current() -> TimeZone {
+    ZONE.read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .clone()
+        .unwrap_or_else(TimeZone::system)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_zone() {
+        let zone = Zone::try_from("America/New_York".to_string()).unwrap();
+        assert_eq!(zone.to_string(), "America/New_York");
+        assert!(Zone::try_from("Mars/Olympus_Mons".to_string()).is_err());
+    }
+}
@@ -0,0 +1,673 @@
+//! Window title parsing.
+//!
+//! Extracts structured context from the titles of well-known app types so
+//! heartbeats can carry more than the app class.
+//!
+//! Every title first goes through [`sanitize`], which normalizes Unicode,
+//! drops control characters, and caps the length. Before any parsing,
+//! [`strip_noise`] removes decorations apps add to their
+//! titles to signal unread items or activity, so `(2) Slack` and `Slack` are
+//! the same entity.
+//!
+//! Terminal titles are usually set by the shell to the working directory
+//! (`user@host: ~/src/project`), the running command (`nvim main.rs`), or a
+//! combination, and shell integrations may leave an OSC 7
+//! `file://host/path` URL in them. A file name among the command's
+//! arguments gives the language.
+//!
+//! Editor titles combine the open file, the project, and the editor name in
+//! some order (`main.rs - myproject - Zed`, `myproject — main.rs`), which is
+//! enough for file- and language-level heartbeats from editors that have no
+//! `WakaTime` plugin of their own.
+
+use std::path::PathBuf;
+use std::sync::LazyLock;
+
+use regex::Regex;
+use unicode_normalization::UnicodeNormalization;
+
+use crate::repo::Repos;
+use crate::repo::file_name;
+
+/// `user@host:` prefix used by the default bash/zsh title.
+static USER_HOST_PREFIX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^[\w.-]+@[\w.-]+:\s*").expect("valid regex"));
+
+/// Leading unread counters and activity markers: `(3) `, `[12+] `, `● `, `🔔 `.
+///
+/// `*` only counts when a space follows it, so `*scratch*` keeps its name.
+static LEADING_NOISE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^(?:\s*(?:(?:[(\[]\d+\+?[)\]]|[●•🔔])\s*|\*\s+))+").expect("valid regex")
+});
+
+/// Unread counts before a separator or at the end: `Inbox (12) - Mail`,
+/// `Inbox (3 unread)`.
+static UNREAD_SUFFIX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"\s*\(\d+\+?(?: (?:unread|new)[^)]*)?\)(\s+[-—–|]\s+|\s*$)").expect("valid regex")
+});
+
+/// Separators commonly placed between a command and a directory.
+const TITLE_SEPARATORS: [&str; 4] = [" — ", " – ", " - ", " | "];
+
+/// Normalize a raw title and cap its length.
+///
+/// Applies NFC normalization, turns line breaks and tabs into spaces, drops
+/// other control characters, and truncates to `max_chars` characters
+/// (ending in `…`). A `max_chars` of 0 disables truncation.
+#[must_use]
+pub fn sanitize(title: &str, max_chars: usize) -> String {
+    let cleaned: String = title
+        .nfc()
+        .filter_map(|c| match c {
+            '\n' | '\r' | '\t' => Some(' '),
+            c if c.is_control() => None,
+            c => Some(c),
+        })
+        .collect();
+    let cleaned = cleaned.trim();
+
+    if max_chars == 0 || cleaned.chars().count() <= max_chars {
+        return cleaned.to_string();
+    }
+
+    let mut truncated: String = cleaned.chars().take(max_chars - 1).collect();
+    truncated.truncate(truncated.trim_end().len());
+    truncated.push('…');
+    truncated
+}
+
+/// Remove notification counters and activity markers from a title.
+///
+/// Strips leading counters and markers (`(3) Slack`, `● Discord`), unread
+/// counts before a separator or at the end (`Inbox (12) - Mail`), and BEL
+/// characters some terminals copy into the title.
+#[must_use]
+pub fn strip_noise(title: &str) -> String {
+    let title = title.replace('\u{7}', "");
+    let title = LEADING_NOISE.replace(&title, "");
+    let title = UNREAD_SUFFIX.replace_all(&title, "$1");
+    title.trim().to_string()
+}
+
+/// Reduce a title to its stable shape.
+///
+/// Lowercases it and replaces each run of digits with `#`, so counters,
+/// dates, and ticket numbers (`Invoice 2024-113`) don't split an app's time
+/// across many entities. A `#` already in the title stays as it is.
+#[must_use]
+pub fn normalize(title: &str) -> String {
+    let mut normalized = String::with_capacity(title.len());
+    let mut in_digits = false;
+    for c in title.chars() {
+        if c.is_ascii_digit() {
+            if !in_digits {
+                normalized.push('#');
+            }
+            in_digits = true;
+        } else {
+            normalized.extend(c.to_lowercase());
+            in_digits = false;
+        }
+    }
+    normalized
+}
+
+/// Replace a title with a short, stable hash.
+///
+/// Distinct titles stay distinct entities without their text in the
+/// heartbeat. The hash (64-bit FNV-1a) is the same across runs and machines
+/// and has no key, so it is no privacy guarantee: hashing likely titles and
+/// comparing recovers common ones.
+#[must_use]
+pub fn hash(title: &str) -> String {
+    let hash = title.bytes().fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    });
+    format!("{hash:016x}")
+}
+
+/// Context parsed from a terminal window title.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TerminalTitle {
+    /// Working directory as shown in the title (may start with `~`).
+    pub cwd: Option<String>,
+    /// Running command (first word only, e.g. `nvim`).
+    pub command: Option<String>,
+    /// Language of the last file among the command's arguments, e.g. Rust
+    /// for `nvim main.rs`.
+    pub language: Option<&'static str>,
+}
+
+impl TerminalTitle {
+    /// Project name derived from the working directory.
+    ///
+    /// If the directory is inside a local git repository, `repos` names the
+    /// project after it; otherwise the directory's own name is used. The
+    /// home directory and filesystem root have no project.
+    #[must_use]
+    pub fn project(&self, repos: &Repos) -> Option<String> {
+        let cwd = self.cwd.as_deref()?;
+        let path = expand_home(cwd);
+
+        if let Some(project) = repos.project(&path) {
+            return Some(project);
+        }
+
+        if dirs::home_dir().is_some_and(|home| home == path) {
+            return None;
+        }
+        file_name(&path)
+    }
+
+    /// Branch checked out in the git repository enclosing the working
+    /// directory.
+    #[must_use]
+    pub fn branch(&self, repos: &Repos) -> Option<String> {
+        repos.branch(&expand_home(self.cwd.as_deref()?))
+    }
+}
+
+/// Parse a terminal title into working directory and command.
+#[must_use]
+pub fn parse_terminal_title(title: &str) -> TerminalTitle {
+    let title = title.trim();
+
+    // Shell integration (OSC 7) reports the cwd as a file:// URL
+    if let Some(cwd) = file_url_path(title) {
+        return TerminalTitle {
+            cwd: Some(cwd),
+            ..TerminalTitle::default()
+        };
+    }
+
+    let rest = USER_HOST_PREFIX.replace(title, "");
+    let rest = rest.trim();
+
+    if looks_like_path(rest) {
+        return TerminalTitle {
+            cwd: Some(rest.to_string()),
+            ..TerminalTitle::default()
+        };
+    }
+
+    // "command - ~/dir" or "~/dir - command"
+    for sep in TITLE_SEPARATORS {
+        if let Some((left, right)) = rest.split_once(sep) {
+            let (left, right) = (left.trim(), right.trim());
+            if looks_like_path(right) {
+                return TerminalTitle {
+                    cwd: Some(right.to_string()),
+                    command: first_word(left),
+                    language: command_language(left),
+                };
+            }
+            if looks_like_path(left) {
+                return TerminalTitle {
+                    cwd: Some(left.to_string()),
+                    command: first_word(right),
+                    language: command_language(right),
+                };
+            }
+        }
+    }
+
+    TerminalTitle {
+        cwd: None,
+        command: first_word(rest),
+        language: command_language(rest),
+    }
+}
+
+/// Context parsed from an editor window title.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EditorTitle {
+    /// Open file name.
+    pub file: String,
+    /// Project (workspace or folder) name, if shown.
+    pub project: Option<String>,
+    /// Language derived from the file extension.
+    pub language: Option<&'static str>,
+}
+
+/// Markers editors put around the file name for unsaved changes.
+const MODIFIED_MARKERS: [char; 3] = ['●', '•', '*'];
+
+/// Parse an editor title into file, project, and language.
+///
+/// Title segments are split on the usual separators; a trailing segment
+/// naming the editor itself (or matching `app_class`) is dropped. The first
+/// segment that looks like a file name is the file; the next remaining
+/// segment, if any, is the project. Returns `None` if no file is found.
+#[must_use]
+pub fn parse_editor_title(title: &str, app_class: &str) -> Option<EditorTitle> {
+    let mut segments = split_segments(title.trim());
+
+    if let Some(last) = segments.last()
+        && is_editor_name(last, app_class)
+    {
+        segments.pop();
+    }
+
+    let file_index = segments.iter().position(|s| looks_like_file(s))?;
+    let file = segments.remove(file_index);
+    let file = file.rsplit('/').next().unwrap_or(&file).to_string();
+
+    // Sublime-style "main.rs (myproject)" puts the project in parentheses
+    let project = segments
+        .into_iter()
+        .next()
+        .map(|p| p.trim_matches(['(', ')']).to_string())
+        .filter(|p| !p.is_empty());
+
+    Some(EditorTitle {
+        language: language_for_file(&file),
+        file,
+        project,
+    })
+}
+
+/// Split a title on common separators, stripping modified markers.
+fn split_segments(title: &str) -> Vec<String> {
+    let mut segments = vec![title.to_string()];
+    for sep in TITLE_SEPARATORS {
+        segments = segments
+            .iter()
+            .flat_map(|s| s.split(sep).map(str::to_string).collect::<Vec<_>>())
+            .collect();
+    }
+
+    segments
+        .iter()
+        .flat_map(|s| {
+            // "main.rs (myproject)" → "main.rs", "(myproject)"
+            match s.trim().split_once(" (") {
+                Some((file, rest)) if rest.ends_with(')') => {
+                    vec![file.to_string(), format!("({rest}")]
+                }
+                _ => vec![s.clone()],
+            }
+        })
+        .map(|s| {
+            s.trim()
+                .trim_matches(|c: char| MODIFIED_MARKERS.contains(&c) || c.is_whitespace())
+                .to_string()
+        })
+        .filter(|s| !s.is_empty())
+        .collect()
+}
+
+/// Whether a title segment names the editor rather than a file or project.
+fn is_editor_name(segment: &str, app_class: &str) -> bool {
+    const EDITOR_NAMES: [&str; 10] = [
+        "zed",
+        "gedit",
+        "text editor",
+        "kate",
+        "kwrite",
+        "sublime text",
+        "geany",
+        "mousepad",
+        "lapce",
+        "gnome text editor",
+    ];
+
+    let segment = segment.to_lowercase();
+    let class = app_class.to_lowercase();
+    let class_tail = class.rsplit('.').next().unwrap_or(&class);
+
+    EDITOR_NAMES.iter().any(|name| segment.starts_with(name))
+        || segment == class
+        || segment == class_tail
+}
+
+/// Whether a segment looks like a file name (`name.ext`, no spaces).
+fn looks_like_file(segment: &str) -> bool {
+    let name = segment.rsplit('/').next().unwrap_or(segment);
+    if name.contains(char::is_whitespace) {
+        return false;
+    }
+    match name.rsplit_once('.') {
+        Some((stem, ext)) => {
+            (!stem.is_empty() || name.starts_with('.'))
+                && (1..=10).contains(&ext.len())
+                && ext.chars().all(|c| c.is_ascii_alphanumeric())
+        }
+        None => known_file_name(name).is_some(),
+    }
+}
+
+/// Languages for extension-less file names.
+fn known_file_name(name: &str) -> Option<&'static str> {
+    match name {
+        "Makefile" | "makefile" | "GNUmakefile" => Some("Makefile"),
+        "Dockerfile" => Some("Docker"),
+        "Justfile" | "justfile" => Some("Just"),
+        _ => None,
+    }
+}
+
+/// Map a file name to a `WakaTime` language name by extension.
+#[must_use]
+pub fn language_for_file(file: &str) -> Option<&'static str> {
+    if let Some(language) = known_file_name(file) {
+        return Some(language);
+    }
+
+    let (_, ext) = file.rsplit_once('.')?;
+    let language = match ext.to_ascii_lowercase().as_str() {
+        "rs" => "Rust",
+        "py" | "pyi" => "Python",
+        "js" | "mjs" | "cjs" => "JavaScript",
+        "jsx" => "JSX",
+        "ts" | "mts" | "cts" => "TypeScript",
+        "tsx" => "TSX",
+        "go" => "Go",
+        "c" | "h" => "C",
+        "cc" | "cpp" | "cxx" | "hpp" | "hh" => "C++",
+        "cs" => "C#",
+        "java" => "Java",
+        "kt" | "kts" => "Kotlin",
+        "swift" => "Swift",
+        "rb" => "Ruby",
+        "php" => "PHP",
+        "lua" => "Lua",
+        "zig" => "Zig",
+        "hs" => "Haskell",
+        "ex" | "exs" => "Elixir",
+        "dart" => "Dart",
+        "nix" => "Nix",
+        "sh" | "bash" | "zsh" => "Bash",
+        "fish" => "Fish",
+        "sql" => "SQL",
+        "html" | "htm" => "HTML",
+        "css" => "CSS",
+        "scss" => "SCSS",
+        "vue" => "Vue.js",
+        "svelte" => "Svelte",
+        "json" => "JSON",
+        "yaml" | "yml" => "YAML",
+        "toml" => "TOML",
+        "xml" => "XML",
+        "md" | "markdown" => "Markdown",
+        "rst" => "reStructuredText",
+        "tex" => "TeX",
+        "txt" => "Text",
+        _ => return None,
+    };
+    Some(language)
+}
+
+/// Whether a title fragment is a bare directory path.
+fn looks_like_path(s: &str) -> bool {
+    (s == "~" || s.starts_with("~/") || s.starts_with('/')) && !s.contains(char::is_whitespace)
+}
+
+/// First whitespace-separated word, if any.
+fn first_word(s: &str) -> Option<String> {
+    s.split_whitespace().next().map(str::to_string)
+}
+
+/// Language of the last argument of `command` that names a known file.
+fn command_language(command: &str) -> Option<&'static str> {
+    command
+        .split_whitespace()
+        .skip(1)
+        .filter_map(|arg| language_for_file(arg.rsplit('/').next().unwrap_or(arg)))
+        .last()
+}
+
+/// Extract the path from a `file://host/path` URL embedded in a title.
+fn file_url_path(title: &str) -> Option<String> {
+    let start = title.find("file://")?;
+    let rest = &title[start + "file://".len()..];
+    let rest = rest
+        .split(|c: char| c.is_whitespace() || c.is_control())
+        .next()?;
+    let path = &rest[rest.find('/')?..];
+    Some(percent_decode(path))
+}
+
+/// Decode `%XX` escapes (as used in OSC 7 URLs).
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%'
+            && let Some(hex) = s.get(i + 1..i + 3)
+            && let Ok(byte) = u8::from_str_radix(hex, 16)
+        {
+            out.push(byte);
+            i += 3;
+        } else {
+            out.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// Expand a leading `~` to the home directory.
+pub(crate) fn expand_home(path: &str) -> PathBuf {
+    if let Some(home) = dirs::home_dir() {
+        if path == "~" {
+            return home;
+        }
+        if let Some(rest) = path.strip_prefix("~/") {
+            return home.join(rest);
+        }
+    }
+    PathBuf::from(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cwd(title: &str) -> Option<String> {
+        parse_terminal_title(title).cwd
+    }
+
+    #[test]
+    fn test_user_host_prefix() {
+        assert_eq!(
+            cwd("josh@laptop: ~/src/wakatime-focusd").as_deref(),
+            Some("~/src/wakatime-focusd")
+        );
+        assert_eq!(cwd("josh@laptop:/etc").as_deref(), Some("/etc"));
+    }
+
+    #[test]
+    fn test_bare_path() {
+        assert_eq!(cwd("~/src/project").as_deref(), Some("~/src/project"));
+        assert_eq!(cwd("~").as_deref(), Some("~"));
+    }
+
+    #[test]
+    fn test_command_only() {
+        let parsed = parse_terminal_title("nvim main.rs");
+        assert_eq!(parsed.cwd, None);
+        assert_eq!(parsed.command.as_deref(), Some("nvim"));
+    }
+
+    #[test]
+    fn test_command_language() {
+        assert_eq!(parse_terminal_title("nvim main.rs").language, Some("Rust"));
+        assert_eq!(
+            parse_terminal_title("vim -p src/app.py notes - ~/src/project").language,
+            Some("Python")
+        );
+        assert_eq!(parse_terminal_title("cargo test").language, None);
+        assert_eq!(parse_terminal_title("~/src/project").language, None);
+    }
+
+    #[test]
+    fn test_command_and_path() {
+        let parsed = parse_terminal_title("cargo test - ~/src/project");
+        assert_eq!(parsed.cwd.as_deref(), Some("~/src/project"));
+        assert_eq!(parsed.command.as_deref(), Some("cargo"));
+
+        let parsed = parse_terminal_title("~/src/project — htop");
+        assert_eq!(parsed.cwd.as_deref(), Some("~/src/project"));
+        assert_eq!(parsed.command.as_deref(), Some("htop"));
+    }
+
+    #[test]
+    fn test_osc7_file_url() {
+        let parsed = parse_terminal_title("\u{1b}]7;file://laptop/home/josh/my%20project\u{7}");
+        assert_eq!(parsed.cwd.as_deref(), Some("/home/josh/my project"));
+    }
+
+    #[test]
+    fn test_project_from_directory_name() {
+        let parsed = TerminalTitle {
+            cwd: Some("/nonexistent/src/client-x".to_string()),
+            ..TerminalTitle::default()
+        };
+        assert_eq!(
+            parsed.project(&Repos::default()).as_deref(),
+            Some("client-x")
+        );
+    }
+
+    #[test]
+    fn test_project_from_repo_root() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = dir.path().join("myrepo");
+        std::fs::create_dir_all(repo.join(".git")).unwrap();
+        std::fs::create_dir_all(repo.join("src/nested")).unwrap();
+
+        let parsed = TerminalTitle {
+            cwd: Some(repo.join("src/nested").display().to_string()),
+            ..TerminalTitle::default()
+        };
+        assert_eq!(parsed.project(&Repos::default()).as_deref(), Some("myrepo"));
+    }
+
+    #[test]
+    fn test_sanitize_normalizes_unicode() {
+        // "e" + combining acute accent composes to "é"
+        assert_eq!(sanitize("caf\u{65}\u{301}", 0), "caf\u{e9}");
+    }
+
+    #[test]
+    fn test_sanitize_strips_control_characters() {
+        assert_eq!(sanitize("a\tb\nc\u{1b}[0md\u{7}", 0), "a b c[0md");
+    }
+
+    #[test]
+    fn test_sanitize_truncates_with_ellipsis() {
+        assert_eq!(sanitize("abcdef", 6), "abcdef");
+        assert_eq!(sanitize("abcdefg", 6), "abcde…");
+        assert_eq!(sanitize("abcd efg", 6), "abcd…");
+        assert_eq!(sanitize("日本語のタイトル", 4), "日本語…");
+        assert_eq!(sanitize(&"x".repeat(1000), 0).len(), 1000);
+    }
+
+    #[test]
+    fn test_normalize_masks_numbers() {
+        assert_eq!(normalize("Invoice 2024-113 - Mail"), "invoice #-# - mail");
+        assert_eq!(normalize("Straße"), "straße");
+        assert_eq!(normalize("Issue #12"), "issue ##");
+        assert_ne!(normalize("#12"), normalize("12"));
+    }
+
+    #[test]
+    fn test_hash_is_stable() {
+        assert_eq!(hash(""), "cbf29ce484222325");
+        assert_eq!(hash("GitHub"), hash("GitHub"));
+        assert_ne!(hash("GitHub"), hash("Github"));
+    }
+
+    #[test]
+    fn test_strip_noise_leading_counters() {
+        assert_eq!(strip_noise("(2) Slack"), "Slack");
+        assert_eq!(
+            strip_noise("[12+] Inbox - Thunderbird"),
+            "Inbox - Thunderbird"
+        );
+        assert_eq!(strip_noise("● (3) Discord"), "Discord");
+        assert_eq!(strip_noise("🔔 vim"), "vim");
+    }
+
+    #[test]
+    fn test_strip_noise_unread_suffixes() {
+        assert_eq!(strip_noise("Inbox (12) - Mail"), "Inbox - Mail");
+        assert_eq!(strip_noise("Inbox (3 unread)"), "Inbox");
+        assert_eq!(strip_noise("general | Slack (4)"), "general | Slack");
+    }
+
+    #[test]
+    fn test_strip_noise_keeps_meaningful_text() {
+        assert_eq!(strip_noise("Slack"), "Slack");
+        assert_eq!(strip_noise("copy (2).txt - gedit"), "copy (2).txt - gedit");
+        assert_eq!(
+            strip_noise("main.rs - myproject - Zed"),
+            "main.rs - myproject - Zed"
+        );
+        assert_eq!(strip_noise("make\u{7}"), "make");
+        assert_eq!(
+            strip_noise("*scratch* - GNU Emacs"),
+            "*scratch* - GNU Emacs"
+        );
+        assert_eq!(strip_noise("* notes.md - Zed"), "notes.md - Zed");
+    }
+
+    fn editor(title: &str, class: &str) -> Option<EditorTitle> {
+        parse_editor_title(title, class)
+    }
+
+    #[test]
+    fn test_editor_file_project_app() {
+        let parsed = editor("main.rs - myproject - Zed", "dev.zed.Zed").unwrap();
+        assert_eq!(parsed.file, "main.rs");
+        assert_eq!(parsed.project.as_deref(), Some("myproject"));
+        assert_eq!(parsed.language, Some("Rust"));
+    }
+
+    #[test]
+    fn test_editor_project_first() {
+        let parsed = editor("myproject — src/lib.rs", "dev.zed.Zed").unwrap();
+        assert_eq!(parsed.file, "lib.rs");
+        assert_eq!(parsed.project.as_deref(), Some("myproject"));
+    }
+
+    #[test]
+    fn test_editor_modified_marker_and_parenthesized_project() {
+        let parsed = editor("● app.py (backend) - Sublime Text", "sublime_text").unwrap();
+        assert_eq!(parsed.file, "app.py");
+        assert_eq!(parsed.project.as_deref(), Some("backend"));
+        assert_eq!(parsed.language, Some("Python"));
+    }
+
+    #[test]
+    fn test_editor_file_only() {
+        let parsed = editor("*notes.md — Kate", "org.kde.kate").unwrap();
+        assert_eq!(parsed.file, "notes.md");
+        assert_eq!(parsed.project, None);
+        assert_eq!(parsed.language, Some("Markdown"));
+    }
+
+    #[test]
+    fn test_editor_without_file() {
+        assert_eq!(editor("Welcome - Zed", "dev.zed.Zed"), None);
+        assert_eq!(editor("Zed", "dev.zed.Zed"), None);
+    }
+
+    #[test]
+    fn test_language_for_file() {
+        assert_eq!(language_for_file("Makefile"), Some("Makefile"));
+        assert_eq!(language_for_file("App.TSX"), Some("TSX"));
+        assert_eq!(language_for_file("archive.xyz"), None);
+        assert_eq!(language_for_file("README"), None);
+    }
+
+    #[test]
+    fn test_no_project_for_root() {
+        let parsed = TerminalTitle {
+            cwd: Some("/".to_string()),
+            ..TerminalTitle::default()
+        };
+        assert_eq!(parsed.project(&Repos::default()), None);
+    }
+}
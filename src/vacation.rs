@@ -0,0 +1,383 @@
+//! Vacation mode.
+//!
+//! `wakatime-focusd pause` stops sending heartbeats, indefinitely or until a
+//! date, and `holidays` in the config lists days off. The daemon keeps
+//! running and tracking focus throughout; [`VacationSender`] drops heartbeats
+//! on the way out, so time off doesn't mean disabling the service. The pause
+//! is saved to disk and survives restarts.
+
+use std::collections::BTreeSet;
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::PoisonError;
+
+use anyhow::Context;
+use anyhow::Result;
+use futures_util::future::BoxFuture;
+use jiff::Timestamp;
+use jiff::civil;
+use jiff::tz::TimeZone;
+use serde::Deserialize;
+use serde::Serialize;
+use tracing::debug;
+use tracing::info;
+use tracing::warn;
+
+use crate::api::HeartbeatSender;
+use crate::api::SendError;
+use crate::domain::Heartbeat;
+use crate::domain::unix_time_now;
+use crate::timezone;
+
+/// Pause state file name inside the data directory.
+const PAUSE_FILE: &str = "pause.json";
+
+/// A calendar date in local time, written `YYYY-MM-DD`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Date(civil::Date);
+
+impl Date {
+    /// The local date at a Unix timestamp, in the configured time zone.
+    #[must_use]
+    pub fn local(unix_time: f64) -> Option<Self> {
+        Self::in_zone(unix_time, &timezone::current())
+    }
+
+    /// The date at a Unix timestamp in `tz`.
+    #[must_use]
+    pub fn in_zone(unix_time: f64, tz: &TimeZone) -> Option<Self> {
+        // Whole seconds are all a date needs
+        #[allow(clippy::cast_possible_truncation)]
+        let timestamp = Timestamp::from_second(unix_time.floor() as i64).ok()?;
+        Some(Self(tz.to_datetime(timestamp).date()))
+    }
+
+    /// Today's local date.
+    #[must_use]
+    pub fn today() -> Option<Self> {
+        Self::local(unix_time_now())
+    }
+}
+
+impl FromStr for Date {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.trim()
+            .parse()
+            .map(Self)
+            .map_err(|_| format!("invalid date '{s}', expected YYYY-MM-DD"))
+    }
+}
+
+impl TryFrom<String> for Date {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<Date> for String {
+    fn from(date: Date) -> Self {
+        date.to_string()
+    }
+}
+
+impl fmt::Display for Date {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// A pause set with `wakatime-focusd pause`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Pause {
+    /// Heartbeats resume on this date; `None` pauses until resumed.
+    #[serde(default)]
+    pub until: Option<Date>,
+}
+
+/// Shared pause state, persisted to the data directory, and the configured
+/// holidays.
+#[derive(Debug, Default)]
+pub struct PauseState {
+    pause: Mutex<Option<Pause>>,
+    path: Option<PathBuf>,
+    holidays: Mutex<BTreeSet<Date>>,
+}
+
+impl PauseState {
+    /// Load the saved pause from the default location.
+    ///
+    /// A missing or unreadable file means not paused.
+    #[must_use]
+    pub fn load() -> Self {
+        let path = dirs::data_dir().map(|d| d.join("wakatime-focusd").join(PAUSE_FILE));
+        let pause = path
+            .as_ref()
+            .and_then(|p| std::fs::read_to_string(p).ok())
+            .and_then(|content| match serde_json::from_str::<Pause>(&content) {
+                Ok(pause) => Some(pause),
+                Err(e) => {
+                    warn!("Ignoring invalid pause file: {e}");
+                    None
+                }
+            });
+        if let Some(ref pause) = pause {
+            log_pause(pause);
+        }
+        Self {
+            pause: Mutex::new(pause),
+            path,
+            holidays: Mutex::default(),
+        }
+    }
+
+    /// Replace the configured holidays.
+    pub fn set_holidays(&self, holidays: &[Date]) {
+        *self.holidays.lock().unwrap_or_else(PoisonError::into_inner) =
+            holidays.iter().copied().collect();
+    }
+
+    /// Whether heartbeats stamped today are dropped, by a pause or a holiday.
+    #[must_use]
+    pub fn is_paused_today(&self) -> bool {
+        let today = Date::today();
+        self.covers(today) || self.is_holiday(today)
+    }
+
+    /// Pause heartbeats, replacing any earlier pause.
+    pub fn pause(&self, pause: Pause) -> Result<()> {
+        log_pause(&pause);
+        self.save(Some(&pause))?;
+        *self.lock() = Some(pause);
+        Ok(())
+    }
+
+    /// Resume heartbeats. Returns `true` if they were paused.
+    pub fn resume(&self) -> Result<bool> {
+        let resumed = self.lock().take().is_some();
+        if resumed {
+            info!("Heartbeats resumed");
+        }
+        self.save(None)?;
+        Ok(resumed)
+    }
+
+    /// The active pause, if any. A pause whose end date has arrived is
+    /// cleared.
+    #[must_use]
+    pub fn current(&self) -> Option<Pause> {
+        let today = Date::today();
+        let mut pause = self.lock();
+        if let Some(Pause { until: Some(until) }) = *pause
+            && today.is_some_and(|today| today >= until)
+        {
+            info!("Pause ended on {until}, heartbeats resumed");
+            *pause = None;
+            drop(pause);
+            if let Err(e) = self.save(None) {
+                warn!("{e:#}");
+            }
+            return None;
+        }
+        pause.clone()
+    }
+
+    fn is_holiday(&self, date: Option<Date>) -> bool {
+        date.is_some_and(|date| {
+            self.holidays
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .contains(&date)
+        })
+    }
+
+    /// Whether heartbeats stamped on `date` fall inside the pause.
+    fn covers(&self, date: Option<Date>) -> bool {
+        match self.current() {
+            Some(Pause { until: Some(until) }) => date.is_none_or(|date| date < until),
+            Some(Pause { until: None }) => true,
+            None => false,
+        }
+    }
+
+    fn save(&self, pause: Option<&Pause>) -> Result<()> {
+        let Some(ref path) = self.path else {
+            return Ok(());
+        };
+        match pause {
+            Some(pause) => {
+                if let Some(parent) = path.parent() {
+                    std::fs::create_dir_all(parent).with_context(|| {
+                        format!("Failed to create directory {}", parent.display())
+                    })?;
+                }
+                std::fs::write(path, serde_json::to_string(pause)?)
+                    .with_context(|| format!("Failed to save pause to {}", path.display()))
+            }
+            None => match std::fs::remove_file(path) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                    Err(e).with_context(|| format!("Failed to remove {}", path.display()))
+                }
+                _ => Ok(()),
+            },
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Option<Pause>> {
+        self.pause.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+fn log_pause(pause: &Pause) {
+    if let Some(until) = pause.until {
+        info!("Heartbeats paused until {until}");
+    } else {
+        info!("Heartbeats paused until resumed");
+    }
+}
+
+/// A [`HeartbeatSender`] wrapper that drops heartbeats while paused or on
+/// holidays.
+pub struct VacationSender<S> {
+    inner: S,
+    pause: Arc<PauseState>,
+}
+
+impl<S> VacationSender<S> {
+    /// Wrap `inner`, dropping heartbeats during `pause` and its holidays.
+    pub fn new(inner: S, pause: Arc<PauseState>) -> Self {
+        Self { inner, pause }
+    }
+}
+
+impl<S: HeartbeatSender + Sync> HeartbeatSender for VacationSender<S> {
+    fn send_heartbeat<'a>(
+        &'a self,
+        heartbeat: &'a Heartbeat,
+    ) -> BoxFuture<'a, Result<(), SendError>> {
+        let date = Date::local(heartbeat.time);
+        if self.pause.covers(date) {
+            debug!("Skipping heartbeat: paused");
+            return Box::pin(async { Ok(()) });
+        }
+        if self.pause.is_holiday(date) {
+            debug!("Skipping heartbeat: holiday");
+            return Box::pin(async { Ok(()) });
+        }
+        self.inner.send_heartbeat(heartbeat)
+    }
+
+    fn flush(&self) -> BoxFuture<'_, Result<(), SendError>> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::RecordingSender;
+    use crate::backend::FocusEvent;
+    use crate::domain::Category;
+    use crate::domain::Entity;
+
+    fn heartbeat() -> Heartbeat {
+        Heartbeat::new(
+            Entity::new("kitty"),
+            Category::Coding,
+            FocusEvent::new("kitty".to_string(), None, None),
+        )
+    }
+
+    fn date(s: &str) -> Date {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_parse_date() {
+        assert_eq!(date("2025-01-05").to_string(), "2025-01-05");
+        assert_eq!(date("2024-02-29").to_string(), "2024-02-29");
+        assert!("2025-02-29".parse::<Date>().is_err());
+        assert!("2025-13-01".parse::<Date>().is_err());
+        assert!("2025-1-5".parse::<Date>().is_err());
+        assert!("tomorrow".parse::<Date>().is_err());
+        assert!(date("2024-12-31") < date("2025-01-01"));
+    }
+
+    #[test]
+    fn test_local_date_follows_dst() {
+        let tz = TimeZone::get("America/New_York").unwrap();
+        let on = |unix_time: f64| Date::in_zone(unix_time, &tz).unwrap().to_string();
+        // 2024-03-10 has 23 hours, 2024-11-03 has 25
+        for (midnight, day, previous) in [
+            (1_710_046_800.0, "2024-03-10", "2024-03-09"),
+            (1_710_129_600.0, "2024-03-11", "2024-03-10"),
+            (1_730_606_400.0, "2024-11-03", "2024-11-02"),
+            (1_730_696_400.0, "2024-11-04", "2024-11-03"),
+        ] {
+            assert_eq!(on(midnight), day);
+            assert_eq!(on(midnight - 1.0), previous);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_holidays_and_pause_drop_heartbeats() {
+        let today = Date::today().unwrap();
+        let pause = Arc::new(PauseState::default());
+        let sent = RecordingSender::default();
+        let sender = VacationSender::new(sent.clone(), Arc::clone(&pause));
+        pause.set_holidays(&[today]);
+        assert!(pause.is_paused_today());
+        sender.send_heartbeat(&heartbeat()).await.unwrap();
+        assert_eq!(sent.sent().len(), 0);
+
+        pause.set_holidays(&[]);
+        sender.send_heartbeat(&heartbeat()).await.unwrap();
+        pause.pause(Pause { until: None }).unwrap();
+        sender.send_heartbeat(&heartbeat()).await.unwrap();
+        assert!(pause.resume().unwrap());
+        sender.send_heartbeat(&heartbeat()).await.unwrap();
+        assert_eq!(sent.sent().len(), 2);
+    }
+
+    #[test]
+    fn test_pause_ends_on_until_date() {
+        let today = Date::today().unwrap();
+        let pause = PauseState::default();
+        pause.pause(Pause { until: Some(today) }).unwrap();
+        assert_eq!(pause.current(), None);
+
+        pause
+            .pause(Pause {
+                until: Some(date("9999-12-31")),
+            })
+            .unwrap();
+        assert!(pause.covers(Some(today)));
+        assert!(!pause.covers(Some(date("9999-12-31"))));
+    }
+
+    #[test]
+    fn test_pause_persists() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(PAUSE_FILE);
+        let state = PauseState {
+            path: Some(path.clone()),
+            ..PauseState::default()
+        };
+        let pause = Pause {
+            until: Some(date("9999-12-31")),
+        };
+        state.pause(pause.clone()).unwrap();
+        let saved: Pause = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(saved, pause);
+
+        state.resume().unwrap();
+        assert!(!path.exists());
+    }
+}
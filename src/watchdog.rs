@@ -0,0 +1,593 @@
+//! Periodic self-diagnostics.
+//!
+//! [`Watchdog`] checks once a minute that the focus backend still delivers
+//! events, the idle monitor still polls, and heartbeats still go out. A
+//! failed check restarts the affected subsystem and is recorded as an
+//! [`Incident`], which `service status` reports.
+//!
+//! The backend and sender are observed through [`WatchedSource`] and
+//! [`WatchedSender`], which wrap them in the daemon's pipeline. A panic in
+//! either, or in a task started with [`Watchdog::supervise`], is caught and
+//! restarts the subsystem as well, so tracking doesn't stop while the
+//! process stays alive. Restarts are counted per subsystem for status.
+
+use std::any::Any;
+use std::collections::BTreeMap;
+use std::collections::VecDeque;
+use std::fmt;
+use std::future::Future;
+use std::panic::AssertUnwindSafe;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::PoisonError;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::AtomicU32;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+use std::time::SystemTime;
+
+use futures_util::FutureExt;
+use futures_util::future::BoxFuture;
+use serde::Deserialize;
+use serde::Serialize;
+use tokio::sync::Notify;
+use tokio::time::Instant;
+use tokio_util::sync::CancellationToken;
+use tracing::warn;
+
+use crate::api::HeartbeatSender;
+use crate::api::SendError;
+use crate::backend::FocusError;
+use crate::backend::FocusEvent;
+use crate::backend::FocusSource;
+use crate::config::Config;
+use crate::domain::Heartbeat;
+use crate::idle::IdleMonitor;
+
+/// How often the watchdog runs its checks.
+const CHECK_INTERVAL: Duration = Duration::from_mins(1);
+
+/// Consecutive failed sends or flushes before the sender is restarted.
+const SEND_FAILURE_THRESHOLD: u32 = 5;
+
+/// Incidents kept for status reports.
+const MAX_INCIDENTS: usize = 10;
+
+/// Delay before a supervised task is restarted after its first panic.
+const SUPERVISOR_INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+
+/// Maximum delay between restarts of a supervised task.
+const SUPERVISOR_MAX_BACKOFF: Duration = Duration::from_mins(5);
+
+/// A subsystem the watchdog can restart.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Subsystem {
+    /// The focus backend connection.
+    Backend,
+    /// The logind idle monitor.
+    IdleMonitor,
+    /// The heartbeat sender.
+    Sender,
+    /// The control socket server.
+    ControlSocket,
+}
+
+impl fmt::Display for Subsystem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Backend => "backend",
+            Self::IdleMonitor => "idle monitor",
+            Self::Sender => "sender",
+            Self::ControlSocket => "control socket",
+        })
+    }
+}
+
+/// A failed check and the restart it triggered.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Incident {
+    /// The subsystem that was restarted.
+    pub subsystem: Subsystem,
+    /// What the check found.
+    pub message: String,
+    /// When it happened, in seconds since the Unix epoch.
+    pub unix_time: u64,
+}
+
+/// Tracks subsystem health and restarts subsystems that stop working.
+#[derive(Debug)]
+pub struct Watchdog {
+    /// Whether checks run at all (`watchdog` config option).
+    enabled: AtomicBool,
+    /// Restart the backend after this long without focus events while the
+    /// user is active; `None` disables the check.
+    backend_silence: Mutex<Option<Duration>>,
+    /// When the backend last delivered an event (or was connected).
+    last_event: Mutex<Instant>,
+    /// Sends and flushes that failed in a row.
+    send_failures: AtomicU32,
+    /// The idle monitor currently in use.
+    idle_monitor: Mutex<Option<Arc<IdleMonitor>>>,
+    /// Set when the backend should reconnect, until a fresh source is
+    /// connected.
+    backend_restart: AtomicBool,
+    /// Wakes a [`WatchedSource`] waiting for its next event to reconnect.
+    restart_backend: Notify,
+    /// Set when the sender should be rebuilt, until the daemon takes it.
+    sender_restart: AtomicBool,
+    /// Panic message of the sender, waiting for the next check to restart
+    /// it.
+    sender_panic: Mutex<Option<String>>,
+    /// Most recent incidents, oldest first.
+    incidents: Mutex<VecDeque<Incident>>,
+    /// Restarts since startup, by subsystem.
+    restarts: Mutex<BTreeMap<Subsystem, u32>>,
+}
+
+impl Watchdog {
+    /// Create a watchdog with all checks disabled until configured.
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            enabled: AtomicBool::new(false),
+            backend_silence: Mutex::new(None),
+            last_event: Mutex::new(Instant::now()),
+            send_failures: AtomicU32::new(0),
+            idle_monitor: Mutex::new(None),
+            backend_restart: AtomicBool::new(false),
+            restart_backend: Notify::new(),
+            sender_restart: AtomicBool::new(false),
+            sender_panic: Mutex::new(None),
+            incidents: Mutex::new(VecDeque::new()),
+            restarts: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Apply config and watch `idle_monitor`.
+    ///
+    /// Called on startup and again whenever the idle monitor is replaced.
+    pub fn configure(&self, config: &Config, idle_monitor: &Arc<IdleMonitor>) {
+        self.enabled.store(config.watchdog, Ordering::Relaxed);
+        *lock(&self.backend_silence) = (config.watchdog_backend_silence_minutes > 0)
+            .then(|| Duration::from_mins(config.watchdog_backend_silence_minutes));
+        *lock(&self.idle_monitor) = Some(Arc::clone(idle_monitor));
+    }
+
+    /// Record that the backend delivered an event.
+    pub fn record_event(&self) {
+        *lock(&self.last_event) = Instant::now();
+    }
+
+    /// Record the outcome of a send or flush.
+    pub fn record_send(&self, ok: bool) {
+        if ok {
+            self.send_failures.store(0, Ordering::Relaxed);
+        } else {
+            self.send_failures.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Recent incidents, oldest first.
+    #[must_use]
+    pub fn incidents(&self) -> Vec<Incident> {
+        lock(&self.incidents).iter().cloned().collect()
+    }
+
+    /// Restarts since startup, by subsystem.
+    #[must_use]
+    pub fn restarts(&self) -> BTreeMap<Subsystem, u32> {
+        lock(&self.restarts).clone()
+    }
+
+    /// Run the task made by `task` until it returns or `shutdown` is
+    /// cancelled, starting it over if it panics.
+    ///
+    /// Restarts back off from one second to five minutes while the task
+    /// keeps panicking, and go back to one second once it has run for longer
+    /// than that.
+    pub fn supervise<F, Fut>(
+        self: &Arc<Self>,
+        subsystem: Subsystem,
+        shutdown: CancellationToken,
+        mut task: F,
+    ) where
+        F: FnMut() -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let watchdog = Arc::clone(self);
+        tokio::spawn(async move {
+            let mut backoff = SUPERVISOR_INITIAL_BACKOFF;
+            loop {
+                let started = Instant::now();
+                let panic = tokio::select! {
+                    () = shutdown.cancelled() => return,
+                    result = AssertUnwindSafe(task()).catch_unwind() => match result {
+                        Ok(()) => return,
+                        Err(panic) => panic,
+                    },
+                };
+                watchdog.record_panic(subsystem, panic.as_ref());
+
+                if started.elapsed() > backoff {
+                    backoff = SUPERVISOR_INITIAL_BACKOFF;
+                }
+                tokio::select! {
+                    () = shutdown.cancelled() => return,
+                    () = tokio::time::sleep(backoff) => {}
+                }
+                backoff = (backoff * 2).min(SUPERVISOR_MAX_BACKOFF);
+            }
+        });
+    }
+
+    /// Record that `subsystem` panicked and is being restarted.
+    fn record_panic(&self, subsystem: Subsystem, panic: &(dyn Any + Send)) {
+        self.record_incident(subsystem, format!("Panicked: {}", panic_message(panic)));
+    }
+
+    /// Whether the sender should be rebuilt, clearing the request.
+    ///
+    /// The daemon checks this when the event loop returns for a reload, and
+    /// rebuilds the sender from the config in use instead of reloading it.
+    pub fn take_sender_restart(&self) -> bool {
+        self.sender_restart.swap(false, Ordering::Relaxed)
+    }
+
+    /// Run the checks every minute until `shutdown` is cancelled.
+    ///
+    /// The sender is restarted by signalling `reload`, so the event loop
+    /// returns and the daemon rebuilds it; see
+    /// [`take_sender_restart`](Self::take_sender_restart).
+    pub fn start(self: Arc<Self>, reload: Arc<Notify>, shutdown: CancellationToken) {
+        tokio::spawn(async move {
+            let mut timer =
+                tokio::time::interval_at(Instant::now() + CHECK_INTERVAL, CHECK_INTERVAL);
+            loop {
+                tokio::select! {
+                    () = shutdown.cancelled() => return,
+                    _ = timer.tick() => self.check(&reload),
+                }
+            }
+        });
+    }
+
+    /// Run all checks once, restarting whatever failed.
+    fn check(&self, reload: &Notify) {
+        if !self.enabled.load(Ordering::Relaxed) {
+            return;
+        }
+        let idle_monitor = lock(&self.idle_monitor).clone();
+
+        let silence = *lock(&self.backend_silence);
+        if let Some(silence) = silence
+            && lock(&self.last_event).elapsed() >= silence
+            && idle_monitor
+                .as_ref()
+                .is_none_or(|m| m.away_reason().is_none())
+        {
+            self.record_incident(
+                Subsystem::Backend,
+                format!(
+                    "No focus events for {} minutes while the session was active",
+                    silence.as_secs() / 60
+                ),
+            );
+            self.record_event();
+            // Only a source waiting right now is woken, so no permit is left
+            // behind for the next one; the flag covers a source that is busy
+            self.backend_restart.store(true, Ordering::Relaxed);
+            self.restart_backend.notify_waiters();
+        }
+
+        if let Some(ref idle_monitor) = idle_monitor
+            && idle_monitor.is_stalled()
+        {
+            self.record_incident(Subsystem::IdleMonitor, "Idle state stopped updating".into());
+            idle_monitor.restart();
+        }
+
+        let panic = lock(&self.sender_panic).take();
+        if let Some(message) = panic {
+            self.record_incident(Subsystem::Sender, format!("Panicked: {message}"));
+            self.send_failures.store(0, Ordering::Relaxed);
+            self.sender_restart.store(true, Ordering::Relaxed);
+            reload.notify_one();
+            return;
+        }
+
+        let failures = self.send_failures.load(Ordering::Relaxed);
+        if failures >= SEND_FAILURE_THRESHOLD {
+            self.record_incident(
+                Subsystem::Sender,
+                format!("{failures} heartbeat sends failed in a row"),
+            );
+            self.send_failures.store(0, Ordering::Relaxed);
+            self.sender_restart.store(true, Ordering::Relaxed);
+            reload.notify_one();
+        }
+    }
+
+    fn record_incident(&self, subsystem: Subsystem, message: String) {
+        warn!("Watchdog: {message}, restarting {subsystem}");
+        let unix_time = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        let mut incidents = lock(&self.incidents);
+        if incidents.len() == MAX_INCIDENTS {
+            incidents.pop_front();
+        }
+        incidents.push_back(Incident {
+            subsystem,
+            message,
+            unix_time,
+        });
+        *lock(&self.restarts).entry(subsystem).or_default() += 1;
+    }
+}
+
+impl Default for Watchdog {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// The message a panic was raised with.
+fn panic_message(panic: &(dyn Any + Send)) -> &str {
+    panic
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| panic.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("unknown cause")
+}
+
+/// Focus source that reports liveness to the watchdog and reconnects when
+/// told to.
+pub struct WatchedSource {
+    inner: Box<dyn FocusSource>,
+    watchdog: Arc<Watchdog>,
+}
+
+impl WatchedSource {
+    /// Wrap a freshly connected source.
+    pub fn new(inner: Box<dyn FocusSource>, watchdog: Arc<Watchdog>) -> Self {
+        watchdog.record_event();
+        watchdog.backend_restart.store(false, Ordering::Relaxed);
+        Self { inner, watchdog }
+    }
+}
+
+impl FocusSource for WatchedSource {
+    fn next_event(&mut self) -> BoxFuture<'_, Result<FocusEvent, FocusError>> {
+        Box::pin(async move {
+            // Registered before the flag is read, so a restart in between
+            // still wakes it
+            let restart = self.watchdog.restart_backend.notified();
+            if self.watchdog.backend_restart.swap(false, Ordering::Relaxed) {
+                return Err(FocusError::Stalled);
+            }
+            tokio::select! {
+                event = AssertUnwindSafe(self.inner.next_event()).catch_unwind() => match event {
+                    Ok(event) => {
+                        if event.is_ok() {
+                            self.watchdog.record_event();
+                        }
+                        event
+                    }
+                    Err(panic) => {
+                        self.watchdog.record_panic(Subsystem::Backend, panic.as_ref());
+                        Err(FocusError::Panicked)
+                    }
+                },
+                () = restart => {
+                    self.watchdog.backend_restart.store(false, Ordering::Relaxed);
+                    Err(FocusError::Stalled)
+                }
+            }
+        })
+    }
+}
+
+/// Heartbeat sender that reports send failures to the watchdog.
+pub struct WatchedSender<S> {
+    inner: S,
+    watchdog: Arc<Watchdog>,
+}
+
+impl<S> WatchedSender<S> {
+    /// Wrap `inner`.
+    pub fn new(inner: S, watchdog: Arc<Watchdog>) -> Self {
+        Self { inner, watchdog }
+    }
+}
+
+impl<S: Sync> WatchedSender<S> {
+    /// Record the outcome of a send or flush. A panic fails it, and the
+    /// next check restarts the sender.
+    fn watch<'a>(
+        &'a self,
+        send: BoxFuture<'a, Result<(), SendError>>,
+    ) -> BoxFuture<'a, Result<(), SendError>> {
+        Box::pin(async move {
+            let result = match AssertUnwindSafe(send).catch_unwind().await {
+                Ok(result) => result,
+                Err(panic) => {
+                    let message = panic_message(panic.as_ref()).to_string();
+                    warn!("Heartbeat sender panicked: {message}");
+                    *lock(&self.watchdog.sender_panic) = Some(message);
+                    Err(SendError::Panicked)
+                }
+            };
+            self.watchdog.record_send(result.is_ok());
+            result
+        })
+    }
+}
+
+impl<S: HeartbeatSender + Sync> HeartbeatSender for WatchedSender<S> {
+    fn send_heartbeat<'a>(
+        &'a self,
+        heartbeat: &'a Heartbeat,
+    ) -> BoxFuture<'a, Result<(), SendError>> {
+        self.watch(self.inner.send_heartbeat(heartbeat))
+    }
+
+    fn flush(&self) -> BoxFuture<'_, Result<(), SendError>> {
+        self.watch(self.inner.flush())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn watchdog() -> (Watchdog, Arc<IdleMonitor>) {
+        let watchdog = Watchdog::new();
+        let idle_monitor = Arc::new(IdleMonitor::new());
+        watchdog.configure(&Config::default(), &idle_monitor);
+        (watchdog, idle_monitor)
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_silent_backend_is_restarted_while_active() {
+        let (watchdog, idle_monitor) = watchdog();
+        let reload = Notify::new();
+
+        tokio::time::advance(Duration::from_mins(59)).await;
+        watchdog.check(&reload);
+        assert!(watchdog.incidents().is_empty());
+
+        // Silence while idle is expected
+        idle_monitor.set_idle(true);
+        tokio::time::advance(Duration::from_mins(1)).await;
+        watchdog.check(&reload);
+        assert!(watchdog.incidents().is_empty());
+
+        idle_monitor.set_idle(false);
+        watchdog.check(&reload);
+        let incidents = watchdog.incidents();
+        assert_eq!(incidents.len(), 1);
+        assert_eq!(incidents[0].subsystem, Subsystem::Backend);
+        // The restart is waiting for the source
+        assert!(watchdog.backend_restart.load(Ordering::Relaxed));
+    }
+
+    struct Silent;
+
+    impl FocusSource for Silent {
+        fn next_event(&mut self) -> BoxFuture<'_, Result<FocusEvent, FocusError>> {
+            Box::pin(std::future::pending())
+        }
+    }
+
+    /// Have the backend check restart the backend.
+    async fn restart_backend(watchdog: &Watchdog) {
+        tokio::time::advance(Duration::from_hours(1)).await;
+        watchdog.check(&Notify::new());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_backend_restart_reaches_one_source() {
+        let (watchdog, _idle_monitor) = watchdog();
+        let watchdog = Arc::new(watchdog);
+
+        // A source waiting for its next event is woken
+        let mut source = WatchedSource::new(Box::new(Silent), Arc::clone(&watchdog));
+        let next = tokio::spawn(async move { source.next_event().await.map(|_| ()) });
+        tokio::task::yield_now().await;
+        restart_backend(&watchdog).await;
+        assert!(matches!(next.await.unwrap(), Err(FocusError::Stalled)));
+
+        // A busy source stops at its next event
+        let mut source = WatchedSource::new(Box::new(Silent), Arc::clone(&watchdog));
+        restart_backend(&watchdog).await;
+        assert!(matches!(
+            source.next_event().await,
+            Err(FocusError::Stalled)
+        ));
+
+        // The source connected after a restart isn't restarted again
+        restart_backend(&watchdog).await;
+        let mut source = WatchedSource::new(Box::new(Silent), Arc::clone(&watchdog));
+        assert!(
+            tokio::time::timeout(Duration::from_secs(1), source.next_event())
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_failing_sender_is_restarted() {
+        let (watchdog, _idle_monitor) = watchdog();
+        let reload = Notify::new();
+
+        for _ in 0..SEND_FAILURE_THRESHOLD - 1 {
+            watchdog.record_send(false);
+        }
+        watchdog.record_send(true);
+        watchdog.record_send(false);
+        watchdog.check(&reload);
+        assert!(watchdog.incidents().is_empty());
+
+        for _ in 0..SEND_FAILURE_THRESHOLD {
+            watchdog.record_send(false);
+        }
+        watchdog.check(&reload);
+        assert_eq!(watchdog.incidents()[0].subsystem, Subsystem::Sender);
+        tokio::time::timeout(Duration::ZERO, reload.notified())
+            .await
+            .unwrap();
+        // Rebuilt from the config in use, once
+        assert!(watchdog.take_sender_restart());
+        assert!(!watchdog.take_sender_restart());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_disabled_watchdog_records_nothing() {
+        let watchdog = Watchdog::new();
+        let idle_monitor = Arc::new(IdleMonitor::new());
+        let config = Config {
+            watchdog: false,
+            ..Config::default()
+        };
+        watchdog.configure(&config, &idle_monitor);
+
+        tokio::time::advance(Duration::from_hours(2)).await;
+        for _ in 0..SEND_FAILURE_THRESHOLD {
+            watchdog.record_send(false);
+        }
+        watchdog.check(&Notify::new());
+        assert!(watchdog.incidents().is_empty());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_supervise_restarts_panicking_task() {
+        let watchdog = Arc::new(Watchdog::new());
+        let shutdown = CancellationToken::new();
+        let runs = Arc::new(AtomicU32::new(0));
+
+        let task_runs = Arc::clone(&runs);
+        watchdog.supervise(Subsystem::ControlSocket, shutdown.clone(), move || {
+            let runs = Arc::clone(&task_runs);
+            async move {
+                // Panics twice, then keeps running
+                assert!(runs.fetch_add(1, Ordering::Relaxed) >= 2, "accept failed");
+                std::future::pending::<()>().await;
+            }
+        });
+
+        // Restarted after 1s, then after 2s
+        tokio::time::sleep(Duration::from_millis(2500)).await;
+        assert_eq!(runs.load(Ordering::Relaxed), 2);
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        assert_eq!(runs.load(Ordering::Relaxed), 3);
+
+        assert_eq!(watchdog.restarts()[&Subsystem::ControlSocket], 2);
+        assert_eq!(watchdog.incidents()[0].message, "Panicked: accept failed");
+        shutdown.cancel();
+    }
+}
@@ -0,0 +1,271 @@
+//! Workspace projects.
+//!
+//! `wakatime-focusd workspace bind 3 client-x` binds a workspace to a
+//! project over the control socket, and `[workspace_projects]` in the config
+//! binds them up front. Heartbeats from windows on a bound workspace carry its
+//! project, applied by [`WorkspaceProjectSender`] on the way out. A pinned
+//! project still wins. Bindings made at runtime are saved to the data
+//! directory and survive restarts.
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::PoisonError;
+
+use anyhow::Context;
+use anyhow::Result;
+use futures_util::future::BoxFuture;
+use tracing::info;
+use tracing::warn;
+
+use crate::api::HeartbeatSender;
+use crate::api::SendError;
+use crate::domain::Heartbeat;
+
+/// Workspace bindings file name inside the data directory.
+const BINDINGS_FILE: &str = "workspaces.json";
+
+/// Shared workspace-to-project bindings: the configured ones and those made
+/// at runtime, which take precedence.
+#[derive(Debug, Default)]
+pub struct WorkspaceProjects {
+    configured: Mutex<BTreeMap<String, String>>,
+    bound: Mutex<BTreeMap<String, String>>,
+    path: Option<PathBuf>,
+}
+
+impl WorkspaceProjects {
+    /// Load the saved bindings from the default location.
+    ///
+    /// A missing or unreadable file means no runtime bindings.
+    #[must_use]
+    pub fn load() -> Self {
+        let path = dirs::data_dir().map(|d| d.join("wakatime-focusd").join(BINDINGS_FILE));
+        let bound = path
+            .as_ref()
+            .and_then(|p| std::fs::read_to_string(p).ok())
+            .and_then(|content| match serde_json::from_str(&content) {
+                Ok(bound) => Some(bound),
+                Err(e) => {
+                    warn!("Ignoring invalid workspace bindings file: {e}");
+                    None
+                }
+            })
+            .unwrap_or_default();
+        Self {
+            configured: Mutex::default(),
+            bound: Mutex::new(bound),
+            path,
+        }
+    }
+
+    /// Replace the bindings from the config.
+    pub fn set_configured(&self, bindings: &BTreeMap<String, String>) {
+        bindings.clone_into(
+            &mut self
+                .configured
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner),
+        );
+    }
+
+    /// Bind `workspace` to `project`, replacing any earlier binding.
+    pub fn bind(&self, workspace: String, project: String) -> Result<()> {
+        info!("Bound workspace '{workspace}' to project '{project}'");
+        let mut bound = self.lock();
+        bound.insert(workspace, project);
+        self.save(&bound)
+    }
+
+    /// Remove the runtime binding of `workspace`. Returns `true` if it was
+    /// bound; a binding from the config stays in effect.
+    pub fn unbind(&self, workspace: &str) -> Result<bool> {
+        let mut bound = self.lock();
+        let unbound = bound.remove(workspace).is_some();
+        if unbound {
+            info!("Unbound workspace '{workspace}'");
+        }
+        self.save(&bound)?;
+        Ok(unbound)
+    }
+
+    /// The project bound to `workspace`, if any.
+    #[must_use]
+    pub fn project(&self, workspace: &str) -> Option<String> {
+        if let Some(project) = self.lock().get(workspace) {
+            return Some(project.clone());
+        }
+        self.configured
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(workspace)
+            .cloned()
+    }
+
+    /// All bindings in effect, by workspace.
+    #[must_use]
+    pub fn bindings(&self) -> BTreeMap<String, String> {
+        let mut bindings = self
+            .configured
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone();
+        bindings.extend(self.lock().clone());
+        bindings
+    }
+
+    fn save(&self, bound: &BTreeMap<String, String>) -> Result<()> {
+        let Some(ref path) = self.path else {
+            return Ok(());
+        };
+        if bound.is_empty() {
+            return match std::fs::remove_file(path) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                    Err(e).with_context(|| format!("Failed to remove {}", path.display()))
+                }
+                _ => Ok(()),
+            };
+        }
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create directory {}", parent.display()))?;
+        }
+        std::fs::write(path, serde_json::to_string(bound)?)
+            .with_context(|| format!("Failed to save workspace bindings to {}", path.display()))
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, BTreeMap<String, String>> {
+        self.bound.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// A [`HeartbeatSender`] wrapper that applies workspace projects.
+pub struct WorkspaceProjectSender<S> {
+    inner: S,
+    workspaces: Arc<WorkspaceProjects>,
+}
+
+impl<S> WorkspaceProjectSender<S> {
+    /// Wrap `inner`, setting the project of heartbeats from bound
+    /// workspaces.
+    pub fn new(inner: S, workspaces: Arc<WorkspaceProjects>) -> Self {
+        Self { inner, workspaces }
+    }
+}
+
+impl<S: HeartbeatSender + Sync> HeartbeatSender for WorkspaceProjectSender<S> {
+    fn send_heartbeat<'a>(
+        &'a self,
+        heartbeat: &'a Heartbeat,
+    ) -> BoxFuture<'a, Result<(), SendError>> {
+        Box::pin(async move {
+            let project = heartbeat
+                .source
+                .workspace
+                .as_deref()
+                .and_then(|workspace| self.workspaces.project(workspace));
+            if let Some(project) = project {
+                // A branch belongs to the project it was read for
+                let branch = heartbeat
+                    .branch
+                    .clone()
+                    .filter(|_| heartbeat.project.as_ref() == Some(&project));
+                let bound = Heartbeat {
+                    project: Some(project),
+                    branch,
+                    ..heartbeat.clone()
+                };
+                self.inner.send_heartbeat(&bound).await
+            } else {
+                self.inner.send_heartbeat(heartbeat).await
+            }
+        })
+    }
+
+    fn flush(&self) -> BoxFuture<'_, Result<(), SendError>> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::RecordingSender;
+    use crate::backend::FocusEvent;
+    use crate::domain::Category;
+    use crate::domain::Entity;
+    use crate::project::PinnedProjectSender;
+    use crate::project::ProjectPin;
+
+    fn heartbeat(workspace: &str) -> Heartbeat {
+        Heartbeat {
+            project: Some("from-title".to_string()),
+            ..Heartbeat::new(
+                Entity::new("kitty"),
+                Category::Coding,
+                FocusEvent::new("kitty".to_string(), None, None)
+                    .with_workspace(Some(workspace.to_string())),
+            )
+        }
+    }
+
+    #[tokio::test]
+    async fn test_bound_workspace_sets_project() {
+        let workspaces = Arc::new(WorkspaceProjects::default());
+        workspaces.set_configured(&BTreeMap::from([
+            ("2".to_string(), "configured".to_string()),
+            ("3".to_string(), "configured".to_string()),
+        ]));
+        workspaces
+            .bind("3".to_string(), "client-x".to_string())
+            .unwrap();
+        let pin = Arc::new(ProjectPin::new());
+        let sent = RecordingSender::default();
+        let sender = WorkspaceProjectSender::new(
+            PinnedProjectSender::new(sent.clone(), Arc::clone(&pin)),
+            Arc::clone(&workspaces),
+        );
+
+        for workspace in ["1", "2", "3"] {
+            sender.send_heartbeat(&heartbeat(workspace)).await.unwrap();
+        }
+        // A pinned project wins over the workspace
+        pin.set("pinned".to_string(), None);
+        sender.send_heartbeat(&heartbeat("3")).await.unwrap();
+
+        assert_eq!(
+            sent.projects(),
+            vec![
+                Some("from-title".to_string()),
+                Some("configured".to_string()),
+                Some("client-x".to_string()),
+                Some("pinned".to_string()),
+            ]
+        );
+
+        // Unbinding falls back to the config
+        assert!(workspaces.unbind("3").unwrap());
+        assert!(!workspaces.unbind("3").unwrap());
+        assert_eq!(workspaces.project("3").as_deref(), Some("configured"));
+    }
+
+    #[test]
+    fn test_bindings_persist() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(BINDINGS_FILE);
+        let workspaces = WorkspaceProjects {
+            path: Some(path.clone()),
+            ..WorkspaceProjects::default()
+        };
+        workspaces
+            .bind("3".to_string(), "client-x".to_string())
+            .unwrap();
+        let saved: BTreeMap<String, String> =
+            serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(saved.get("3").map(String::as_str), Some("client-x"));
+
+        workspaces.unbind("3").unwrap();
+        assert!(!path.exists());
+    }
+}
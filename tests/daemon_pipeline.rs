@@ -15,9 +15,14 @@ use tokio::sync::mpsc;
 use tokio_util::sync::CancellationToken;
 use wakatime_focusd::EventLoopOutcome;
 use wakatime_focusd::api::HeartbeatSender;
+use wakatime_focusd::api::SendError;
+use wakatime_focusd::backend;
+use wakatime_focusd::backend::Backend;
 use wakatime_focusd::backend::FocusError;
 use wakatime_focusd::backend::FocusEvent;
 use wakatime_focusd::backend::FocusSource;
+use wakatime_focusd::browser::BrowserAwareSource;
+use wakatime_focusd::browser::BrowserTabs;
 use wakatime_focusd::config::Config;
 use wakatime_focusd::domain::Heartbeat;
 use wakatime_focusd::idle::IdleMonitor;
@@ -88,7 +93,10 @@ impl RecordingSender {
 }
 
 impl HeartbeatSender for RecordingSender {
-    fn send_heartbeat<'a>(&'a self, heartbeat: &'a Heartbeat) -> BoxFuture<'a, Result<()>> {
+    fn send_heartbeat<'a>(
+        &'a self,
+        heartbeat: &'a Heartbeat,
+    ) -> BoxFuture<'a, Result<(), SendError>> {
         Box::pin(async move {
             self.sent.lock().unwrap().push(SentRecord {
                 entity: heartbeat.entity.as_str().to_string(),
@@ -101,6 +109,61 @@ impl HeartbeatSender for RecordingSender {
     }
 }
 
+/// A `HeartbeatSender` that fails its first `failures` sends, then records.
+struct FlakySender {
+    inner: RecordingSender,
+    failures: Mutex<usize>,
+}
+
+impl FlakySender {
+    fn new(failures: usize) -> (Self, Arc<Mutex<Vec<SentRecord>>>) {
+        let (inner, sent) = RecordingSender::new();
+        (
+            Self {
+                inner,
+                failures: Mutex::new(failures),
+            },
+            sent,
+        )
+    }
+}
+
+impl HeartbeatSender for FlakySender {
+    fn send_heartbeat<'a>(
+        &'a self,
+        heartbeat: &'a Heartbeat,
+    ) -> BoxFuture<'a, Result<(), SendError>> {
+        {
+            let mut failures = self.failures.lock().unwrap();
+            if *failures > 0 {
+                *failures -= 1;
+                return Box::pin(async { Err(SendError::RateLimited) });
+            }
+        }
+        self.inner.send_heartbeat(heartbeat)
+    }
+}
+
+/// A `HeartbeatSender` that counts flushes.
+#[derive(Default)]
+struct FlushCountingSender {
+    flushes: Mutex<usize>,
+}
+
+impl HeartbeatSender for FlushCountingSender {
+    fn send_heartbeat<'a>(
+        &'a self,
+        _heartbeat: &'a Heartbeat,
+    ) -> BoxFuture<'a, Result<(), SendError>> {
+        Box::pin(async { Ok(()) })
+    }
+
+    fn flush(&self) -> BoxFuture<'_, Result<(), SendError>> {
+        *self.flushes.lock().unwrap() += 1;
+        Box::pin(async { Ok(()) })
+    }
+}
+
 fn event(class: &str, title: Option<&str>) -> FocusEvent {
     FocusEvent::new(class.to_string(), title.map(str::to_string), None)
 }
@@ -140,7 +203,7 @@ async fn run_pipeline(events: Vec<FocusEvent>, config: Config) -> Vec<SentRecord
 async fn test_full_pipeline_basic_events() {
     let events = vec![
         event("firefox", Some("GitHub")),
-        event("code", Some("main.rs")),
+        event("gedit", Some("main.rs")),
         event("kitty", None),
     ];
 
@@ -148,7 +211,7 @@ async fn test_full_pipeline_basic_events() {
 
     assert_eq!(sent.len(), 3);
     assert_eq!(sent[0].entity, "firefox");
-    assert_eq!(sent[1].entity, "code");
+    assert_eq!(sent[1].entity, "gedit");
     assert_eq!(sent[2].entity, "kitty");
 }
 
@@ -158,7 +221,7 @@ async fn test_empty_events_skipped() {
     let events = vec![
         event("firefox", None),
         event("", None), // empty class
-        event("code", Some("main.rs")),
+        event("gedit", Some("main.rs")),
         event("", Some("ghost")), // empty class with title
     ];
 
@@ -166,21 +229,21 @@ async fn test_empty_events_skipped() {
 
     assert_eq!(sent.len(), 2);
     assert_eq!(sent[0].entity, "firefox");
-    assert_eq!(sent[1].entity, "code");
+    assert_eq!(sent[1].entity, "gedit");
 }
 
 // Test: allowlist filters events
 #[tokio::test]
 async fn test_allowlist_filters_events() {
     let config = Config {
-        app_allowlist: Some(vec!["firefox".to_string(), "code".to_string()]),
+        app_allowlist: Some(vec!["firefox".to_string(), "gedit".to_string()]),
         ..Config::default()
     };
 
     let events = vec![
         event("firefox", None),
         event("slack", None), // not in allowlist
-        event("code", None),
+        event("gedit", None),
         event("spotify", None), // not in allowlist
     ];
 
@@ -188,7 +251,7 @@ async fn test_allowlist_filters_events() {
 
     assert_eq!(sent.len(), 2);
     assert_eq!(sent[0].entity, "firefox");
-    assert_eq!(sent[1].entity, "code");
+    assert_eq!(sent[1].entity, "gedit");
 }
 
 // Test: denylist filters events
@@ -202,7 +265,7 @@ async fn test_denylist_filters_events() {
     let events = vec![
         event("firefox", None),
         event("slack", None), // denied
-        event("code", None),
+        event("gedit", None),
         event("spotify", None), // denied
     ];
 
@@ -210,7 +273,41 @@ async fn test_denylist_filters_events() {
 
     assert_eq!(sent.len(), 2);
     assert_eq!(sent[0].entity, "firefox");
-    assert_eq!(sent[1].entity, "code");
+    assert_eq!(sent[1].entity, "gedit");
+}
+
+// Test: excluded apps count as one anonymous entity with track_hidden_apps
+#[tokio::test]
+async fn test_hidden_apps_tracked_anonymously() {
+    use wakatime_focusd::config::CategoryRule;
+    use wakatime_focusd::domain::Category;
+
+    let config = Config {
+        app_denylist: Some(vec!["slack".to_string(), "spotify".to_string()]),
+        track_hidden_apps: true,
+        category_rules: vec![CategoryRule {
+            pattern: "slack".to_string(),
+            category: Category::Communicating,
+        }],
+        ..Config::default()
+    };
+
+    let events = vec![
+        event("firefox", None),
+        event("slack", Some("general - Acme")),
+        event("spotify", Some("Song")), // same hidden entity, throttled
+        event("gedit", None),
+    ];
+
+    let sent = run_pipeline(events, config).await;
+
+    assert_eq!(sent.len(), 3);
+    assert_eq!(sent[0].entity, "firefox");
+    assert_eq!(sent[1].entity, "Hidden");
+    assert_eq!(sent[1].app_class, "Hidden");
+    assert_eq!(sent[1].title, None);
+    assert_eq!(sent[1].category, "coding");
+    assert_eq!(sent[2].entity, "gedit");
 }
 
 // Test: denylist overrides allowlist
@@ -233,6 +330,22 @@ async fn test_denylist_overrides_allowlist() {
     assert_eq!(sent[0].entity, "firefox");
 }
 
+// Test: apps with their own WakaTime plugin are skipped and end the previous
+// app's run, so returning to it starts a new one
+#[tokio::test]
+async fn test_defer_to_plugin_skips_app() {
+    let events = vec![
+        event("firefox", None),
+        event("code", None), // VS Code defers to its plugin by default
+        event("firefox", None),
+    ];
+
+    let sent = run_pipeline(events, Config::default()).await;
+
+    let entities: Vec<_> = sent.iter().map(|r| r.entity.as_str()).collect();
+    assert_eq!(entities, vec!["firefox", "firefox"]);
+}
+
 // Test: rapid focus switching (A → B → A within throttle window) - all three send
 // because entity changes each time
 #[tokio::test]
@@ -244,7 +357,7 @@ async fn test_rapid_focus_switching_different_entities() {
 
     let events = vec![
         event("firefox", None),
-        event("code", None),
+        event("gedit", None),
         event("firefox", None), // back to firefox - entity changed, should send
     ];
 
@@ -252,7 +365,7 @@ async fn test_rapid_focus_switching_different_entities() {
 
     assert_eq!(sent.len(), 3);
     assert_eq!(sent[0].entity, "firefox");
-    assert_eq!(sent[1].entity, "code");
+    assert_eq!(sent[1].entity, "gedit");
     assert_eq!(sent[2].entity, "firefox");
 }
 
@@ -280,7 +393,7 @@ async fn test_same_entity_throttled() {
 // Test: idle suppression - events arrive while session is idle
 #[tokio::test]
 async fn test_idle_suppression() {
-    let source = MockFocusSource::from_events(vec![event("firefox", None), event("code", None)]);
+    let source = MockFocusSource::from_events(vec![event("firefox", None), event("gedit", None)]);
     let (sender, sent) = RecordingSender::new();
     let idle_monitor = IdleMonitor::new();
     let shutdown = CancellationToken::new();
@@ -304,6 +417,72 @@ async fn test_idle_suppression() {
     assert!(sent.is_empty(), "no heartbeats should be sent while idle");
 }
 
+// Test: meeting mode - video calls are tracked while idle
+#[tokio::test]
+async fn test_meeting_sent_while_idle() {
+    let source = MockFocusSource::from_events(vec![
+        event("zoom", Some("Zoom Meeting")),
+        event("gedit", None),
+    ]);
+    let (sender, sent) = RecordingSender::new();
+    let idle_monitor = IdleMonitor::new();
+    let shutdown = CancellationToken::new();
+    let reload = Notify::new();
+    idle_monitor.set_idle(true);
+
+    let config = Config {
+        meeting_mode: true,
+        ..Config::default()
+    };
+    let outcome = run_event_loop(
+        Box::new(source),
+        &config,
+        &sender,
+        &idle_monitor,
+        &shutdown,
+        &reload,
+        false,
+    )
+    .await;
+    assert!(matches!(outcome, EventLoopOutcome::SourceError(_)));
+
+    let sent = sent.lock().unwrap().clone();
+    assert_eq!(sent.len(), 1, "only the meeting should be sent while idle");
+    assert_eq!(sent[0].entity, "zoom");
+    assert_eq!(sent[0].category, "communicating");
+}
+
+// Test: meeting mode - a locked screen stops video calls too
+#[tokio::test]
+async fn test_meeting_not_sent_while_locked() {
+    let source = MockFocusSource::from_events(vec![event("zoom", Some("Zoom Meeting"))]);
+    let (sender, sent) = RecordingSender::new();
+    let idle_monitor = IdleMonitor::new().with_lock_gating(true);
+    let shutdown = CancellationToken::new();
+    let reload = Notify::new();
+    idle_monitor.set_locked(true);
+
+    let config = Config {
+        meeting_mode: true,
+        ..Config::default()
+    };
+    let outcome = run_event_loop(
+        Box::new(source),
+        &config,
+        &sender,
+        &idle_monitor,
+        &shutdown,
+        &reload,
+        false,
+    )
+    .await;
+    assert!(matches!(outcome, EventLoopOutcome::SourceError(_)));
+    assert!(
+        sent.lock().unwrap().is_empty(),
+        "no meeting heartbeats while locked"
+    );
+}
+
 // Test: idle transitions — events sent when not idle, suppressed when idle
 #[tokio::test]
 async fn test_idle_transitions() {
@@ -347,11 +526,11 @@ async fn test_idle_transitions() {
     tokio::task::yield_now().await;
 
     // Send event while idle — should be suppressed
-    tx.send(event("code", None)).await.unwrap();
+    tx.send(event("gedit", None)).await.unwrap();
     tokio::time::advance(Duration::from_millis(50)).await;
     tokio::task::yield_now().await;
 
-    // Go back to active
+    // Go back to active — the focused app is caught up right away
     idle_monitor.set_idle(false);
     tokio::task::yield_now().await;
 
@@ -365,9 +544,64 @@ async fn test_idle_transitions() {
     let _ = handle.await;
 
     let sent = sent.lock().unwrap().clone();
-    assert_eq!(sent.len(), 2);
+    assert_eq!(sent.len(), 3);
     assert_eq!(sent[0].entity, "firefox");
-    assert_eq!(sent[1].entity, "kitty");
+    assert_eq!(sent[1].entity, "gedit");
+    assert_eq!(sent[2].entity, "kitty");
+}
+
+// Test: returning from idle sends a heartbeat for the focused app right away,
+// even within the resend interval
+#[tokio::test]
+async fn test_catch_up_heartbeat_after_idle() {
+    tokio::time::pause();
+
+    let (source, tx) = MockFocusSource::with_sender();
+    let (sender, sent) = RecordingSender::new();
+    let idle_monitor = Arc::new(IdleMonitor::new());
+    let shutdown = CancellationToken::new();
+    let reload = Notify::new();
+
+    let idle_ref = Arc::clone(&idle_monitor);
+    let config = Config {
+        min_entity_resend_seconds: 120,
+        heartbeat_interval_seconds: 3600, // disable periodic timer
+        ..Config::default()
+    };
+
+    let handle = tokio::spawn(async move {
+        run_event_loop(
+            Box::new(source),
+            &config,
+            &sender,
+            &idle_ref,
+            &shutdown,
+            &reload,
+            false,
+        )
+        .await
+    });
+
+    tx.send(event("firefox", None)).await.unwrap();
+    tokio::time::advance(Duration::from_millis(50)).await;
+    tokio::task::yield_now().await;
+
+    idle_monitor.set_idle(true);
+    tokio::time::advance(Duration::from_secs(30)).await;
+    idle_monitor.set_idle(false);
+    tokio::task::yield_now().await;
+
+    // Locking and unlocking catches up the same way
+    idle_monitor.set_locked(true);
+    idle_monitor.set_locked(false);
+    tokio::task::yield_now().await;
+
+    drop(tx);
+    let _ = handle.await;
+
+    let sent = sent.lock().unwrap().clone();
+    assert_eq!(sent.len(), 3);
+    assert!(sent.iter().all(|s| s.entity == "firefox"));
 }
 
 // Test: periodic heartbeat timer fires for sustained focus on same app
@@ -523,7 +757,7 @@ async fn test_category_rules_applied() {
 
     let events = vec![
         event("firefox", None),
-        event("code", None),
+        event("gedit", None),
         event("slack", None),
     ];
 
@@ -535,20 +769,19 @@ async fn test_category_rules_applied() {
     assert_eq!(sent[2].category, "communicating");
 }
 
-// Test: title tracking with append strategy
+// Test: title tracking with the full policy
 #[tokio::test]
-async fn test_title_append_strategy() {
-    use wakatime_focusd::config::TitleStrategy;
+async fn test_title_policy_full() {
+    use wakatime_focusd::config::TitlePolicy;
 
     let config = Config {
-        track_titles: true,
-        title_strategy: TitleStrategy::Append,
+        title_policy: TitlePolicy::Full,
         ..Config::default()
     };
 
     let events = vec![
         event("firefox", Some("GitHub")),
-        event("code", None),      // no title
+        event("gedit", None),     // no title
         event("kitty", Some("")), // empty title
     ];
 
@@ -556,18 +789,17 @@ async fn test_title_append_strategy() {
 
     assert_eq!(sent.len(), 3);
     assert_eq!(sent[0].entity, "firefox — GitHub");
-    assert_eq!(sent[1].entity, "code");
+    assert_eq!(sent[1].entity, "gedit");
     assert_eq!(sent[2].entity, "kitty"); // empty title not appended
 }
 
-// Test: title tracking with ignore strategy
+// Test: title tracking with the off policy
 #[tokio::test]
-async fn test_title_ignore_strategy() {
-    use wakatime_focusd::config::TitleStrategy;
+async fn test_title_policy_off() {
+    use wakatime_focusd::config::TitlePolicy;
 
     let config = Config {
-        track_titles: true,
-        title_strategy: TitleStrategy::Ignore,
+        title_policy: TitlePolicy::Off,
         ..Config::default()
     };
 
@@ -630,3 +862,556 @@ async fn test_reload_signal_returns_reload_outcome() {
 
     assert!(matches!(outcome, EventLoopOutcome::Reload));
 }
+
+// Test: leave heartbeat is sent for the previous entity when focus moves away
+#[tokio::test]
+async fn test_leave_heartbeat_on_focus_change() {
+    tokio::time::pause();
+
+    let (source, tx) = MockFocusSource::with_sender();
+    let (sender, sent_arc) = RecordingSender::new();
+    let idle_monitor = IdleMonitor::new();
+    let shutdown = CancellationToken::new();
+    let reload = Notify::new();
+    idle_monitor.disable();
+
+    let config = Config {
+        send_leave_heartbeats: true,
+        heartbeat_interval_seconds: 3600, // disable periodic timer
+        min_entity_resend_seconds: 3600,
+        ..Config::default()
+    };
+
+    let handle = tokio::spawn(async move {
+        run_event_loop(
+            Box::new(source),
+            &config,
+            &sender,
+            &idle_monitor,
+            &shutdown,
+            &reload,
+            false,
+        )
+        .await
+    });
+
+    tx.send(event("firefox", None)).await.unwrap();
+    tokio::time::advance(Duration::from_millis(50)).await;
+    tokio::task::yield_now().await;
+
+    // Stay on firefox long enough for a leave heartbeat to be worthwhile
+    tokio::time::advance(Duration::from_secs(90)).await;
+    tokio::task::yield_now().await;
+
+    tx.send(event("gedit", None)).await.unwrap();
+    tokio::time::advance(Duration::from_millis(50)).await;
+    tokio::task::yield_now().await;
+
+    // Switch back quickly — too soon after gedit's heartbeat for a leave
+    tx.send(event("firefox", None)).await.unwrap();
+    tokio::time::advance(Duration::from_millis(50)).await;
+    tokio::task::yield_now().await;
+
+    drop(tx);
+    let _ = handle.await;
+
+    let sent = sent_arc.lock().unwrap().clone();
+    let entities: Vec<&str> = sent.iter().map(|r| r.entity.as_str()).collect();
+    assert_eq!(entities, vec!["firefox", "firefox", "gedit", "firefox"]);
+}
+
+// Test: adaptive interval shortens periodic heartbeats while focus is stable
+#[tokio::test]
+async fn test_adaptive_interval_shortens_while_stable() {
+    tokio::time::pause();
+
+    let (source, tx) = MockFocusSource::with_sender();
+    let (sender, sent_arc) = RecordingSender::new();
+    let idle_monitor = IdleMonitor::new();
+    let shutdown = CancellationToken::new();
+    let reload = Notify::new();
+    idle_monitor.disable();
+
+    let config = Config {
+        adaptive_interval: true,
+        heartbeat_interval_seconds: 4,
+        adaptive_min_interval_seconds: 1,
+        adaptive_max_interval_seconds: 8,
+        ..Config::default()
+    };
+
+    let handle = tokio::spawn(async move {
+        run_event_loop(
+            Box::new(source),
+            &config,
+            &sender,
+            &idle_monitor,
+            &shutdown,
+            &reload,
+            false,
+        )
+        .await
+    });
+
+    tx.send(event("firefox", None)).await.unwrap();
+    for _ in 0..5 {
+        tokio::time::advance(Duration::from_millis(10)).await;
+        tokio::task::yield_now().await;
+    }
+
+    // Periodic sends at +4s, +6s, +7s, +8s, +9s as the interval halves to 1s.
+    for _ in 0..20 {
+        tokio::time::advance(Duration::from_millis(500)).await;
+        tokio::task::yield_now().await;
+    }
+
+    drop(tx);
+    let _ = handle.await;
+
+    let sent = sent_arc.lock().unwrap().clone();
+    // A fixed 4s interval would give the initial heartbeat plus two periodic ones.
+    assert!(
+        sent.len() >= 5,
+        "expected adaptive interval to produce at least 5 heartbeats, got {}",
+        sent.len()
+    );
+    for record in &sent {
+        assert_eq!(record.entity, "firefox");
+    }
+}
+
+// Test: a failed send doesn't advance the throttle, so the entity isn't throttled
+#[tokio::test]
+async fn test_failed_send_does_not_throttle_entity() {
+    tokio::time::pause();
+
+    let (source, tx) = MockFocusSource::with_sender();
+    let (sender, sent_arc) = FlakySender::new(1);
+    let idle_monitor = IdleMonitor::new();
+    let shutdown = CancellationToken::new();
+    let reload = Notify::new();
+    idle_monitor.disable();
+
+    let config = Config {
+        min_entity_resend_seconds: 120,
+        heartbeat_interval_seconds: 3,
+        ..Config::default()
+    };
+
+    let handle = tokio::spawn(async move {
+        run_event_loop(
+            Box::new(source),
+            &config,
+            &sender,
+            &idle_monitor,
+            &shutdown,
+            &reload,
+            false,
+        )
+        .await
+    });
+
+    // First send fails
+    tx.send(event("firefox", None)).await.unwrap();
+    for _ in 0..5 {
+        tokio::time::advance(Duration::from_millis(10)).await;
+        tokio::task::yield_now().await;
+    }
+
+    // Same entity again right away: not throttled, because nothing was sent yet
+    tx.send(event("firefox", None)).await.unwrap();
+    for _ in 0..5 {
+        tokio::time::advance(Duration::from_millis(10)).await;
+        tokio::task::yield_now().await;
+    }
+
+    drop(tx);
+    let _ = handle.await;
+
+    let sent = sent_arc.lock().unwrap().clone();
+    assert_eq!(sent.len(), 1);
+    assert_eq!(sent[0].entity, "firefox");
+}
+
+// Test: a failed focus-change heartbeat is retried on the next periodic tick
+#[tokio::test]
+async fn test_failed_heartbeat_retried_by_timer() {
+    tokio::time::pause();
+
+    let (source, tx) = MockFocusSource::with_sender();
+    let (sender, sent_arc) = FlakySender::new(1);
+    let idle_monitor = IdleMonitor::new();
+    let shutdown = CancellationToken::new();
+    let reload = Notify::new();
+    idle_monitor.disable();
+
+    let config = Config {
+        min_entity_resend_seconds: 120,
+        heartbeat_interval_seconds: 3,
+        ..Config::default()
+    };
+
+    let handle = tokio::spawn(async move {
+        run_event_loop(
+            Box::new(source),
+            &config,
+            &sender,
+            &idle_monitor,
+            &shutdown,
+            &reload,
+            false,
+        )
+        .await
+    });
+
+    tx.send(event("gedit", None)).await.unwrap();
+    for _ in 0..5 {
+        tokio::time::advance(Duration::from_millis(10)).await;
+        tokio::task::yield_now().await;
+    }
+
+    // Advance past the periodic timer — the failed heartbeat is retried
+    for _ in 0..10 {
+        tokio::time::advance(Duration::from_millis(500)).await;
+        tokio::task::yield_now().await;
+    }
+
+    drop(tx);
+    let _ = handle.await;
+
+    let sent = sent_arc.lock().unwrap().clone();
+    assert_eq!(sent.len(), 1);
+    assert_eq!(sent[0].entity, "gedit");
+}
+
+// Test: resuming from suspend resets the throttle so the same entity is sent again
+#[tokio::test]
+async fn test_resume_resets_throttle() {
+    tokio::time::pause();
+
+    let (source, tx) = MockFocusSource::with_sender();
+    let (sender, sent) = RecordingSender::new();
+    let idle_monitor = Arc::new(IdleMonitor::new());
+    let shutdown = CancellationToken::new();
+    let reload = Notify::new();
+
+    let idle_ref = Arc::clone(&idle_monitor);
+    let config = Config {
+        min_entity_resend_seconds: 120,
+        heartbeat_interval_seconds: 3600, // disable periodic timer
+        ..Config::default()
+    };
+
+    let handle = tokio::spawn(async move {
+        run_event_loop(
+            Box::new(source),
+            &config,
+            &sender,
+            &idle_ref,
+            &shutdown,
+            &reload,
+            false,
+        )
+        .await
+    });
+
+    tx.send(event("firefox", None)).await.unwrap();
+    tokio::time::advance(Duration::from_millis(50)).await;
+    tokio::task::yield_now().await;
+
+    // Same entity — throttled
+    tx.send(event("firefox", None)).await.unwrap();
+    tokio::time::advance(Duration::from_millis(50)).await;
+    tokio::task::yield_now().await;
+
+    idle_monitor.notify_resumed();
+    tokio::task::yield_now().await;
+
+    // Same entity after resume — sent
+    tx.send(event("firefox", None)).await.unwrap();
+    tokio::time::advance(Duration::from_millis(50)).await;
+    tokio::task::yield_now().await;
+
+    drop(tx);
+    let _ = handle.await;
+
+    let sent = sent.lock().unwrap().clone();
+    assert_eq!(sent.len(), 2);
+    assert!(sent.iter().all(|s| s.entity == "firefox"));
+}
+
+// Test: with periodic_requires_activity, periodic heartbeats stop without activity
+#[tokio::test]
+async fn test_periodic_heartbeat_requires_activity() {
+    tokio::time::pause();
+
+    let (source, tx) = MockFocusSource::with_sender();
+    let (sender, sent_arc) = RecordingSender::new();
+    let idle_monitor = Arc::new(IdleMonitor::new());
+    let shutdown = CancellationToken::new();
+    let reload = Notify::new();
+
+    let idle_ref = Arc::clone(&idle_monitor);
+    let config = Config {
+        min_entity_resend_seconds: 2,
+        heartbeat_interval_seconds: 3,
+        periodic_requires_activity: true,
+        ..Config::default()
+    };
+
+    let handle = tokio::spawn(async move {
+        run_event_loop(
+            Box::new(source),
+            &config,
+            &sender,
+            &idle_ref,
+            &shutdown,
+            &reload,
+            false,
+        )
+        .await
+    });
+
+    tx.send(event("firefox", None)).await.unwrap();
+    for _ in 0..5 {
+        tokio::time::advance(Duration::from_millis(10)).await;
+        tokio::task::yield_now().await;
+    }
+
+    // Several periodic ticks with no activity — nothing more is sent
+    for _ in 0..20 {
+        tokio::time::advance(Duration::from_millis(500)).await;
+        tokio::task::yield_now().await;
+    }
+    assert_eq!(sent_arc.lock().unwrap().len(), 1);
+
+    // Returning from idle counts as activity, so the next tick sends
+    idle_monitor.set_idle(true);
+    idle_monitor.set_idle(false);
+    for _ in 0..10 {
+        tokio::time::advance(Duration::from_millis(500)).await;
+        tokio::task::yield_now().await;
+    }
+
+    drop(tx);
+    let _ = handle.await;
+
+    let sent = sent_arc.lock().unwrap().clone();
+    assert_eq!(sent.len(), 2);
+    assert!(sent.iter().all(|s| s.entity == "firefox"));
+}
+
+// Test: browser tab reports turn browser heartbeats into domain heartbeats
+#[tokio::test]
+async fn test_browser_tab_attributes_domain() {
+    tokio::time::pause();
+
+    let (source, tx) = MockFocusSource::with_sender();
+    let tabs = Arc::new(BrowserTabs::new());
+    let source = BrowserAwareSource::new(
+        Box::new(source),
+        Arc::clone(&tabs),
+        &Config::default().browser_classes,
+    );
+    let (sender, sent) = RecordingSender::new();
+    let idle_monitor = IdleMonitor::new();
+    let shutdown = CancellationToken::new();
+    let reload = Notify::new();
+    idle_monitor.disable();
+
+    let config = Config {
+        heartbeat_interval_seconds: 3600, // disable periodic timer
+        ..Config::default()
+    };
+
+    let handle = tokio::spawn(async move {
+        run_event_loop(
+            Box::new(source),
+            &config,
+            &sender,
+            &idle_monitor,
+            &shutdown,
+            &reload,
+            false,
+        )
+        .await
+    });
+
+    let settle = || async {
+        for _ in 0..5 {
+            tokio::time::advance(Duration::from_millis(10)).await;
+            tokio::task::yield_now().await;
+        }
+    };
+
+    tx.send(event("firefox", None)).await.unwrap();
+    settle().await;
+
+    // Tab switch while firefox is focused — new heartbeat for the domain
+    tabs.report(Some("https://github.com/".to_string()));
+    settle().await;
+
+    // Not a browser — tab reports don't apply
+    tx.send(event("gedit", None)).await.unwrap();
+    settle().await;
+    tabs.report(Some("https://docs.rs/".to_string()));
+    settle().await;
+
+    // Back to the browser — picks up the latest tab
+    tx.send(event("firefox", None)).await.unwrap();
+    settle().await;
+
+    drop(tx);
+    let _ = handle.await;
+
+    let entities: Vec<String> = sent
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|s| s.entity.clone())
+        .collect();
+    assert_eq!(entities, vec!["firefox", "github.com", "gedit", "docs.rs"]);
+}
+
+// Test: the mock backend replays a script end to end and finishes cleanly
+#[tokio::test(start_paused = true)]
+async fn test_mock_backend_script_runs_to_completion() {
+    let dir = tempfile::tempdir().unwrap();
+    let script = dir.path().join("focus.jsonl");
+    std::fs::write(
+        &script,
+        concat!(
+            r#"{"app_class": "firefox", "title": "GitHub"}"#,
+            "\n",
+            r#"{"delay_ms": 30000, "app_class": "gedit", "title": "main.rs"}"#,
+            "\n",
+        ),
+    )
+    .unwrap();
+
+    let source = backend::connect_with_script(Backend::Mock, Some(&script))
+        .await
+        .unwrap();
+    let (sender, sent) = RecordingSender::new();
+    let idle_monitor = IdleMonitor::new();
+    idle_monitor.disable();
+
+    let outcome = run_event_loop(
+        source,
+        &Config::default(),
+        &sender,
+        &idle_monitor,
+        &CancellationToken::new(),
+        &Notify::new(),
+        false,
+    )
+    .await;
+
+    assert!(matches!(outcome, EventLoopOutcome::Finished));
+    let sent = sent.lock().unwrap();
+    assert_eq!(sent.len(), 2);
+    assert_eq!(sent[0].app_class, "firefox");
+    assert_eq!(sent[1].app_class, "gedit");
+}
+
+/// Helper: run the periodic timer for 13 seconds, returning how many flushes happened.
+async fn count_flushes(on_battery: bool) -> usize {
+    let (source, _tx) = MockFocusSource::with_sender();
+    let sender = Arc::new(FlushCountingSender::default());
+    let idle_monitor = Arc::new(IdleMonitor::new());
+    let shutdown = CancellationToken::new();
+    idle_monitor.disable();
+    idle_monitor.set_on_battery(on_battery);
+
+    let config = Config {
+        heartbeat_interval_seconds: 3,
+        battery_flush_interval_seconds: 10,
+        ..Config::default()
+    };
+
+    let handle = tokio::spawn({
+        let sender = Arc::clone(&sender);
+        let shutdown = shutdown.clone();
+        async move {
+            run_event_loop(
+                Box::new(source),
+                &config,
+                sender.as_ref(),
+                &idle_monitor,
+                &shutdown,
+                &Notify::new(),
+                false,
+            )
+            .await
+        }
+    });
+
+    for _ in 0..26 {
+        tokio::time::advance(Duration::from_millis(500)).await;
+        tokio::task::yield_now().await;
+    }
+    shutdown.cancel();
+    assert!(matches!(handle.await.unwrap(), EventLoopOutcome::Shutdown));
+
+    *sender.flushes.lock().unwrap()
+}
+
+// Test: on battery, timer flushes are deferred until the battery flush interval
+#[tokio::test(start_paused = true)]
+async fn test_battery_defers_flushes() {
+    // Ticks at 0, 3, 6, 9 and 12 seconds, plus the shutdown flush
+    assert_eq!(count_flushes(false).await, 6);
+    // Only the 12 second tick is past the 10 second battery interval
+    assert_eq!(count_flushes(true).await, 2);
+}
+
+// Test: after a resume, the periodic tick is delayed by at most the jitter
+#[tokio::test(start_paused = true)]
+async fn test_resume_tick_is_jittered() {
+    let (source, _tx) = MockFocusSource::with_sender();
+    let sender = Arc::new(FlushCountingSender::default());
+    let idle_monitor = Arc::new(IdleMonitor::new());
+    let shutdown = CancellationToken::new();
+    idle_monitor.disable();
+
+    let config = Config {
+        heartbeat_interval_seconds: 3600,
+        periodic_jitter_seconds: 30,
+        ..Config::default()
+    };
+
+    let handle = tokio::spawn({
+        let sender = Arc::clone(&sender);
+        let idle_monitor = Arc::clone(&idle_monitor);
+        let shutdown = shutdown.clone();
+        async move {
+            run_event_loop(
+                Box::new(source),
+                &config,
+                sender.as_ref(),
+                &idle_monitor,
+                &shutdown,
+                &Notify::new(),
+                false,
+            )
+            .await
+        }
+    });
+
+    // The first tick fires right away
+    tokio::time::advance(Duration::from_secs(1)).await;
+    tokio::task::yield_now().await;
+    assert_eq!(*sender.flushes.lock().unwrap(), 1);
+
+    idle_monitor.notify_resumed();
+    tokio::task::yield_now().await;
+    for _ in 0..31 {
+        tokio::time::advance(Duration::from_secs(1)).await;
+        tokio::task::yield_now().await;
+    }
+    // One tick within the jitter, then nothing until the next interval
+    assert_eq!(*sender.flushes.lock().unwrap(), 2);
+
+    shutdown.cancel();
+    assert!(matches!(handle.await.unwrap(), EventLoopOutcome::Shutdown));
+}
@@ -4,6 +4,8 @@
 //! service, connects the real `GnomeSource` backend, and verifies `FocusEvent`
 //! output.
 
+#![cfg(feature = "gnome")]
+
 use std::collections::HashMap;
 use std::env;
 use std::sync::Arc;
@@ -5,6 +5,8 @@
 //! and calls `FocusChanged` on the daemon's `org.wakatime.focusd` receiver to
 //! simulate `KWin` script callbacks.
 
+#![cfg(feature = "kde")]
+
 use std::env;
 use std::sync::Arc;
 use std::time::Duration;
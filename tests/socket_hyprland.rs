@@ -4,6 +4,8 @@
 //! `activewindowv2>>` lines, connects the real `HyprlandSource` backend,
 //! and verifies `FocusEvent` output.
 
+#![cfg(feature = "hyprland")]
+
 use std::env;
 use std::time::Duration;
 
@@ -12,6 +14,8 @@ use tempfile::TempDir;
 use tokio::io::AsyncWriteExt;
 use tokio::net::UnixListener;
 use wakatime_focusd::backend::Backend;
+use wakatime_focusd::backend::fake_hyprland;
+use wakatime_focusd::backend::fake_hyprland::FakeHyprland;
 use wakatime_focusd::backend::{
     self,
 };
@@ -107,9 +111,87 @@ async fn reconnection_after_eof() {
     assert_eq!(event.title, Some("main.rs".to_string()));
 }
 
+#[tokio::test]
+#[serial]
+async fn cancelled_reads_keep_their_event() {
+    let (tmp, listener) = setup();
+    // A request socket that never answers, so PID lookups wait it out
+    let _requests = UnixListener::bind(tmp.path().join("hypr/test_instance/.socket.sock")).unwrap();
+    let (rest_tx, rest_rx) = tokio::sync::oneshot::channel();
+
+    tokio::spawn(async move {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        stream
+            .write_all(b"activewindowv2>>0xabc123\nactivewindow>>fire")
+            .await
+            .unwrap();
+        rest_rx.await.unwrap();
+        stream.write_all(b"fox,Tab\n").await.unwrap();
+    });
+
+    let mut source = backend::connect(Backend::Hyprland).await.unwrap();
+    // Cancelled in the middle of a line
+    assert!(
+        tokio::time::timeout(Duration::from_millis(100), source.next_event())
+            .await
+            .is_err()
+    );
+    rest_tx.send(()).unwrap();
+    // Cancelled while looking up the window's process
+    assert!(
+        tokio::time::timeout(Duration::from_millis(300), source.next_event())
+            .await
+            .is_err()
+    );
+
+    let event = tokio::time::timeout(TEST_TIMEOUT, source.next_event())
+        .await
+        .expect("timed out")
+        .unwrap();
+    assert_eq!(event.app_class, "firefox");
+    assert_eq!(event.title, Some("Tab".to_string()));
+    assert_eq!(event.window_id, Some("0xabc123".to_string()));
+}
+
 // Read errors follow the same reconnection code path as EOF
 // (self.reader = None → reconnect), so the EOF test above covers both.
 
+#[tokio::test]
+#[serial]
+async fn restarted_instance_found() {
+    let (tmp, listener) = setup();
+    // Hyprland exits, leaving its socket behind, and starts again under a
+    // new signature
+    drop(listener);
+    let new_dir = tmp.path().join("hypr").join("restarted_instance");
+    std::fs::create_dir_all(&new_dir).unwrap();
+    let listener = UnixListener::bind(new_dir.join(".socket2.sock")).unwrap();
+
+    tokio::spawn(async move {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        stream
+            .write_all(b"activewindow>>kitty,~/src\n")
+            .await
+            .unwrap();
+    });
+
+    let mut attempts = 0;
+    let mut source = loop {
+        attempts += 1;
+        match backend::connect(Backend::Hyprland).await {
+            Ok(source) => break source,
+            Err(e) => assert!(attempts < 5, "never found the new instance: {e}"),
+        }
+    };
+    assert!(attempts > 1, "the dead socket should fail first");
+
+    let event = tokio::time::timeout(TEST_TIMEOUT, source.next_event())
+        .await
+        .expect("timed out")
+        .unwrap();
+    assert_eq!(event.app_class, "kitty");
+}
+
 #[tokio::test]
 #[serial]
 async fn malformed_lines_skipped() {
@@ -165,3 +247,47 @@ async fn empty_class_skipped() {
     assert_eq!(event.app_class, "kitty");
     assert_eq!(event.title, Some("Terminal".to_string()));
 }
+
+#[tokio::test]
+#[serial]
+async fn fake_server_replays_script() {
+    let tmp = TempDir::new().unwrap();
+    let fake = FakeHyprland::bind(tmp.path(), "fake_instance").unwrap();
+    unsafe {
+        env::set_var("XDG_RUNTIME_DIR", tmp.path());
+        env::set_var("HYPRLAND_INSTANCE_SIGNATURE", "fake_instance");
+    }
+    let script = fake_hyprland::parse_script(
+        "workspace>>3\n\
+         activewindow>>kitty,~/src/project\n\
+         activewindowv2>>0x1\n\
+         disconnect\n\
+         sleep 10\n\
+         activewindow>>firefox,GitHub\n\
+         activewindowv2>>0x2\n",
+    )
+    .unwrap();
+    let replay = tokio::spawn(async move { fake.replay(&script).await });
+
+    let mut source = backend::connect(Backend::Hyprland).await.unwrap();
+    let event = tokio::time::timeout(TEST_TIMEOUT, source.next_event())
+        .await
+        .expect("timed out")
+        .unwrap();
+    assert_eq!(event.app_class, "kitty");
+    assert_eq!(event.workspace.as_deref(), Some("3"));
+
+    let event = tokio::time::timeout(TEST_TIMEOUT, source.next_event())
+        .await
+        .expect("reconnection timed out")
+        .unwrap();
+    assert_eq!(event.app_class, "firefox");
+    assert_eq!(event.window_id.as_deref(), Some("0x2"));
+
+    drop(source);
+    tokio::time::timeout(TEST_TIMEOUT, replay)
+        .await
+        .expect("replay did not finish")
+        .unwrap()
+        .unwrap();
+}
@@ -3,6 +3,8 @@
 //! Spins up a fake socket server that speaks the Niri JSON-lines protocol,
 //! connects the real `NiriSource` backend, and verifies `FocusEvent` output.
 
+#![cfg(feature = "niri")]
+
 use std::env;
 use std::time::Duration;
 
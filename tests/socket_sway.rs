@@ -4,6 +4,8 @@
 //! protocol, connects the real `SwaySource` backend, and verifies `FocusEvent`
 //! output.
 
+#![cfg(feature = "sway")]
+
 use std::env;
 use std::time::Duration;
 
@@ -5,6 +5,8 @@
 //! toplevel handles with focus state, and verifies `FocusEvent` output from
 //! the real backend.
 
+#![cfg(feature = "cosmic")]
+
 use std::collections::HashMap;
 use std::env;
 use std::os::fd::AsFd;
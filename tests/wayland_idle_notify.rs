@@ -0,0 +1,235 @@
+//! Integration tests for the ext-idle-notify idle source against a mock
+//! Wayland compositor.
+//!
+//! Starts a minimal Wayland server that advertises a seat and
+//! `ext_idle_notifier_v1`, reports every new idle notification as idle, and
+//! notes when the client disconnects.
+
+#![cfg(feature = "idle-notify")]
+
+use std::env;
+use std::os::fd::AsFd;
+use std::os::fd::AsRawFd;
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+use serial_test::serial;
+use tokio_util::sync::CancellationToken;
+use wakatime_focusd::idle::IdleMonitor;
+use wakatime_focusd::idle::IdleSource;
+use wakatime_focusd::idle::wayland::WaylandSource;
+use wayland_protocols::ext::idle_notify::v1::server::ext_idle_notification_v1;
+use wayland_protocols::ext::idle_notify::v1::server::ext_idle_notification_v1::ExtIdleNotificationV1;
+use wayland_protocols::ext::idle_notify::v1::server::ext_idle_notifier_v1;
+use wayland_protocols::ext::idle_notify::v1::server::ext_idle_notifier_v1::ExtIdleNotifierV1;
+use wayland_server::Client;
+use wayland_server::DataInit;
+use wayland_server::Dispatch;
+use wayland_server::DisplayHandle;
+use wayland_server::GlobalDispatch;
+use wayland_server::ListeningSocket;
+use wayland_server::New;
+use wayland_server::backend::ClientData;
+use wayland_server::backend::ClientId;
+use wayland_server::backend::DisconnectReason;
+use wayland_server::protocol::wl_seat;
+use wayland_server::protocol::wl_seat::WlSeat;
+
+const TEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Mock compositor state
+struct MockCompositor;
+
+// Notes when its client disconnects
+struct Tracked(Arc<AtomicBool>);
+
+impl ClientData for Tracked {
+    fn initialized(&self, _client_id: ClientId) {}
+
+    fn disconnected(&self, _client_id: ClientId, _reason: DisconnectReason) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+}
+
+impl GlobalDispatch<WlSeat, ()> for MockCompositor {
+    fn bind(
+        _state: &mut Self,
+        _dh: &DisplayHandle,
+        _client: &Client,
+        resource: New<WlSeat>,
+        _global_data: &(),
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        data_init.init(resource, ());
+    }
+}
+
+impl Dispatch<WlSeat, ()> for MockCompositor {
+    fn request(
+        _state: &mut Self,
+        _client: &Client,
+        _resource: &WlSeat,
+        _request: wl_seat::Request,
+        _data: &(),
+        _dhandle: &DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+    }
+}
+
+impl GlobalDispatch<ExtIdleNotifierV1, ()> for MockCompositor {
+    fn bind(
+        _state: &mut Self,
+        _dh: &DisplayHandle,
+        _client: &Client,
+        resource: New<ExtIdleNotifierV1>,
+        _global_data: &(),
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        data_init.init(resource, ());
+    }
+}
+
+// Every new notification goes idle right away
+impl Dispatch<ExtIdleNotifierV1, ()> for MockCompositor {
+    fn request(
+        _state: &mut Self,
+        _client: &Client,
+        _resource: &ExtIdleNotifierV1,
+        request: ext_idle_notifier_v1::Request,
+        _data: &(),
+        _dhandle: &DisplayHandle,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        if let ext_idle_notifier_v1::Request::GetIdleNotification { id, .. } = request {
+            data_init.init(id, ()).idled();
+        }
+    }
+}
+
+impl Dispatch<ExtIdleNotificationV1, ()> for MockCompositor {
+    fn request(
+        _state: &mut Self,
+        _client: &Client,
+        _resource: &ExtIdleNotificationV1,
+        _request: ext_idle_notification_v1::Request,
+        _data: &(),
+        _dhandle: &DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+    }
+}
+
+/// Point the client at a mock display and run it until `stop` is set.
+fn spawn_server(
+    name: &str,
+    disconnected: Arc<AtomicBool>,
+    stop: Arc<AtomicBool>,
+) -> (tempfile::TempDir, tokio::task::JoinHandle<()>) {
+    let tmp = tempfile::TempDir::new().unwrap();
+
+    // SAFETY: tests run serially so env var mutation is safe.
+    unsafe {
+        env::set_var("XDG_RUNTIME_DIR", tmp.path());
+        env::set_var("WAYLAND_DISPLAY", name);
+    }
+
+    let socket = ListeningSocket::bind(name).unwrap();
+    let mut display = wayland_server::Display::<MockCompositor>::new().unwrap();
+    let server = tokio::task::spawn_blocking(move || {
+        let mut dh = display.handle();
+        let _seat = dh.create_global::<MockCompositor, WlSeat, ()>(1, ());
+        let _notifier = dh.create_global::<MockCompositor, ExtIdleNotifierV1, ()>(1, ());
+        let mut comp = MockCompositor;
+
+        while !stop.load(Ordering::Relaxed) {
+            if let Some(stream) = socket.accept().unwrap() {
+                dh.insert_client(stream, Arc::new(Tracked(Arc::clone(&disconnected))))
+                    .unwrap();
+            }
+
+            let fd = display.backend().poll_fd().as_fd().as_raw_fd();
+            let mut pollfd = [libc::pollfd {
+                fd,
+                events: libc::POLLIN,
+                revents: 0,
+            }];
+            unsafe {
+                libc::poll(pollfd.as_mut_ptr(), 1, 50);
+            }
+
+            display.dispatch_clients(&mut comp).unwrap();
+            display.flush_clients().unwrap();
+        }
+    });
+    (tmp, server)
+}
+
+/// Run `source` until it reports the session idle.
+async fn wait_until_idle(
+    source: &mut WaylandSource,
+    monitor: &IdleMonitor,
+    shutdown: &CancellationToken,
+) {
+    let run = source.run(monitor, shutdown);
+    tokio::pin!(run);
+    tokio::time::timeout(TEST_TIMEOUT, async {
+        while !monitor.is_idle() {
+            tokio::select! {
+                result = &mut run => panic!("idle source stopped: {result:?}"),
+                () = tokio::time::sleep(Duration::from_millis(20)) => {}
+            }
+        }
+    })
+    .await
+    .expect("timed out waiting for idle");
+}
+
+#[tokio::test]
+#[serial]
+async fn idle_notification_reaches_the_monitor() {
+    let stop = Arc::new(AtomicBool::new(false));
+    let (_tmp, server) = spawn_server("wayland-test-idle", Arc::default(), Arc::clone(&stop));
+
+    let mut source = WaylandSource::connect(Duration::from_mins(5)).unwrap();
+    let monitor = IdleMonitor::new();
+    let shutdown = CancellationToken::new();
+    wait_until_idle(&mut source, &monitor, &shutdown).await;
+    assert!(monitor.is_idle());
+
+    stop.store(true, Ordering::Relaxed);
+    let _ = server.await;
+}
+
+#[tokio::test]
+#[serial]
+async fn dropping_the_source_stops_its_event_loop() {
+    let disconnected = Arc::new(AtomicBool::new(false));
+    let stop = Arc::new(AtomicBool::new(false));
+    let (_tmp, server) = spawn_server(
+        "wayland-test-idle-drop",
+        Arc::clone(&disconnected),
+        Arc::clone(&stop),
+    );
+
+    let mut source = WaylandSource::connect(Duration::from_mins(5)).unwrap();
+    let monitor = IdleMonitor::new();
+    let shutdown = CancellationToken::new();
+    wait_until_idle(&mut source, &monitor, &shutdown).await;
+    // Nothing more comes from the compositor, the thread waits for it
+    drop(source);
+
+    // The connection only closes once the event loop thread has exited
+    tokio::time::timeout(TEST_TIMEOUT, async {
+        while !disconnected.load(Ordering::Relaxed) {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    })
+    .await
+    .expect("the event loop thread kept the connection open");
+
+    stop.store(true, Ordering::Relaxed);
+    let _ = server.await;
+}
@@ -5,6 +5,8 @@
 //! `zwlr_foreign_toplevel_manager_v1`, creates toplevel handles with focus
 //! state, and verifies `FocusEvent` output from the real backend.
 
+#![cfg(feature = "wlr-foreign-toplevel")]
+
 use std::env;
 use std::os::fd::AsFd;
 use std::os::fd::AsRawFd;
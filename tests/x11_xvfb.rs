@@ -4,6 +4,8 @@
 //! `_NET_WM_NAME` properties, sets `_NET_ACTIVE_WINDOW` on the root window,
 //! and verifies `FocusEvent` output from the real X11 backend.
 
+#![cfg(feature = "x11")]
+
 use std::env;
 use std::process::Child;
 use std::process::Command;